@@ -1,6 +1,6 @@
 // build.rs
 // Compiles Swift library and tells cargo how to link it
-// Supports both macOS and iOS targets
+// Supports both macOS and iOS targets; other targets skip the Swift bridge
 
 use std::env;
 use std::path::PathBuf;
@@ -41,16 +41,14 @@ fn main() {
     } else if is_macos {
         ("libFoundationModelsFFI.dylib", "dylib", "", "dylib")
     } else {
-        panic!(
-            "Unsupported target platform: {}. \
-             This crate only supports Apple platforms (macOS, iOS). \
-             Build this on macOS or use cross-compilation with appropriate targets:\n\
-             - aarch64-apple-ios (iOS device)\n\
-             - aarch64-apple-ios-sim (iOS simulator)\n\
-             - aarch64-apple-darwin (Apple Silicon macOS)\n\
-             - x86_64-apple-darwin (Intel macOS)",
+        // Non-Apple targets build without the Swift bridge: the FFI layer is
+        // replaced by stubs that report the system model as unavailable, so the
+        // crate still type-checks and can run against other `ModelBackend`s
+        println!(
+            "cargo:warning=Target {} is not an Apple platform - skipping Swift compilation",
             target
         );
+        return;
     };
 
     let lib_path = PathBuf::from(&out_dir).join(lib_name);
@@ -0,0 +1,319 @@
+// fm-bindings-derive/src/generable.rs
+// #[derive(Generable)] - builds a GenerationSchema from a struct or enum definition
+
+use super::partial::partial;
+use proc_macro2::TokenStream;
+use quote::{quote, quote_spanned};
+use syn::ext::IdentExt;
+use syn::spanned::Spanned;
+use syn::{
+    Attribute, Data, DeriveInput, Error, Expr, ExprRange, Field, Fields, GenericParam, LitStr,
+    RangeLimits, Result, Variant, parse_quote,
+};
+
+/// Guides given with `#[guide(...)]` on one item
+#[derive(Default)]
+struct Guides {
+    description: Option<LitStr>,
+    range: Option<Bounds>,
+    regex: Option<LitStr>,
+    count: Option<Bounds>,
+}
+
+/// Inclusive bounds of a `range` or `count` guide, as expressions
+struct Bounds {
+    min: Option<TokenStream>,
+    max: Option<TokenStream>,
+    span: proc_macro2::Span,
+}
+
+pub fn expand(input: &DeriveInput) -> Result<TokenStream> {
+    let guides = parse_guides(&input.attrs)?;
+    reject_value_guides(&guides, "types")?;
+
+    let name = input.ident.unraw().to_string();
+    let schema = match &input.data {
+        Data::Struct(data) => struct_schema(&name, &data.fields, input)?,
+        Data::Enum(data) => {
+            if data.variants.is_empty() {
+                return Err(Error::new_spanned(
+                    &input.ident,
+                    "Generable cannot be derived for enums without variants",
+                ));
+            }
+            enum_schema(&name, data.variants.iter())?
+        }
+        Data::Union(_) => {
+            return Err(Error::new_spanned(
+                &input.ident,
+                "Generable cannot be derived for unions",
+            ));
+        }
+    };
+    let schema = with_description(schema, &guides.description);
+
+    // Every type parameter must itself be generable
+    let mut generics = input.generics.clone();
+    for param in &mut generics.params {
+        if let GenericParam::Type(param) = param {
+            param.bounds.push(parse_quote!(::fm_bindings::Generable));
+        }
+    }
+    let (impl_generics, ty_generics, where_clause) = generics.split_for_impl();
+    let ident = &input.ident;
+    let partial = partial(input, &generics);
+    let partial_ty = &partial.ty;
+    let partial_definition = &partial.definition;
+
+    Ok(quote! {
+        impl #impl_generics ::fm_bindings::Generable for #ident #ty_generics #where_clause {
+            type Partial = #partial_ty;
+
+            fn generation_schema() -> ::fm_bindings::GenerationSchema {
+                #schema
+            }
+        }
+
+        #partial_definition
+    })
+}
+
+fn struct_schema(name: &str, fields: &Fields, input: &DeriveInput) -> Result<TokenStream> {
+    match fields {
+        Fields::Named(fields) => object_schema(name, fields.named.iter()),
+        // A newtype is generated (and deserialized) as the value it wraps
+        Fields::Unnamed(fields) if fields.unnamed.len() == 1 => {
+            let field = &fields.unnamed[0];
+            let guides = parse_guides(&field.attrs)?;
+            field_schema(field, &guides)
+        }
+        _ => Err(Error::new_spanned(
+            &input.ident,
+            "Generable can only be derived for structs with named fields or newtype structs",
+        )),
+    }
+}
+
+pub fn object_schema<'a>(
+    name: &str,
+    fields: impl Iterator<Item = &'a Field>,
+) -> Result<TokenStream> {
+    let properties = fields.map(property).collect::<Result<Vec<_>>>()?;
+    Ok(quote! {
+        ::fm_bindings::GenerationSchema::object(#name)
+            #(.with_property(#properties))*
+    })
+}
+
+fn property(field: &Field) -> Result<TokenStream> {
+    let guides = parse_guides(&field.attrs)?;
+    let name = field
+        .ident
+        .as_ref()
+        .map(|ident| ident.unraw().to_string())
+        .unwrap_or_default();
+    let ty = &field.ty;
+    let schema = field_schema(field, &guides)?;
+    let description = guides
+        .description
+        .map(|description| quote!(.with_description(#description)));
+
+    Ok(quote! {
+        ::fm_bindings::Property::new(#name, #schema)
+            .with_optional(<#ty as ::fm_bindings::Generable>::OPTIONAL)
+            #description
+    })
+}
+
+/// The schema of a field's type, refined by its value guides
+fn field_schema(field: &Field, guides: &Guides) -> Result<TokenStream> {
+    let ty = &field.ty;
+    let mut assertions = Vec::new();
+    let mut refinements = Vec::new();
+
+    if let Some(Bounds { min, max, span }) = &guides.range {
+        let min = option_tokens(min.as_ref().map(|min| quote!((#min) as f64)));
+        let max = option_tokens(max.as_ref().map(|max| quote!((#max) as f64)));
+        assertions.push(quote_spanned! {*span=>
+            ::fm_bindings::__private::assert_range_guide::<#ty>();
+        });
+        refinements.push(quote!(.with_range(#min, #max)));
+    }
+    if let Some(regex) = &guides.regex {
+        assertions.push(quote_spanned! {regex.span()=>
+            ::fm_bindings::__private::assert_regex_guide::<#ty>();
+        });
+        refinements.push(quote!(.with_pattern(#regex)));
+    }
+    if let Some(Bounds { min, max, span }) = &guides.count {
+        let min = option_tokens(min.as_ref().map(|min| quote!((#min) as u32)));
+        let max = option_tokens(max.as_ref().map(|max| quote!((#max) as u32)));
+        assertions.push(quote_spanned! {*span=>
+            ::fm_bindings::__private::assert_count_guide::<#ty>();
+        });
+        refinements.push(quote!(.with_count(#min, #max)));
+    }
+
+    Ok(quote! {{
+        #(#assertions)*
+        <#ty as ::fm_bindings::Generable>::generation_schema() #(#refinements)*
+    }})
+}
+
+/// Enums follow serde's default (externally tagged) representation: unit variants
+/// are strings, other variants are objects with the variant name as only key
+fn enum_schema<'a>(name: &str, variants: impl Iterator<Item = &'a Variant>) -> Result<TokenStream> {
+    let variants: Vec<&Variant> = variants.collect();
+
+    if variants.iter().all(|variant| variant.fields.is_empty()) {
+        for variant in &variants {
+            reject_value_guides(&parse_guides(&variant.attrs)?, "variants")?;
+        }
+        let choices = variants
+            .iter()
+            .map(|variant| variant.ident.unraw().to_string());
+        return Ok(quote! {
+            ::fm_bindings::GenerationSchema::enumeration(#name, [#(#choices),*])
+        });
+    }
+
+    let mut choices = Vec::new();
+    for variant in variants {
+        let guides = parse_guides(&variant.attrs)?;
+        reject_value_guides(&guides, "variants")?;
+        let variant_name = variant.ident.unraw().to_string();
+
+        let choice = match &variant.fields {
+            Fields::Unit => {
+                let choice_name = format!("{}{}", name, variant_name);
+                quote! {
+                    ::fm_bindings::GenerationSchema::enumeration(#choice_name, [#variant_name])
+                }
+            }
+            Fields::Unnamed(fields) if fields.unnamed.len() == 1 => {
+                let field = &fields.unnamed[0];
+                let value = field_schema(field, &parse_guides(&field.attrs)?)?;
+                tagged(&variant_name, value)
+            }
+            Fields::Named(fields) => {
+                let value =
+                    object_schema(&format!("{}{}", name, variant_name), fields.named.iter())?;
+                tagged(&variant_name, value)
+            }
+            Fields::Unnamed(_) => {
+                return Err(Error::new_spanned(
+                    &variant.ident,
+                    "Generable variants can have at most one unnamed field",
+                ));
+            }
+        };
+        choices.push(with_description(choice, &guides.description));
+    }
+
+    Ok(quote! {
+        ::fm_bindings::GenerationSchema::any_of(#name, ::std::vec![#(#choices),*])
+    })
+}
+
+/// An object holding `value` under the variant name
+fn tagged(variant_name: &str, value: TokenStream) -> TokenStream {
+    quote! {
+        ::fm_bindings::GenerationSchema::object(#variant_name)
+            .with_property(::fm_bindings::Property::new(#variant_name, #value))
+    }
+}
+
+fn with_description(schema: TokenStream, description: &Option<LitStr>) -> TokenStream {
+    match description {
+        Some(description) => quote!(#schema.with_description(#description)),
+        None => schema,
+    }
+}
+
+fn option_tokens(value: Option<TokenStream>) -> TokenStream {
+    match value {
+        Some(value) => quote!(::core::option::Option::Some(#value)),
+        None => quote!(::core::option::Option::None),
+    }
+}
+
+/// Value guides only make sense on fields
+fn reject_value_guides(guides: &Guides, what: &str) -> Result<()> {
+    let span = guides
+        .range
+        .as_ref()
+        .map(|bounds| bounds.span)
+        .or_else(|| guides.regex.as_ref().map(LitStr::span))
+        .or_else(|| guides.count.as_ref().map(|bounds| bounds.span));
+    match span {
+        Some(span) => Err(Error::new(
+            span,
+            format!(
+                "`range`, `regex` and `count` guides apply to fields, not {}",
+                what
+            ),
+        )),
+        None => Ok(()),
+    }
+}
+
+fn parse_guides(attrs: &[Attribute]) -> Result<Guides> {
+    let mut guides = Guides::default();
+    for attr in attrs.iter().filter(|attr| attr.path().is_ident("guide")) {
+        attr.parse_nested_meta(|meta| {
+            if meta.path.is_ident("description") {
+                guides.description = Some(meta.value()?.parse()?);
+            } else if meta.path.is_ident("regex") {
+                guides.regex = Some(meta.value()?.parse()?);
+            } else if meta.path.is_ident("range") {
+                let expr: Expr = meta.value()?.parse()?;
+                guides.range = Some(bounds(&expr, false)?);
+            } else if meta.path.is_ident("count") {
+                let expr: Expr = meta.value()?.parse()?;
+                guides.count = Some(bounds(&expr, true)?);
+            } else {
+                return Err(meta
+                    .error("unknown guide, expected `description`, `range`, `regex` or `count`"));
+            }
+            Ok(())
+        })?;
+    }
+    Ok(guides)
+}
+
+/// Reads `a..=b`, `a..`, `..=b`, or (when `exact` is allowed) a single value
+fn bounds(expr: &Expr, exact: bool) -> Result<Bounds> {
+    let span = expr.span();
+    match expr {
+        Expr::Range(ExprRange {
+            start, limits, end, ..
+        }) => {
+            let max = match (limits, end) {
+                (RangeLimits::Closed(_), Some(end)) => Some(quote!(#end)),
+                (RangeLimits::HalfOpen(_), None) => None,
+                // Exclusive bounds only make sense for whole numbers
+                (RangeLimits::HalfOpen(_), Some(end)) if exact => Some(quote!((#end) - 1)),
+                _ => {
+                    return Err(Error::new(
+                        span,
+                        "expected an inclusive range such as `0..=10`, `0..` or `..=10`",
+                    ));
+                }
+            };
+            Ok(Bounds {
+                min: start.as_ref().map(|start| quote!(#start)),
+                max,
+                span,
+            })
+        }
+        _ if exact => Ok(Bounds {
+            min: Some(quote!(#expr)),
+            max: Some(quote!(#expr)),
+            span,
+        }),
+        _ => Err(Error::new(
+            span,
+            "expected a range such as `0..=10`, `0..` or `..=10`",
+        )),
+    }
+}
@@ -0,0 +1,43 @@
+// fm-bindings-derive/src/lib.rs
+// Procedural macros for fm-bindings
+
+//! Derive macros for [`fm-bindings`](https://docs.rs/fm-bindings)
+//!
+//! This crate is re-exported by `fm-bindings` (with the default `derive` feature)
+//! and is not meant to be used directly. See `fm_bindings::Generable` for the
+//! supported types and `#[guide(...)]` attributes, and `fm_bindings::fm_tool`
+//! for tool functions.
+
+use proc_macro::TokenStream;
+use syn::{DeriveInput, ItemFn, parse_macro_input};
+
+mod generable;
+mod partial;
+mod tool;
+
+/// Derives `fm_bindings::Generable`, the generation schema of a type
+///
+/// Also defines `{Type}Partial`, the type's partially generated counterpart
+/// delivered while streaming.
+#[proc_macro_derive(Generable, attributes(guide))]
+pub fn derive_generable(input: TokenStream) -> TokenStream {
+    let input = parse_macro_input!(input as DeriveInput);
+    generable::expand(&input)
+        .unwrap_or_else(syn::Error::into_compile_error)
+        .into()
+}
+
+/// Turns a function into an `fm_bindings::Tool` the model can call
+///
+/// Defines a unit struct named after the function in UpperCamelCase, whose
+/// `Tool` implementation decodes the model's arguments and calls the function.
+#[proc_macro_attribute]
+pub fn fm_tool(args: TokenStream, input: TokenStream) -> TokenStream {
+    let mut settings = tool::ToolArgs::default();
+    let parser = syn::meta::parser(|meta| settings.parse(meta));
+    parse_macro_input!(args with parser);
+    let function = parse_macro_input!(input as ItemFn);
+    tool::expand(settings, function)
+        .unwrap_or_else(syn::Error::into_compile_error)
+        .into()
+}
@@ -0,0 +1,261 @@
+// fm-bindings-derive/src/partial.rs
+// The partially generated counterpart of a Generable type, used while streaming
+
+use proc_macro2::TokenStream;
+use quote::{format_ident, quote};
+use syn::ext::IdentExt;
+use syn::{Attribute, Data, DeriveInput, Fields, Generics, Ident, Type};
+
+/// The `Partial` type of a derived `Generable` impl, and its definition if it needs one
+///
+/// Structs with named fields and enums get a mirror type whose fields are all
+/// optional; newtype structs reuse the partial type of the value they wrap.
+pub struct Partial {
+    pub ty: TokenStream,
+    pub definition: TokenStream,
+}
+
+pub fn partial(input: &DeriveInput, generics: &Generics) -> Partial {
+    let ident = format_ident!("{}Partial", input.ident);
+    let (_, ty_generics, _) = generics.split_for_impl();
+    let ty = quote!(#ident #ty_generics);
+
+    let definition = match &input.data {
+        Data::Struct(data) => match &data.fields {
+            Fields::Named(_) => struct_partial(input, &ident, &data.fields, generics),
+            Fields::Unnamed(fields) => {
+                let inner = &fields.unnamed[0].ty;
+                return Partial {
+                    ty: quote!(<#inner as ::fm_bindings::Generable>::Partial),
+                    definition: TokenStream::new(),
+                };
+            }
+            Fields::Unit => TokenStream::new(),
+        },
+        Data::Enum(data) => enum_partial(input, &ident, data.variants.iter(), generics),
+        Data::Union(_) => TokenStream::new(),
+    };
+
+    Partial { ty, definition }
+}
+
+fn struct_partial(
+    input: &DeriveInput,
+    ident: &Ident,
+    fields: &Fields,
+    generics: &Generics,
+) -> TokenStream {
+    let vis = &input.vis;
+    let docs = docs(&input.attrs);
+    let name = input.ident.unraw().to_string();
+    let summary = format!(
+        " Partially generated [`{}`], received while streaming: fields are `None` until the model has started them",
+        name
+    );
+    let (impl_generics, ty_generics, where_clause) = generics.split_for_impl();
+    let partial_fields = partial_fields(fields);
+    let names: Vec<&Ident> = fields
+        .iter()
+        .filter_map(|field| field.ident.as_ref())
+        .collect();
+    let labels = names.iter().map(|name| name.unraw().to_string());
+    let ident_label = ident.unraw().to_string();
+
+    let serde = serde_attributes();
+    quote! {
+        #[doc = #summary]
+        #(#docs)*
+        #serde
+        #vis struct #ident #impl_generics #where_clause {
+            #(#partial_fields,)*
+        }
+
+        impl #impl_generics ::core::default::Default for #ident #ty_generics #where_clause {
+            fn default() -> Self {
+                Self { #(#names: ::core::option::Option::None,)* }
+            }
+        }
+
+        impl #impl_generics ::core::clone::Clone for #ident #ty_generics #where_clause {
+            fn clone(&self) -> Self {
+                Self { #(#names: ::core::clone::Clone::clone(&self.#names),)* }
+            }
+        }
+
+        impl #impl_generics ::core::fmt::Debug for #ident #ty_generics #where_clause {
+            fn fmt(&self, f: &mut ::core::fmt::Formatter<'_>) -> ::core::fmt::Result {
+                f.debug_struct(#ident_label)
+                    #(.field(#labels, &self.#names))*
+                    .finish()
+            }
+        }
+
+        impl #impl_generics ::core::cmp::PartialEq for #ident #ty_generics #where_clause {
+            fn eq(&self, other: &Self) -> bool {
+                true #(&& self.#names == other.#names)*
+            }
+        }
+    }
+}
+
+fn enum_partial<'a>(
+    input: &DeriveInput,
+    ident: &Ident,
+    variants: impl Iterator<Item = &'a syn::Variant>,
+    generics: &Generics,
+) -> TokenStream {
+    let vis = &input.vis;
+    let docs = docs(&input.attrs);
+    let summary = format!(
+        " Partially generated [`{}`], received while streaming: a variant's fields are `None` until the model has started them",
+        input.ident.unraw()
+    );
+    let (impl_generics, ty_generics, where_clause) = generics.split_for_impl();
+
+    let mut definitions = Vec::new();
+    let mut clones = Vec::new();
+    let mut debugs = Vec::new();
+    let mut eqs = Vec::new();
+    for variant in variants {
+        let variant_ident = &variant.ident;
+        let label = variant_ident.unraw().to_string();
+        let variant_docs = docs_of(&variant.attrs);
+        match &variant.fields {
+            Fields::Unit => {
+                definitions.push(quote!(#(#variant_docs)* #variant_ident));
+                clones.push(quote!(Self::#variant_ident => Self::#variant_ident));
+                debugs.push(quote!(Self::#variant_ident => f.write_str(#label)));
+                eqs.push(quote!((Self::#variant_ident, Self::#variant_ident) => true));
+            }
+            Fields::Unnamed(fields) => {
+                let ty = &fields.unnamed[0].ty;
+                definitions.push(quote! {
+                    #(#variant_docs)* #variant_ident(<#ty as ::fm_bindings::Generable>::Partial)
+                });
+                clones.push(quote! {
+                    Self::#variant_ident(value) => Self::#variant_ident(::core::clone::Clone::clone(value))
+                });
+                debugs.push(quote! {
+                    Self::#variant_ident(value) => f.debug_tuple(#label).field(value).finish()
+                });
+                eqs.push(quote! {
+                    (Self::#variant_ident(left), Self::#variant_ident(right)) => left == right
+                });
+            }
+            Fields::Named(_) => {
+                let partial_fields = partial_fields(&variant.fields);
+                let names: Vec<&Ident> = variant
+                    .fields
+                    .iter()
+                    .filter_map(|field| field.ident.as_ref())
+                    .collect();
+                let labels = names.iter().map(|name| name.unraw().to_string());
+                let left: Vec<Ident> = names
+                    .iter()
+                    .map(|name| format_ident!("left_{}", name))
+                    .collect();
+                let right: Vec<Ident> = names
+                    .iter()
+                    .map(|name| format_ident!("right_{}", name))
+                    .collect();
+                definitions.push(quote! {
+                    #(#variant_docs)* #variant_ident { #(#partial_fields,)* }
+                });
+                clones.push(quote! {
+                    Self::#variant_ident { #(#names,)* } => Self::#variant_ident {
+                        #(#names: ::core::clone::Clone::clone(#names),)*
+                    }
+                });
+                debugs.push(quote! {
+                    Self::#variant_ident { #(#names,)* } => f.debug_struct(#label)
+                        #(.field(#labels, #names))*
+                        .finish()
+                });
+                eqs.push(quote! {
+                    (
+                        Self::#variant_ident { #(#names: #left,)* },
+                        Self::#variant_ident { #(#names: #right,)* },
+                    ) => true #(&& #left == #right)*
+                });
+            }
+        }
+    }
+
+    let serde = serde_attributes();
+    quote! {
+        #[doc = #summary]
+        #(#docs)*
+        #serde
+        #vis enum #ident #impl_generics #where_clause {
+            #(#definitions,)*
+        }
+
+        impl #impl_generics ::core::clone::Clone for #ident #ty_generics #where_clause {
+            fn clone(&self) -> Self {
+                match self { #(#clones,)* }
+            }
+        }
+
+        impl #impl_generics ::core::fmt::Debug for #ident #ty_generics #where_clause {
+            fn fmt(&self, f: &mut ::core::fmt::Formatter<'_>) -> ::core::fmt::Result {
+                match self { #(#debugs,)* }
+            }
+        }
+
+        impl #impl_generics ::core::cmp::PartialEq for #ident #ty_generics #where_clause {
+            #[allow(unreachable_patterns)]
+            fn eq(&self, other: &Self) -> bool {
+                match (self, other) {
+                    #(#eqs,)*
+                    _ => false,
+                }
+            }
+        }
+    }
+}
+
+/// Named fields made optional; a field that cannot be read yet is `None`
+fn partial_fields(fields: &Fields) -> Vec<TokenStream> {
+    fields
+        .iter()
+        .map(|field| {
+            let vis = &field.vis;
+            let name = &field.ident;
+            let ty: &Type = &field.ty;
+            let docs = docs_of(&field.attrs);
+            quote! {
+                #(#docs)*
+                #[serde(default, deserialize_with = "::fm_bindings::__private::lenient")]
+                #vis #name: ::core::option::Option<<#ty as ::fm_bindings::Generable>::Partial>
+            }
+        })
+        .collect()
+}
+
+/// Deserialize through the serde re-exported by fm-bindings, with no inferred
+/// bounds: `Generable` already requires partial types to be deserializable
+fn serde_attributes() -> TokenStream {
+    quote! {
+        #[derive(::fm_bindings::__private::serde::Deserialize)]
+        #[serde(crate = "::fm_bindings::__private::serde", bound = "")]
+    }
+}
+
+/// Doc comments of the original type, after a blank line separating them from the summary
+fn docs(attrs: &[Attribute]) -> Vec<TokenStream> {
+    let docs = docs_of(attrs);
+    if docs.is_empty() {
+        return docs;
+    }
+    let mut with_separator = vec![quote!(#[doc = ""])];
+    with_separator.extend(docs);
+    with_separator
+}
+
+fn docs_of(attrs: &[Attribute]) -> Vec<TokenStream> {
+    attrs
+        .iter()
+        .filter(|attr| attr.path().is_ident("doc"))
+        .map(|attr| quote!(#attr))
+        .collect()
+}
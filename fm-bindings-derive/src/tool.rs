@@ -0,0 +1,214 @@
+// fm-bindings-derive/src/tool.rs
+// #[fm_tool] - turns a function into an fm_bindings::Tool
+
+use super::generable::object_schema;
+use proc_macro2::TokenStream;
+use quote::{format_ident, quote, quote_spanned};
+use syn::ext::IdentExt;
+use syn::meta::ParseNestedMeta;
+use syn::spanned::Spanned;
+use syn::{
+    Attribute, Error, Expr, ExprLit, Field, FieldMutability, FnArg, Ident, ItemFn, Lit, LitStr,
+    Meta, Pat, Result, Visibility, parse_quote,
+};
+
+/// Settings given as `#[fm_tool(name = "...", description = "...")]`
+#[derive(Default)]
+pub struct ToolArgs {
+    name: Option<LitStr>,
+    description: Option<LitStr>,
+}
+
+impl ToolArgs {
+    pub fn parse(&mut self, meta: ParseNestedMeta) -> Result<()> {
+        if meta.path.is_ident("name") {
+            self.name = Some(meta.value()?.parse()?);
+        } else if meta.path.is_ident("description") {
+            self.description = Some(meta.value()?.parse()?);
+        } else {
+            return Err(meta.error("unknown setting, expected `name` or `description`"));
+        }
+        Ok(())
+    }
+}
+
+pub fn expand(args: ToolArgs, mut function: ItemFn) -> Result<TokenStream> {
+    check_signature(&function)?;
+
+    let fn_ident = function.sig.ident.clone();
+    let fn_name = fn_ident.unraw().to_string();
+    let tool_ident = format_ident!("{}", upper_camel_case(&fn_name), span = fn_ident.span());
+    let tool_name = args
+        .name
+        .map(|name| name.value())
+        .unwrap_or_else(|| fn_name.clone());
+    let description = match args.description {
+        Some(description) => description.value(),
+        None => doc_text(&function.attrs).ok_or_else(|| {
+            Error::new_spanned(
+                &fn_ident,
+                "tools need a description: add a doc comment or `#[fm_tool(description = \"...\")]`",
+            )
+        })?,
+    };
+
+    // Parameters become the fields of the arguments object; their doc comments and
+    // guides describe the properties, and are removed from the function itself
+    let mut fields = Vec::new();
+    for input in &mut function.sig.inputs {
+        let FnArg::Typed(param) = input else {
+            unreachable!("receivers are rejected by check_signature");
+        };
+        let Pat::Ident(pat) = &*param.pat else {
+            unreachable!("patterns are rejected by check_signature");
+        };
+        let mut attrs = Vec::new();
+        if let Some(doc) = doc_text(&param.attrs) {
+            attrs.push(parse_quote!(#[guide(description = #doc)]));
+        }
+        attrs.extend(
+            param
+                .attrs
+                .iter()
+                .filter(|attr| attr.path().is_ident("guide"))
+                .cloned(),
+        );
+        param
+            .attrs
+            .retain(|attr| !attr.path().is_ident("guide") && !attr.path().is_ident("doc"));
+
+        fields.push(Field {
+            attrs,
+            vis: Visibility::Inherited,
+            mutability: FieldMutability::None,
+            ident: Some(pat.ident.clone()),
+            colon_token: Some(Default::default()),
+            ty: (*param.ty).clone(),
+        });
+    }
+
+    let schema = object_schema(&format!("{}Arguments", tool_ident), fields.iter())?;
+    let names: Vec<&Ident> = fields
+        .iter()
+        .filter_map(|field| field.ident.as_ref())
+        .collect();
+    let types = fields.iter().map(|field| &field.ty);
+    let vis = &function.vis;
+    // Errors about unsupported return types point at the return type
+    let output = quote_spanned! {function.sig.output.span()=>
+        ::fm_bindings::__private::ToolOutput::into_tool_output(#fn_ident(#(#names),*))
+    };
+    let summary = format!(
+        " Tool calling [`{}`], generated by `#[fm_tool]`: register it with `SessionBuilder::tool`",
+        fn_name
+    );
+
+    Ok(quote! {
+        #function
+
+        #[doc = #summary]
+        #[derive(::core::fmt::Debug, ::core::clone::Clone, ::core::marker::Copy, ::core::default::Default)]
+        #vis struct #tool_ident;
+
+        const _: () = {
+            #[derive(::fm_bindings::__private::serde::Deserialize)]
+            #[serde(crate = "::fm_bindings::__private::serde")]
+            struct Arguments {
+                #(#names: #types,)*
+            }
+
+            impl ::fm_bindings::Tool for #tool_ident {
+                fn name(&self) -> &str {
+                    #tool_name
+                }
+
+                fn description(&self) -> &str {
+                    #description
+                }
+
+                fn arguments_schema(&self) -> ::fm_bindings::GenerationSchema {
+                    #schema
+                }
+
+                fn call(
+                    &self,
+                    arguments: ::fm_bindings::__private::serde_json::Value,
+                ) -> ::fm_bindings::Result<::std::string::String> {
+                    let Arguments { #(#names,)* } =
+                        ::fm_bindings::__private::tool_arguments(#tool_name, arguments)?;
+                    #output
+                }
+            }
+        };
+    })
+}
+
+/// Tools are called synchronously with owned, named arguments
+fn check_signature(function: &ItemFn) -> Result<()> {
+    let sig = &function.sig;
+    if let Some(asyncness) = &sig.asyncness {
+        return Err(Error::new_spanned(
+            asyncness,
+            "tool functions cannot be async",
+        ));
+    }
+    if !sig.generics.params.is_empty() {
+        return Err(Error::new_spanned(
+            &sig.generics,
+            "tool functions cannot be generic",
+        ));
+    }
+    for input in &sig.inputs {
+        match input {
+            FnArg::Receiver(receiver) => {
+                return Err(Error::new_spanned(
+                    receiver,
+                    "tool functions cannot take `self`",
+                ));
+            }
+            FnArg::Typed(param) => match &*param.pat {
+                Pat::Ident(pat) if pat.by_ref.is_none() && pat.subpat.is_none() => {}
+                other => {
+                    return Err(Error::new_spanned(
+                        other,
+                        "tool parameters must be plain names, which become the argument names",
+                    ));
+                }
+            },
+        }
+    }
+    Ok(())
+}
+
+/// The text of the doc comments in `attrs`, if any
+fn doc_text(attrs: &[Attribute]) -> Option<String> {
+    let lines: Vec<String> = attrs
+        .iter()
+        .filter_map(|attr| match &attr.meta {
+            Meta::NameValue(meta) if meta.path.is_ident("doc") => match &meta.value {
+                Expr::Lit(ExprLit {
+                    lit: Lit::Str(text),
+                    ..
+                }) => Some(text.value().trim().to_string()),
+                _ => None,
+            },
+            _ => None,
+        })
+        .collect();
+    let text = lines.join("\n").trim().to_string();
+    (!text.is_empty()).then_some(text)
+}
+
+/// `get_weather` -> `GetWeather`
+fn upper_camel_case(name: &str) -> String {
+    name.split('_')
+        .filter(|word| !word.is_empty())
+        .map(|word| {
+            let mut chars = word.chars();
+            chars
+                .next()
+                .map(|first| first.to_uppercase().chain(chars).collect::<String>())
+                .unwrap_or_default()
+        })
+        .collect()
+}
@@ -0,0 +1,289 @@
+// src/backend.rs
+// Backend abstraction - the model implementation behind a LanguageModelSession
+
+use super::cancel::CancelHandle;
+use super::error::{Error, Result};
+use super::model::Availability;
+use super::options::GenerationOptions;
+use super::schema::GenerationSchema;
+use super::tokens::{self, TokenInput};
+use super::tool::{Tool, ToolJournal};
+use super::transcript::Transcript;
+use std::sync::{Arc, Mutex};
+
+/// A language model implementation that a [`LanguageModelSession`] delegates to
+///
+/// The session validates input and exposes the public API; the backend performs
+/// the actual generation. The Swift bridge to Apple's Foundation Models is one
+/// implementation ([`SystemBackend`]), and any other model can be plugged in with
+/// [`LanguageModelSession::with_backend`].
+///
+/// Both generation methods block until the request has completed. Requests passed
+/// to a backend have already been validated by the session (non-empty prompt, no
+/// null bytes, option values in range). Backends apply the generation options they
+/// support and ignore the others.
+///
+/// A request may carry a [`CancelHandle`]. Backends check
+/// [`GenerationRequest::is_cancelled`] as they generate and, once it is set, stop
+/// and return `Error::Cancelled` with the response generated so far. The session
+/// also stops passing on chunks after cancellation, so a backend that never checks
+/// only wastes the rest of its generation.
+///
+/// [`LanguageModelSession`]: crate::LanguageModelSession
+/// [`LanguageModelSession::with_backend`]: crate::LanguageModelSession::with_backend
+/// [`SystemBackend`]: crate::SystemBackend
+///
+/// # Examples
+///
+/// ```
+/// use fm_bindings::{GenerationRequest, LanguageModelSession, ModelBackend, Result};
+///
+/// struct Echo;
+///
+/// impl ModelBackend for Echo {
+///     fn is_available(&self) -> bool {
+///         true
+///     }
+///
+///     fn stream_response(
+///         &self,
+///         request: &GenerationRequest<'_>,
+///         on_chunk: &mut dyn FnMut(&str),
+///     ) -> Result<()> {
+///         on_chunk(request.prompt);
+///         Ok(())
+///     }
+///
+///     fn cancel_stream(&self) {}
+/// }
+///
+/// # fn main() -> Result<()> {
+/// let session = LanguageModelSession::with_backend(Echo)?;
+/// assert_eq!(session.response("hello")?, "hello");
+/// # Ok(())
+/// # }
+/// ```
+pub trait ModelBackend: Send + Sync {
+    /// Returns true if the model can currently serve requests
+    fn is_available(&self) -> bool;
+
+    /// Returns whether the model can currently serve requests, and if not, why
+    ///
+    /// A session cannot be created on a backend that is not [`Availability::Available`],
+    /// and the reason is returned in `Error::ModelNotAvailable`. The default
+    /// implementation reports an unavailable backend as [`Availability::Other`].
+    fn availability(&self) -> Availability {
+        if self.is_available() {
+            Availability::Available
+        } else {
+            Availability::Other("The model backend is not available".into())
+        }
+    }
+
+    /// Generates a complete response to the given request
+    ///
+    /// The default implementation collects the chunks of [`stream_response`](Self::stream_response).
+    fn response(&self, request: &GenerationRequest<'_>) -> Result<String> {
+        let mut text = String::new();
+        match self.stream_response(request, &mut |chunk| text.push_str(chunk)) {
+            Ok(()) => Ok(text),
+            Err(Error::Cancelled { .. }) => Err(Error::Cancelled { partial: text }),
+            Err(error) => Err(error),
+        }
+    }
+
+    /// Generates a response, calling `on_chunk` with each incremental text delta
+    ///
+    /// Returns once the stream has completed, failed, or been cancelled.
+    fn stream_response(
+        &self,
+        request: &GenerationRequest<'_>,
+        on_chunk: &mut dyn FnMut(&str),
+    ) -> Result<()>;
+
+    /// Generates a response, calling `on_snapshot` with the whole response so far
+    /// each time it grows
+    ///
+    /// Used to stream guided generation, where the response is a JSON document that
+    /// is only meaningful as a whole. The default implementation accumulates the
+    /// deltas of [`stream_response`](Self::stream_response); backends that receive
+    /// snapshots from their model can pass them on directly.
+    fn stream_snapshots(
+        &self,
+        request: &GenerationRequest<'_>,
+        on_snapshot: &mut dyn FnMut(&str),
+    ) -> Result<()> {
+        let mut text = String::new();
+        self.stream_response(request, &mut |chunk| {
+            text.push_str(chunk);
+            on_snapshot(&text);
+        })
+    }
+
+    /// Loads the model ahead of the first request, so that it answers sooner
+    ///
+    /// `request` describes the session as for a generation request, but its prompt
+    /// is only the expected beginning of the next prompt, and is empty when that is
+    /// unknown. The default implementation does nothing, which suits backends
+    /// without a costly start.
+    fn prewarm(&self, _request: &GenerationRequest<'_>) -> Result<()> {
+        Ok(())
+    }
+
+    /// Discards the conversation state the backend keeps between requests, if any
+    ///
+    /// Called when the session has replaced its transcript, for example to apply a
+    /// [`ContextPolicy`](crate::ContextPolicy), or after a request stopped through a
+    /// [`CancelHandle`](crate::CancelHandle), which the transcript does not record, so
+    /// that the next request starts over from the transcript it carries. The default implementation does nothing,
+    /// which suits backends that send the whole transcript with every request.
+    fn reset(&self) {}
+
+    /// Returns the size of the model's context window, in tokens
+    ///
+    /// Instructions, transcript, prompt and response must fit in it together. The
+    /// default implementation returns [`tokens::DEFAULT_CONTEXT_SIZE`], the size of
+    /// the on-device system model.
+    fn context_size(&self) -> usize {
+        tokens::DEFAULT_CONTEXT_SIZE
+    }
+
+    /// Counts the tokens the model would see for `input`
+    ///
+    /// The default implementation returns the heuristic estimate of the
+    /// [`tokens`] module; backends with access to their tokenizer should count
+    /// exactly.
+    fn token_count(&self, input: TokenInput<'_>) -> Result<usize> {
+        Ok(input.estimate())
+    }
+
+    /// Cancels the stream currently in progress, if any
+    ///
+    /// A cancelled stream returns normally with the chunks delivered so far. This
+    /// stops whichever request the backend is serving; a request's own
+    /// [`CancelHandle`] stops only that request.
+    fn cancel_stream(&self);
+}
+
+/// Everything a backend needs to answer one prompt of a session
+///
+/// Instructions come from the developer and are kept apart from the user's prompt,
+/// so backends can hand them to the model with higher precedence (a system message,
+/// or Foundation Models' session instructions).
+///
+/// `transcript` holds everything exchanged in the session before this prompt,
+/// including the instructions entry. Stateless backends replay it to the model with
+/// every request; backends that keep their own conversation state (such as
+/// [`SystemBackend`]) only need it to restore a resumed session.
+///
+/// `tools` are the tools registered on the session. Backends that support tool
+/// calling offer them to the model and run them with [`Tool::call`]; the session
+/// records the calls in its transcript and turns tool errors into
+/// `Error::ToolCallFailed`, so backends only need to propagate them.
+///
+/// [`SystemBackend`]: crate::SystemBackend
+#[derive(Debug, Clone, Copy)]
+#[non_exhaustive]
+pub struct GenerationRequest<'a> {
+    /// The user's prompt
+    pub prompt: &'a str,
+
+    /// Developer instructions of the session, if any
+    pub instructions: Option<&'a str>,
+
+    /// The session transcript so far, not including this prompt
+    pub transcript: &'a Transcript,
+
+    /// Options controlling sampling and response length
+    pub options: &'a GenerationOptions,
+
+    /// Schema the response must follow, for guided generation
+    ///
+    /// When set, the response is a JSON document matching the schema instead of
+    /// free-form text.
+    pub schema: Option<&'a GenerationSchema>,
+
+    /// Tools the model may call while answering
+    pub tools: &'a [Arc<dyn Tool>],
+
+    /// Handle that stops this request when cancelled, if any
+    pub cancel_handle: Option<&'a CancelHandle>,
+
+    /// Journal of the session's tool calls for this request, for backends that
+    /// report calls without running the tools
+    pub(crate) journal: Option<&'a Mutex<ToolJournal>>,
+}
+
+impl<'a> GenerationRequest<'a> {
+    /// Creates a free-form request for `prompt` without instructions, history or options
+    pub fn new(prompt: &'a str) -> Self {
+        Self {
+            prompt,
+            instructions: None,
+            transcript: &EMPTY_TRANSCRIPT,
+            options: &DEFAULT_OPTIONS,
+            schema: None,
+            tools: &[],
+            cancel_handle: None,
+            journal: None,
+        }
+    }
+
+    /// Sets the session instructions
+    pub fn with_instructions(mut self, instructions: Option<&'a str>) -> Self {
+        self.instructions = instructions;
+        self
+    }
+
+    /// Sets the transcript of the conversation so far
+    pub fn with_transcript(mut self, transcript: &'a Transcript) -> Self {
+        self.transcript = transcript;
+        self
+    }
+
+    /// Sets the generation options
+    pub fn with_options(mut self, options: &'a GenerationOptions) -> Self {
+        self.options = options;
+        self
+    }
+
+    /// Sets the schema the response must follow
+    pub fn with_schema(mut self, schema: Option<&'a GenerationSchema>) -> Self {
+        self.schema = schema;
+        self
+    }
+
+    /// Sets the tools the model may call
+    pub fn with_tools(mut self, tools: &'a [Arc<dyn Tool>]) -> Self {
+        self.tools = tools;
+        self
+    }
+
+    /// Sets the handle that cancels the request
+    pub fn with_cancel_handle(mut self, cancel_handle: Option<&'a CancelHandle>) -> Self {
+        self.cancel_handle = cancel_handle;
+        self
+    }
+
+    /// Sets the journal the session records this request's tool calls in
+    pub(crate) fn with_journal(mut self, journal: Option<&'a Mutex<ToolJournal>>) -> Self {
+        self.journal = journal;
+        self
+    }
+
+    /// Returns true if the request's cancel handle has been cancelled
+    pub fn is_cancelled(&self) -> bool {
+        self.cancel_handle.is_some_and(CancelHandle::is_cancelled)
+    }
+
+    /// Returns the tool named `name`, if the request offers one
+    pub fn tool(&self, name: &str) -> Option<&'a dyn Tool> {
+        self.tools
+            .iter()
+            .find(|tool| tool.name() == name)
+            .map(|tool| tool.as_ref())
+    }
+}
+
+static EMPTY_TRANSCRIPT: Transcript = Transcript::new();
+static DEFAULT_OPTIONS: GenerationOptions = GenerationOptions::new();
@@ -0,0 +1,135 @@
+// src/cancel.rs
+// Cancel handles - stopping one request without touching the others
+
+use std::fmt;
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex, MutexGuard, PoisonError};
+
+/// Stops the requests of a session it is attached to
+///
+/// Attach a handle with [`LanguageModelSession::cancellable`], send the request,
+/// and call [`cancel`](Self::cancel) from any thread to stop it. The request then
+/// returns `Error::Cancelled` with the text generated so far. Requests without the
+/// handle, on the same session or others, are not affected, unlike
+/// [`LanguageModelSession::cancel_stream`] which stops whatever the backend is
+/// streaming.
+///
+/// Clones share their state, so a clone can be kept by whoever decides to cancel.
+/// A cancelled handle stays cancelled: a request sent with it afterwards returns
+/// `Error::Cancelled` at once. Create a new handle for every request.
+///
+/// [`LanguageModelSession::cancellable`]: crate::LanguageModelSession::cancellable
+/// [`LanguageModelSession::cancel_stream`]: crate::LanguageModelSession::cancel_stream
+///
+/// # Examples
+///
+/// ```no_run
+/// use fm_bindings::{CancelHandle, Error, LanguageModelSession};
+/// use std::thread;
+/// use std::time::Duration;
+///
+/// # fn main() -> Result<(), Box<dyn std::error::Error>> {
+/// let session = LanguageModelSession::new()?;
+/// let handle = CancelHandle::new();
+///
+/// let canceller = handle.clone();
+/// thread::spawn(move || {
+///     thread::sleep(Duration::from_secs(2));
+///     canceller.cancel();
+/// });
+///
+/// match session.cancellable(&handle).response("Write a long essay") {
+///     Ok(essay) => println!("{}", essay),
+///     Err(Error::Cancelled { partial }) => println!("Stopped after: {}", partial),
+///     Err(error) => return Err(error.into()),
+/// }
+/// # Ok(())
+/// # }
+/// ```
+#[derive(Clone, Default)]
+pub struct CancelHandle {
+    shared: Arc<Shared>,
+}
+
+#[derive(Default)]
+struct Shared {
+    cancelled: AtomicBool,
+    next_hook: AtomicUsize,
+    hooks: Mutex<Vec<(usize, Hook)>>,
+}
+
+type Hook = Arc<dyn Fn() + Send + Sync>;
+
+impl CancelHandle {
+    /// Creates a handle that is not cancelled
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Cancels the requests sent with this handle
+    ///
+    /// Returns immediately; the request stops as soon as its backend notices,
+    /// at the latest before its next chunk. Calling it again has no effect.
+    pub fn cancel(&self) {
+        if self.shared.cancelled.swap(true, Ordering::SeqCst) {
+            return;
+        }
+        // Hooks run outside the lock, so they may register or drop others
+        let hooks: Vec<Hook> = self
+            .lock_hooks()
+            .iter()
+            .map(|(_, hook)| hook.clone())
+            .collect();
+        for hook in hooks {
+            hook();
+        }
+    }
+
+    /// Returns true once [`cancel`](Self::cancel) has been called
+    pub fn is_cancelled(&self) -> bool {
+        self.shared.cancelled.load(Ordering::SeqCst)
+    }
+
+    /// Runs `hook` when the handle is cancelled, for as long as the guard lives
+    ///
+    /// Backends that block outside Rust use this to stop the call in progress. If
+    /// the handle is already cancelled the hook runs at once. The hook may run more
+    /// than once when registration races with cancellation, so it must tolerate that.
+    pub(crate) fn on_cancel(&self, hook: impl Fn() + Send + Sync + 'static) -> CancelHook<'_> {
+        let id = self.shared.next_hook.fetch_add(1, Ordering::Relaxed);
+        let hook: Hook = Arc::new(hook);
+        self.lock_hooks().push((id, hook.clone()));
+        if self.is_cancelled() {
+            hook();
+        }
+        CancelHook { handle: self, id }
+    }
+
+    // Hooks are only pushed and removed whole, so the list survives a panic
+    fn lock_hooks(&self) -> MutexGuard<'_, Vec<(usize, Hook)>> {
+        self.shared
+            .hooks
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+    }
+}
+
+impl fmt::Debug for CancelHandle {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("CancelHandle")
+            .field("cancelled", &self.is_cancelled())
+            .finish()
+    }
+}
+
+/// Unregisters a hook added with [`CancelHandle::on_cancel`] when dropped
+pub(crate) struct CancelHook<'a> {
+    handle: &'a CancelHandle,
+    id: usize,
+}
+
+impl Drop for CancelHook<'_> {
+    fn drop(&mut self) {
+        self.handle.lock_hooks().retain(|(id, _)| *id != self.id);
+    }
+}
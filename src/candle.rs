@@ -0,0 +1,375 @@
+// src/candle.rs
+// In-process CPU inference backend built on candle (enabled by the `candle` feature)
+
+//! In-process CPU inference with [candle](https://github.com/huggingface/candle)
+//!
+//! [`CandleBackend`] loads a quantized Llama-family model from a GGUF file together
+//! with its `tokenizer.json`, and runs generation on the CPU inside the current
+//! process. It needs neither a GPU nor network access, which makes it suitable for
+//! offline build agents.
+//!
+//! Prompts are passed to the model verbatim unless a prompt template is set with
+//! [`CandleBackend::with_prompt_template`]; instruction-tuned models usually expect
+//! their chat template. Session instructions fill the template's `{instructions}`
+//! placeholder, or are prepended to the prompt when the template has none.
+//!
+//! Generation is greedy unless the request's [`GenerationOptions`] ask for a
+//! temperature or a random sampling mode.
+//!
+//! Decoding is not constrained: for guided requests the JSON Schema of the response
+//! is appended to the prompt, and the model is trusted to follow it.
+//!
+//! Earlier turns of the session are rendered into the model input: each earlier
+//! prompt goes through the template, followed by its response, before the new
+//! prompt. The instructions appear in the first turn only. The key/value cache is
+//! not kept between requests, so the whole conversation is processed every time.
+//!
+//! The backend does not call tools; a request from a session with tools fails with
+//! `Error::InvalidInput`.
+//!
+//! Token counts use the model's tokenizer, and the context size is the
+//! `context_length` declared in the GGUF metadata.
+//!
+//! [`GenerationOptions`]: crate::GenerationOptions
+//!
+//! ```no_run
+//! use fm_bindings::candle::CandleBackend;
+//! use fm_bindings::LanguageModelSession;
+//!
+//! # fn main() -> fm_bindings::Result<()> {
+//! let backend = CandleBackend::load("models/tinyllama.Q4_K_M.gguf", "models/tokenizer.json")?
+//!     .with_prompt_template("<|system|>\n{instructions}</s>\n<|user|>\n{prompt}</s>\n<|assistant|>\n")
+//!     .with_max_tokens(256);
+//! let session = LanguageModelSession::with_backend(backend)?;
+//! session.stream_response("Name three rivers", |chunk| print!("{}", chunk))?;
+//! # Ok(())
+//! # }
+//! ```
+
+use super::backend::{GenerationRequest, ModelBackend};
+use super::error::{Error, Result};
+use super::options::{GenerationOptions, SamplingMode};
+use super::tokens::{self, TokenInput};
+use super::transcript::TranscriptEntry;
+use candle_core::quantized::gguf_file;
+use candle_core::{Device, Tensor};
+use candle_transformers::generation::{LogitsProcessor, Sampling};
+use candle_transformers::models::quantized_llama::ModelWeights;
+use std::fs::File;
+use std::path::Path;
+use std::sync::Mutex;
+use std::sync::atomic::{AtomicBool, Ordering};
+use tokenizers::Tokenizer;
+
+/// Placeholder replaced by the prompt in prompt templates
+const PROMPT_PLACEHOLDER: &str = "{prompt}";
+
+/// Placeholder replaced by the session instructions in prompt templates
+const INSTRUCTIONS_PLACEHOLDER: &str = "{instructions}";
+
+/// End-of-sequence tokens used by common model families
+const EOS_TOKENS: &[&str] = &[
+    "</s>",
+    "<|endoftext|>",
+    "<|end_of_text|>",
+    "<|eot_id|>",
+    "<|im_end|>",
+    "<end_of_turn>",
+];
+
+/// A backend running a local quantized model on the CPU
+///
+/// Requests are served one at a time; concurrent requests wait for the model.
+pub struct CandleBackend {
+    model: Mutex<ModelWeights>,
+    tokenizer: Tokenizer,
+    eos_tokens: Vec<u32>,
+    device: Device,
+    prompt_template: String,
+    max_tokens: usize,
+    context_size: usize,
+    seed: u64,
+    cancelled: AtomicBool,
+}
+
+impl CandleBackend {
+    /// Loads a GGUF model and its tokenizer
+    ///
+    /// # Errors
+    ///
+    /// Returns `Error::InvalidInput` if either file is missing or cannot be parsed.
+    pub fn load(model_path: impl AsRef<Path>, tokenizer_path: impl AsRef<Path>) -> Result<Self> {
+        let model_path = model_path.as_ref();
+        let tokenizer_path = tokenizer_path.as_ref();
+        let invalid_model = |e: &dyn std::fmt::Display| {
+            Error::InvalidInput(format!("Cannot load model {}: {}", model_path.display(), e))
+        };
+
+        let device = Device::Cpu;
+        let mut file = File::open(model_path).map_err(|e| invalid_model(&e))?;
+        let content = gguf_file::Content::read(&mut file).map_err(|e| invalid_model(&e))?;
+
+        // Prefer the EOS token declared by the model over name-based detection
+        let declared_eos = content
+            .metadata
+            .get("tokenizer.ggml.eos_token_id")
+            .and_then(|value| value.to_u32().ok());
+        let context_size = content
+            .metadata
+            .get("general.architecture")
+            .and_then(|value| value.to_string().ok())
+            .and_then(|arch| content.metadata.get(&format!("{}.context_length", arch)))
+            .and_then(|value| value.to_u32().ok())
+            .map_or(tokens::DEFAULT_CONTEXT_SIZE, |size| size as usize);
+
+        let model =
+            ModelWeights::from_gguf(content, &mut file, &device).map_err(|e| invalid_model(&e))?;
+
+        let tokenizer = Tokenizer::from_file(tokenizer_path).map_err(|e| {
+            Error::InvalidInput(format!(
+                "Cannot load tokenizer {}: {}",
+                tokenizer_path.display(),
+                e
+            ))
+        })?;
+
+        let mut eos_tokens: Vec<u32> = EOS_TOKENS
+            .iter()
+            .filter_map(|token| tokenizer.token_to_id(token))
+            .collect();
+        eos_tokens.extend(declared_eos);
+
+        Ok(Self {
+            model: Mutex::new(model),
+            tokenizer,
+            eos_tokens,
+            device,
+            prompt_template: PROMPT_PLACEHOLDER.to_string(),
+            max_tokens: 512,
+            context_size,
+            seed: 0,
+            cancelled: AtomicBool::new(false),
+        })
+    }
+
+    /// Wraps each prompt in `template`, replacing `{prompt}` with the prompt text
+    /// and `{instructions}` with the session instructions (empty if none, and in
+    /// every turn after the first)
+    pub fn with_prompt_template(mut self, template: impl Into<String>) -> Self {
+        self.prompt_template = template.into();
+        self
+    }
+
+    /// Stops generation after `max_tokens` tokens (default 512)
+    ///
+    /// A request's `maximum_response_tokens` option takes precedence.
+    pub fn with_max_tokens(mut self, max_tokens: usize) -> Self {
+        self.max_tokens = max_tokens;
+        self
+    }
+
+    /// Sets the seed used by random sampling modes that do not carry their own
+    pub fn with_seed(mut self, seed: u64) -> Self {
+        self.seed = seed;
+        self
+    }
+
+    /// Counts the tokens of a text, falling back on the estimate if it cannot be encoded
+    fn count_tokens(&self, text: &str) -> usize {
+        self.tokenizer
+            .encode(text, false)
+            .map_or_else(|_| tokens::estimate(text), |encoding| encoding.len())
+    }
+
+    /// Renders the model input for a request
+    ///
+    /// Every earlier turn is rendered through the template and followed by its
+    /// response; the instructions go into the first turn only.
+    fn render(&self, request: &GenerationRequest<'_>) -> String {
+        let prompt = match request.schema {
+            Some(schema) => format!(
+                "{}\n\nRespond only with JSON matching this JSON Schema:\n{}",
+                request.prompt,
+                schema.to_json_schema()
+            ),
+            None => request.prompt.to_string(),
+        };
+
+        let mut instructions = request.instructions.unwrap_or_default();
+        let mut input = String::new();
+        let mut earlier_prompt = None;
+        for entry in request.transcript.iter() {
+            match entry {
+                TranscriptEntry::Prompt { text } => earlier_prompt = Some(text),
+                TranscriptEntry::Response { text: response } => {
+                    if let Some(earlier_prompt) = earlier_prompt.take() {
+                        let instructions = std::mem::take(&mut instructions);
+                        input.push_str(&self.render_turn(instructions, earlier_prompt));
+                        input.push_str(response);
+                        input.push('\n');
+                    }
+                }
+                // Instructions come with the request, and tools are not supported
+                _ => {}
+            }
+        }
+        input.push_str(&self.render_turn(instructions, &prompt));
+        input
+    }
+
+    /// Renders one prompt through the template, with the instructions if not empty
+    fn render_turn(&self, instructions: &str, prompt: &str) -> String {
+        if self.prompt_template.contains(INSTRUCTIONS_PLACEHOLDER) || instructions.is_empty() {
+            return self
+                .prompt_template
+                .replace(INSTRUCTIONS_PLACEHOLDER, instructions)
+                .replace(PROMPT_PLACEHOLDER, prompt);
+        }
+
+        let prompt = format!("{}\n\n{}", instructions, prompt);
+        self.prompt_template.replace(PROMPT_PLACEHOLDER, &prompt)
+    }
+
+    /// Builds the token sampler for a request's options
+    ///
+    /// Without options generation is greedy. A temperature alone samples from the
+    /// whole distribution; the sampling mode's seed takes precedence over the
+    /// backend seed.
+    fn sampler(&self, options: &GenerationOptions) -> LogitsProcessor {
+        let temperature = options.temperature.unwrap_or(1.0);
+        let seed = options
+            .sampling
+            .and_then(|sampling| sampling.seed())
+            .unwrap_or(self.seed);
+        let sampling = match options.sampling {
+            _ if temperature == 0.0 => Sampling::ArgMax,
+            Some(SamplingMode::Greedy) => Sampling::ArgMax,
+            Some(SamplingMode::TopK { k, .. }) => Sampling::TopK {
+                k: k as usize,
+                temperature,
+            },
+            Some(SamplingMode::Nucleus {
+                probability_threshold,
+                ..
+            }) => Sampling::TopP {
+                p: probability_threshold,
+                temperature,
+            },
+            None if options.temperature.is_some() => Sampling::All { temperature },
+            None => Sampling::ArgMax,
+        };
+        LogitsProcessor::from_sampling(seed, sampling)
+    }
+
+    fn generate(
+        &self,
+        request: &GenerationRequest<'_>,
+        on_chunk: &mut dyn FnMut(&str),
+    ) -> candle_core::Result<()> {
+        let text = self.render(request);
+        let encoding = self
+            .tokenizer
+            .encode(text, true)
+            .map_err(candle_core::Error::msg)?;
+        let prompt_tokens = encoding.get_ids();
+
+        let mut model = self
+            .model
+            .lock()
+            .map_err(|_| candle_core::Error::msg("model lock poisoned"))?;
+        let mut sampler = self.sampler(request.options);
+        let max_tokens = request
+            .options
+            .maximum_response_tokens
+            .map_or(self.max_tokens, |tokens| tokens as usize);
+
+        // Index 0 resets the model's key/value cache for the new request
+        let input = Tensor::new(prompt_tokens, &self.device)?.unsqueeze(0)?;
+        let logits = model.forward(&input, 0)?.squeeze(0)?;
+        let mut next = sampler.sample(&logits)?;
+
+        let mut generated = Vec::new();
+        let mut emitted = 0;
+
+        for index in 0..max_tokens {
+            if self.cancelled.load(Ordering::SeqCst)
+                || request.is_cancelled()
+                || self.eos_tokens.contains(&next)
+            {
+                break;
+            }
+            generated.push(next);
+
+            // Decode the whole answer so multi-token characters and leading spaces
+            // come out right, and only emit once the tail is a complete character
+            let decoded = self
+                .tokenizer
+                .decode(&generated, true)
+                .map_err(candle_core::Error::msg)?;
+            if !decoded.ends_with('\u{fffd}')
+                && let Some(delta) = decoded.get(emitted..)
+                && !delta.is_empty()
+            {
+                on_chunk(delta);
+                emitted = decoded.len();
+            }
+
+            let input = Tensor::new(&[next], &self.device)?.unsqueeze(0)?;
+            let logits = model
+                .forward(&input, prompt_tokens.len() + index)?
+                .squeeze(0)?;
+            next = sampler.sample(&logits)?;
+        }
+
+        Ok(())
+    }
+}
+
+impl ModelBackend for CandleBackend {
+    fn is_available(&self) -> bool {
+        true
+    }
+
+    fn stream_response(
+        &self,
+        request: &GenerationRequest<'_>,
+        on_chunk: &mut dyn FnMut(&str),
+    ) -> Result<()> {
+        if let Some(tool) = request.tools.first() {
+            return Err(Error::InvalidInput(format!(
+                "CandleBackend does not support tools, but the session has {:?}",
+                tool.name()
+            )));
+        }
+
+        self.cancelled.store(false, Ordering::SeqCst);
+        let mut partial = String::new();
+        self.generate(request, &mut |chunk| {
+            partial.push_str(chunk);
+            on_chunk(chunk);
+        })
+        .map_err(|e| Error::GenerationError(e.to_string()))?;
+
+        if request.is_cancelled() {
+            return Err(Error::Cancelled { partial });
+        }
+        Ok(())
+    }
+
+    fn context_size(&self) -> usize {
+        self.context_size
+    }
+
+    fn token_count(&self, input: TokenInput<'_>) -> Result<usize> {
+        Ok(match input {
+            TokenInput::Text(text) => self.count_tokens(text),
+            TokenInput::Transcript(transcript) => {
+                tokens::count_transcript(transcript, |text| self.count_tokens(text))
+            }
+        })
+    }
+
+    fn cancel_stream(&self) {
+        self.cancelled.store(true, Ordering::SeqCst);
+    }
+}
@@ -0,0 +1,617 @@
+// src/cassette.rs
+// Record-and-replay of model interactions through JSONL cassette files
+
+//! Record-and-replay of model interactions
+//!
+//! [`RecordingBackend`] wraps another [`ModelBackend`] and appends every request
+//! to a cassette file: the prompt, instructions, generation options and schema, each
+//! streamed chunk and tool call with its offset from the start of the request, and
+//! the final outcome. [`ReplayBackend`] serves a cassette back in order without
+//! touching the wrapped model, so sessions recorded on a Mac can be replayed on any
+//! platform.
+//!
+//! Blocking responses, streamed deltas and guided snapshots are recorded through
+//! the wrapped backend's own method for each, so recording does not change how the
+//! model is asked. Replay serves every method from any recording: deltas are
+//! joined into snapshots or a whole response, and snapshots collapse to the last
+//! one when deltas or a whole response are asked for.
+//!
+//! A cassette is a JSONL file with one interaction per line:
+//!
+//! ```json
+//! {"prompt":"What is 2+2?","chunks":[{"offset_ms":180,"text":"2 + 2"},{"offset_ms":215,"text":" = 4"}],"outcome":{"status":"completed","elapsed_ms":230}}
+//! ```
+//!
+//! # Examples
+//!
+//! Record on a machine with Apple Intelligence:
+//!
+//! ```no_run
+//! use fm_bindings::cassette::RecordingBackend;
+//! use fm_bindings::{LanguageModelSession, SystemBackend};
+//!
+//! # fn main() -> fm_bindings::Result<()> {
+//! let recorder = RecordingBackend::create(SystemBackend::new(), "tests/cassettes/math.jsonl")?;
+//! let session = LanguageModelSession::with_backend(recorder)?;
+//! session.response("What is 2+2?")?;
+//! # Ok(())
+//! # }
+//! ```
+//!
+//! Replay anywhere:
+//!
+//! ```no_run
+//! use fm_bindings::cassette::ReplayBackend;
+//! use fm_bindings::LanguageModelSession;
+//!
+//! # fn main() -> fm_bindings::Result<()> {
+//! let session = LanguageModelSession::with_backend(ReplayBackend::open("tests/cassettes/math.jsonl")?)?;
+//! let answer = session.response("What is 2+2?")?;
+//! # Ok(())
+//! # }
+//! ```
+//!
+//! [`ModelBackend`]: crate::ModelBackend
+
+use super::backend::{GenerationRequest, ModelBackend};
+use super::error::{Error, Result};
+use super::model::Availability;
+use super::options::GenerationOptions;
+use super::schema::GenerationSchema;
+use super::tokens::TokenInput;
+use super::tool::Tool;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::collections::VecDeque;
+use std::fs::File;
+use std::io::{BufRead, BufReader, BufWriter, Write};
+use std::path::Path;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex, PoisonError};
+use std::thread;
+use std::time::{Duration, Instant};
+
+/// One request/response exchange as stored in a cassette
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct Interaction {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    instructions: Option<String>,
+    prompt: String,
+    #[serde(default, skip_serializing_if = "GenerationOptions::is_default")]
+    options: GenerationOptions,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    schema: Option<GenerationSchema>,
+    #[serde(default, skip_serializing_if = "Delivery::is_deltas")]
+    delivery: Delivery,
+    chunks: Vec<RecordedChunk>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    tool_calls: Vec<RecordedToolCall>,
+    outcome: Outcome,
+}
+
+/// What the chunks of an interaction are, after the backend method that made them
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+enum Delivery {
+    /// Text deltas of `stream_response`
+    #[default]
+    Deltas,
+    /// Whole responses so far, from `stream_snapshots`
+    Snapshots,
+    /// The single chunk of a blocking `response`
+    Whole,
+}
+
+impl Delivery {
+    fn is_deltas(&self) -> bool {
+        *self == Delivery::Deltas
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct RecordedChunk {
+    /// Milliseconds since the request started
+    offset_ms: u64,
+    text: String,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct RecordedToolCall {
+    /// Milliseconds since the request started, when the tool returned
+    offset_ms: u64,
+    name: String,
+    arguments: Value,
+    #[serde(flatten)]
+    result: ToolResult,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+enum ToolResult {
+    Output(String),
+    Error(Error),
+}
+
+impl From<&Result<String>> for ToolResult {
+    fn from(result: &Result<String>) -> Self {
+        match result {
+            Ok(output) => ToolResult::Output(output.clone()),
+            Err(error) => ToolResult::Error(error.clone()),
+        }
+    }
+}
+
+impl From<ToolResult> for Result<String> {
+    fn from(result: ToolResult) -> Self {
+        match result {
+            ToolResult::Output(output) => Ok(output),
+            ToolResult::Error(error) => Err(error),
+        }
+    }
+}
+
+/// A tool of a recorded request, noting every call for the cassette
+struct TapedTool {
+    tool: Arc<dyn Tool>,
+    start: Instant,
+    calls: Arc<Mutex<Vec<RecordedToolCall>>>,
+}
+
+impl Tool for TapedTool {
+    fn name(&self) -> &str {
+        self.tool.name()
+    }
+
+    fn description(&self) -> &str {
+        self.tool.description()
+    }
+
+    fn arguments_schema(&self) -> GenerationSchema {
+        self.tool.arguments_schema()
+    }
+
+    fn call(&self, arguments: Value) -> Result<String> {
+        let result = self.tool.call(arguments.clone());
+        let call = RecordedToolCall {
+            offset_ms: millis(self.start.elapsed()),
+            name: self.tool.name().to_string(),
+            arguments,
+            result: ToolResult::from(&result),
+        };
+        self.calls
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .push(call);
+        result
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(tag = "status", rename_all = "snake_case")]
+enum Outcome {
+    Completed { elapsed_ms: u64 },
+    Failed { elapsed_ms: u64, error: Error },
+}
+
+/// A backend that records every interaction with the wrapped backend to a cassette
+///
+/// Availability and cancellation are forwarded to the wrapped backend unchanged.
+/// Each interaction is flushed to disk as soon as the request finishes.
+///
+/// A request whose interaction cannot be written still returns the wrapped
+/// backend's result. The write failure is held and returned by the next request
+/// instead, before it reaches the wrapped backend, or by [`finish`](Self::finish).
+pub struct RecordingBackend<B> {
+    inner: B,
+    writer: Mutex<BufWriter<File>>,
+    write_error: Mutex<Option<Error>>,
+}
+
+impl<B: ModelBackend> RecordingBackend<B> {
+    /// Wraps `inner`, recording to a new cassette at `path`
+    ///
+    /// An existing file at `path` is truncated.
+    ///
+    /// # Errors
+    ///
+    /// Returns `Error::InvalidInput` if the cassette file cannot be created.
+    pub fn create(inner: B, path: impl AsRef<Path>) -> Result<Self> {
+        let path = path.as_ref();
+        let file = File::create(path).map_err(|e| {
+            Error::InvalidInput(format!("Cannot create cassette {}: {}", path.display(), e))
+        })?;
+
+        Ok(Self {
+            inner,
+            writer: Mutex::new(BufWriter::new(file)),
+            write_error: Mutex::new(None),
+        })
+    }
+
+    /// Returns the wrapped backend, dropping any held write failure
+    pub fn into_inner(self) -> B {
+        self.inner
+    }
+
+    /// Returns the wrapped backend once every interaction has been written
+    ///
+    /// # Errors
+    ///
+    /// Returns `Error::InternalError` if the last interaction could not be written.
+    pub fn finish(self) -> Result<B> {
+        match self.take_write_error() {
+            Some(error) => Err(error),
+            None => Ok(self.inner),
+        }
+    }
+
+    fn take_write_error(&self) -> Option<Error> {
+        self.write_error
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .take()
+    }
+
+    fn write(&self, interaction: &Interaction) -> Result<()> {
+        let line = serde_json::to_string(interaction)
+            .map_err(|e| Error::InternalError(format!("Cannot encode interaction: {}", e)))?;
+
+        let mut writer = self.writer.lock().map_err(|_| Error::PoisonError)?;
+        writeln!(writer, "{}", line)
+            .and_then(|()| writer.flush())
+            .map_err(|e| Error::InternalError(format!("Cannot write cassette: {}", e)))
+    }
+
+    /// Runs one request on the wrapped backend and records it
+    ///
+    /// `generate` calls the wrapped backend's method for `delivery`, passing every
+    /// chunk it delivers to the recording callback.
+    fn record_request(
+        &self,
+        request: &GenerationRequest<'_>,
+        delivery: Delivery,
+        generate: impl FnOnce(&GenerationRequest<'_>, &mut dyn FnMut(&str)) -> Result<()>,
+    ) -> Result<()> {
+        if let Some(error) = self.take_write_error() {
+            return Err(error);
+        }
+
+        let start = Instant::now();
+        let mut chunks = Vec::new();
+
+        // The inner backend calls the tools through wrappers that note each call
+        let tool_calls = Arc::new(Mutex::new(Vec::new()));
+        let tools: Vec<Arc<dyn Tool>> = request
+            .tools
+            .iter()
+            .map(|tool| {
+                Arc::new(TapedTool {
+                    tool: tool.clone(),
+                    start,
+                    calls: tool_calls.clone(),
+                }) as Arc<dyn Tool>
+            })
+            .collect();
+        let request = &request.with_tools(&tools);
+
+        let result = generate(request, &mut |chunk| {
+            chunks.push(RecordedChunk {
+                offset_ms: millis(start.elapsed()),
+                text: chunk.to_string(),
+            });
+        });
+
+        let elapsed_ms = millis(start.elapsed());
+        let outcome = match &result {
+            Ok(()) => Outcome::Completed { elapsed_ms },
+            Err(error) => Outcome::Failed {
+                elapsed_ms,
+                error: error.clone(),
+            },
+        };
+
+        let written = self.write(&Interaction {
+            instructions: request.instructions.map(str::to_string),
+            prompt: request.prompt.to_string(),
+            options: request.options.clone(),
+            schema: request.schema.cloned(),
+            delivery,
+            chunks,
+            tool_calls: std::mem::take(
+                &mut tool_calls.lock().unwrap_or_else(PoisonError::into_inner),
+            ),
+            outcome,
+        });
+        if let Err(error) = written {
+            *self
+                .write_error
+                .lock()
+                .unwrap_or_else(PoisonError::into_inner) = Some(error);
+        }
+
+        result
+    }
+}
+
+impl<B: ModelBackend> ModelBackend for RecordingBackend<B> {
+    fn is_available(&self) -> bool {
+        self.inner.is_available()
+    }
+
+    fn availability(&self) -> Availability {
+        self.inner.availability()
+    }
+
+    fn prewarm(&self, request: &GenerationRequest<'_>) -> Result<()> {
+        self.inner.prewarm(request)
+    }
+
+    fn reset(&self) {
+        self.inner.reset();
+    }
+
+    fn context_size(&self) -> usize {
+        self.inner.context_size()
+    }
+
+    fn token_count(&self, input: TokenInput<'_>) -> Result<usize> {
+        self.inner.token_count(input)
+    }
+
+    fn response(&self, request: &GenerationRequest<'_>) -> Result<String> {
+        let mut response = String::new();
+        self.record_request(request, Delivery::Whole, |request, record| {
+            response = self.inner.response(request)?;
+            record(&response);
+            Ok(())
+        })?;
+        Ok(response)
+    }
+
+    fn stream_response(
+        &self,
+        request: &GenerationRequest<'_>,
+        on_chunk: &mut dyn FnMut(&str),
+    ) -> Result<()> {
+        self.record_request(request, Delivery::Deltas, |request, record| {
+            self.inner.stream_response(request, &mut |chunk| {
+                record(chunk);
+                on_chunk(chunk);
+            })
+        })
+    }
+
+    fn stream_snapshots(
+        &self,
+        request: &GenerationRequest<'_>,
+        on_snapshot: &mut dyn FnMut(&str),
+    ) -> Result<()> {
+        self.record_request(request, Delivery::Snapshots, |request, record| {
+            self.inner.stream_snapshots(request, &mut |snapshot| {
+                record(snapshot);
+                on_snapshot(snapshot);
+            })
+        })
+    }
+
+    fn cancel_stream(&self) {
+        self.inner.cancel_stream();
+    }
+}
+
+/// A backend that serves the interactions of a cassette in recorded order
+///
+/// Each request must carry the same prompt as the next recorded interaction
+/// (recorded instructions, options and schemas are informational and not compared);
+/// otherwise it fails with `Error::CassetteMismatch` and the interaction is
+/// not consumed. By default chunks are delivered immediately; see
+/// [`with_recorded_timing`](Self::with_recorded_timing) to reproduce the original pacing.
+///
+/// Recorded tool calls are not run again: their arguments and outputs go straight
+/// into the session transcript, in their place among the chunks, and a recorded
+/// tool failure is reported as `Error::ToolCallFailed` as it was when recorded.
+pub struct ReplayBackend {
+    interactions: Mutex<VecDeque<Interaction>>,
+    recorded_timing: bool,
+    cancelled: AtomicBool,
+}
+
+impl ReplayBackend {
+    /// Loads the cassette at `path`
+    ///
+    /// # Errors
+    ///
+    /// Returns `Error::InvalidInput` if the file cannot be read or a line is not
+    /// a valid interaction.
+    pub fn open(path: impl AsRef<Path>) -> Result<Self> {
+        let path = path.as_ref();
+        let file = File::open(path).map_err(|e| {
+            Error::InvalidInput(format!("Cannot open cassette {}: {}", path.display(), e))
+        })?;
+
+        let mut interactions = VecDeque::new();
+        for (index, line) in BufReader::new(file).lines().enumerate() {
+            let line = line.map_err(|e| {
+                Error::InvalidInput(format!("Cannot read cassette {}: {}", path.display(), e))
+            })?;
+            if line.trim().is_empty() {
+                continue;
+            }
+
+            let interaction = serde_json::from_str(&line).map_err(|e| {
+                Error::InvalidInput(format!(
+                    "Invalid interaction on line {} of cassette {}: {}",
+                    index + 1,
+                    path.display(),
+                    e
+                ))
+            })?;
+            interactions.push_back(interaction);
+        }
+
+        Ok(Self {
+            interactions: Mutex::new(interactions),
+            recorded_timing: false,
+            cancelled: AtomicBool::new(false),
+        })
+    }
+
+    /// Delivers chunks at the offsets they were recorded with
+    pub fn with_recorded_timing(mut self) -> Self {
+        self.recorded_timing = true;
+        self
+    }
+
+    /// Returns the number of interactions not yet replayed
+    pub fn remaining(&self) -> usize {
+        self.interactions.lock().map(|i| i.len()).unwrap_or(0)
+    }
+
+    fn next_interaction(&self, prompt: &str) -> Result<Interaction> {
+        let mut interactions = self.interactions.lock().map_err(|_| Error::PoisonError)?;
+
+        match interactions.pop_front() {
+            Some(next) if next.prompt == prompt => Ok(next),
+            next => {
+                // Leave a mismatched interaction in place so the caller can recover
+                let expected = next.map(|next| {
+                    let expected = next.prompt.clone();
+                    interactions.push_front(next);
+                    expected
+                });
+                Err(Error::CassetteMismatch {
+                    expected,
+                    actual: prompt.to_string(),
+                })
+            }
+        }
+    }
+
+    /// Serves the next interaction, delivering its chunks the way `wanted` expects
+    ///
+    /// Returns the whole response: the joined deltas, or the last snapshot.
+    fn replay(
+        &self,
+        request: &GenerationRequest<'_>,
+        wanted: Delivery,
+        deliver: &mut dyn FnMut(&str),
+    ) -> Result<String> {
+        let interaction = self.next_interaction(request.prompt)?;
+        self.cancelled.store(false, Ordering::SeqCst);
+        let start = Instant::now();
+        let recorded = interaction.delivery;
+
+        // Tool calls are replayed before the first chunk that came after them
+        let mut tool_calls = interaction.tool_calls.into_iter().peekable();
+        let mut replay_tool_calls = |offset_ms: u64| {
+            while let Some(call) = tool_calls.next_if(|call| call.offset_ms <= offset_ms) {
+                replay_tool_call(request, call);
+            }
+        };
+
+        let mut text = String::new();
+        let mut stopped = false;
+        for chunk in &interaction.chunks {
+            if self.recorded_timing {
+                wait_until(start, chunk.offset_ms);
+            }
+            if self.cancelled.load(Ordering::SeqCst) {
+                stopped = true;
+                break;
+            }
+            if request.is_cancelled() {
+                return Err(Error::Cancelled { partial: text });
+            }
+            replay_tool_calls(chunk.offset_ms);
+
+            if recorded == Delivery::Snapshots {
+                text.clone_from(&chunk.text);
+                if wanted == Delivery::Snapshots {
+                    deliver(&text);
+                }
+            } else {
+                text.push_str(&chunk.text);
+                match wanted {
+                    Delivery::Deltas => deliver(&chunk.text),
+                    Delivery::Snapshots => deliver(&text),
+                    Delivery::Whole => {}
+                }
+            }
+        }
+        // Snapshots cannot be split into deltas, so the last one is a single delta
+        if recorded == Delivery::Snapshots && wanted == Delivery::Deltas && !text.is_empty() {
+            deliver(&text);
+        }
+        if stopped {
+            return Ok(text);
+        }
+        replay_tool_calls(u64::MAX);
+
+        match interaction.outcome {
+            Outcome::Completed { elapsed_ms } => {
+                if self.recorded_timing {
+                    wait_until(start, elapsed_ms);
+                }
+                Ok(text)
+            }
+            Outcome::Failed { elapsed_ms, error } => {
+                if self.recorded_timing {
+                    wait_until(start, elapsed_ms);
+                }
+                Err(error)
+            }
+        }
+    }
+}
+
+impl ModelBackend for ReplayBackend {
+    fn is_available(&self) -> bool {
+        true
+    }
+
+    fn response(&self, request: &GenerationRequest<'_>) -> Result<String> {
+        self.replay(request, Delivery::Whole, &mut |_| {})
+    }
+
+    fn stream_response(
+        &self,
+        request: &GenerationRequest<'_>,
+        on_chunk: &mut dyn FnMut(&str),
+    ) -> Result<()> {
+        self.replay(request, Delivery::Deltas, on_chunk).map(drop)
+    }
+
+    fn stream_snapshots(
+        &self,
+        request: &GenerationRequest<'_>,
+        on_snapshot: &mut dyn FnMut(&str),
+    ) -> Result<()> {
+        self.replay(request, Delivery::Snapshots, on_snapshot)
+            .map(drop)
+    }
+
+    fn cancel_stream(&self) {
+        self.cancelled.store(true, Ordering::SeqCst);
+    }
+}
+
+/// Adds a recorded tool call to the request's journal, as if the tool had run
+fn replay_tool_call(request: &GenerationRequest<'_>, call: RecordedToolCall) {
+    if let Some(journal) = request.journal {
+        journal
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .replay(&call.name, call.arguments, &call.result.into());
+    }
+}
+
+fn millis(duration: Duration) -> u64 {
+    duration.as_millis().try_into().unwrap_or(u64::MAX)
+}
+
+fn wait_until(start: Instant, offset_ms: u64) {
+    let target = Duration::from_millis(offset_ms);
+    if let Some(remaining) = target.checked_sub(start.elapsed()) {
+        thread::sleep(remaining);
+    }
+}
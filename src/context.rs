@@ -0,0 +1,215 @@
+// src/context.rs
+// Context policies - keeping a conversation within the model's context window
+
+use super::backend::{GenerationRequest, ModelBackend};
+use super::error::{Error, Result};
+use super::options::GenerationOptions;
+use super::tokens::TokenInput;
+use super::transcript::{Transcript, TranscriptEntry};
+
+/// Share of the context window kept free for the response when the request sets
+/// no `maximum_response_tokens`: a quarter
+const RESPONSE_SHARE: usize = 4;
+
+/// Share of the context window a summary of older turns may take: an eighth
+const SUMMARY_SHARE: usize = 8;
+
+/// Instructions of the request that summarizes older turns
+const SUMMARY_INSTRUCTIONS: &str = "You summarize conversations between a user and an assistant. \
+    Keep every fact, name, number and decision the conversation may refer to later. \
+    Reply with the summary only.";
+
+/// How a session keeps its conversation within the model's context window
+///
+/// Set with [`SessionBuilder::context_policy`](crate::SessionBuilder::context_policy).
+/// Unless the policy is [`Fail`](Self::Fail), the session measures the transcript
+/// and prompt with the backend's [`token_count`](ModelBackend::token_count) before
+/// each request, keeping room for the response: `maximum_response_tokens` if the
+/// request sets it, a quarter of the [`context_size`](ModelBackend::context_size)
+/// otherwise. If they do not fit, the policy shortens the transcript first. When
+/// the model still reports `Error::ContextWindowExceeded`, the policy shortens the
+/// transcript by at least a quarter and the request is sent once more, unless a
+/// stream had already delivered chunks.
+///
+/// Instructions are always kept. Turns, each a prompt with its tool activity and
+/// response, are kept or removed whole, oldest first. The session's
+/// [`transcript`](crate::LanguageModelSession::transcript) reflects the shortened
+/// conversation.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash)]
+#[non_exhaustive]
+pub enum ContextPolicy {
+    /// Send every request as it is and let an overflowing one fail with
+    /// `Error::ContextWindowExceeded`
+    #[default]
+    Fail,
+
+    /// Drop the oldest turns until the request fits
+    SlidingWindow,
+
+    /// Replace the oldest turns with a summary written by the model itself
+    ///
+    /// The most recent turns that fit in the budget, less room for the summary,
+    /// are kept as they are. The others are summarized in a separate request and
+    /// replaced by a [`SUMMARY_PROMPT`](Self::SUMMARY_PROMPT) turn whose response
+    /// is the summary.
+    Summarize,
+}
+
+impl ContextPolicy {
+    /// Prompt recorded before the summary in a transcript shortened by
+    /// [`Summarize`](Self::Summarize)
+    pub const SUMMARY_PROMPT: &str = "Summarize our conversation so far.";
+}
+
+/// Fits a transcript into the backend's context window according to `policy`
+///
+/// Returns the transcript to use instead, or `None` if it is kept as it is. With
+/// `forced` the model has reported the context window exceeded, so the budget is
+/// lowered below the measured size to make sure the transcript shrinks.
+pub(crate) fn fit(
+    policy: ContextPolicy,
+    backend: &dyn ModelBackend,
+    transcript: &Transcript,
+    prompt: &str,
+    options: &GenerationOptions,
+    forced: bool,
+) -> Result<Option<Transcript>> {
+    if policy == ContextPolicy::Fail {
+        return Ok(None);
+    }
+
+    let (instructions, turns) = split(transcript);
+    let count = |transcript: &Transcript| backend.token_count(TokenInput::Transcript(transcript));
+    let instruction_tokens = count(&instructions)?;
+    let turn_tokens = turns.iter().map(count).collect::<Result<Vec<_>>>()?;
+    let used = instruction_tokens + turn_tokens.iter().sum::<usize>();
+
+    let context_size = backend.context_size();
+    let reserve = options
+        .maximum_response_tokens
+        .map_or(context_size / RESPONSE_SHARE, |tokens| tokens as usize);
+    let prompt_tokens = backend.token_count(TokenInput::Text(prompt))?;
+    let mut budget = context_size.saturating_sub(reserve + prompt_tokens);
+    if forced {
+        budget = budget.min(used - used / 4);
+    }
+    if used <= budget || turns.is_empty() {
+        return Ok(None);
+    }
+    let turn_budget = budget.saturating_sub(instruction_tokens);
+
+    let fitted = match policy {
+        ContextPolicy::Fail => return Ok(None),
+        ContextPolicy::SlidingWindow => {
+            let keep = fitting_suffix(&turn_tokens, turn_budget);
+            join(instructions, &turns[turns.len() - keep..], None)
+        }
+        ContextPolicy::Summarize => {
+            let summary_budget = context_size / SUMMARY_SHARE;
+            let keep = fitting_suffix(&turn_tokens, turn_budget.saturating_sub(summary_budget));
+            let older = turns.len() - keep;
+
+            // Only the most recent older turns that fit in the summary request are summarized
+            let input_budget = context_size.saturating_sub(summary_budget * 2);
+            let summarized = fitting_suffix(&turn_tokens[..older], input_budget);
+            let summary = summarize(backend, &turns[older - summarized..older], summary_budget)?;
+            join(instructions, &turns[older..], summary)
+        }
+    };
+    Ok(Some(fitted))
+}
+
+/// Splits a transcript into its instructions and its turns
+///
+/// A turn starts at each prompt; entries before the first prompt, other than
+/// instructions, form a turn of their own.
+fn split(transcript: &Transcript) -> (Transcript, Vec<Transcript>) {
+    let mut instructions = Transcript::new();
+    let mut turns: Vec<Transcript> = Vec::new();
+    for entry in transcript.iter() {
+        match entry {
+            TranscriptEntry::Instructions { .. } => instructions.push(entry.clone()),
+            TranscriptEntry::Prompt { .. } => turns.push(Transcript::from(vec![entry.clone()])),
+            _ => match turns.last_mut() {
+                Some(turn) => turn.push(entry.clone()),
+                None => turns.push(Transcript::from(vec![entry.clone()])),
+            },
+        }
+    }
+    (instructions, turns)
+}
+
+/// Returns how many of the last items fit in `budget` together
+fn fitting_suffix(tokens: &[usize], budget: usize) -> usize {
+    let mut total = 0;
+    tokens
+        .iter()
+        .rev()
+        .take_while(|&&tokens| {
+            total += tokens;
+            total <= budget
+        })
+        .count()
+}
+
+/// Rebuilds a transcript from its instructions, an optional summary and the kept turns
+fn join(instructions: Transcript, turns: &[Transcript], summary: Option<String>) -> Transcript {
+    let mut transcript = instructions;
+    if let Some(summary) = summary {
+        transcript.push(TranscriptEntry::prompt(ContextPolicy::SUMMARY_PROMPT));
+        transcript.push(TranscriptEntry::response(summary));
+    }
+    for entry in turns.iter().flat_map(Transcript::iter) {
+        transcript.push(entry.clone());
+    }
+    transcript
+}
+
+/// Asks the model for a summary of `turns`, in a request of its own
+///
+/// Returns `None` if there is nothing to summarize. The backend is reset before
+/// and after, so that a backend keeping conversation state neither mixes the
+/// summary into the session nor keeps it.
+fn summarize(
+    backend: &dyn ModelBackend,
+    turns: &[Transcript],
+    max_tokens: usize,
+) -> Result<Option<String>> {
+    let mut conversation = String::new();
+    for entry in turns.iter().flat_map(Transcript::iter) {
+        let line = match entry {
+            TranscriptEntry::Prompt { text } => format!("User: {}", text),
+            TranscriptEntry::Response { text } => format!("Assistant: {}", text),
+            TranscriptEntry::ToolCall {
+                name, arguments, ..
+            } => format!("Assistant called tool {} with {}", name, arguments),
+            TranscriptEntry::ToolOutput { name, output, .. } => {
+                format!("Tool {} returned: {}", name, output)
+            }
+            _ => continue,
+        };
+        conversation.push_str(&line);
+        conversation.push('\n');
+    }
+    if conversation.is_empty() {
+        return Ok(None);
+    }
+
+    let prompt = format!("Summarize this conversation:\n\n{}", conversation);
+    let options = GenerationOptions::default()
+        .with_maximum_response_tokens(max_tokens.try_into().unwrap_or(u32::MAX));
+    let request = GenerationRequest::new(&prompt)
+        .with_instructions(Some(SUMMARY_INSTRUCTIONS))
+        .with_options(&options);
+
+    backend.reset();
+    let summary = backend.response(&request);
+    backend.reset();
+    let summary = summary?;
+    if summary.trim().is_empty() {
+        return Err(Error::GenerationError(
+            "The model returned an empty summary".into(),
+        ));
+    }
+    Ok(Some(summary))
+}
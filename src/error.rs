@@ -1,18 +1,55 @@
 // src/error.rs
 // Error types for Foundation Models bindings
 
+use super::model::Availability;
+use super::refusal::Refusal;
+use serde::{Deserialize, Serialize};
 use std::fmt;
+use std::path::PathBuf;
 
 /// Errors that can occur when using Foundation Models
-#[derive(Debug, Clone)]
+///
+/// Errors serialize so that recorded failures can be replayed from a cassette.
+/// New variants may be added as Foundation Models reports new failures, so matches
+/// need a wildcard arm.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[non_exhaustive]
 pub enum Error {
     /// The Foundation Model is not available on this system
-    /// This typically means Apple Intelligence is not enabled
-    ModelNotAvailable,
+    /// The reason tells whether the device is not eligible, Apple Intelligence is
+    /// turned off or the model is still downloading; it is never `Available`
+    ModelNotAvailable(Availability),
 
     /// The system returned an error during generation
     GenerationError(String),
 
+    /// The model's guardrails flagged the prompt or the response as unsafe
+    /// See [`SystemLanguageModel::with_guardrails`](crate::SystemLanguageModel::with_guardrails)
+    GuardrailViolation(String),
+
+    /// The transcript and prompt no longer fit in the model's context window
+    /// Start a new session, or one from a shortened transcript, to continue
+    ContextWindowExceeded(String),
+
+    /// The model does not support the language or locale of the prompt
+    UnsupportedLanguage(String),
+
+    /// The model assets are not on the device, for example while they are downloading
+    AssetsUnavailable(String),
+
+    /// The system is throttling requests, typically for an app in the background
+    RateLimited(String),
+
+    /// The session is already answering another request
+    ConcurrentRequests(String),
+
+    /// The model declined to answer the request
+    /// [`Refusal::explanation`] asks the model why
+    Refusal(Refusal),
+
+    /// The response could not be decoded into the requested structure
+    DecodingFailure(String),
+
     /// Invalid input was provided (e.g., empty prompt)
     InvalidInput(String),
 
@@ -22,20 +59,65 @@ pub enum Error {
     /// A mutex or synchronization primitive was poisoned
     /// This indicates a panic occurred while holding a lock
     PoisonError,
+
+    /// No adapter exists at the given path
+    AdapterNotFound(PathBuf),
+
+    /// The adapter package is malformed or its name is invalid
+    InvalidAdapter(String),
+
+    /// The adapter was trained for a different version of the base model
+    /// Adapters must be retrained for each base model update
+    IncompatibleAdapter(String),
+
+    /// A replayed request did not match the next interaction in the cassette
+    /// `expected` is `None` when the cassette has no interactions left
+    CassetteMismatch {
+        expected: Option<String>,
+        actual: String,
+    },
+
+    /// A tool called by the model returned an error, which ended the request
+    ToolCallFailed { tool: String, message: String },
+
+    /// The request was stopped through its [`CancelHandle`](crate::CancelHandle)
+    /// `partial` is the response generated until then, possibly empty
+    Cancelled { partial: String },
 }
 
 impl fmt::Display for Error {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         match self {
-            Error::ModelNotAvailable => {
-                write!(
-                    f,
-                    "Foundation Model not available. Enable Apple Intelligence in System Settings."
-                )
+            Error::ModelNotAvailable(reason) => {
+                write!(f, "Foundation Model not available: {}", reason)
             }
             Error::GenerationError(msg) => {
                 write!(f, "Generation error: {}", msg)
             }
+            Error::GuardrailViolation(msg) => {
+                write!(f, "Guardrail violation: {}", msg)
+            }
+            Error::ContextWindowExceeded(msg) => {
+                write!(f, "Context window exceeded: {}", msg)
+            }
+            Error::UnsupportedLanguage(msg) => {
+                write!(f, "Unsupported language: {}", msg)
+            }
+            Error::AssetsUnavailable(msg) => {
+                write!(f, "Model assets unavailable: {}", msg)
+            }
+            Error::RateLimited(msg) => {
+                write!(f, "Rate limited: {}", msg)
+            }
+            Error::ConcurrentRequests(msg) => {
+                write!(f, "Concurrent requests: {}", msg)
+            }
+            Error::Refusal(refusal) => {
+                write!(f, "Refusal: {}", refusal)
+            }
+            Error::DecodingFailure(msg) => {
+                write!(f, "Decoding failure: {}", msg)
+            }
             Error::InvalidInput(msg) => {
                 write!(f, "Invalid input: {}", msg)
             }
@@ -48,10 +130,80 @@ impl fmt::Display for Error {
                     "Synchronization primitive poisoned due to panic while holding lock"
                 )
             }
+            Error::AdapterNotFound(path) => {
+                write!(f, "Adapter not found: {}", path.display())
+            }
+            Error::InvalidAdapter(msg) => {
+                write!(f, "Invalid adapter: {}", msg)
+            }
+            Error::IncompatibleAdapter(msg) => {
+                write!(f, "Adapter is incompatible with the system model: {}", msg)
+            }
+            Error::CassetteMismatch {
+                expected: Some(expected),
+                actual,
+            } => {
+                write!(
+                    f,
+                    "Cassette mismatch: expected prompt {:?}, got {:?}",
+                    expected, actual
+                )
+            }
+            Error::CassetteMismatch {
+                expected: None,
+                actual,
+            } => {
+                write!(
+                    f,
+                    "Cassette mismatch: no recorded interaction left for prompt {:?}",
+                    actual
+                )
+            }
+            Error::ToolCallFailed { tool, message } => {
+                write!(f, "Tool {:?} failed: {}", tool, message)
+            }
+            Error::Cancelled { .. } => write!(f, "Request cancelled"),
         }
     }
 }
 
+impl Error {
+    /// Returns true if the same request may succeed when sent again later
+    ///
+    /// This covers throttling, a session busy with another request, model assets
+    /// that are still downloading and output that could not be decoded. Other
+    /// errors will fail the same way until the input or the system changes.
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// use fm_bindings::LanguageModelSession;
+    /// use std::{thread, time::Duration};
+    ///
+    /// # fn main() -> fm_bindings::Result<()> {
+    /// let session = LanguageModelSession::new()?;
+    /// let response = loop {
+    ///     match session.response("Summarize the news") {
+    ///         Err(error) if error.is_retryable() => thread::sleep(Duration::from_secs(1)),
+    ///         result => break result?,
+    ///     }
+    /// };
+    /// println!("{}", response);
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn is_retryable(&self) -> bool {
+        matches!(
+            self,
+            Error::RateLimited(_)
+                | Error::ConcurrentRequests(_)
+                | Error::AssetsUnavailable(_)
+                | Error::DecodingFailure(_)
+                | Error::ModelNotAvailable(Availability::ModelNotReady)
+        )
+    }
+}
+
 impl std::error::Error for Error {}
 
 /// Result type alias for Foundation Models operations
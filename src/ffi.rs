@@ -1,6 +1,8 @@
 // src/ffi.rs
 // FFI (Foreign Function Interface) layer for Swift FoundationModels integration
 // This module contains all C-ABI declarations for both blocking and streaming modes
+// On non-Apple targets the Swift library is not built; the declarations are
+// replaced by stubs with the same signatures that report the model as unavailable
 
 use std::os::raw::{c_char, c_void};
 
@@ -19,54 +21,399 @@ pub type ChunkCallbackWithData = extern "C" fn(*const c_char, *mut c_void);
 pub type DoneCallbackWithData = extern "C" fn(*mut c_void);
 
 /// Called when an error occurs during generation
+/// - kind: one of the `ERROR_*` codes
 /// - error: null-terminated C string containing error message
+/// - detail: for `ERROR_REFUSAL`, a refusal handle owned by the callee that must be
+///   released with `fm_refusal_destroy`; null otherwise
 /// - user_data: opaque pointer to user state
-pub type ErrorCallbackWithData = extern "C" fn(*const c_char, *mut c_void);
+pub type ErrorCallbackWithData = extern "C" fn(i32, *const c_char, *mut c_void, *mut c_void);
+
+// Error kinds reported through ErrorCallbackWithData
+// Must match Swift's FFIErrorKind
+pub const ERROR_GENERATION: i32 = 0;
+pub const ERROR_INTERNAL: i32 = 1;
+pub const ERROR_INVALID_INPUT: i32 = 2;
+pub const ERROR_MODEL_NOT_AVAILABLE: i32 = 3;
+pub const ERROR_CONTEXT_WINDOW_EXCEEDED: i32 = 4;
+pub const ERROR_GUARDRAIL_VIOLATION: i32 = 5;
+pub const ERROR_UNSUPPORTED_LANGUAGE: i32 = 6;
+pub const ERROR_ASSETS_UNAVAILABLE: i32 = 7;
+pub const ERROR_RATE_LIMITED: i32 = 8;
+pub const ERROR_CONCURRENT_REQUESTS: i32 = 9;
+pub const ERROR_REFUSAL: i32 = 10;
+pub const ERROR_DECODING_FAILURE: i32 = 11;
+
+/// Called by a tool with its result, before the tool callback returns
+/// - output: null-terminated C string with the tool's output, or its error message
+/// - is_error: true if the tool failed
+/// - context: the opaque pointer passed to the tool callback
+pub type ToolResultCallback = extern "C" fn(*const c_char, bool, *mut c_void);
+
+/// Called when the model calls a tool registered on the session
+/// - name: null-terminated C string with the tool name
+/// - arguments: null-terminated C string with the JSON arguments
+/// - user_data: opaque pointer given when the session was created
+/// - context: opaque pointer to pass back to on_result
+/// - on_result: must be called exactly once, before returning
+pub type ToolCallbackWithData =
+    extern "C" fn(*const c_char, *const c_char, *mut c_void, *mut c_void, ToolResultCallback);
+
+/// Called with the availability of the system model
+/// - status: one of the `AVAILABILITY_*` codes
+/// - detail: null-terminated description of an unknown unavailability reason, or null
+/// - user_data: opaque pointer to user state
+pub type AvailabilityCallback = extern "C" fn(i32, *const c_char, *mut c_void);
+
+// Availability codes reported through AvailabilityCallback
+// Must match the codes in Swift's fm_model_availability
+pub const AVAILABILITY_AVAILABLE: i32 = 0;
+pub const AVAILABILITY_DEVICE_NOT_ELIGIBLE: i32 = 1;
+pub const AVAILABILITY_APPLE_INTELLIGENCE_NOT_ENABLED: i32 = 2;
+pub const AVAILABILITY_MODEL_NOT_READY: i32 = 3;
+pub const AVAILABILITY_OTHER: i32 = 4;
+
+/// Called when an adapter cannot be loaded
+/// - kind: one of the `ADAPTER_ERROR_*` codes
+/// - message: null-terminated description of the error
+/// - user_data: opaque pointer to user state
+pub type AdapterErrorCallback = extern "C" fn(i32, *const c_char, *mut c_void);
+
+// Adapter error codes reported through AdapterErrorCallback
+// Must match the codes in Swift's fm_adapter_validate
+pub const ADAPTER_ERROR_INVALID_ASSET: i32 = 1;
+pub const ADAPTER_ERROR_INVALID_NAME: i32 = 2;
+pub const ADAPTER_ERROR_INCOMPATIBLE: i32 = 3;
+pub const ADAPTER_ERROR_OTHER: i32 = 4;
 
 // External Swift Functions
 // These functions are implemented in Swift and exported via @_cdecl
 
+#[cfg(target_vendor = "apple")]
 #[link(name = "FoundationModelsFFI", kind = "static")]
 unsafe extern "C" {
-    /// Check if Foundation Model is available on this system
-    /// Returns true if the model is available, false otherwise
+    /// Report the availability of a system model
+    /// Calls on_availability exactly once, before returning, with an
+    /// `AVAILABILITY_*` code and, for `AVAILABILITY_OTHER`, a description
+    ///
+    /// - model_json: null-terminated serialized `SystemLanguageModel`, or null
+    ///   for the default model
     ///
     /// This should be called before creating a session to fail-fast
     /// if Apple Intelligence is not enabled or the system is unsupported
-    pub fn fm_check_availability() -> bool;
+    pub fn fm_model_availability(
+        model_json: *const c_char,
+        user_data: *mut c_void,
+        on_availability: AvailabilityCallback,
+    );
+
+    /// Check that an adapter package can be loaded by the system model
+    /// Returns true if it can; otherwise calls on_error exactly once, before
+    /// returning, with an `ADAPTER_ERROR_*` code and a description
+    ///
+    /// - path: null-terminated path of an `.fmadapter` package
+    pub fn fm_adapter_validate(
+        path: *const c_char,
+        user_data: *mut c_void,
+        on_error: AdapterErrorCallback,
+    ) -> bool;
+
+    /// Create a Foundation Models session and return an opaque handle to it
+    /// The Swift session keeps the conversation transcript, so every request
+    /// made through the handle sees the earlier turns
+    ///
+    /// - model_json: null-terminated serialized `SystemLanguageModel`, or null
+    ///   for the default model; a model with an adapter loads it from its path
+    /// - instructions: null-terminated C string, or null for no instructions
+    /// - tools_json: null-terminated JSON array of tool definitions (name,
+    ///   description and a serialized `GenerationSchema` as parameters), or null
+    ///   for no tools
+    /// - tool_user_data: opaque pointer passed to every on_tool_call; it must stay
+    ///   valid until the session is destroyed
+    /// - on_tool_call: called when the model calls one of the tools
+    ///
+    /// Returns null if no session could be created. The handle must be
+    /// released with `fm_session_destroy`
+    pub fn fm_session_create(
+        model_json: *const c_char,
+        instructions: *const c_char,
+        tools_json: *const c_char,
+        tool_user_data: *mut c_void,
+        on_tool_call: Option<ToolCallbackWithData>,
+    ) -> *mut c_void;
+
+    /// Create a session that resumes a saved conversation
+    /// Like `fm_session_create`, but the Swift session starts from the given
+    /// transcript, so its instructions and earlier turns are part of the context
+    ///
+    /// - transcript_json: null-terminated C string with a serialized `Transcript`
+    /// - model_json, tools_json, tool_user_data, on_tool_call: as for `fm_session_create`
+    ///
+    /// Returns null if the transcript cannot be decoded or no session could be
+    /// created. The handle must be released with `fm_session_destroy`
+    pub fn fm_session_create_from_transcript(
+        model_json: *const c_char,
+        transcript_json: *const c_char,
+        tools_json: *const c_char,
+        tool_user_data: *mut c_void,
+        on_tool_call: Option<ToolCallbackWithData>,
+    ) -> *mut c_void;
+
+    /// Release a session handle created by `fm_session_create`
+    /// Cancels any request still in flight on the session
+    pub fn fm_session_destroy(session: *mut c_void);
 
-    /// Generate a complete response (blocking mode)
+    /// Generate a complete response within a session (blocking mode)
     /// Waits for the entire response before returning via callbacks
     ///
+    /// - session: handle from `fm_session_create`
     /// - prompt: null-terminated C string
+    /// - options_json: null-terminated serialized `GenerationOptions`, or null for defaults
+    /// - schema_json: null-terminated serialized `GenerationSchema`, or null for text;
+    ///   with a schema each chunk is the JSON of the whole snapshot so far
+    /// - cancel_token: handle from `fm_cancel_token_create` that stops this request,
+    ///   or null
     /// - user_data: opaque pointer passed to all callbacks
     /// - on_chunk: called for each chunk generated
     /// - on_done: called when generation completes
     /// - on_error: called if error occurs
-    pub fn fm_response(
+    pub fn fm_session_response(
+        session: *mut c_void,
         prompt: *const c_char,
+        options_json: *const c_char,
+        schema_json: *const c_char,
+        cancel_token: *mut c_void,
         user_data: *mut c_void,
         on_chunk: ChunkCallbackWithData,
         on_done: DoneCallbackWithData,
         on_error: ErrorCallbackWithData,
     );
 
-    /// Start streaming a Foundation Model response
-    /// Returns immediately and delivers chunks via callbacks
+    /// Stream a response within a session
+    /// Delivers chunks via callbacks as they arrive, returning once the stream ends
     ///
+    /// - session: handle from `fm_session_create`
     /// - prompt: null-terminated C string
+    /// - options_json: null-terminated serialized `GenerationOptions`, or null for defaults
+    /// - schema_json: null-terminated serialized `GenerationSchema`, or null for text;
+    ///   with a schema each chunk is the JSON of the whole snapshot so far
+    /// - cancel_token: handle from `fm_cancel_token_create` that stops this request,
+    ///   or null
     /// - user_data: opaque pointer passed to all callbacks
     /// - on_chunk: called for each chunk as it arrives
     /// - on_done: called when stream completes
     /// - on_error: called if error occurs
-    pub fn fm_start_stream(
+    pub fn fm_session_stream(
+        session: *mut c_void,
         prompt: *const c_char,
+        options_json: *const c_char,
+        schema_json: *const c_char,
+        cancel_token: *mut c_void,
+        user_data: *mut c_void,
+        on_chunk: ChunkCallbackWithData,
+        on_done: DoneCallbackWithData,
+        on_error: ErrorCallbackWithData,
+    );
+
+    /// Cancel the request currently in flight on a session, if any
+    pub fn fm_session_cancel(session: *mut c_void);
+
+    /// Create a token that cancels the single request it is passed to
+    /// The handle must be released with `fm_cancel_token_destroy`
+    pub fn fm_cancel_token_create() -> *mut c_void;
+
+    /// Cancel the request of a token, now or as soon as it starts
+    /// Safe to call from any thread, any number of times
+    pub fn fm_cancel_token_cancel(token: *mut c_void);
+
+    /// Release a token created by `fm_cancel_token_create`
+    pub fn fm_cancel_token_destroy(token: *mut c_void);
+
+    /// Ask the model to load the session's resources ahead of its next request
+    /// Returns immediately; loading continues in the background
+    ///
+    /// - session: handle from `fm_session_create`
+    /// - prompt_prefix: null-terminated beginning of the next prompt, or null
+    pub fn fm_session_prewarm(session: *mut c_void, prompt_prefix: *const c_char);
+
+    /// Get the size of a system model's context window, in tokens
+    /// Returns -1 if the model is invalid or the OS cannot report the size
+    ///
+    /// - model_json: null-terminated serialized `SystemLanguageModel`, or null for the default model
+    pub fn fm_model_context_size(model_json: *const c_char) -> i64;
+
+    /// Count tokens with a system model's tokenizer (blocking)
+    /// Returns -1 if the model is invalid, the input cannot be decoded or the OS
+    /// cannot count tokens
+    ///
+    /// - model_json: null-terminated serialized `SystemLanguageModel`, or null for the default model
+    /// - text: null-terminated text to count, or null
+    /// - transcript_json: null-terminated serialized `Transcript` to count if text is null
+    pub fn fm_model_token_count(
+        model_json: *const c_char,
+        text: *const c_char,
+        transcript_json: *const c_char,
+    ) -> i64;
+
+    /// Ask the model to explain a refusal (blocking mode)
+    /// The explanation is delivered as a single chunk
+    ///
+    /// - refusal: handle passed to an error callback with `ERROR_REFUSAL`
+    /// - user_data: opaque pointer passed to all callbacks
+    /// - on_chunk: called with the explanation
+    /// - on_done: called when the explanation is complete
+    /// - on_error: called if error occurs
+    pub fn fm_refusal_explanation(
+        refusal: *mut c_void,
         user_data: *mut c_void,
         on_chunk: ChunkCallbackWithData,
         on_done: DoneCallbackWithData,
         on_error: ErrorCallbackWithData,
     );
 
-    /// Stop/cancel current stream
-    pub fn fm_stop_stream();
+    /// Release a refusal handle passed to an error callback
+    pub fn fm_refusal_destroy(refusal: *mut c_void);
+}
+
+#[cfg(not(target_vendor = "apple"))]
+pub use self::unsupported::*;
+
+// Stand-ins for the Swift functions on platforms without FoundationModels
+// They keep the same contract as the bridge: every request terminates through
+// exactly one of the done/error callbacks
+#[cfg(not(target_vendor = "apple"))]
+#[allow(clippy::missing_safety_doc, clippy::too_many_arguments)]
+mod unsupported {
+    use super::{
+        AVAILABILITY_DEVICE_NOT_ELIGIBLE, AdapterErrorCallback, AvailabilityCallback,
+        ChunkCallbackWithData, DoneCallbackWithData, ERROR_MODEL_NOT_AVAILABLE,
+        ErrorCallbackWithData, ToolCallbackWithData,
+    };
+    use std::os::raw::{c_char, c_void};
+
+    const NOT_AVAILABLE: &std::ffi::CStr = c"Foundation Models are not available on this platform";
+
+    pub unsafe fn fm_model_availability(
+        _model_json: *const c_char,
+        user_data: *mut c_void,
+        on_availability: AvailabilityCallback,
+    ) {
+        on_availability(
+            AVAILABILITY_DEVICE_NOT_ELIGIBLE,
+            std::ptr::null(),
+            user_data,
+        );
+    }
+
+    // Adapters cannot be inspected without the framework; sessions on them
+    // fail anyway since the model is unavailable
+    pub unsafe fn fm_adapter_validate(
+        _path: *const c_char,
+        _user_data: *mut c_void,
+        _on_error: AdapterErrorCallback,
+    ) -> bool {
+        true
+    }
+
+    pub unsafe fn fm_session_create(
+        _model_json: *const c_char,
+        _instructions: *const c_char,
+        _tools_json: *const c_char,
+        _tool_user_data: *mut c_void,
+        _on_tool_call: Option<ToolCallbackWithData>,
+    ) -> *mut c_void {
+        std::ptr::null_mut()
+    }
+
+    pub unsafe fn fm_session_create_from_transcript(
+        _model_json: *const c_char,
+        _transcript_json: *const c_char,
+        _tools_json: *const c_char,
+        _tool_user_data: *mut c_void,
+        _on_tool_call: Option<ToolCallbackWithData>,
+    ) -> *mut c_void {
+        std::ptr::null_mut()
+    }
+
+    pub unsafe fn fm_session_destroy(_session: *mut c_void) {}
+
+    pub unsafe fn fm_session_response(
+        _session: *mut c_void,
+        _prompt: *const c_char,
+        _options_json: *const c_char,
+        _schema_json: *const c_char,
+        _cancel_token: *mut c_void,
+        user_data: *mut c_void,
+        _on_chunk: ChunkCallbackWithData,
+        _on_done: DoneCallbackWithData,
+        on_error: ErrorCallbackWithData,
+    ) {
+        on_error(
+            ERROR_MODEL_NOT_AVAILABLE,
+            NOT_AVAILABLE.as_ptr(),
+            std::ptr::null_mut(),
+            user_data,
+        );
+    }
+
+    pub unsafe fn fm_session_stream(
+        _session: *mut c_void,
+        _prompt: *const c_char,
+        _options_json: *const c_char,
+        _schema_json: *const c_char,
+        _cancel_token: *mut c_void,
+        user_data: *mut c_void,
+        _on_chunk: ChunkCallbackWithData,
+        _on_done: DoneCallbackWithData,
+        on_error: ErrorCallbackWithData,
+    ) {
+        on_error(
+            ERROR_MODEL_NOT_AVAILABLE,
+            NOT_AVAILABLE.as_ptr(),
+            std::ptr::null_mut(),
+            user_data,
+        );
+    }
+
+    pub unsafe fn fm_session_cancel(_session: *mut c_void) {}
+
+    // Requests fail at once without the framework, so there is nothing to cancel
+    pub unsafe fn fm_cancel_token_create() -> *mut c_void {
+        std::ptr::null_mut()
+    }
+
+    pub unsafe fn fm_cancel_token_cancel(_token: *mut c_void) {}
+
+    pub unsafe fn fm_cancel_token_destroy(_token: *mut c_void) {}
+
+    pub unsafe fn fm_session_prewarm(_session: *mut c_void, _prompt_prefix: *const c_char) {}
+
+    pub unsafe fn fm_model_context_size(_model_json: *const c_char) -> i64 {
+        -1
+    }
+
+    pub unsafe fn fm_model_token_count(
+        _model_json: *const c_char,
+        _text: *const c_char,
+        _transcript_json: *const c_char,
+    ) -> i64 {
+        -1
+    }
+
+    // No refusal handle is ever handed out without the framework
+    pub unsafe fn fm_refusal_explanation(
+        _refusal: *mut c_void,
+        user_data: *mut c_void,
+        _on_chunk: ChunkCallbackWithData,
+        _on_done: DoneCallbackWithData,
+        on_error: ErrorCallbackWithData,
+    ) {
+        on_error(
+            ERROR_MODEL_NOT_AVAILABLE,
+            NOT_AVAILABLE.as_ptr(),
+            std::ptr::null_mut(),
+            user_data,
+        );
+    }
+
+    pub unsafe fn fm_refusal_destroy(_refusal: *mut c_void) {}
 }
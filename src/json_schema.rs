@@ -0,0 +1,512 @@
+// src/json_schema.rs
+// JSON Schema documents - conversion to GenerationSchema and checking of generated values
+
+use super::error::{Error, Result};
+use super::schema::{GenerationSchema, Property};
+use serde_json::{Map, Value};
+
+/// Keywords that only annotate a schema and do not constrain generation
+const ANNOTATIONS: &[&str] = &[
+    "$schema",
+    "$id",
+    "$comment",
+    "title",
+    "description",
+    "default",
+    "examples",
+    "format",
+    "deprecated",
+    "readOnly",
+    "writeOnly",
+];
+
+/// Keywords with a generation schema equivalent
+const SUPPORTED: &[&str] = &[
+    "type",
+    "properties",
+    "required",
+    "additionalProperties",
+    "items",
+    "minItems",
+    "maxItems",
+    "enum",
+    "const",
+    "pattern",
+    "minimum",
+    "maximum",
+    "exclusiveMinimum",
+    "anyOf",
+    "oneOf",
+    "$ref",
+    "$defs",
+    "definitions",
+];
+
+impl GenerationSchema {
+    /// Converts a [JSON Schema](https://json-schema.org) document into a generation schema
+    ///
+    /// Supports the subset of JSON Schema that constrained generation can enforce:
+    ///
+    /// * `type` - `object`, `array`, `string`, `integer`, `number` and `boolean`;
+    ///   `null` is only accepted alongside another type (as for `Option` fields)
+    /// * objects - `properties`, `required` and `additionalProperties: false`;
+    ///   properties missing from `required` are optional
+    /// * arrays - `items`, `minItems` and `maxItems`
+    /// * strings - `pattern`, and `enum`/`const` with string values
+    /// * numbers - `minimum` and `maximum`, and `exclusiveMinimum` for integers
+    /// * `anyOf`/`oneOf`, and `$ref` to `#/$defs/...` or `#/definitions/...`, as
+    ///   produced by [`schemars`](https://docs.rs/schemars)
+    ///
+    /// Annotations (`title`, `description`, `format`, `default`, ...) are accepted;
+    /// descriptions are passed on to the model. Object names come from `title`, the
+    /// `$defs` key, or the property path.
+    ///
+    /// # Errors
+    ///
+    /// Returns `Error::InvalidInput` naming the location of the first unsupported
+    /// keyword or value, e.g. ``Unsupported JSON Schema keyword `not` at #/properties/id``.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use fm_bindings::GenerationSchema;
+    /// use serde_json::json;
+    ///
+    /// # fn main() -> fm_bindings::Result<()> {
+    /// let schema = GenerationSchema::from_json_schema(&json!({
+    ///     "title": "Ticket",
+    ///     "type": "object",
+    ///     "properties": {
+    ///         "summary": { "type": "string" },
+    ///         "priority": { "enum": ["low", "high"] }
+    ///     },
+    ///     "required": ["summary", "priority"]
+    /// }))?;
+    /// assert_eq!(schema.name(), Some("Ticket"));
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn from_json_schema(document: &Value) -> Result<Self> {
+        let name = document
+            .get("title")
+            .and_then(Value::as_str)
+            .unwrap_or("Response");
+        Converter {
+            root: document,
+            resolving: Vec::new(),
+        }
+        .convert(document, name, "#")
+    }
+
+    /// Checks that a generated value has the shape of the schema
+    ///
+    /// Patterns are left to the model and not checked here.
+    pub(crate) fn check(&self, value: &Value) -> std::result::Result<(), String> {
+        self.check_at(value, "#")
+    }
+
+    fn check_at(&self, value: &Value, path: &str) -> std::result::Result<(), String> {
+        let mismatch = |expected: &str| Err(format!("expected {} at {}", expected, path));
+        match self {
+            Self::Object { properties, .. } => {
+                let Some(object) = value.as_object() else {
+                    return mismatch("an object");
+                };
+                for property in properties {
+                    let path = format!("{}/{}", path, property.name);
+                    match object.get(&property.name) {
+                        Some(Value::Null) | None if property.optional => {}
+                        Some(value) => property.schema.check_at(value, &path)?,
+                        None => return Err(format!("missing property {}", path)),
+                    }
+                }
+                match object
+                    .keys()
+                    .find(|key| properties.iter().all(|property| &property.name != *key))
+                {
+                    Some(key) => Err(format!("unexpected property {}/{}", path, key)),
+                    None => Ok(()),
+                }
+            }
+            Self::AnyOf { choices, .. } => {
+                if choices
+                    .iter()
+                    .any(|choice| choice.check_at(value, path).is_ok())
+                {
+                    Ok(())
+                } else {
+                    mismatch("a value matching one of the choices")
+                }
+            }
+            Self::Enum { choices, .. } => match value.as_str() {
+                Some(text) if choices.iter().any(|choice| choice == text) => Ok(()),
+                _ => mismatch(&format!("one of {:?}", choices)),
+            },
+            Self::Array {
+                items,
+                min_items,
+                max_items,
+            } => {
+                let Some(array) = value.as_array() else {
+                    return mismatch("an array");
+                };
+                let len = array.len() as u64;
+                if min_items.is_some_and(|min| len < u64::from(min))
+                    || max_items.is_some_and(|max| len > u64::from(max))
+                {
+                    return Err(format!("{} items out of bounds at {}", len, path));
+                }
+                array.iter().enumerate().try_for_each(|(index, item)| {
+                    items.check_at(item, &format!("{}/{}", path, index))
+                })
+            }
+            Self::String { .. } => match value {
+                Value::String(_) => Ok(()),
+                _ => mismatch("a string"),
+            },
+            Self::Integer { minimum, maximum } => match value.as_i64() {
+                Some(n)
+                    if minimum.is_none_or(|min| n >= min) && maximum.is_none_or(|max| n <= max) =>
+                {
+                    Ok(())
+                }
+                _ => mismatch("an integer within bounds"),
+            },
+            Self::Number { minimum, maximum } => match value.as_f64() {
+                Some(n)
+                    if minimum.is_none_or(|min| n >= min) && maximum.is_none_or(|max| n <= max) =>
+                {
+                    Ok(())
+                }
+                _ => mismatch("a number within bounds"),
+            },
+            Self::Boolean => match value {
+                Value::Bool(_) => Ok(()),
+                _ => mismatch("a boolean"),
+            },
+        }
+    }
+}
+
+/// Walks a JSON Schema document, resolving references against its root
+struct Converter<'a> {
+    root: &'a Value,
+    /// References being converted, to reject recursive schemas
+    resolving: Vec<String>,
+}
+
+impl<'a> Converter<'a> {
+    /// Converts the schema at `path`; `name` names it if it becomes an object,
+    /// any-of or enum schema without a title of its own
+    fn convert(&mut self, schema: &'a Value, name: &str, path: &str) -> Result<GenerationSchema> {
+        let Some(object) = schema.as_object() else {
+            return Err(unsupported(format!(
+                "Boolean JSON Schemas are not supported at {}",
+                path
+            )));
+        };
+        if let Some(keyword) = object
+            .keys()
+            .find(|key| !ANNOTATIONS.contains(&key.as_str()) && !SUPPORTED.contains(&key.as_str()))
+        {
+            return Err(unsupported(format!(
+                "Unsupported JSON Schema keyword `{}` at {}",
+                keyword, path
+            )));
+        }
+
+        let name = object.get("title").and_then(Value::as_str).unwrap_or(name);
+        let description = object.get("description").and_then(Value::as_str);
+        let schema = self.convert_keywords(object, name, path)?;
+        Ok(match description {
+            Some(description) => schema.with_description(description),
+            None => schema,
+        })
+    }
+
+    fn convert_keywords(
+        &mut self,
+        object: &'a Map<String, Value>,
+        name: &str,
+        path: &str,
+    ) -> Result<GenerationSchema> {
+        if let Some(reference) = object.get("$ref") {
+            return self.convert_reference(reference, path);
+        }
+        for keyword in ["anyOf", "oneOf"] {
+            if let Some(choices) = object.get(keyword) {
+                return self.convert_choices(choices, name, &format!("{}/{}", path, keyword));
+            }
+        }
+        if let Some(values) = object.get("enum") {
+            return enumeration(values, name, path);
+        }
+        if let Some(value) = object.get("const") {
+            return enumeration(&Value::Array(vec![value.clone()]), name, path);
+        }
+
+        let kind = match object.get("type") {
+            Some(kind) => schema_type(kind, path)?,
+            None if object.contains_key("properties") => "object",
+            None if object.contains_key("items") => "array",
+            None => {
+                return Err(unsupported(format!(
+                    "JSON Schema without a `type` at {}",
+                    path
+                )));
+            }
+        };
+
+        match kind {
+            "object" => self.convert_object(object, name, path),
+            "array" => {
+                let items = object
+                    .get("items")
+                    .ok_or_else(|| unsupported(format!("Array without `items` at {}", path)))?;
+                let items = self.convert(items, name, &format!("{}/items", path))?;
+                Ok(GenerationSchema::array(items).with_count(
+                    count(object, "minItems", path)?,
+                    count(object, "maxItems", path)?,
+                ))
+            }
+            "string" => match object.get("pattern") {
+                Some(Value::String(pattern)) => {
+                    Ok(GenerationSchema::string().with_pattern(pattern))
+                }
+                Some(_) => Err(unsupported(format!(
+                    "`pattern` must be a string at {}",
+                    path
+                ))),
+                None => Ok(GenerationSchema::string()),
+            },
+            "integer" => {
+                let exclusive =
+                    bound(object, "exclusiveMinimum", path)?.map(|min| min.floor() + 1.0);
+                Ok(GenerationSchema::integer()
+                    .with_range(bound(object, "minimum", path)?, None)
+                    .with_range(exclusive, bound(object, "maximum", path)?))
+            }
+            "number" => {
+                if object.contains_key("exclusiveMinimum") {
+                    return Err(unsupported(format!(
+                        "`exclusiveMinimum` is only supported for integers at {}",
+                        path
+                    )));
+                }
+                Ok(GenerationSchema::number().with_range(
+                    bound(object, "minimum", path)?,
+                    bound(object, "maximum", path)?,
+                ))
+            }
+            "boolean" => Ok(GenerationSchema::boolean()),
+            other => Err(unsupported(format!(
+                "Unsupported JSON Schema type `{}` at {}",
+                other, path
+            ))),
+        }
+    }
+
+    fn convert_object(
+        &mut self,
+        object: &'a Map<String, Value>,
+        name: &str,
+        path: &str,
+    ) -> Result<GenerationSchema> {
+        match object.get("additionalProperties") {
+            None | Some(Value::Bool(false)) => {}
+            Some(_) => {
+                return Err(unsupported(format!(
+                    "Only `additionalProperties: false` is supported at {}",
+                    path
+                )));
+            }
+        }
+
+        let required: Vec<&str> = match object.get("required") {
+            Some(Value::Array(keys)) => keys.iter().filter_map(Value::as_str).collect(),
+            Some(_) => {
+                return Err(unsupported(format!(
+                    "`required` must be an array at {}",
+                    path
+                )));
+            }
+            None => Vec::new(),
+        };
+
+        let mut schema = GenerationSchema::object(name);
+        let properties = match object.get("properties") {
+            Some(Value::Object(properties)) => properties,
+            Some(_) => {
+                return Err(unsupported(format!(
+                    "`properties` must be an object at {}",
+                    path
+                )));
+            }
+            None => return Ok(schema),
+        };
+        for (key, value) in properties {
+            let property_path = format!("{}/properties/{}", path, key);
+            let property_name = format!("{}{}", name, upper_camel_case(key));
+            let property_schema = self.convert(value, &property_name, &property_path)?;
+            let mut property = Property::new(key.as_str(), property_schema)
+                .with_optional(!required.contains(&key.as_str()));
+            if let Some(description) = value.get("description").and_then(Value::as_str) {
+                property = property.with_description(description);
+            }
+            schema = schema.with_property(property);
+        }
+        Ok(schema)
+    }
+
+    fn convert_choices(
+        &mut self,
+        choices: &'a Value,
+        name: &str,
+        path: &str,
+    ) -> Result<GenerationSchema> {
+        let Some(choices) = choices.as_array() else {
+            return Err(unsupported(format!("Choices must be an array at {}", path)));
+        };
+
+        // A null choice only marks the value as optional
+        let mut converted = Vec::new();
+        for (index, choice) in choices.iter().enumerate() {
+            if choice.get("type").and_then(Value::as_str) == Some("null") {
+                continue;
+            }
+            let choice_name = format!("{}{}", name, index + 1);
+            let choice_path = format!("{}/{}", path, index);
+            converted.push(self.convert(choice, &choice_name, &choice_path)?);
+        }
+
+        match converted.len() {
+            0 => Err(unsupported(format!("No non-null choice at {}", path))),
+            1 => Ok(converted.remove(0)),
+            _ => Ok(GenerationSchema::any_of(name, converted)),
+        }
+    }
+
+    fn convert_reference(&mut self, reference: &'a Value, path: &str) -> Result<GenerationSchema> {
+        let Some(reference) = reference.as_str() else {
+            return Err(unsupported(format!("`$ref` must be a string at {}", path)));
+        };
+        let (container, key) = reference
+            .strip_prefix("#/$defs/")
+            .map(|key| ("$defs", key))
+            .or_else(|| {
+                reference
+                    .strip_prefix("#/definitions/")
+                    .map(|key| ("definitions", key))
+            })
+            .ok_or_else(|| {
+                unsupported(format!(
+                    "Only local `$ref`s to `#/$defs/` or `#/definitions/` are supported, found `{}` at {}",
+                    reference, path
+                ))
+            })?;
+        let target = self
+            .root
+            .get(container)
+            .and_then(|definitions| definitions.get(key))
+            .ok_or_else(|| unsupported(format!("Unresolved `$ref` `{}` at {}", reference, path)))?;
+
+        if self
+            .resolving
+            .iter()
+            .any(|resolving| resolving == reference)
+        {
+            return Err(unsupported(format!(
+                "Recursive `$ref` `{}` is not supported at {}",
+                reference, path
+            )));
+        }
+        self.resolving.push(reference.to_string());
+        let schema = self.convert(target, key, &format!("#/{}/{}", container, key));
+        self.resolving.pop();
+        schema
+    }
+}
+
+/// The single non-null type of a `type` keyword
+fn schema_type<'v>(kind: &'v Value, path: &str) -> Result<&'v str> {
+    let types: Vec<&str> = match kind {
+        Value::String(kind) => vec![kind.as_str()],
+        Value::Array(kinds) => kinds.iter().filter_map(Value::as_str).collect(),
+        _ => Vec::new(),
+    };
+    let mut non_null = types.into_iter().filter(|kind| *kind != "null");
+    match (non_null.next(), non_null.next()) {
+        (Some(kind), None) => Ok(kind),
+        (None, _) => Err(unsupported(format!(
+            "`null` type is not supported at {}",
+            path
+        ))),
+        (Some(_), Some(_)) => Err(unsupported(format!(
+            "Multiple types are not supported at {}, use `anyOf`",
+            path
+        ))),
+    }
+}
+
+fn enumeration(values: &Value, name: &str, path: &str) -> Result<GenerationSchema> {
+    let Some(values) = values.as_array() else {
+        return Err(unsupported(format!("`enum` must be an array at {}", path)));
+    };
+    let choices = values
+        .iter()
+        .filter(|value| !value.is_null())
+        .map(|value| {
+            value.as_str().ok_or_else(|| {
+                unsupported(format!(
+                    "Only string `enum` and `const` values are supported at {}",
+                    path
+                ))
+            })
+        })
+        .collect::<Result<Vec<_>>>()?;
+    Ok(GenerationSchema::enumeration(name, choices))
+}
+
+fn bound(object: &Map<String, Value>, keyword: &str, path: &str) -> Result<Option<f64>> {
+    object
+        .get(keyword)
+        .map(|value| {
+            value
+                .as_f64()
+                .ok_or_else(|| unsupported(format!("`{}` must be a number at {}", keyword, path)))
+        })
+        .transpose()
+}
+
+fn count(object: &Map<String, Value>, keyword: &str, path: &str) -> Result<Option<u32>> {
+    object
+        .get(keyword)
+        .map(|value| {
+            value
+                .as_u64()
+                .and_then(|count| u32::try_from(count).ok())
+                .ok_or_else(|| {
+                    unsupported(format!(
+                        "`{}` must be a non-negative integer at {}",
+                        keyword, path
+                    ))
+                })
+        })
+        .transpose()
+}
+
+fn upper_camel_case(key: &str) -> String {
+    key.split(['_', '-', ' '])
+        .map(|word| {
+            let mut chars = word.chars();
+            chars
+                .next()
+                .map(|first| first.to_uppercase().chain(chars).collect::<String>())
+                .unwrap_or_default()
+        })
+        .collect()
+}
+
+fn unsupported(message: String) -> Error {
+    Error::InvalidInput(message)
+}
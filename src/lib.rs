@@ -12,8 +12,33 @@
 //!
 //! - **Blocking Response**: Get complete responses with `response()`
 //! - **Streaming Response**: Get real-time incremental updates with `stream_response()`
+//! - Multi-turn sessions that remember earlier prompts and responses
+//! - Guided generation of Rust types with `#[derive(Generable)]` and
+//!   [`response_as`](LanguageModelSession::response_as), or streamed as partial
+//!   values with [`stream_response_as`](LanguageModelSession::stream_response_as)
+//! - Runtime schemas, including JSON Schema documents, with
+//!   [`response_with_schema`](LanguageModelSession::response_with_schema)
+//! - Tool calling: the model can call Rust functions implementing [`Tool`], or
+//!   plain functions annotated with `#[fm_tool]`
+//! - Specialised [`SystemLanguageModel`] configurations such as content tagging,
+//!   configurable [`Guardrails`] and custom adapters
+//! - [`GenerationOptions`] for temperature, response length and sampling mode
+//! - Context size and token counts, with a heuristic [`tokens`] estimator where
+//!   the platform cannot count
+//! - [`ContextPolicy`] to drop or summarize older turns of long conversations
+//! - Per-request cancellation with a [`CancelHandle`], for blocking and streaming
+//!   requests alike
+//! - Serializable [`Transcript`]s to inspect, persist and resume conversations
 //! - Type-safe error handling with `Result<T, Error>`
+//! - Detailed [`Availability`] of the system model, so apps can tell users why it
+//!   cannot be used, and an [`AvailabilityWatcher`] reporting when that changes
 //! - Zero-copy FFI layer for optimal performance
+//! - Pluggable [`ModelBackend`] trait, so code using the session API also builds
+//!   and can be tested on non-Apple platforms
+//! - Record-and-replay of sessions through JSONL [`cassette`] files
+//! - Scripted `mock` model for deterministic tests (`mock` feature)
+//! - `openai` backend for OpenAI-compatible chat completion servers (`openai` feature)
+//! - `candle` backend running local GGUF models on the CPU in-process (`candle` feature)
 //!
 //! ## Examples
 //!
@@ -50,10 +75,89 @@
 //! ```
 
 // Internal modules
+mod backend;
+mod cancel;
+mod context;
 mod error;
 mod ffi;
+mod json_schema;
+mod model;
+mod options;
+mod partial;
+mod refusal;
+mod schema;
 mod session;
+mod system;
+mod tool;
+mod transcript;
+mod watcher;
+
+#[cfg(feature = "candle")]
+pub mod candle;
+pub mod cassette;
+#[cfg(feature = "mock")]
+pub mod mock;
+#[cfg(feature = "openai")]
+pub mod openai;
+pub mod tokens;
 
 // Public API exports
+pub use backend::{GenerationRequest, ModelBackend};
+pub use cancel::CancelHandle;
+pub use context::ContextPolicy;
 pub use error::{Error, Result};
-pub use session::LanguageModelSession;
+pub use model::{Availability, Guardrails, SystemLanguageModel, UseCase};
+pub use options::{GenerationOptions, SamplingMode};
+pub use refusal::Refusal;
+pub use schema::{Generable, GenerationSchema, Property};
+pub use session::{LanguageModelSession, SessionBuilder};
+pub use system::SystemBackend;
+pub use tokens::TokenInput;
+pub use tool::Tool;
+pub use transcript::{Transcript, TranscriptEntry};
+pub use watcher::{AvailabilityChange, AvailabilitySource, AvailabilityWatcher, WatchHandle};
+
+/// Derives [`Generable`] (`derive` feature, enabled by default)
+#[cfg(feature = "derive")]
+pub use fm_bindings_derive::Generable;
+
+/// Turns a function into a [`Tool`] (`derive` feature, enabled by default)
+///
+/// The function's doc comment describes the tool to the model, and its parameters
+/// become the tool's arguments: each parameter type must implement [`Generable`],
+/// `Option` parameters are optional, and a parameter's doc comment or
+/// `#[guide(...)]` attribute describes and constrains it as on a derived field.
+/// The function returns `String`, or `Result<String, E>` with an error that
+/// converts into [`Error`].
+///
+/// The macro keeps the function and defines a unit struct named after it in
+/// UpperCamelCase (`get_weather` becomes `GetWeather`) implementing [`Tool`].
+/// The tool is called by the function's name unless `#[fm_tool(name = "...")]`
+/// says otherwise, and `#[fm_tool(description = "...")]` replaces the doc comment.
+///
+/// # Examples
+///
+/// ```no_run
+/// use fm_bindings::{LanguageModelSession, fm_tool};
+///
+/// /// Returns the forecast for a city
+/// #[fm_tool]
+/// fn get_forecast(
+///     /// City name, e.g. "Paris"
+///     city: String,
+///     #[guide(description = "Days ahead", range = 0..=7)] days: Option<u8>,
+/// ) -> String {
+///     format!("Sunny in {} for {} days", city, days.unwrap_or(1))
+/// }
+///
+/// # fn main() -> fm_bindings::Result<()> {
+/// let session = LanguageModelSession::builder().tool(GetForecast).build()?;
+/// let response = session.response("Will it rain in Paris tomorrow?")?;
+/// # Ok(())
+/// # }
+/// ```
+#[cfg(feature = "derive")]
+pub use fm_bindings_derive::fm_tool;
+
+#[doc(hidden)]
+pub use schema::__private;
@@ -0,0 +1,290 @@
+// src/mock.rs
+// Scripted mock model for deterministic tests (enabled by the `mock` feature)
+
+//! Scripted in-process model for testing code built on [`LanguageModelSession`]
+//!
+//! [`MockModel`] implements [`ModelBackend`] by replaying a queue of canned
+//! [`MockResponse`]s, one per request, so application tests run on any platform
+//! without Apple Intelligence.
+//!
+//! ```
+//! use fm_bindings::mock::{MockModel, MockResponse};
+//! use fm_bindings::{Error, LanguageModelSession};
+//! use std::time::Duration;
+//!
+//! # fn main() -> fm_bindings::Result<()> {
+//! let model = MockModel::new();
+//! model
+//!     .push("4")
+//!     .push(MockResponse::chunks(["Once", " upon", " a time"]).with_chunk_delay(Duration::from_millis(5)))
+//!     .push(Error::GenerationError("rate limited".into()));
+//!
+//! let session = LanguageModelSession::with_backend(model.clone())?;
+//! assert_eq!(session.response("What is 2+2?")?, "4");
+//!
+//! let mut story = String::new();
+//! session.stream_response("Tell me a story", |chunk| story.push_str(chunk))?;
+//! assert_eq!(story, "Once upon a time");
+//!
+//! assert!(session.response("Again").is_err());
+//! assert_eq!(model.prompts(), ["What is 2+2?", "Tell me a story", "Again"]);
+//! # Ok(())
+//! # }
+//! ```
+//!
+//! [`LanguageModelSession`]: crate::LanguageModelSession
+
+use super::backend::{GenerationRequest, ModelBackend};
+use super::error::{Error, Result};
+use super::model::Availability;
+use super::options::GenerationOptions;
+use super::schema::GenerationSchema;
+use std::collections::VecDeque;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::Duration;
+
+/// A scripted model that serves queued responses in order
+///
+/// Clones share the same script, so a test can keep a handle to the model after
+/// moving a clone into [`LanguageModelSession::with_backend`] and keep queueing
+/// responses or inspect the prompts that were sent.
+///
+/// When the queue is empty, requests fail with `Error::InternalError`.
+///
+/// [`LanguageModelSession::with_backend`]: crate::LanguageModelSession::with_backend
+#[derive(Clone, Default)]
+pub struct MockModel {
+    inner: Arc<MockState>,
+}
+
+#[derive(Default)]
+struct MockState {
+    script: Mutex<Script>,
+    cancelled: AtomicBool,
+}
+
+#[derive(Default)]
+struct Script {
+    /// Why the model is unavailable, or `None` while it is available
+    unavailable: Option<Availability>,
+    queue: VecDeque<MockResponse>,
+    prompts: Vec<String>,
+    instructions: Vec<Option<String>>,
+    options: Vec<GenerationOptions>,
+    schemas: Vec<Option<GenerationSchema>>,
+}
+
+impl MockModel {
+    /// Creates an available model with an empty script
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Creates a model that reports itself unavailable
+    ///
+    /// Creating a session from it fails with `Error::ModelNotAvailable`, giving
+    /// [`Availability::AppleIntelligenceNotEnabled`] as the reason.
+    pub fn unavailable() -> Self {
+        let model = Self::default();
+        model.set_available(false);
+        model
+    }
+
+    /// Changes the availability reported by the model
+    ///
+    /// While unavailable, requests on existing sessions fail with
+    /// `Error::ModelNotAvailable` without consuming the script. An unavailable
+    /// model reports [`Availability::AppleIntelligenceNotEnabled`]; use
+    /// [`set_availability`](Self::set_availability) for other reasons.
+    pub fn set_available(&self, available: bool) {
+        self.set_availability(if available {
+            Availability::Available
+        } else {
+            Availability::AppleIntelligenceNotEnabled
+        });
+    }
+
+    /// Changes the availability reported by the model, including the reason it is unavailable
+    pub fn set_availability(&self, availability: Availability) {
+        self.script().unavailable = (!availability.is_available()).then_some(availability);
+    }
+
+    /// Queues the response for the next unanswered request
+    ///
+    /// Accepts a [`MockResponse`], plain text (delivered as a single chunk), or an
+    /// [`Error`] to fail the request with.
+    pub fn push(&self, response: impl Into<MockResponse>) -> &Self {
+        self.script().queue.push_back(response.into());
+        self
+    }
+
+    /// Returns the number of queued responses not yet served
+    pub fn remaining(&self) -> usize {
+        self.script().queue.len()
+    }
+
+    /// Returns every prompt the model has received, in order
+    pub fn prompts(&self) -> Vec<String> {
+        self.script().prompts.clone()
+    }
+
+    fn script(&self) -> std::sync::MutexGuard<'_, Script> {
+        // A panicking test thread must not take the rest of the script down with it
+        self.inner
+            .script
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+
+    /// Returns the instructions sent with each prompt, in the order of [`prompts`](Self::prompts)
+    pub fn instructions(&self) -> Vec<Option<String>> {
+        self.script().instructions.clone()
+    }
+
+    /// Returns the generation options sent with each prompt, in the order of [`prompts`](Self::prompts)
+    pub fn options(&self) -> Vec<GenerationOptions> {
+        self.script().options.clone()
+    }
+
+    /// Returns the schema of each prompt (`None` for free-form text), in the order of [`prompts`](Self::prompts)
+    pub fn schemas(&self) -> Vec<Option<GenerationSchema>> {
+        self.script().schemas.clone()
+    }
+
+    fn next_response(&self, request: &GenerationRequest<'_>) -> Result<MockResponse> {
+        let prompt = request.prompt;
+        let mut script = self.script();
+        if let Some(reason) = &script.unavailable {
+            return Err(Error::ModelNotAvailable(reason.clone()));
+        }
+
+        script.prompts.push(prompt.to_string());
+        script
+            .instructions
+            .push(request.instructions.map(str::to_string));
+        script.options.push(request.options.clone());
+        script.schemas.push(request.schema.cloned());
+        script.queue.pop_front().ok_or_else(|| {
+            Error::InternalError(format!(
+                "MockModel has no response queued for prompt: {prompt:?}"
+            ))
+        })
+    }
+}
+
+impl ModelBackend for MockModel {
+    fn is_available(&self) -> bool {
+        self.script().unavailable.is_none()
+    }
+
+    fn availability(&self) -> Availability {
+        self.script()
+            .unavailable
+            .clone()
+            .unwrap_or(Availability::Available)
+    }
+
+    fn stream_response(
+        &self,
+        request: &GenerationRequest<'_>,
+        on_chunk: &mut dyn FnMut(&str),
+    ) -> Result<()> {
+        let response = self.next_response(request)?;
+        self.inner.cancelled.store(false, Ordering::SeqCst);
+
+        for (index, chunk) in response.chunks.iter().enumerate() {
+            if !response.chunk_delay.is_zero() {
+                thread::sleep(response.chunk_delay);
+            }
+            // Cancellation ends the stream normally, like the system model
+            if self.inner.cancelled.load(Ordering::SeqCst) {
+                return Ok(());
+            }
+            if request.is_cancelled() {
+                return Err(Error::Cancelled {
+                    partial: response.chunks[..index].concat(),
+                });
+            }
+            on_chunk(chunk);
+        }
+
+        match response.error {
+            Some(error) => Err(error),
+            None => Ok(()),
+        }
+    }
+
+    fn cancel_stream(&self) {
+        self.inner.cancelled.store(true, Ordering::SeqCst);
+    }
+}
+
+/// One scripted reply of a [`MockModel`]
+///
+/// A response delivers its chunks in order, optionally sleeping before each one,
+/// and then either succeeds or fails with the configured error.
+#[derive(Debug, Clone, Default)]
+pub struct MockResponse {
+    chunks: Vec<String>,
+    chunk_delay: Duration,
+    error: Option<Error>,
+}
+
+impl MockResponse {
+    /// A response delivered as a single chunk
+    pub fn text(text: impl Into<String>) -> Self {
+        Self::chunks([text.into()])
+    }
+
+    /// A response delivered with the given chunk boundaries
+    pub fn chunks<I, S>(chunks: I) -> Self
+    where
+        I: IntoIterator<Item = S>,
+        S: Into<String>,
+    {
+        Self {
+            chunks: chunks.into_iter().map(Into::into).collect(),
+            ..Self::default()
+        }
+    }
+
+    /// A request that fails immediately with the given error
+    pub fn error(error: Error) -> Self {
+        Self {
+            error: Some(error),
+            ..Self::default()
+        }
+    }
+
+    /// Sleeps for `delay` before delivering each chunk
+    pub fn with_chunk_delay(mut self, delay: Duration) -> Self {
+        self.chunk_delay = delay;
+        self
+    }
+
+    /// Fails with `error` after all chunks have been delivered
+    pub fn then_fail(mut self, error: Error) -> Self {
+        self.error = Some(error);
+        self
+    }
+}
+
+impl From<&str> for MockResponse {
+    fn from(text: &str) -> Self {
+        Self::text(text)
+    }
+}
+
+impl From<String> for MockResponse {
+    fn from(text: String) -> Self {
+        Self::text(text)
+    }
+}
+
+impl From<Error> for MockResponse {
+    fn from(error: Error) -> Self {
+        Self::error(error)
+    }
+}
@@ -0,0 +1,427 @@
+// src/model.rs
+// System language model - configuration and availability of Apple's on-device model
+
+use super::error::{Error, Result};
+use super::ffi;
+use super::tokens::{self, TokenInput};
+use serde::{Deserialize, Serialize};
+use std::ffi::{CStr, CString};
+use std::fmt;
+use std::os::raw::{c_char, c_void};
+use std::path::{Path, PathBuf};
+
+/// Apple's on-device language model
+///
+/// Use it to find out whether the model can serve requests before creating a
+/// [`LanguageModelSession`], and why it cannot when it is unavailable.
+///
+/// The default model is Apple's general-purpose configuration with the default
+/// guardrails. Select a specialised one with [`with_use_case`](Self::with_use_case),
+/// relax the guardrails with [`with_guardrails`](Self::with_guardrails), or load a
+/// custom adapter with [`with_adapter`](Self::with_adapter), and create sessions on it
+/// with [`LanguageModelSession::with_model`].
+///
+/// [`LanguageModelSession`]: crate::LanguageModelSession
+/// [`LanguageModelSession::with_model`]: crate::LanguageModelSession::with_model
+///
+/// # Examples
+///
+/// ```no_run
+/// use fm_bindings::{Availability, SystemLanguageModel};
+///
+/// match SystemLanguageModel::default().availability() {
+///     Availability::Available => println!("Ready"),
+///     Availability::AppleIntelligenceNotEnabled => {
+///         println!("Turn on Apple Intelligence in System Settings")
+///     }
+///     Availability::ModelNotReady => println!("The model is still downloading"),
+///     other => println!("Unavailable: {}", other),
+/// }
+/// ```
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize)]
+pub struct SystemLanguageModel {
+    use_case: UseCase,
+    guardrails: Guardrails,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    adapter: Option<PathBuf>,
+}
+
+impl SystemLanguageModel {
+    /// Returns the default, general-purpose model
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Returns the base model specialised by the adapter at `path`
+    ///
+    /// `path` is an `.fmadapter` package trained with Apple's adapter training
+    /// toolkit for the base model installed on the device. An adapter replaces the
+    /// [use case](Self::with_use_case); guardrails still apply.
+    ///
+    /// # Errors
+    ///
+    /// * `Error::AdapterNotFound` - If nothing exists at `path`
+    /// * `Error::InvalidAdapter` - If `path` is not a valid adapter package
+    /// * `Error::IncompatibleAdapter` - If the adapter was trained for another
+    ///   version of the base model
+    /// * `Error::InvalidInput` - If `path` is not valid UTF-8 or contains a null byte
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// use fm_bindings::{LanguageModelSession, SystemLanguageModel};
+    ///
+    /// # fn main() -> fm_bindings::Result<()> {
+    /// let model = SystemLanguageModel::with_adapter("adapters/support.fmadapter")?;
+    /// let session = LanguageModelSession::with_model(model)?;
+    /// let response = session.response("How do I reset my router?")?;
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn with_adapter(path: impl AsRef<Path>) -> Result<Self> {
+        let path = path.as_ref();
+        validate_adapter(path)?;
+        Ok(Self {
+            adapter: Some(path.to_path_buf()),
+            ..Self::default()
+        })
+    }
+
+    /// Returns the path of the model's adapter, if it has one
+    pub fn adapter(&self) -> Option<&Path> {
+        self.adapter.as_deref()
+    }
+
+    /// Selects the configuration of the model specialised for `use_case`
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// use fm_bindings::{LanguageModelSession, SystemLanguageModel, UseCase};
+    ///
+    /// # fn main() -> fm_bindings::Result<()> {
+    /// let model = SystemLanguageModel::new().with_use_case(UseCase::ContentTagging);
+    /// let session = LanguageModelSession::with_model(model)?;
+    /// let tags = session.response("Tag this review: the battery lasts for days.")?;
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn with_use_case(mut self, use_case: UseCase) -> Self {
+        self.use_case = use_case;
+        self
+    }
+
+    /// Returns the use case the model is configured for
+    pub fn use_case(&self) -> UseCase {
+        self.use_case
+    }
+
+    /// Selects the guardrails applied to prompts and responses
+    ///
+    /// A request stopped by the guardrails fails with `Error::GuardrailViolation`.
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// use fm_bindings::{Guardrails, LanguageModelSession, SystemLanguageModel};
+    ///
+    /// # fn main() -> fm_bindings::Result<()> {
+    /// let model = SystemLanguageModel::new()
+    ///     .with_guardrails(Guardrails::PermissiveContentTransformations);
+    /// let session = LanguageModelSession::builder()
+    ///     .model(model)
+    ///     .instructions("Summarise the support ticket in one sentence.")
+    ///     .build()?;
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn with_guardrails(mut self, guardrails: Guardrails) -> Self {
+        self.guardrails = guardrails;
+        self
+    }
+
+    /// Returns the guardrails the model is configured with
+    pub fn guardrails(&self) -> Guardrails {
+        self.guardrails
+    }
+
+    /// Returns the model's current availability
+    ///
+    /// On platforms without the FoundationModels framework this is always
+    /// [`Availability::DeviceNotEligible`].
+    pub fn availability(&self) -> Availability {
+        let c_model = match self.to_c_json() {
+            Ok(c_model) => c_model,
+            Err(error) => return Availability::Other(error.to_string()),
+        };
+
+        let mut availability = Availability::Other("No availability reported".into());
+        unsafe {
+            ffi::fm_model_availability(
+                c_model.as_ref().map_or(std::ptr::null(), |c| c.as_ptr()),
+                &mut availability as *mut Availability as *mut c_void,
+                availability_callback,
+            );
+        }
+        availability
+    }
+
+    /// Returns true if the model can currently serve requests
+    pub fn is_available(&self) -> bool {
+        self.availability().is_available()
+    }
+
+    /// Returns the size of the model's context window, in tokens
+    ///
+    /// Instructions, transcript, prompt and response must fit in it together. Where
+    /// the OS cannot report the size (before macOS 26.4 / iOS 26.4, and on other
+    /// platforms) this is [`tokens::DEFAULT_CONTEXT_SIZE`].
+    pub fn context_size(&self) -> usize {
+        let Ok(c_model) = self.to_c_json() else {
+            return tokens::DEFAULT_CONTEXT_SIZE;
+        };
+        let size = unsafe {
+            ffi::fm_model_context_size(c_model.as_ref().map_or(std::ptr::null(), |c| c.as_ptr()))
+        };
+        usize::try_from(size).unwrap_or(tokens::DEFAULT_CONTEXT_SIZE)
+    }
+
+    /// Counts the tokens of a text or a transcript
+    ///
+    /// The model's tokenizer is used from macOS 26.4 / iOS 26.4; elsewhere, or if it
+    /// fails, the count is the heuristic estimate of the [`tokens`] module.
+    ///
+    /// # Errors
+    ///
+    /// * `Error::InvalidInput` - If the text or transcript contains a null byte
+    /// * `Error::InternalError` - If the transcript cannot be serialized
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// use fm_bindings::SystemLanguageModel;
+    ///
+    /// # fn main() -> fm_bindings::Result<()> {
+    /// let model = SystemLanguageModel::new();
+    /// let prompt = "Summarize this article";
+    /// if model.token_count(prompt)? > model.context_size() / 2 {
+    ///     println!("The prompt leaves little room for the response");
+    /// }
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn token_count<'a>(&self, input: impl Into<TokenInput<'a>>) -> Result<usize> {
+        let input = input.into();
+        let c_model = self.to_c_json()?;
+        let (c_text, c_transcript) = match input {
+            TokenInput::Text(text) => {
+                let c_text = CString::new(text)
+                    .map_err(|_| Error::InvalidInput("Text contains null byte".into()))?;
+                (Some(c_text), None)
+            }
+            TokenInput::Transcript(transcript) => {
+                let c_transcript = CString::new(transcript.to_json()?)
+                    .map_err(|_| Error::InvalidInput("Transcript contains null byte".into()))?;
+                (None, Some(c_transcript))
+            }
+        };
+
+        let count = unsafe {
+            ffi::fm_model_token_count(
+                c_model.as_ref().map_or(std::ptr::null(), |c| c.as_ptr()),
+                c_text.as_ref().map_or(std::ptr::null(), |c| c.as_ptr()),
+                c_transcript
+                    .as_ref()
+                    .map_or(std::ptr::null(), |c| c.as_ptr()),
+            )
+        };
+        Ok(usize::try_from(count).unwrap_or_else(|_| input.estimate()))
+    }
+
+    /// Checks the model's adapter again, since it may have changed since it was loaded
+    pub(crate) fn validate_adapter(&self) -> Result<()> {
+        self.adapter.as_deref().map_or(Ok(()), validate_adapter)
+    }
+
+    /// Serializes the configuration for the FFI, or returns `None` for the default model
+    ///
+    /// The default model is passed as null so the bridge uses `SystemLanguageModel.default`.
+    pub(crate) fn to_c_json(&self) -> Result<Option<CString>> {
+        if *self == Self::default() {
+            return Ok(None);
+        }
+        let json = serde_json::to_string(self)
+            .map_err(|e| Error::InternalError(format!("Cannot serialize model: {}", e)))?;
+        CString::new(json)
+            .map(Some)
+            .map_err(|_| Error::InvalidInput("Model configuration contains null byte".into()))
+    }
+}
+
+/// What the system model is specialised for
+///
+/// See Apple's [`SystemLanguageModel.UseCase`](https://developer.apple.com/documentation/foundationmodels/systemlanguagemodel/usecase).
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+#[non_exhaustive]
+pub enum UseCase {
+    /// General-purpose text generation
+    #[default]
+    General,
+
+    /// Extracting tags, topics, entities and similar labels from text
+    ContentTagging,
+}
+
+/// Whether the system model can serve requests, and if not, why
+///
+/// Carried by [`Error::ModelNotAvailable`] so that callers can show the right
+/// guidance when a session cannot be created.
+///
+/// [`Error::ModelNotAvailable`]: crate::Error::ModelNotAvailable
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum Availability {
+    /// The model is ready
+    Available,
+
+    /// The device does not support Apple Intelligence
+    DeviceNotEligible,
+
+    /// Apple Intelligence is supported but turned off in System Settings
+    AppleIntelligenceNotEnabled,
+
+    /// The model assets are still downloading or being prepared
+    ModelNotReady,
+
+    /// Any other reason, with its description
+    Other(String),
+}
+
+impl Availability {
+    /// Returns true for [`Availability::Available`]
+    pub fn is_available(&self) -> bool {
+        matches!(self, Availability::Available)
+    }
+}
+
+impl fmt::Display for Availability {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Availability::Available => write!(f, "the model is available"),
+            Availability::DeviceNotEligible => {
+                write!(f, "this device does not support Apple Intelligence")
+            }
+            Availability::AppleIntelligenceNotEnabled => {
+                write!(
+                    f,
+                    "Apple Intelligence is not enabled. Enable it in System Settings."
+                )
+            }
+            Availability::ModelNotReady => {
+                write!(f, "the model is still downloading. Try again later.")
+            }
+            Availability::Other(reason) => write!(f, "{}", reason),
+        }
+    }
+}
+
+/// Checks that `path` is an adapter the system model can load
+fn validate_adapter(path: &Path) -> Result<()> {
+    if !path.exists() {
+        return Err(Error::AdapterNotFound(path.to_path_buf()));
+    }
+    let c_path = path
+        .to_str()
+        .ok_or_else(|| Error::InvalidInput("Adapter path is not valid UTF-8".into()))
+        .and_then(|path| {
+            CString::new(path)
+                .map_err(|_| Error::InvalidInput("Adapter path contains null byte".into()))
+        })?;
+
+    let mut error = None;
+    let valid = unsafe {
+        ffi::fm_adapter_validate(
+            c_path.as_ptr(),
+            &mut error as *mut Option<Error> as *mut c_void,
+            adapter_error_callback,
+        )
+    };
+    match error {
+        Some(error) => Err(error),
+        None if valid => Ok(()),
+        None => Err(Error::InvalidAdapter(format!(
+            "Cannot load adapter {}",
+            path.display()
+        ))),
+    }
+}
+
+/// Records the error reported by `fm_adapter_validate`
+///
+/// `user_data` points to the `Option<Error>` to fill in.
+extern "C" fn adapter_error_callback(kind: i32, message: *const c_char, user_data: *mut c_void) {
+    if user_data.is_null() {
+        return;
+    }
+
+    let message = if message.is_null() {
+        String::new()
+    } else {
+        unsafe { CStr::from_ptr(message) }
+            .to_string_lossy()
+            .into_owned()
+    };
+    let error = match kind {
+        ffi::ADAPTER_ERROR_INCOMPATIBLE => Error::IncompatibleAdapter(message),
+        ffi::ADAPTER_ERROR_INVALID_ASSET
+        | ffi::ADAPTER_ERROR_INVALID_NAME
+        | ffi::ADAPTER_ERROR_OTHER => Error::InvalidAdapter(message),
+        _ => Error::InvalidAdapter(format!("Unknown adapter error {}: {}", kind, message)),
+    };
+    unsafe {
+        *(user_data as *mut Option<Error>) = Some(error);
+    }
+}
+
+/// Records the availability reported by `fm_model_availability`
+///
+/// `user_data` points to the `Availability` to overwrite.
+extern "C" fn availability_callback(status: i32, detail: *const c_char, user_data: *mut c_void) {
+    if user_data.is_null() {
+        return;
+    }
+
+    let availability = match status {
+        ffi::AVAILABILITY_AVAILABLE => Availability::Available,
+        ffi::AVAILABILITY_DEVICE_NOT_ELIGIBLE => Availability::DeviceNotEligible,
+        ffi::AVAILABILITY_APPLE_INTELLIGENCE_NOT_ENABLED => {
+            Availability::AppleIntelligenceNotEnabled
+        }
+        ffi::AVAILABILITY_MODEL_NOT_READY => Availability::ModelNotReady,
+        ffi::AVAILABILITY_OTHER if !detail.is_null() => Availability::Other(
+            unsafe { CStr::from_ptr(detail) }
+                .to_string_lossy()
+                .into_owned(),
+        ),
+        _ => Availability::Other(format!("Unknown availability status {}", status)),
+    };
+    unsafe {
+        *(user_data as *mut Availability) = availability;
+    }
+}
+
+/// Guardrails applied by the system model to prompts and responses
+///
+/// See Apple's [`SystemLanguageModel.Guardrails`](https://developer.apple.com/documentation/foundationmodels/systemlanguagemodel/guardrails).
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+#[non_exhaustive]
+pub enum Guardrails {
+    /// The framework's default guardrails
+    #[default]
+    Default,
+
+    /// Guardrails that allow transforming user-supplied text on sensitive topics,
+    /// such as summarising or rewriting it, while still blocking unsafe generation
+    PermissiveContentTransformations,
+}
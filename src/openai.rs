@@ -0,0 +1,680 @@
+// src/openai.rs
+// Backend for OpenAI-compatible chat completion servers (enabled by the `openai` feature)
+
+//! Backend for OpenAI-compatible `/v1/chat/completions` servers
+//!
+//! [`OpenAiBackend`] serves a [`LanguageModelSession`] from any server speaking the
+//! OpenAI chat completions protocol, such as a local llama.cpp server or vLLM.
+//! Streaming uses server-sent events, so the session API behaves the same as with
+//! the system model, which lets the same code paths run on non-Apple hosts.
+//!
+//! ```no_run
+//! use fm_bindings::openai::OpenAiBackend;
+//! use fm_bindings::LanguageModelSession;
+//!
+//! # fn main() -> fm_bindings::Result<()> {
+//! let backend = OpenAiBackend::new("http://localhost:8080/v1", "qwen2.5-3b-instruct");
+//! let session = LanguageModelSession::with_backend(backend)?;
+//! session.stream_response("Tell me a story", |chunk| print!("{}", chunk))?;
+//! # Ok(())
+//! # }
+//! ```
+//!
+//! # Generation options
+//!
+//! `temperature` and `maximum_response_tokens` are sent as `temperature` and
+//! `max_tokens`. Greedy sampling is sent as a temperature of 0, top-k as `top_k`
+//! (a llama.cpp and vLLM extension) and nucleus sampling as `top_p`; sampling seeds
+//! are sent as `seed`.
+//!
+//! # Guided generation
+//!
+//! Schemas of guided requests are sent as a `json_schema` `response_format`, which
+//! llama.cpp and vLLM enforce with constrained decoding.
+//!
+//! # Tool calling
+//!
+//! The session's tools are sent as `tools` of type `function`, with their argument
+//! schemas as `parameters`. When the model answers with `tool_calls`, the backend
+//! runs the tools and sends their outputs back as `tool` messages, for up to
+//! eight rounds per prompt. A tool that fails has its error sent back as its
+//! output, so the model can recover; the transcript records it the same way. A
+//! call to an unknown tool or with arguments that are not JSON is an
+//! `Error::GenerationError`.
+//!
+//! # Error mapping
+//!
+//! * Connection failures and `503 Service Unavailable` - `Error::ModelNotAvailable`,
+//!   with `Availability::Other` describing the failure
+//! * `400 Bad Request` - `Error::InvalidInput`, or `Error::ContextWindowExceeded` for
+//!   OpenAI's `context_length_exceeded` code, llama.cpp's `exceed_context_size_error`
+//!   type and vLLM's "maximum context length" message; stream error events are
+//!   mapped the same way
+//! * `429 Too Many Requests` - `Error::RateLimited`
+//! * Completions stopped with the `content_filter` finish reason - `Error::GuardrailViolation`
+//! * Messages carrying a `refusal` - `Error::Refusal`, with the refusal as its explanation
+//! * Other HTTP errors, error events and malformed streams - `Error::GenerationError`
+//!
+//! [`LanguageModelSession`]: crate::LanguageModelSession
+
+use super::backend::{GenerationRequest, ModelBackend};
+use super::error::{Error, Result};
+use super::model::Availability;
+use super::options::{GenerationOptions, SamplingMode};
+use super::refusal::Refusal;
+use super::transcript::TranscriptEntry;
+use serde::Deserialize;
+use serde_json::{Value, json};
+use std::io::{BufRead, BufReader};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::time::Duration;
+
+/// Most rounds of tool calls the model may make before answering a prompt
+const MAX_TOOL_ROUNDS: usize = 8;
+
+/// Longest wait for a connection to the server, for every request
+const CONNECT_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Longest wait for the availability probe, which runs while a session is built
+const AVAILABILITY_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// A backend that talks to an OpenAI-compatible chat completions endpoint
+pub struct OpenAiBackend {
+    agent: ureq::Agent,
+    base_url: String,
+    model: String,
+    api_key: Option<String>,
+    cancelled: AtomicBool,
+}
+
+impl OpenAiBackend {
+    /// Creates a backend for `model` served under `base_url`
+    ///
+    /// `base_url` is the API root including the version segment, e.g.
+    /// `http://localhost:8080/v1`; requests go to `{base_url}/chat/completions`.
+    ///
+    /// Connecting to the server times out after 10 seconds, and so does the
+    /// availability check against `{base_url}/models`. Completions have no overall
+    /// limit, since a long response may legitimately take minutes; set one with
+    /// [`with_timeout`](Self::with_timeout).
+    pub fn new(base_url: impl Into<String>, model: impl Into<String>) -> Self {
+        Self {
+            agent: agent(None),
+            base_url: base_url.into().trim_end_matches('/').to_string(),
+            model: model.into(),
+            api_key: None,
+            cancelled: AtomicBool::new(false),
+        }
+    }
+
+    /// Sends `key` as a bearer token with every request
+    pub fn with_api_key(mut self, key: impl Into<String>) -> Self {
+        self.api_key = Some(key.into());
+        self
+    }
+
+    /// Fails requests that take longer than `timeout` in total
+    ///
+    /// The availability check keeps its own 10 second limit if that is shorter.
+    pub fn with_timeout(mut self, timeout: Duration) -> Self {
+        self.agent = agent(Some(timeout));
+        self
+    }
+
+    /// Posts a chat completion for `request`
+    ///
+    /// `turns` are the tool calls and outputs exchanged so far for this prompt.
+    fn post_completion(
+        &self,
+        request: &GenerationRequest<'_>,
+        turns: &[Value],
+        stream: bool,
+    ) -> Result<ureq::http::Response<ureq::Body>> {
+        // Instructions travel as a system message, separate from the user's prompt
+        let mut messages = Vec::new();
+        if let Some(instructions) = request.instructions {
+            messages.push(json!({ "role": "system", "content": instructions }));
+        }
+        // The server is stateless, so the transcript is replayed with every request
+        for entry in request.transcript {
+            messages.push(match entry {
+                // Already sent as the system message above
+                TranscriptEntry::Instructions { .. } => continue,
+                TranscriptEntry::Prompt { text } => json!({ "role": "user", "content": text }),
+                TranscriptEntry::Response { text } => {
+                    json!({ "role": "assistant", "content": text })
+                }
+                TranscriptEntry::ToolCall {
+                    id,
+                    name,
+                    arguments,
+                } => json!({
+                    "role": "assistant",
+                    "tool_calls": [{
+                        "id": id,
+                        "type": "function",
+                        "function": { "name": name, "arguments": arguments.to_string() },
+                    }],
+                }),
+                TranscriptEntry::ToolOutput { id, output, .. } => {
+                    json!({ "role": "tool", "tool_call_id": id, "content": output })
+                }
+            });
+        }
+        messages.push(json!({ "role": "user", "content": request.prompt }));
+        messages.extend_from_slice(turns);
+
+        let mut body = json!({
+            "model": self.model,
+            "messages": messages,
+            "stream": stream,
+        });
+        apply_options(&mut body, request.options);
+        if !request.tools.is_empty() {
+            let tools: Vec<Value> = request
+                .tools
+                .iter()
+                .map(|tool| {
+                    json!({
+                        "type": "function",
+                        "function": {
+                            "name": tool.name(),
+                            "description": tool.description(),
+                            "parameters": tool.arguments_schema().to_json_schema(),
+                        },
+                    })
+                })
+                .collect();
+            body["tools"] = json!(tools);
+        }
+        if let Some(schema) = request.schema {
+            body["response_format"] = json!({
+                "type": "json_schema",
+                "json_schema": {
+                    "name": schema.name().unwrap_or("response"),
+                    "schema": schema.to_json_schema(),
+                    "strict": true,
+                },
+            });
+        }
+
+        let mut request = self
+            .agent
+            .post(format!("{}/chat/completions", self.base_url))
+            .header("Content-Type", "application/json");
+        if stream {
+            request = request.header("Accept", "text/event-stream");
+        }
+        if let Some(key) = &self.api_key {
+            request = request.header("Authorization", format!("Bearer {}", key));
+        }
+
+        let response = request.send(body.to_string()).map_err(transport_error)?;
+        check_status(response)
+    }
+
+    /// Streams one completion, returning the tool calls the model made, if any
+    ///
+    /// A cancelled stream, by `cancel_stream` or the request's handle, returns no
+    /// tool calls, so no further round is started.
+    fn stream_round(
+        &self,
+        request: &GenerationRequest<'_>,
+        turns: &[Value],
+        on_chunk: &mut dyn FnMut(&str),
+    ) -> Result<Vec<ToolCall>> {
+        let response = self.post_completion(request, turns, true)?;
+        let mut events = BufReader::new(response.into_body().into_reader());
+        let mut calls = Vec::new();
+        let mut refusal = String::new();
+        let mut finished = false;
+
+        while let Some(data) = next_event(&mut events)? {
+            // Dropping the response closes the connection, which stops generation server-side
+            if self.cancelled.load(Ordering::SeqCst) || request.is_cancelled() {
+                return Ok(Vec::new());
+            }
+            if data == "[DONE]" {
+                return check_refusal(refusal).map(|()| calls);
+            }
+
+            let chunk: CompletionChunk = serde_json::from_str(&data)
+                .map_err(|e| Error::GenerationError(format!("Malformed stream event: {}", e)))?;
+            if let Some(error) = chunk.error {
+                return Err(if error.is_context_overflow() {
+                    Error::ContextWindowExceeded(error.message)
+                } else {
+                    Error::GenerationError(error.message)
+                });
+            }
+
+            for choice in chunk.choices {
+                if let Some(delta) = choice.delta {
+                    if let Some(content) = delta.content
+                        && !content.is_empty()
+                    {
+                        on_chunk(&content);
+                    }
+                    if let Some(piece) = delta.refusal {
+                        refusal.push_str(&piece);
+                    }
+                    // Tool calls arrive in pieces, keyed by their index
+                    for call in delta.tool_calls {
+                        accumulate(&mut calls, call);
+                    }
+                }
+                check_finish_reason(choice.finish_reason.as_deref())?;
+                finished |= choice.finish_reason.is_some();
+            }
+        }
+
+        // Some servers close the stream after the final chunk without sending [DONE]
+        if self.cancelled.load(Ordering::SeqCst) || request.is_cancelled() {
+            Ok(Vec::new())
+        } else if finished {
+            check_refusal(refusal).map(|()| calls)
+        } else {
+            Err(Error::GenerationError(
+                "Stream closed before completion".into(),
+            ))
+        }
+    }
+}
+
+impl ModelBackend for OpenAiBackend {
+    fn is_available(&self) -> bool {
+        self.availability().is_available()
+    }
+
+    fn availability(&self) -> Availability {
+        // A server that accepts the connection but never answers must not hang the
+        // session builder
+        let timeout = match self.agent.config().timeouts().global {
+            Some(timeout) => timeout.min(AVAILABILITY_TIMEOUT),
+            None => AVAILABILITY_TIMEOUT,
+        };
+        let mut request = self
+            .agent
+            .get(format!("{}/models", self.base_url))
+            .config()
+            .timeout_global(Some(timeout))
+            .build();
+        if let Some(key) = &self.api_key {
+            request = request.header("Authorization", format!("Bearer {}", key));
+        }
+
+        match request
+            .call()
+            .map_err(transport_error)
+            .and_then(check_status)
+        {
+            Ok(_) => Availability::Available,
+            Err(Error::ModelNotAvailable(reason)) => reason,
+            Err(error) => Availability::Other(error.to_string()),
+        }
+    }
+
+    fn response(&self, request: &GenerationRequest<'_>) -> Result<String> {
+        // A blocking completion cannot be interrupted, so cancellable requests are streamed
+        if request.cancel_handle.is_some() {
+            let mut text = String::new();
+            self.stream_response(request, &mut |chunk| text.push_str(chunk))?;
+            return Ok(text);
+        }
+
+        let mut turns = Vec::new();
+        for _ in 0..=MAX_TOOL_ROUNDS {
+            let mut response = self.post_completion(request, &turns, false)?;
+            let body = response
+                .body_mut()
+                .read_to_string()
+                .map_err(|e| Error::GenerationError(format!("Failed to read response: {}", e)))?;
+
+            let completion: Completion = serde_json::from_str(&body)
+                .map_err(|e| Error::GenerationError(format!("Malformed completion: {}", e)))?;
+
+            let choice =
+                completion.choices.into_iter().next().ok_or_else(|| {
+                    Error::GenerationError("Completion contains no choices".into())
+                })?;
+            check_finish_reason(choice.finish_reason.as_deref())?;
+            let message = choice
+                .message
+                .ok_or_else(|| Error::GenerationError("Completion contains no message".into()))?;
+            check_refusal(message.refusal.unwrap_or_default())?;
+
+            // A whole message lists complete calls, without stream indices
+            let calls: Vec<ToolCall> = message.tool_calls.into_iter().map(ToolCall::from).collect();
+            if calls.is_empty() {
+                return Ok(message.content.unwrap_or_default());
+            }
+            turns.extend(call_tools(request, calls)?);
+        }
+        Err(too_many_tool_rounds())
+    }
+
+    fn stream_response(
+        &self,
+        request: &GenerationRequest<'_>,
+        on_chunk: &mut dyn FnMut(&str),
+    ) -> Result<()> {
+        self.cancelled.store(false, Ordering::SeqCst);
+        let mut turns = Vec::new();
+        let mut partial = String::new();
+        for _ in 0..=MAX_TOOL_ROUNDS {
+            let calls = self.stream_round(request, &turns, &mut |chunk| {
+                partial.push_str(chunk);
+                on_chunk(chunk);
+            })?;
+            if request.is_cancelled() {
+                return Err(Error::Cancelled { partial });
+            }
+            if calls.is_empty() {
+                return Ok(());
+            }
+            turns.extend(call_tools(request, calls)?);
+        }
+        Err(too_many_tool_rounds())
+    }
+
+    fn cancel_stream(&self) {
+        self.cancelled.store(true, Ordering::SeqCst);
+    }
+}
+
+/// A tool call of the model, assembled from one or more deltas
+#[derive(Default)]
+struct ToolCall {
+    id: String,
+    name: String,
+    arguments: String,
+}
+
+impl From<ToolCallDelta> for ToolCall {
+    fn from(call: ToolCallDelta) -> Self {
+        Self {
+            id: call.id.unwrap_or_default(),
+            name: call.function.name.unwrap_or_default(),
+            arguments: call.function.arguments.unwrap_or_default(),
+        }
+    }
+}
+
+/// Adds a tool call delta of a stream to the calls it belongs to
+fn accumulate(calls: &mut Vec<ToolCall>, delta: ToolCallDelta) {
+    if calls.len() <= delta.index {
+        calls.resize_with(delta.index + 1, ToolCall::default);
+    }
+    let call = &mut calls[delta.index];
+    if let Some(id) = delta.id {
+        call.id = id;
+    }
+    if let Some(name) = delta.function.name {
+        call.name.push_str(&name);
+    }
+    if let Some(arguments) = delta.function.arguments {
+        call.arguments.push_str(&arguments);
+    }
+}
+
+/// Runs the tools the model called and returns the messages to send back
+///
+/// The messages are the assistant's tool calls followed by one `tool` message per output.
+fn call_tools(request: &GenerationRequest<'_>, mut calls: Vec<ToolCall>) -> Result<Vec<Value>> {
+    for (index, call) in calls.iter_mut().enumerate() {
+        if call.id.is_empty() {
+            call.id = format!("call_{}", index);
+        }
+    }
+
+    let mut messages = vec![json!({
+        "role": "assistant",
+        "tool_calls": calls
+            .iter()
+            .map(|call| json!({
+                "id": call.id,
+                "type": "function",
+                "function": { "name": call.name, "arguments": call.arguments },
+            }))
+            .collect::<Vec<_>>(),
+    })];
+    for call in calls {
+        let tool = request.tool(&call.name).ok_or_else(|| {
+            Error::GenerationError(format!("Model called unknown tool {:?}", call.name))
+        })?;
+        // Tools without parameters may be called with no arguments at all
+        let arguments = if call.arguments.trim().is_empty() {
+            json!({})
+        } else {
+            serde_json::from_str(&call.arguments).map_err(|e| {
+                Error::GenerationError(format!("Invalid arguments for tool {:?}: {}", call.name, e))
+            })?
+        };
+        // The model is told about a failed tool, and the journal keeps the failure
+        let output = tool
+            .call(arguments)
+            .unwrap_or_else(|error| error.to_string());
+        messages.push(json!({ "role": "tool", "tool_call_id": call.id, "content": output }));
+    }
+    Ok(messages)
+}
+
+fn too_many_tool_rounds() -> Error {
+    Error::GenerationError(format!(
+        "Model was still calling tools after {} rounds",
+        MAX_TOOL_ROUNDS
+    ))
+}
+
+fn agent(timeout: Option<Duration>) -> ureq::Agent {
+    ureq::Agent::config_builder()
+        .http_status_as_error(false)
+        .timeout_connect(Some(CONNECT_TIMEOUT))
+        .timeout_global(timeout)
+        .build()
+        .into()
+}
+
+/// Adds the generation options to a chat completion request body
+fn apply_options(body: &mut serde_json::Value, options: &GenerationOptions) {
+    if let Some(temperature) = options.temperature {
+        body["temperature"] = json!(temperature);
+    }
+    if let Some(tokens) = options.maximum_response_tokens {
+        body["max_tokens"] = json!(tokens);
+    }
+    match options.sampling {
+        Some(SamplingMode::Greedy) => body["temperature"] = json!(0.0),
+        Some(SamplingMode::TopK { k, .. }) => body["top_k"] = json!(k),
+        Some(SamplingMode::Nucleus {
+            probability_threshold,
+            ..
+        }) => body["top_p"] = json!(probability_threshold),
+        None => {}
+    }
+    if let Some(seed) = options.sampling.and_then(|sampling| sampling.seed()) {
+        body["seed"] = json!(seed);
+    }
+}
+
+/// Maps a failure to reach the server onto an `Error`
+fn transport_error(error: ureq::Error) -> Error {
+    match error {
+        ureq::Error::ConnectionFailed | ureq::Error::HostNotFound => Error::ModelNotAvailable(
+            Availability::Other(format!("Cannot reach the server: {}", error)),
+        ),
+        ureq::Error::Io(e) if e.kind() == std::io::ErrorKind::ConnectionRefused => {
+            Error::ModelNotAvailable(Availability::Other(format!(
+                "Cannot reach the server: {}",
+                e
+            )))
+        }
+        other => Error::GenerationError(format!("Request failed: {}", other)),
+    }
+}
+
+/// Maps a non-success HTTP status onto an `Error`, using the server's message if any
+fn check_status(
+    mut response: ureq::http::Response<ureq::Body>,
+) -> Result<ureq::http::Response<ureq::Body>> {
+    let status = response.status();
+    if status.is_success() {
+        return Ok(response);
+    }
+
+    let body = response.body_mut().read_to_string().unwrap_or_default();
+    let (message, overflow) = match serde_json::from_str::<ErrorBody>(&body) {
+        Ok(ErrorBody::Nested { error } | ErrorBody::Flat(error)) => {
+            let overflow = error.is_context_overflow();
+            (error.message, overflow)
+        }
+        Err(_) => (body, false),
+    };
+    let message = format!("HTTP {}: {}", status.as_u16(), message.trim());
+
+    Err(match (status.as_u16(), overflow) {
+        (400, true) => Error::ContextWindowExceeded(message),
+        (429, _) => Error::RateLimited(message),
+        (503, _) => Error::ModelNotAvailable(Availability::Other(message)),
+        (400, _) => Error::InvalidInput(message),
+        _ => Error::GenerationError(message),
+    })
+}
+
+/// Fails a completion that the server's content filter stopped
+fn check_finish_reason(finish_reason: Option<&str>) -> Result<()> {
+    if finish_reason == Some("content_filter") {
+        return Err(Error::GuardrailViolation(
+            "The server's content filter stopped the response".into(),
+        ));
+    }
+    Ok(())
+}
+
+/// Fails a completion in which the model explained why it declined the request
+fn check_refusal(explanation: String) -> Result<()> {
+    if explanation.is_empty() {
+        return Ok(());
+    }
+    Err(Error::Refusal(
+        Refusal::new("The model declined the request").with_explanation(explanation),
+    ))
+}
+
+/// Reads the next server-sent event and returns its data
+///
+/// Multi-line data fields are joined with newlines; comments and other fields
+/// are ignored. Returns `None` once the stream is exhausted.
+fn next_event(reader: &mut impl BufRead) -> Result<Option<String>> {
+    let mut data: Option<String> = None;
+    let mut line = String::new();
+
+    loop {
+        line.clear();
+        let read = reader
+            .read_line(&mut line)
+            .map_err(|e| Error::GenerationError(format!("Failed to read stream: {}", e)))?;
+        if read == 0 {
+            return Ok(data);
+        }
+
+        let line = line.trim_end_matches(['\r', '\n']);
+        if line.is_empty() {
+            if data.is_some() {
+                return Ok(data);
+            }
+            continue;
+        }
+
+        if let Some(value) = line.strip_prefix("data:") {
+            let value = value.strip_prefix(' ').unwrap_or(value);
+            match &mut data {
+                Some(data) => {
+                    data.push('\n');
+                    data.push_str(value);
+                }
+                None => data = Some(value.to_string()),
+            }
+        }
+    }
+}
+
+// Wire Types
+
+#[derive(Deserialize)]
+struct Completion {
+    choices: Vec<Choice>,
+}
+
+#[derive(Deserialize)]
+struct Choice {
+    message: Option<Message>,
+    finish_reason: Option<String>,
+}
+
+#[derive(Deserialize)]
+struct CompletionChunk {
+    #[serde(default)]
+    choices: Vec<ChunkChoice>,
+    error: Option<ErrorDetail>,
+}
+
+#[derive(Deserialize)]
+struct ChunkChoice {
+    delta: Option<Message>,
+    finish_reason: Option<String>,
+}
+
+#[derive(Deserialize)]
+struct Message {
+    content: Option<String>,
+    refusal: Option<String>,
+    #[serde(default)]
+    tool_calls: Vec<ToolCallDelta>,
+}
+
+/// A tool call, or the piece of one carried by a stream event
+///
+/// `index` only appears in stream events, where it tells which call a piece belongs to.
+#[derive(Deserialize)]
+struct ToolCallDelta {
+    #[serde(default)]
+    index: usize,
+    id: Option<String>,
+    #[serde(default)]
+    function: FunctionDelta,
+}
+
+#[derive(Deserialize, Default)]
+struct FunctionDelta {
+    name: Option<String>,
+    arguments: Option<String>,
+}
+
+/// An error response: OpenAI and llama.cpp nest the details, older vLLM does not
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum ErrorBody {
+    Nested { error: ErrorDetail },
+    Flat(ErrorDetail),
+}
+
+/// The details of an error; `code` is a string for OpenAI and an HTTP status for
+/// llama.cpp and vLLM, which name the error in `type` instead
+#[derive(Deserialize)]
+struct ErrorDetail {
+    message: String,
+    #[serde(default)]
+    code: Option<Value>,
+    #[serde(default, rename = "type")]
+    kind: Option<String>,
+}
+
+impl ErrorDetail {
+    /// Returns true if the error says the request does not fit the context window
+    fn is_context_overflow(&self) -> bool {
+        self.code.as_ref().and_then(Value::as_str) == Some("context_length_exceeded")
+            || self.kind.as_deref() == Some("exceed_context_size_error")
+            || self.message.contains("maximum context length")
+    }
+}
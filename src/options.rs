@@ -0,0 +1,178 @@
+// src/options.rs
+// Generation options - per-request control over sampling and response length
+
+use super::error::{Error, Result};
+use serde::{Deserialize, Serialize};
+
+/// Options that control how the model generates a response
+///
+/// Mirrors Foundation Models' `GenerationOptions`. Every option is optional; unset
+/// options leave the choice to the model. Pass options per request with
+/// [`LanguageModelSession::response_with_options`] or
+/// [`LanguageModelSession::stream_response_with_options`].
+///
+/// [`LanguageModelSession::response_with_options`]: crate::LanguageModelSession::response_with_options
+/// [`LanguageModelSession::stream_response_with_options`]: crate::LanguageModelSession::stream_response_with_options
+///
+/// # Examples
+///
+/// ```
+/// use fm_bindings::{GenerationOptions, SamplingMode};
+///
+/// // Reproducible output for regression tests
+/// let options = GenerationOptions::new()
+///     .with_sampling(SamplingMode::Greedy)
+///     .with_maximum_response_tokens(200);
+/// ```
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[non_exhaustive]
+pub struct GenerationOptions {
+    /// Scales the randomness of sampling: lower values give more predictable output
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub temperature: Option<f64>,
+
+    /// Upper bound on the number of tokens in the response
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub maximum_response_tokens: Option<u32>,
+
+    /// Strategy for picking each token
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub sampling: Option<SamplingMode>,
+}
+
+/// How the model picks each token of a response
+///
+/// Serialized with a `mode` tag, e.g. `{"mode":"top_k","k":40,"seed":7}`.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "mode", rename_all = "snake_case")]
+#[non_exhaustive]
+pub enum SamplingMode {
+    /// Always pick the most likely token, so the same input gives the same output
+    Greedy,
+
+    /// Sample among the `k` most likely tokens
+    ///
+    /// A fixed `seed` makes sampling repeatable; `None` uses a random seed.
+    TopK {
+        k: u32,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        seed: Option<u64>,
+    },
+
+    /// Sample among the most likely tokens whose cumulative probability reaches
+    /// `probability_threshold` (between 0 and 1)
+    ///
+    /// A fixed `seed` makes sampling repeatable; `None` uses a random seed.
+    Nucleus {
+        probability_threshold: f64,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        seed: Option<u64>,
+    },
+}
+
+impl GenerationOptions {
+    /// Creates options that leave every choice to the model
+    pub const fn new() -> Self {
+        Self {
+            temperature: None,
+            maximum_response_tokens: None,
+            sampling: None,
+        }
+    }
+
+    /// Sets the sampling temperature (0 or more)
+    pub fn with_temperature(mut self, temperature: f64) -> Self {
+        self.temperature = Some(temperature);
+        self
+    }
+
+    /// Limits the response to `tokens` tokens
+    pub fn with_maximum_response_tokens(mut self, tokens: u32) -> Self {
+        self.maximum_response_tokens = Some(tokens);
+        self
+    }
+
+    /// Sets the sampling mode
+    pub fn with_sampling(mut self, sampling: SamplingMode) -> Self {
+        self.sampling = Some(sampling);
+        self
+    }
+
+    /// Returns true if no option is set
+    pub fn is_default(&self) -> bool {
+        *self == Self::default()
+    }
+
+    /// Rejects values no model accepts
+    pub(crate) fn validate(&self) -> Result<()> {
+        if let Some(temperature) = self.temperature
+            && !(temperature.is_finite() && temperature >= 0.0)
+        {
+            return Err(Error::InvalidInput(format!(
+                "Temperature must be a finite number of at least 0, got {}",
+                temperature
+            )));
+        }
+        if self.maximum_response_tokens == Some(0) {
+            return Err(Error::InvalidInput(
+                "Maximum response tokens must be at least 1".into(),
+            ));
+        }
+        match self.sampling {
+            Some(SamplingMode::TopK { k: 0, .. }) => Err(Error::InvalidInput(
+                "Top-k sampling needs k of at least 1".into(),
+            )),
+            Some(SamplingMode::Nucleus {
+                probability_threshold,
+                ..
+            }) if !(probability_threshold > 0.0 && probability_threshold <= 1.0) => {
+                Err(Error::InvalidInput(format!(
+                    "Nucleus probability threshold must be in (0, 1], got {}",
+                    probability_threshold
+                )))
+            }
+            _ => Ok(()),
+        }
+    }
+}
+
+impl SamplingMode {
+    /// Top-k sampling with a random seed
+    pub fn top_k(k: u32) -> Self {
+        Self::TopK { k, seed: None }
+    }
+
+    /// Nucleus (top-p) sampling with a random seed
+    pub fn nucleus(probability_threshold: f64) -> Self {
+        Self::Nucleus {
+            probability_threshold,
+            seed: None,
+        }
+    }
+
+    /// Makes a random sampling mode repeatable; greedy sampling has no seed
+    pub fn with_seed(self, seed: u64) -> Self {
+        match self {
+            Self::Greedy => Self::Greedy,
+            Self::TopK { k, .. } => Self::TopK {
+                k,
+                seed: Some(seed),
+            },
+            Self::Nucleus {
+                probability_threshold,
+                ..
+            } => Self::Nucleus {
+                probability_threshold,
+                seed: Some(seed),
+            },
+        }
+    }
+
+    /// Returns the seed of a random sampling mode
+    pub fn seed(&self) -> Option<u64> {
+        match self {
+            Self::Greedy => None,
+            Self::TopK { seed, .. } | Self::Nucleus { seed, .. } => *seed,
+        }
+    }
+}
@@ -0,0 +1,88 @@
+// src/partial.rs
+// Lenient parsing of JSON documents that are still being generated
+
+use serde_json::Value;
+
+/// Parses the longest readable prefix of an unfinished JSON document
+///
+/// Open strings, arrays and objects are closed; a trailing value that cannot be
+/// completed yet (a dangling key, `tru`, `1.`) is dropped. Returns `None` when
+/// nothing readable has been generated.
+pub(crate) fn parse_partial(text: &str) -> Option<Value> {
+    let mut end = text.len();
+    loop {
+        let prefix = &text[..end];
+        if let Ok(value) = serde_json::from_str(&close(prefix)) {
+            return Some(value);
+        }
+        end = cut_point(prefix)?;
+    }
+}
+
+/// Appends what `prefix` needs to be a complete document, if it has no dangling value
+fn close(prefix: &str) -> String {
+    let scan = scan(prefix);
+    let mut closed = prefix.to_string();
+    if scan.in_string {
+        if scan.escaped {
+            closed.pop();
+        }
+        closed.push('"');
+    } else {
+        closed.truncate(prefix.trim_end().len());
+    }
+    for open in scan.stack.iter().rev() {
+        closed.push(if *open == b'{' { '}' } else { ']' });
+    }
+    closed
+}
+
+/// Where to cut `prefix` to drop its last, unfinished element
+///
+/// Cuts before the last separator, or just after the last opening bracket.
+fn cut_point(prefix: &str) -> Option<usize> {
+    let scan = scan(prefix);
+    let last = scan.structural.last()?;
+    match prefix.as_bytes()[*last] {
+        b'{' | b'[' if *last + 1 < prefix.len() => Some(last + 1),
+        _ => Some(*last),
+    }
+}
+
+#[derive(Default)]
+struct Scan {
+    /// Open objects and arrays, innermost last
+    stack: Vec<u8>,
+    /// Offsets of brackets and separators outside strings
+    structural: Vec<usize>,
+    in_string: bool,
+    escaped: bool,
+}
+
+fn scan(text: &str) -> Scan {
+    let mut scan = Scan::default();
+    for (offset, byte) in text.bytes().enumerate() {
+        if scan.in_string {
+            match byte {
+                _ if scan.escaped => scan.escaped = false,
+                b'\\' => scan.escaped = true,
+                b'"' => scan.in_string = false,
+                _ => {}
+            }
+            continue;
+        }
+        match byte {
+            b'"' => scan.in_string = true,
+            b'{' | b'[' => {
+                scan.stack.push(byte);
+                scan.structural.push(offset);
+            }
+            b'}' | b']' => {
+                scan.stack.pop();
+            }
+            b',' | b':' => scan.structural.push(offset),
+            _ => {}
+        }
+    }
+    scan
+}
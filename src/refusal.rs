@@ -0,0 +1,101 @@
+// src/refusal.rs
+// Refusals - requests the model declined, with a way to ask why
+
+use super::error::{Error, Result};
+use super::system::RefusalHandle;
+use serde::{Deserialize, Serialize};
+use std::fmt;
+use std::sync::Arc;
+
+/// A request the model declined to answer, carried by [`Error::Refusal`]
+///
+/// Foundation Models can explain a refusal on demand: [`explanation`](Self::explanation)
+/// asks the system model why it declined, which is a generation request of its own.
+/// Other backends may attach the explanation up front with
+/// [`with_explanation`](Self::with_explanation).
+///
+/// Refusals serialize with their message and any explanation already attached;
+/// the link back to the system model is not kept, so a refusal replayed from a
+/// cassette can only return an attached explanation.
+///
+/// # Examples
+///
+/// ```no_run
+/// use fm_bindings::{Error, LanguageModelSession};
+///
+/// # fn main() -> fm_bindings::Result<()> {
+/// let session = LanguageModelSession::new()?;
+/// match session.response("Tell me a secret") {
+///     Ok(response) => println!("{}", response),
+///     Err(Error::Refusal(refusal)) => println!("Declined: {}", refusal.explanation()?),
+///     Err(error) => return Err(error),
+/// }
+/// # Ok(())
+/// # }
+/// ```
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Refusal {
+    message: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    explanation: Option<String>,
+    #[serde(skip)]
+    handle: Option<Arc<RefusalHandle>>,
+}
+
+impl Refusal {
+    /// Creates a refusal with the error message reported by the backend
+    pub fn new(message: impl Into<String>) -> Self {
+        Self {
+            message: message.into(),
+            explanation: None,
+            handle: None,
+        }
+    }
+
+    /// Attaches the model's explanation, for backends that report it with the refusal
+    pub fn with_explanation(mut self, explanation: impl Into<String>) -> Self {
+        self.explanation = Some(explanation.into());
+        self
+    }
+
+    /// Creates a refusal whose explanation is fetched from the Swift bridge
+    pub(crate) fn from_system(message: String, handle: RefusalHandle) -> Self {
+        Self {
+            handle: Some(Arc::new(handle)),
+            ..Self::new(message)
+        }
+    }
+
+    /// Returns the error message reported with the refusal
+    pub fn message(&self) -> &str {
+        &self.message
+    }
+
+    /// Returns the model's explanation of why it declined the request
+    ///
+    /// An attached explanation is returned as is. Otherwise the system model is
+    /// asked for one, which blocks like [`LanguageModelSession::response`](crate::LanguageModelSession::response).
+    ///
+    /// # Errors
+    ///
+    /// * `Error::GenerationError` - If no explanation is attached and the refusal
+    ///   did not come from the system model, or generating it fails
+    /// * Any other error the system model reports while explaining
+    pub fn explanation(&self) -> Result<String> {
+        if let Some(explanation) = &self.explanation {
+            return Ok(explanation.clone());
+        }
+        match &self.handle {
+            Some(handle) => handle.explanation(),
+            None => Err(Error::GenerationError(
+                "No explanation is available for this refusal".into(),
+            )),
+        }
+    }
+}
+
+impl fmt::Display for Refusal {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.message)
+    }
+}
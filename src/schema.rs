@@ -0,0 +1,613 @@
+// src/schema.rs
+// Generation schemas - constrain the model's output to a Rust type
+
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+use serde_json::{Value, json};
+use std::fmt::Debug;
+
+/// A type the model can generate directly, through guided generation
+///
+/// Implement it with `#[derive(Generable)]` (together with `serde::Deserialize`)
+/// and request a value with [`LanguageModelSession::response_as`]. The schema
+/// constrains decoding, so the model's output always has the shape of the type.
+///
+/// The derive supports structs with named fields, newtype structs and enums whose
+/// variants are units, newtypes or have named fields. Field guides refine the schema:
+///
+/// * `#[guide(description = "...")]` - explains the field, struct, enum or variant
+/// * `#[guide(range = 1..=10)]` - bounds a number (`a..=b`, `a..` or `..=b`)
+/// * `#[guide(regex = "^[A-Z]{3}$")]` - constrains a string to a pattern
+/// * `#[guide(count = 3)]` or `#[guide(count = 1..=5)]` - bounds a list's length
+///
+/// Field names are used as they are written; serde attributes that rename fields
+/// or change the enum representation are not reflected in the schema.
+///
+/// The derive also defines `{Type}Partial`, the [`Partial`](Self::Partial) type
+/// streamed by [`LanguageModelSession::stream_response_as`]: a mirror of the type
+/// whose fields are all `Option`s, filled in as the model generates them.
+///
+/// [`LanguageModelSession::response_as`]: crate::LanguageModelSession::response_as
+/// [`LanguageModelSession::stream_response_as`]: crate::LanguageModelSession::stream_response_as
+///
+/// # Examples
+///
+/// ```no_run
+/// use fm_bindings::{Generable, LanguageModelSession};
+/// use serde::Deserialize;
+///
+/// #[derive(Debug, Generable, Deserialize)]
+/// #[guide(description = "A recipe suggestion")]
+/// struct Recipe {
+///     title: String,
+///     #[guide(description = "Preparation time in minutes", range = 5..=240)]
+///     minutes: u32,
+///     #[guide(count = 3..=8)]
+///     ingredients: Vec<String>,
+///     difficulty: Difficulty,
+/// }
+///
+/// #[derive(Debug, Generable, Deserialize)]
+/// enum Difficulty {
+///     Easy,
+///     Medium,
+///     Hard,
+/// }
+///
+/// # fn main() -> fm_bindings::Result<()> {
+/// let session = LanguageModelSession::new()?;
+/// let recipe: Recipe = session.response_as("Suggest a quick vegetarian dinner")?;
+/// println!("{} ({} min)", recipe.title, recipe.minutes);
+/// # Ok(())
+/// # }
+/// ```
+pub trait Generable: DeserializeOwned {
+    /// True for types that a property may omit, such as `Option<T>`
+    #[doc(hidden)]
+    const OPTIONAL: bool = false;
+
+    /// The value as seen part-way through generation
+    ///
+    /// Strings may be cut short and fields not generated yet are missing. Scalars
+    /// and strings are their own partial type.
+    type Partial: DeserializeOwned + Clone + Debug + PartialEq;
+
+    /// Returns the schema the model's output must follow
+    fn generation_schema() -> GenerationSchema;
+}
+
+/// The shape of a generated value
+///
+/// Mirrors Foundation Models' `DynamicGenerationSchema`. Schemas are usually
+/// derived with `#[derive(Generable)]`, but can also be built by hand.
+///
+/// Serialized with a `type` tag, e.g. `{"type":"integer","minimum":0}`.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+#[non_exhaustive]
+pub enum GenerationSchema {
+    /// An object with named properties
+    Object {
+        name: String,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        description: Option<String>,
+        properties: Vec<Property>,
+    },
+
+    /// One of several schemas
+    AnyOf {
+        name: String,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        description: Option<String>,
+        choices: Vec<GenerationSchema>,
+    },
+
+    /// One of a fixed set of strings
+    Enum {
+        name: String,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        description: Option<String>,
+        choices: Vec<String>,
+    },
+
+    /// A list of values of the same schema
+    Array {
+        items: Box<GenerationSchema>,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        min_items: Option<u32>,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        max_items: Option<u32>,
+    },
+
+    /// A string, optionally matching a regular expression
+    String {
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        pattern: Option<String>,
+    },
+
+    /// A whole number within optional bounds (inclusive)
+    Integer {
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        minimum: Option<i64>,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        maximum: Option<i64>,
+    },
+
+    /// A floating point number within optional bounds (inclusive)
+    Number {
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        minimum: Option<f64>,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        maximum: Option<f64>,
+    },
+
+    /// `true` or `false`
+    Boolean,
+}
+
+/// A named property of an object schema
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[non_exhaustive]
+pub struct Property {
+    /// Key of the property in the generated object
+    pub name: String,
+
+    /// Explains the property to the model
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub description: Option<String>,
+
+    /// Schema of the property's value
+    pub schema: GenerationSchema,
+
+    /// Whether the model may leave the property out
+    #[serde(default)]
+    pub optional: bool,
+}
+
+impl GenerationSchema {
+    /// An object schema without properties
+    pub fn object(name: impl Into<String>) -> Self {
+        Self::Object {
+            name: name.into(),
+            description: None,
+            properties: Vec::new(),
+        }
+    }
+
+    /// A schema matching any one of `choices`
+    pub fn any_of(name: impl Into<String>, choices: Vec<GenerationSchema>) -> Self {
+        Self::AnyOf {
+            name: name.into(),
+            description: None,
+            choices,
+        }
+    }
+
+    /// A schema matching one of the given strings
+    pub fn enumeration<I, S>(name: impl Into<String>, choices: I) -> Self
+    where
+        I: IntoIterator<Item = S>,
+        S: Into<String>,
+    {
+        Self::Enum {
+            name: name.into(),
+            description: None,
+            choices: choices.into_iter().map(Into::into).collect(),
+        }
+    }
+
+    /// A list of `items`
+    pub fn array(items: GenerationSchema) -> Self {
+        Self::Array {
+            items: Box::new(items),
+            min_items: None,
+            max_items: None,
+        }
+    }
+
+    /// Any string
+    pub fn string() -> Self {
+        Self::String { pattern: None }
+    }
+
+    /// Any whole number
+    pub fn integer() -> Self {
+        Self::Integer {
+            minimum: None,
+            maximum: None,
+        }
+    }
+
+    /// Any floating point number
+    pub fn number() -> Self {
+        Self::Number {
+            minimum: None,
+            maximum: None,
+        }
+    }
+
+    /// A boolean
+    pub fn boolean() -> Self {
+        Self::Boolean
+    }
+
+    /// Adds a property to an object schema; has no effect on other schemas
+    pub fn with_property(mut self, property: Property) -> Self {
+        if let Self::Object { properties, .. } = &mut self {
+            properties.push(property);
+        }
+        self
+    }
+
+    /// Describes an object, any-of or enum schema; has no effect on other schemas
+    ///
+    /// Descriptions of other values belong on the [`Property`] holding them.
+    pub fn with_description(mut self, text: impl Into<String>) -> Self {
+        if let Self::Object { description, .. }
+        | Self::AnyOf { description, .. }
+        | Self::Enum { description, .. } = &mut self
+        {
+            *description = Some(text.into());
+        }
+        self
+    }
+
+    /// Bounds a numeric schema (inclusive); has no effect on other schemas
+    ///
+    /// Integer bounds are rounded inwards. A bound narrows the schema's existing
+    /// bounds, it never widens them.
+    pub fn with_range(mut self, min: Option<f64>, max: Option<f64>) -> Self {
+        match &mut self {
+            Self::Integer { minimum, maximum } => {
+                if let Some(min) = min {
+                    *minimum =
+                        Some(minimum.map_or(min.ceil() as i64, |m| m.max(min.ceil() as i64)));
+                }
+                if let Some(max) = max {
+                    *maximum =
+                        Some(maximum.map_or(max.floor() as i64, |m| m.min(max.floor() as i64)));
+                }
+            }
+            Self::Number { minimum, maximum } => {
+                if let Some(min) = min {
+                    *minimum = Some(minimum.map_or(min, |m| m.max(min)));
+                }
+                if let Some(max) = max {
+                    *maximum = Some(maximum.map_or(max, |m| m.min(max)));
+                }
+            }
+            _ => {}
+        }
+        self
+    }
+
+    /// Constrains a string schema to a regular expression; has no effect on other schemas
+    pub fn with_pattern(mut self, regex: impl Into<String>) -> Self {
+        if let Self::String { pattern } = &mut self {
+            *pattern = Some(regex.into());
+        }
+        self
+    }
+
+    /// Bounds the length of an array schema (inclusive); has no effect on other schemas
+    pub fn with_count(mut self, min: Option<u32>, max: Option<u32>) -> Self {
+        if let Self::Array {
+            min_items,
+            max_items,
+            ..
+        } = &mut self
+        {
+            *min_items = min;
+            *max_items = max;
+        }
+        self
+    }
+
+    /// Returns the equivalent [JSON Schema](https://json-schema.org) document
+    ///
+    /// Useful for backends that accept JSON Schema, and for showing the schema to
+    /// the model in a prompt.
+    pub fn to_json_schema(&self) -> Value {
+        match self {
+            Self::Object {
+                description,
+                properties,
+                ..
+            } => {
+                let mut schema = json!({
+                    "type": "object",
+                    "properties": properties
+                        .iter()
+                        .map(|property| {
+                            let mut value = property.schema.to_json_schema();
+                            if let Some(description) = &property.description {
+                                value["description"] = json!(description);
+                            }
+                            (property.name.clone(), value)
+                        })
+                        .collect::<serde_json::Map<_, _>>(),
+                    "required": properties
+                        .iter()
+                        .filter(|property| !property.optional)
+                        .map(|property| property.name.as_str())
+                        .collect::<Vec<_>>(),
+                    "additionalProperties": false,
+                });
+                with_json_description(&mut schema, description);
+                schema
+            }
+            Self::AnyOf {
+                description,
+                choices,
+                ..
+            } => {
+                let mut schema = json!({
+                    "anyOf": choices.iter().map(Self::to_json_schema).collect::<Vec<_>>(),
+                });
+                with_json_description(&mut schema, description);
+                schema
+            }
+            Self::Enum {
+                description,
+                choices,
+                ..
+            } => {
+                let mut schema = json!({ "type": "string", "enum": choices });
+                with_json_description(&mut schema, description);
+                schema
+            }
+            Self::Array {
+                items,
+                min_items,
+                max_items,
+            } => {
+                let mut schema = json!({ "type": "array", "items": items.to_json_schema() });
+                if let Some(min) = min_items {
+                    schema["minItems"] = json!(min);
+                }
+                if let Some(max) = max_items {
+                    schema["maxItems"] = json!(max);
+                }
+                schema
+            }
+            Self::String { pattern } => {
+                let mut schema = json!({ "type": "string" });
+                if let Some(pattern) = pattern {
+                    schema["pattern"] = json!(pattern);
+                }
+                schema
+            }
+            Self::Integer { minimum, maximum } => {
+                let mut schema = json!({ "type": "integer" });
+                if let Some(minimum) = minimum {
+                    schema["minimum"] = json!(minimum);
+                }
+                if let Some(maximum) = maximum {
+                    schema["maximum"] = json!(maximum);
+                }
+                schema
+            }
+            Self::Number { minimum, maximum } => {
+                let mut schema = json!({ "type": "number" });
+                if let Some(minimum) = minimum {
+                    schema["minimum"] = json!(minimum);
+                }
+                if let Some(maximum) = maximum {
+                    schema["maximum"] = json!(maximum);
+                }
+                schema
+            }
+            Self::Boolean => json!({ "type": "boolean" }),
+        }
+    }
+
+    /// Returns the name of an object, any-of or enum schema
+    pub fn name(&self) -> Option<&str> {
+        match self {
+            Self::Object { name, .. } | Self::AnyOf { name, .. } | Self::Enum { name, .. } => {
+                Some(name)
+            }
+            _ => None,
+        }
+    }
+}
+
+fn with_json_description(schema: &mut Value, description: &Option<String>) {
+    if let Some(description) = description {
+        schema["description"] = json!(description);
+    }
+}
+
+impl Property {
+    /// A required property
+    pub fn new(name: impl Into<String>, schema: GenerationSchema) -> Self {
+        Self {
+            name: name.into(),
+            description: None,
+            schema,
+            optional: false,
+        }
+    }
+
+    /// Explains the property to the model
+    pub fn with_description(mut self, description: impl Into<String>) -> Self {
+        self.description = Some(description.into());
+        self
+    }
+
+    /// Sets whether the model may leave the property out
+    pub fn with_optional(mut self, optional: bool) -> Self {
+        self.optional = optional;
+        self
+    }
+}
+
+// Implementations for standard types
+
+impl Generable for String {
+    type Partial = String;
+
+    fn generation_schema() -> GenerationSchema {
+        GenerationSchema::string()
+    }
+}
+
+impl Generable for bool {
+    type Partial = bool;
+
+    fn generation_schema() -> GenerationSchema {
+        GenerationSchema::boolean()
+    }
+}
+
+macro_rules! generable_integer {
+    ($($ty:ty),*) => {$(
+        impl Generable for $ty {
+            type Partial = $ty;
+
+            fn generation_schema() -> GenerationSchema {
+                // Bounds that do not fit an i64 are left open
+                GenerationSchema::Integer {
+                    minimum: i64::try_from(<$ty>::MIN).ok(),
+                    maximum: i64::try_from(<$ty>::MAX).ok(),
+                }
+            }
+        }
+    )*};
+}
+
+generable_integer!(i8, i16, i32, i64, isize, u8, u16, u32, u64, usize);
+
+impl Generable for f32 {
+    type Partial = f32;
+
+    fn generation_schema() -> GenerationSchema {
+        GenerationSchema::number()
+    }
+}
+
+impl Generable for f64 {
+    type Partial = f64;
+
+    fn generation_schema() -> GenerationSchema {
+        GenerationSchema::number()
+    }
+}
+
+impl<T: Generable> Generable for Vec<T> {
+    type Partial = Vec<T::Partial>;
+
+    fn generation_schema() -> GenerationSchema {
+        GenerationSchema::array(T::generation_schema())
+    }
+}
+
+impl<T: Generable> Generable for Option<T> {
+    const OPTIONAL: bool = true;
+    type Partial = T::Partial;
+
+    fn generation_schema() -> GenerationSchema {
+        T::generation_schema()
+    }
+}
+
+impl<T: Generable> Generable for Box<T> {
+    const OPTIONAL: bool = T::OPTIONAL;
+    type Partial = Box<T::Partial>;
+
+    fn generation_schema() -> GenerationSchema {
+        T::generation_schema()
+    }
+}
+
+/// Support code for `#[derive(Generable)]` and `#[fm_tool]`, not part of the public API
+#[doc(hidden)]
+pub mod __private {
+    use crate::error::{self, Error};
+    use serde::de::{Deserialize, DeserializeOwned, Deserializer};
+
+    pub use serde;
+    pub use serde_json;
+
+    /// Reads a field of a partial type, as `None` if it is not readable yet
+    pub fn lenient<'de, D, T>(deserializer: D) -> Result<Option<T>, D::Error>
+    where
+        D: Deserializer<'de>,
+        T: DeserializeOwned,
+    {
+        let value = serde_json::Value::deserialize(deserializer)?;
+        Ok(serde_json::from_value(value).ok())
+    }
+
+    /// Types that accept a `range` guide
+    #[diagnostic::on_unimplemented(
+        message = "`range` guides apply to numbers, not `{Self}`",
+        label = "this field's type is not a number"
+    )]
+    pub trait RangeGuide {}
+
+    /// Types that accept a `regex` guide
+    #[diagnostic::on_unimplemented(
+        message = "`regex` guides apply to strings, not `{Self}`",
+        label = "this field's type is not a string"
+    )]
+    pub trait RegexGuide {}
+
+    /// Types that accept a `count` guide
+    #[diagnostic::on_unimplemented(
+        message = "`count` guides apply to lists, not `{Self}`",
+        label = "this field's type is not a `Vec`"
+    )]
+    pub trait CountGuide {}
+
+    macro_rules! range_guide {
+        ($($ty:ty),*) => {$( impl RangeGuide for $ty {} )*};
+    }
+
+    range_guide!(i8, i16, i32, i64, isize, u8, u16, u32, u64, usize, f32, f64);
+    impl RegexGuide for String {}
+    impl<T> CountGuide for Vec<T> {}
+
+    impl<T: RangeGuide> RangeGuide for Option<T> {}
+    impl<T: RegexGuide> RegexGuide for Option<T> {}
+    impl<T: CountGuide> CountGuide for Option<T> {}
+    impl<T: RangeGuide> RangeGuide for Box<T> {}
+    impl<T: RegexGuide> RegexGuide for Box<T> {}
+    impl<T: CountGuide> CountGuide for Box<T> {}
+
+    pub fn assert_range_guide<T: RangeGuide + ?Sized>() {}
+    pub fn assert_regex_guide<T: RegexGuide + ?Sized>() {}
+    pub fn assert_count_guide<T: CountGuide + ?Sized>() {}
+
+    /// Decodes the arguments the model generated for a `#[fm_tool]` function
+    pub fn tool_arguments<T: DeserializeOwned>(
+        tool: &str,
+        arguments: serde_json::Value,
+    ) -> error::Result<T> {
+        serde_json::from_value(arguments).map_err(|e| {
+            Error::InvalidInput(format!("Invalid arguments for tool {:?}: {}", tool, e))
+        })
+    }
+
+    /// Return types of `#[fm_tool]` functions
+    #[diagnostic::on_unimplemented(
+        message = "tool functions return `String` or `Result<String, E>`, not `{Self}`",
+        label = "unsupported return type",
+        note = "`E` must convert into `fm_bindings::Error`"
+    )]
+    pub trait ToolOutput {
+        fn into_tool_output(self) -> error::Result<String>;
+    }
+
+    impl ToolOutput for String {
+        fn into_tool_output(self) -> error::Result<String> {
+            Ok(self)
+        }
+    }
+
+    impl<E: Into<Error>> ToolOutput for std::result::Result<String, E> {
+        fn into_tool_output(self) -> error::Result<String> {
+            self.map_err(Into::into)
+        }
+    }
+}
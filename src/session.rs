@@ -1,16 +1,50 @@
 // src/session.rs
 // Language Model Session - the main API for Foundation Models
 
+use super::backend::{GenerationRequest, ModelBackend};
+use super::cancel::CancelHandle;
+use super::context::{self, ContextPolicy};
 use super::error::{Error, Result};
-use super::ffi;
-use std::ffi::CString;
-use std::sync::{Arc, Condvar, Mutex};
+use super::model::{Availability, SystemLanguageModel};
+use super::options::GenerationOptions;
+use super::partial::parse_partial;
+use super::schema::{Generable, GenerationSchema};
+use super::system::SystemBackend;
+use super::tokens::TokenInput;
+use super::tool::{RecordedTool, Tool, ToolJournal};
+use super::transcript::{Transcript, TranscriptEntry};
+use serde_json::Value;
+use std::cell::Cell;
+use std::sync::atomic::AtomicU64;
+use std::sync::{Arc, Mutex, MutexGuard, PoisonError};
+use std::thread::{self, JoinHandle};
 
 /// A session for interacting with Apple's Foundation Models
 ///
 /// This provides access to on-device language models via the FoundationModels framework.
 /// Requires macOS 26+ or iOS 26+ with Apple Intelligence enabled.
 ///
+/// Generation is delegated to a [`ModelBackend`]. [`new`](Self::new) uses the
+/// system model; [`with_backend`](Self::with_backend) accepts any other implementation.
+/// Use [`builder`](Self::builder) to combine a backend with further configuration
+/// such as instructions.
+///
+/// A session is a conversation: every prompt sees the earlier prompts and responses
+/// of the same session. Clones share the backend and the conversation, so a clone
+/// can be handed to another thread (for example to cancel a stream) without
+/// forking the transcript. Create a new session to start a fresh conversation, or
+/// [`with_transcript`](Self::with_transcript) to resume a saved one.
+///
+/// Register [`Tool`]s with [`SessionBuilder::tool`] to let the model call Rust
+/// functions while it answers; each call and its output is recorded in the transcript.
+///
+/// A long conversation eventually outgrows the model's context window. Set a
+/// [`ContextPolicy`] with [`SessionBuilder::context_policy`] to drop or summarize
+/// older turns automatically instead of failing with `Error::ContextWindowExceeded`.
+///
+/// To stop a single request, send it through [`cancellable`](Self::cancellable) with
+/// a [`CancelHandle`]; it then returns `Error::Cancelled` with the partial response.
+///
 /// # Examples
 ///
 /// ## Blocking response
@@ -35,29 +69,310 @@ use std::sync::{Arc, Condvar, Mutex};
 /// # Ok(())
 /// # }
 /// ```
+///
+/// ## Multi-turn conversation
+/// ```no_run
+/// # use fm_bindings::LanguageModelSession;
+/// # fn main() -> Result<(), Box<dyn std::error::Error>> {
+/// let session = LanguageModelSession::new()?;
+/// session.response("My name is Ada.")?;
+/// let response = session.response("What is my name?")?;
+/// println!("{}", response); // mentions Ada
+/// # Ok(())
+/// # }
+/// ```
 #[derive(Clone)]
 pub struct LanguageModelSession {
-    _private: (),
+    backend: Arc<dyn ModelBackend>,
+    instructions: Option<Arc<str>>,
+    transcript: Arc<Mutex<Transcript>>,
+    tools: Arc<[Arc<dyn Tool>]>,
+    call_ids: Arc<AtomicU64>,
+    context_policy: ContextPolicy,
+    cancel_handle: Option<CancelHandle>,
 }
 
 impl LanguageModelSession {
     /// Creates a new language model session
     ///
     /// This checks that the Foundation Model is available on the system.
+    /// On non-Apple platforms the system model is never available.
     ///
     /// # Errors
     ///
-    /// Returns `Error::ModelNotAvailable` if Apple Intelligence is not enabled
-    /// or the system model is unavailable.
+    /// Returns `Error::ModelNotAvailable` if the system model is unavailable, with
+    /// the reason: the device is not eligible, Apple Intelligence is not enabled or
+    /// the model is not ready yet. See [`SystemLanguageModel::availability`].
+    ///
+    /// [`SystemLanguageModel::availability`]: crate::SystemLanguageModel::availability
     pub fn new() -> Result<Self> {
-        // Check availability before creating the session (fail-fast)
-        let is_available = unsafe { ffi::fm_check_availability() };
+        Self::builder().build()
+    }
+
+    /// Creates a new session on the system model with the given instructions
+    ///
+    /// Instructions define the model's role and behaviour for the whole session.
+    /// They come from the developer and are passed to the model separately from
+    /// user prompts, which the model is trained to treat with lower precedence.
+    /// Never put untrusted user input in instructions.
+    ///
+    /// # Errors
+    ///
+    /// * `Error::ModelNotAvailable` - If the Foundation Model is not available
+    /// * `Error::InvalidInput` - If the instructions contain a null byte
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// # use fm_bindings::LanguageModelSession;
+    /// # fn main() -> Result<(), Box<dyn std::error::Error>> {
+    /// let session = LanguageModelSession::with_instructions(
+    ///     "You are a support agent. Answer in at most two sentences.",
+    /// )?;
+    /// let response = session.response("How do I reset my password?")?;
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn with_instructions(instructions: impl Into<String>) -> Result<Self> {
+        Self::builder().instructions(instructions).build()
+    }
+
+    /// Resumes a conversation on the system model from a saved transcript
+    ///
+    /// The session's instructions are taken from the transcript, and follow-up
+    /// prompts see every earlier entry.
+    ///
+    /// # Errors
+    ///
+    /// * `Error::ModelNotAvailable` - If the Foundation Model is not available
+    /// * `Error::InvalidInput` - If the transcript contains a null byte
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// # use fm_bindings::{LanguageModelSession, Transcript};
+    /// # fn main() -> Result<(), Box<dyn std::error::Error>> {
+    /// let transcript = Transcript::from_json(&std::fs::read_to_string("chat.json")?)?;
+    /// let session = LanguageModelSession::with_transcript(transcript)?;
+    /// let response = session.response("What number did I ask you to remember?")?;
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn with_transcript(transcript: Transcript) -> Result<Self> {
+        Self::builder().transcript(transcript).build()
+    }
 
-        if !is_available {
-            return Err(Error::ModelNotAvailable);
+    /// Creates a new session on a configured system language model
+    ///
+    /// # Errors
+    ///
+    /// Returns `Error::ModelNotAvailable` if that model is unavailable.
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// # use fm_bindings::{LanguageModelSession, SystemLanguageModel, UseCase};
+    /// # fn main() -> Result<(), Box<dyn std::error::Error>> {
+    /// let model = SystemLanguageModel::new().with_use_case(UseCase::ContentTagging);
+    /// let session = LanguageModelSession::with_model(model)?;
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn with_model(model: SystemLanguageModel) -> Result<Self> {
+        Self::builder().model(model).build()
+    }
+
+    /// Creates a new session backed by the given model implementation
+    ///
+    /// This checks that the backend is available before returning the session.
+    ///
+    /// # Errors
+    ///
+    /// Returns `Error::ModelNotAvailable` if the backend reports itself unavailable.
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// # use fm_bindings::{LanguageModelSession, SystemBackend};
+    /// # fn main() -> Result<(), Box<dyn std::error::Error>> {
+    /// let session = LanguageModelSession::with_backend(SystemBackend::new())?;
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn with_backend<B>(backend: B) -> Result<Self>
+    where
+        B: ModelBackend + 'static,
+    {
+        Self::builder().backend(backend).build()
+    }
+
+    /// Returns a builder for configuring a new session
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// # use fm_bindings::{LanguageModelSession, SystemBackend};
+    /// # fn main() -> Result<(), Box<dyn std::error::Error>> {
+    /// let session = LanguageModelSession::builder()
+    ///     .backend(SystemBackend::new())
+    ///     .instructions("Reply in French.")
+    ///     .build()?;
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn builder() -> SessionBuilder {
+        SessionBuilder::default()
+    }
+
+    /// Returns the instructions the session was created with, if any
+    pub fn instructions(&self) -> Option<&str> {
+        self.instructions.as_deref()
+    }
+
+    /// Returns how the session keeps its conversation within the context window
+    pub fn context_policy(&self) -> ContextPolicy {
+        self.context_policy
+    }
+
+    /// Returns a snapshot of the session transcript
+    ///
+    /// The transcript starts with the instructions, if any, followed by every prompt,
+    /// tool call and response of the conversation. Failed requests are not recorded.
+    /// A stream stopped with [`cancel_stream`](Self::cancel_stream) is recorded with
+    /// the partial response the model had produced, while a request stopped through
+    /// a [`CancelHandle`] is not recorded at all, and the backend is reset so that it
+    /// forgets the partial turn as well. Turns dropped or summarized by the
+    /// session's [`ContextPolicy`] are no longer in it.
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// # use fm_bindings::LanguageModelSession;
+    /// # fn main() -> Result<(), Box<dyn std::error::Error>> {
+    /// let session = LanguageModelSession::new()?;
+    /// session.response("Remember the number 42.")?;
+    /// std::fs::write("chat.json", session.transcript().to_json()?)?;
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn transcript(&self) -> Transcript {
+        self.lock_transcript().clone()
+    }
+
+    /// Returns the size of the model's context window, in tokens
+    ///
+    /// The transcript, the next prompt and its response must fit in it together.
+    /// See [`ModelBackend::context_size`].
+    pub fn context_size(&self) -> usize {
+        self.backend.context_size()
+    }
+
+    /// Counts the tokens of a text or a transcript with the session's model
+    ///
+    /// Pass a prompt to check it fits before sending it, or the session's
+    /// [`transcript`](Self::transcript) to see how much of the context window the
+    /// conversation uses. Backends without a tokenizer return the estimate of the
+    /// [`tokens`](crate::tokens) module.
+    ///
+    /// # Errors
+    ///
+    /// * `Error::InvalidInput` - If the text or transcript contains a null byte
+    /// * Any error of the backend's [`ModelBackend::token_count`]
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// # use fm_bindings::LanguageModelSession;
+    /// # fn main() -> fm_bindings::Result<()> {
+    /// let session = LanguageModelSession::new()?;
+    /// let prompt = "Summarize our conversation";
+    /// let used = session.token_count(&session.transcript())? + session.token_count(prompt)?;
+    /// println!("{} of {} tokens used", used, session.context_size());
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn token_count<'a>(&self, input: impl Into<TokenInput<'a>>) -> Result<usize> {
+        self.backend.token_count(input.into())
+    }
+
+    /// Loads the model ahead of the first request, so that it answers sooner
+    ///
+    /// The first request of a session otherwise pays for creating the model's session
+    /// and loading its resources. Call this once the user is likely to send a prompt,
+    /// for example when a chat window opens. If the beginning of the next prompt is
+    /// known, such as a fixed template, pass it as `prompt_prefix` so the model can
+    /// process it in advance.
+    ///
+    /// Blocks until the model has been asked to load, which on the system model
+    /// includes creating the Swift session; loading itself continues in the
+    /// background. See [`prewarm_in_background`](Self::prewarm_in_background) for a
+    /// variant that returns immediately.
+    ///
+    /// # Errors
+    ///
+    /// * `Error::ModelNotAvailable` - If the Foundation Model is not available
+    /// * `Error::InvalidInput` - If the prompt prefix contains a null byte
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// # use fm_bindings::LanguageModelSession;
+    /// # fn main() -> Result<(), Box<dyn std::error::Error>> {
+    /// let session = LanguageModelSession::with_instructions("Translate to French.")?;
+    /// session.prewarm(Some("Translate: "))?;
+    /// // ... later, the first response starts sooner
+    /// let response = session.response("Translate: Good morning")?;
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn prewarm(&self, prompt_prefix: Option<&str>) -> Result<()> {
+        let prompt_prefix = prompt_prefix.unwrap_or_default();
+        if prompt_prefix.contains('\0') {
+            return Err(Error::InvalidInput(
+                "Prompt prefix contains null byte".into(),
+            ));
         }
 
-        Ok(Self { _private: () })
+        let transcript = self.transcript();
+        let request = GenerationRequest::new(prompt_prefix)
+            .with_instructions(self.instructions.as_deref())
+            .with_transcript(&transcript)
+            .with_tools(&self.tools);
+        self.backend.prewarm(&request)
+    }
+
+    /// Loads the model ahead of the first request without blocking the caller
+    ///
+    /// Runs [`prewarm`](Self::prewarm) on a new thread, so an app can warm the model
+    /// while the user is still typing. Join the returned handle to learn whether
+    /// prewarming succeeded, or drop it if that does not matter.
+    ///
+    /// # Errors
+    ///
+    /// Returns `Error::InternalError` if the thread cannot be started.
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// # use fm_bindings::LanguageModelSession;
+    /// # fn main() -> Result<(), Box<dyn std::error::Error>> {
+    /// let session = LanguageModelSession::new()?;
+    /// session.prewarm_in_background(None)?;
+    /// // ... the user types their question meanwhile
+    /// let response = session.response("What is Rust?")?;
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn prewarm_in_background(
+        &self,
+        prompt_prefix: Option<&str>,
+    ) -> Result<JoinHandle<Result<()>>> {
+        let session = self.clone();
+        let prompt_prefix = prompt_prefix.map(str::to_string);
+        thread::Builder::new()
+            .name("fm-prewarm".into())
+            .spawn(move || session.prewarm(prompt_prefix.as_deref()))
+            .map_err(|e| Error::InternalError(format!("Cannot start prewarm thread: {}", e)))
     }
 
     /// Generates a complete response to the given prompt
@@ -74,6 +389,14 @@ impl LanguageModelSession {
     /// * `Error::ModelNotAvailable` - If the Foundation Model is not available
     /// * `Error::InvalidInput` - If the prompt is empty or invalid
     /// * `Error::GenerationError` - If an error occurs during generation
+    /// * `Error::GuardrailViolation` - If the model's guardrails flagged the prompt
+    ///   or the response
+    /// * `Error::ContextWindowExceeded` - If the conversation no longer fits in the
+    ///   model's context window and the session's [`ContextPolicy`] cannot shorten it
+    /// * `Error::ToolCallFailed` - If a tool called by the model returned an error
+    ///
+    /// Transient failures, such as `Error::RateLimited`, are flagged by
+    /// [`Error::is_retryable`].
     ///
     /// # Examples
     ///
@@ -87,45 +410,152 @@ impl LanguageModelSession {
     /// # }
     /// ```
     pub fn response(&self, prompt: &str) -> Result<String> {
-        if prompt.is_empty() {
-            return Err(Error::InvalidInput("Prompt cannot be empty".into()));
-        }
+        self.response_with_options(prompt, &GenerationOptions::default())
+    }
 
-        // Create C string for FFI
-        let c_prompt = CString::new(prompt)
-            .map_err(|_| Error::InvalidInput("Prompt contains null byte".into()))?;
-
-        // Shared state for collecting response
-        let state = Arc::new((Mutex::new(ResponseState::default()), Condvar::new()));
-        let state_clone = Arc::clone(&state);
-
-        // Call Swift FFI with blocking response mode
-        unsafe {
-            ffi::fm_response(
-                c_prompt.as_ptr(),
-                Box::into_raw(Box::new(state_clone)) as *mut _,
-                response_callback,
-                response_done_callback,
-                response_error_callback,
-            );
-        }
+    /// Generates a complete response, controlling sampling and length with `options`
+    ///
+    /// # Errors
+    ///
+    /// * `Error::ModelNotAvailable` - If the Foundation Model is not available
+    /// * `Error::InvalidInput` - If the prompt is empty or invalid, or an option is
+    ///   out of range
+    /// * `Error::GenerationError` - If an error occurs during generation
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// # use fm_bindings::{GenerationOptions, LanguageModelSession, SamplingMode};
+    /// # fn main() -> Result<(), Box<dyn std::error::Error>> {
+    /// let session = LanguageModelSession::new()?;
+    /// let options = GenerationOptions::new()
+    ///     .with_sampling(SamplingMode::Greedy)
+    ///     .with_maximum_response_tokens(100);
+    /// let response = session.response_with_options("Summarize Rust in one line", &options)?;
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn response_with_options(
+        &self,
+        prompt: &str,
+        options: &GenerationOptions,
+    ) -> Result<String> {
+        self.generate(prompt, options, None)
+    }
 
-        // Wait for completion
-        let (mutex, cvar) = &*state;
-        let mut response_state = mutex.lock().map_err(|_| Error::PoisonError)?;
-        while !response_state.finished {
-            response_state = cvar.wait(response_state).map_err(|_| Error::PoisonError)?;
-        }
+    /// Generates a value of type `T`, constraining the model's output to its schema
+    ///
+    /// The schema of `T` (usually derived with `#[derive(Generable)]`) is handed to
+    /// the model with the prompt, so the response is structured data rather than
+    /// free-form text. The response is recorded in the transcript as JSON.
+    ///
+    /// # Errors
+    ///
+    /// * `Error::ModelNotAvailable` - If the Foundation Model is not available
+    /// * `Error::InvalidInput` - If the prompt is empty or invalid
+    /// * `Error::GenerationError` - If an error occurs during generation
+    /// * `Error::DecodingFailure` - If the response cannot be decoded as `T`
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// # use fm_bindings::{Generable, LanguageModelSession};
+    /// # use serde::Deserialize;
+    /// #[derive(Generable, Deserialize)]
+    /// struct Contact {
+    ///     name: String,
+    ///     #[guide(regex = "^[^@ ]+@[^@ ]+$")]
+    ///     email: Option<String>,
+    /// }
+    ///
+    /// # fn main() -> Result<(), Box<dyn std::error::Error>> {
+    /// let session = LanguageModelSession::new()?;
+    /// let contact: Contact =
+    ///     session.response_as("Extract the contact: Reach Jane Roe at jane@example.com")?;
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn response_as<T: Generable>(&self, prompt: &str) -> Result<T> {
+        self.response_as_with_options(prompt, &GenerationOptions::default())
+    }
 
-        // Check for errors
-        if let Some(error) = &response_state.error {
-            if error.contains("not available") {
-                return Err(Error::ModelNotAvailable);
-            }
-            return Err(Error::GenerationError(error.clone()));
-        }
+    /// Generates a value of type `T`, controlling sampling and length with `options`
+    ///
+    /// See [`response_as`](Self::response_as).
+    ///
+    /// # Errors
+    ///
+    /// * `Error::ModelNotAvailable` - If the Foundation Model is not available
+    /// * `Error::InvalidInput` - If the prompt is empty or invalid, or an option is
+    ///   out of range
+    /// * `Error::GenerationError` - If an error occurs during generation
+    /// * `Error::DecodingFailure` - If the response cannot be decoded as `T`
+    pub fn response_as_with_options<T: Generable>(
+        &self,
+        prompt: &str,
+        options: &GenerationOptions,
+    ) -> Result<T> {
+        let schema = T::generation_schema();
+        decode(&self.generate(prompt, options, Some(&schema))?)
+    }
+
+    /// Generates a JSON value following a schema known only at runtime
+    ///
+    /// Use this when the shape of the response comes from configuration or from
+    /// another library rather than a Rust type; build the schema by hand or convert
+    /// a JSON Schema document with [`GenerationSchema::from_json_schema`]. The
+    /// returned value is checked against the schema, so backends without
+    /// constrained decoding cannot hand back a value of the wrong shape.
+    ///
+    /// # Errors
+    ///
+    /// * `Error::ModelNotAvailable` - If the Foundation Model is not available
+    /// * `Error::InvalidInput` - If the prompt is empty or invalid
+    /// * `Error::GenerationError` - If an error occurs during generation
+    /// * `Error::DecodingFailure` - If the response is not JSON matching the schema
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// # use fm_bindings::{GenerationSchema, LanguageModelSession};
+    /// # fn main() -> Result<(), Box<dyn std::error::Error>> {
+    /// let document = serde_json::from_str(&std::fs::read_to_string("ticket.schema.json")?)?;
+    /// let schema = GenerationSchema::from_json_schema(&document)?;
+    ///
+    /// let session = LanguageModelSession::new()?;
+    /// let ticket = session.response_with_schema("File a ticket: the login page is blank", &schema)?;
+    /// println!("{}", ticket["summary"]);
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn response_with_schema(&self, prompt: &str, schema: &GenerationSchema) -> Result<Value> {
+        self.response_with_schema_and_options(prompt, schema, &GenerationOptions::default())
+    }
 
-        Ok(response_state.text.clone())
+    /// Generates a JSON value following `schema`, controlling sampling and length with `options`
+    ///
+    /// See [`response_with_schema`](Self::response_with_schema).
+    ///
+    /// # Errors
+    ///
+    /// * `Error::ModelNotAvailable` - If the Foundation Model is not available
+    /// * `Error::InvalidInput` - If the prompt is empty or invalid, or an option is
+    ///   out of range
+    /// * `Error::GenerationError` - If an error occurs during generation
+    /// * `Error::DecodingFailure` - If the response is not JSON matching the schema
+    pub fn response_with_schema_and_options(
+        &self,
+        prompt: &str,
+        schema: &GenerationSchema,
+        options: &GenerationOptions,
+    ) -> Result<Value> {
+        let json = self.generate(prompt, options, Some(schema))?;
+        let value = serde_json::from_str(&json)
+            .map_err(|e| Error::DecodingFailure(format!("Cannot decode response: {}", e)))?;
+        schema.check(&value).map_err(|e| {
+            Error::DecodingFailure(format!("Response does not match the schema: {}", e))
+        })?;
+        Ok(value)
     }
 
     /// Generates a streaming response to the given prompt
@@ -166,48 +596,194 @@ impl LanguageModelSession {
     where
         F: FnMut(&str),
     {
-        if prompt.is_empty() {
-            return Err(Error::InvalidInput("Prompt cannot be empty".into()));
-        }
+        self.stream_response_with_options(prompt, &GenerationOptions::default(), on_chunk)
+    }
 
-        // Create C string for FFI
-        let c_prompt = CString::new(prompt)
-            .map_err(|_| Error::InvalidInput("Prompt contains null byte".into()))?;
-
-        // Shared state for streaming
-        let state = Arc::new((Mutex::new(StreamState::default()), Condvar::new()));
-        let state_clone = Arc::clone(&state);
-
-        // Call Swift FFI with streaming mode
-        unsafe {
-            ffi::fm_start_stream(
-                c_prompt.as_ptr(),
-                Box::into_raw(Box::new((
-                    state_clone,
-                    Box::new(on_chunk) as Box<dyn FnMut(&str)>,
-                ))) as *mut _,
-                stream_chunk_callback,
-                stream_done_callback,
-                stream_error_callback,
-            );
-        }
+    /// Generates a streaming response, controlling sampling and length with `options`
+    ///
+    /// # Errors
+    ///
+    /// * `Error::ModelNotAvailable` - If the Foundation Model is not available
+    /// * `Error::InvalidInput` - If the prompt is empty or invalid, or an option is
+    ///   out of range
+    /// * `Error::GenerationError` - If an error occurs during generation
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// # use fm_bindings::{GenerationOptions, LanguageModelSession};
+    /// # fn main() -> Result<(), Box<dyn std::error::Error>> {
+    /// let session = LanguageModelSession::new()?;
+    /// let options = GenerationOptions::new().with_temperature(1.5);
+    /// session.stream_response_with_options("Invent a word", &options, |chunk| {
+    ///     print!("{}", chunk);
+    /// })?;
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn stream_response_with_options<F>(
+        &self,
+        prompt: &str,
+        options: &GenerationOptions,
+        mut on_chunk: F,
+    ) -> Result<()>
+    where
+        F: FnMut(&str),
+    {
+        let delivered = Cell::new(false);
+        let retry = || !delivered.get();
+        self.exchange(prompt, options, retry, |request| {
+            let mut response = String::new();
+            let result = self.backend.stream_response(request, &mut |chunk| {
+                if request.is_cancelled() {
+                    return;
+                }
+                delivered.set(true);
+                response.push_str(chunk);
+                on_chunk(chunk);
+            });
+            finish_stream(result, response)
+        })?;
+        Ok(())
+    }
 
-        // Wait for completion
-        let (mutex, cvar) = &*state;
-        let mut stream_state = mutex.lock().map_err(|_| Error::PoisonError)?;
-        while !stream_state.finished {
-            stream_state = cvar.wait(stream_state).map_err(|_| Error::PoisonError)?;
-        }
+    /// Generates a value of type `T`, reporting it as it is being generated
+    ///
+    /// `on_partial` is called with a [`T::Partial`](Generable::Partial) each time the
+    /// generated snapshot grows: a mirror of `T` whose fields are `None` until the
+    /// model has started them, and whose strings may still be cut short. Once the
+    /// stream completes the finished value is decoded and returned.
+    ///
+    /// # Errors
+    ///
+    /// * `Error::ModelNotAvailable` - If the Foundation Model is not available
+    /// * `Error::InvalidInput` - If the prompt is empty or invalid
+    /// * `Error::GenerationError` - If an error occurs during generation
+    /// * `Error::DecodingFailure` - If the final response cannot be decoded as `T`
+    ///   (for example after cancellation)
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// # use fm_bindings::{Generable, LanguageModelSession};
+    /// # use serde::Deserialize;
+    /// #[derive(Generable, Deserialize)]
+    /// struct Profile {
+    ///     name: String,
+    ///     bio: String,
+    /// }
+    ///
+    /// # fn main() -> Result<(), Box<dyn std::error::Error>> {
+    /// let session = LanguageModelSession::new()?;
+    /// let profile: Profile = session.stream_response_as("Invent a character", |partial| {
+    ///     // e.g. ProfilePartial { name: Some("Ada"), bio: None }
+    ///     println!("{:?}", partial);
+    /// })?;
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn stream_response_as<T, F>(&self, prompt: &str, on_partial: F) -> Result<T>
+    where
+        T: Generable,
+        F: FnMut(T::Partial),
+    {
+        self.stream_response_as_with_options(prompt, &GenerationOptions::default(), on_partial)
+    }
 
-        // Check for errors
-        if let Some(error) = &stream_state.error {
-            if error.contains("not available") {
-                return Err(Error::ModelNotAvailable);
-            }
-            return Err(Error::GenerationError(error.clone()));
-        }
+    /// Generates a value of type `T` as it is being generated, controlling sampling
+    /// and length with `options`
+    ///
+    /// See [`stream_response_as`](Self::stream_response_as).
+    ///
+    /// # Errors
+    ///
+    /// * `Error::ModelNotAvailable` - If the Foundation Model is not available
+    /// * `Error::InvalidInput` - If the prompt is empty or invalid, or an option is
+    ///   out of range
+    /// * `Error::GenerationError` - If an error occurs during generation
+    /// * `Error::DecodingFailure` - If the final response cannot be decoded as `T`
+    pub fn stream_response_as_with_options<T, F>(
+        &self,
+        prompt: &str,
+        options: &GenerationOptions,
+        mut on_partial: F,
+    ) -> Result<T>
+    where
+        T: Generable,
+        F: FnMut(T::Partial),
+    {
+        let schema = T::generation_schema();
+        let delivered = Cell::new(false);
+        let retry = || !delivered.get();
+        let response = self.exchange(prompt, options, retry, |request| {
+            let mut response = String::new();
+            let mut last = None;
+            let result = self.backend.stream_snapshots(
+                &request.with_schema(Some(&schema)),
+                &mut |snapshot| {
+                    if request.is_cancelled() {
+                        return;
+                    }
+                    response.clear();
+                    response.push_str(snapshot);
 
-        Ok(())
+                    // Only report snapshots that read as a new partial value
+                    let Some(value) = parse_partial(snapshot) else {
+                        return;
+                    };
+                    if last.as_ref() == Some(&value) {
+                        return;
+                    }
+                    if let Ok(partial) = serde_json::from_value(value.clone()) {
+                        delivered.set(true);
+                        on_partial(partial);
+                    }
+                    last = Some(value);
+                },
+            );
+            finish_stream(result, response)
+        })?;
+        decode(&response)
+    }
+
+    /// Returns a clone of the session whose requests stop when `handle` is cancelled
+    ///
+    /// The clone shares the conversation, so its requests are recorded in the
+    /// session's transcript as usual. A request stopped by the handle returns
+    /// `Error::Cancelled` with the response generated so far and is not recorded,
+    /// unlike a stream stopped with [`cancel_stream`](Self::cancel_stream), which
+    /// ends normally and records its partial response. The backend is then reset
+    /// (see [`ModelBackend::reset`]), so a backend that keeps its own conversation,
+    /// like the system model's Swift session, drops the partial turn too.
+    /// Other requests, including those running on the session at the same time,
+    /// are not affected. This works for blocking and streaming requests alike.
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// # use fm_bindings::{CancelHandle, Error, LanguageModelSession};
+    /// # fn main() -> Result<(), Box<dyn std::error::Error>> {
+    /// let session = LanguageModelSession::new()?;
+    /// let handle = CancelHandle::new();
+    ///
+    /// let stop = handle.clone();
+    /// let result = session.cancellable(&handle).stream_response("Tell me a story", |chunk| {
+    ///     print!("{}", chunk);
+    ///     if chunk.contains("The End") {
+    ///         stop.cancel();
+    ///     }
+    /// });
+    /// if let Err(Error::Cancelled { partial }) = result {
+    ///     println!("\nStopped after {} characters", partial.chars().count());
+    /// }
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn cancellable(&self, handle: &CancelHandle) -> Self {
+        Self {
+            cancel_handle: Some(handle.clone()),
+            ..self.clone()
+        }
     }
 
     /// Cancels the current streaming response
@@ -221,6 +797,7 @@ impl LanguageModelSession {
     /// * This is a global operation that cancels the current stream
     /// * Safe to call even if no stream is active
     /// * After cancellation, the `stream_response` method will return normally
+    /// * To stop one request among several, use [`cancellable`](Self::cancellable)
     ///
     /// # Examples
     ///
@@ -246,150 +823,316 @@ impl LanguageModelSession {
     /// # }
     /// ```
     pub fn cancel_stream(&self) {
-        unsafe {
-            ffi::fm_stop_stream();
+        self.backend.cancel_stream();
+    }
+
+    /// Generates a complete response and records it in the transcript
+    fn generate(
+        &self,
+        prompt: &str,
+        options: &GenerationOptions,
+        schema: Option<&GenerationSchema>,
+    ) -> Result<String> {
+        self.exchange(
+            prompt,
+            options,
+            || true,
+            |request| self.backend.response(&request.with_schema(schema)),
+        )
+    }
+
+    /// Validates a prompt, runs `generate` on its request, and records the exchange
+    ///
+    /// `generate` returns the complete response. If it fails because a tool failed,
+    /// the error is reported as `Error::ToolCallFailed`. The transcript is fitted to
+    /// the context window first; if the model still reports it exceeded, `generate`
+    /// runs once more on a shorter transcript, provided `retry` allows it.
+    fn exchange<R, F>(
+        &self,
+        prompt: &str,
+        options: &GenerationOptions,
+        retry: R,
+        mut generate: F,
+    ) -> Result<String>
+    where
+        R: Fn() -> bool,
+        F: FnMut(&GenerationRequest<'_>) -> Result<String>,
+    {
+        validate_prompt(prompt)?;
+        options.validate()?;
+        let cancel_handle = self.cancel_handle.as_ref();
+        if cancel_handle.is_some_and(CancelHandle::is_cancelled) {
+            return Err(Error::Cancelled {
+                partial: String::new(),
+            });
+        }
+
+        let mut transcript = self.transcript();
+        if let Some(fitted) = self.fit_transcript(&transcript, prompt, options, false)? {
+            transcript = fitted;
+        }
+        let mut retried = false;
+        loop {
+            // The request's tools record their calls in a journal of its own
+            let journal = Arc::new(Mutex::new(ToolJournal::new(self.call_ids.clone())));
+            let tools = RecordedTool::wrap(&self.tools, &journal);
+            let request = GenerationRequest::new(prompt)
+                .with_instructions(self.instructions.as_deref())
+                .with_transcript(&transcript)
+                .with_options(options)
+                .with_tools(&tools)
+                .with_cancel_handle(cancel_handle)
+                .with_journal(Some(&journal));
+
+            let lock_journal = || journal.lock().unwrap_or_else(PoisonError::into_inner);
+            let result = generate(&request);
+            // The backend may keep the turn a cancelled request left behind, which
+            // the transcript does not record, so it starts over from the transcript
+            if request.is_cancelled() {
+                self.backend.reset();
+            }
+            let error = match result {
+                // A backend that finished regardless still counts as cancelled
+                Ok(response) if request.is_cancelled() => {
+                    return Err(Error::Cancelled { partial: response });
+                }
+                Ok(response) => {
+                    let tool_entries = lock_journal().take();
+                    self.record(prompt, tool_entries, &response);
+                    return Ok(response);
+                }
+                Err(error) => lock_journal().failure(error),
+            };
+            if retried || !matches!(error, Error::ContextWindowExceeded(_)) || !retry() {
+                return Err(error);
+            }
+
+            match self.fit_transcript(&transcript, prompt, options, true)? {
+                Some(fitted) => transcript = fitted,
+                None => return Err(error),
+            }
+            retried = true;
         }
     }
-}
 
-// Internal State Types
+    /// Applies the context policy to `transcript`, returning the shortened transcript
+    ///
+    /// A shortened transcript replaces the session's, and the backend is reset so
+    /// that it does not keep the dropped turns.
+    fn fit_transcript(
+        &self,
+        transcript: &Transcript,
+        prompt: &str,
+        options: &GenerationOptions,
+        forced: bool,
+    ) -> Result<Option<Transcript>> {
+        let backend = &*self.backend;
+        let fitted = context::fit(
+            self.context_policy,
+            backend,
+            transcript,
+            prompt,
+            options,
+            forced,
+        )?;
+        if let Some(fitted) = &fitted {
+            *self.lock_transcript() = fitted.clone();
+            backend.reset();
+        }
+        Ok(fitted)
+    }
 
-#[derive(Default)]
-struct ResponseState {
-    text: String,
-    finished: bool,
-    error: Option<String>,
+    /// Appends a completed exchange, with the tool calls made for it, to the transcript
+    fn record(&self, prompt: &str, tool_entries: Vec<TranscriptEntry>, response: &str) {
+        let mut transcript = self.lock_transcript();
+        transcript.push(TranscriptEntry::prompt(prompt));
+        for entry in tool_entries {
+            transcript.push(entry);
+        }
+        transcript.push(TranscriptEntry::response(response));
+    }
+
+    // The transcript is only appended to or replaced whole, so it stays consistent
+    // even if a thread panicked while holding the lock
+    fn lock_transcript(&self) -> MutexGuard<'_, Transcript> {
+        self.transcript
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+    }
 }
 
+/// Configures and creates a [`LanguageModelSession`]
+///
+/// Created with [`LanguageModelSession::builder`]. Without an explicit backend,
+/// the session uses the system model.
 #[derive(Default)]
-struct StreamState {
-    finished: bool,
-    error: Option<String>,
+pub struct SessionBuilder {
+    backend: Option<Box<dyn ModelBackend>>,
+    instructions: Option<String>,
+    transcript: Option<Transcript>,
+    tools: Vec<Arc<dyn Tool>>,
+    context_policy: ContextPolicy,
 }
 
-// C Callbacks for response()
+impl SessionBuilder {
+    /// Uses `backend` instead of the system model
+    pub fn backend<B>(mut self, backend: B) -> Self
+    where
+        B: ModelBackend + 'static,
+    {
+        self.backend = Some(Box::new(backend));
+        self
+    }
 
-extern "C" fn response_callback(
-    chunk: *const std::os::raw::c_char,
-    user_data: *mut std::os::raw::c_void,
-) {
-    if chunk.is_null() || user_data.is_null() {
-        return;
+    /// Uses a configured system language model instead of the default one
+    ///
+    /// See [`LanguageModelSession::with_model`].
+    pub fn model(self, model: SystemLanguageModel) -> Self {
+        self.backend(SystemBackend::with_model(model))
     }
 
-    unsafe {
-        let state = &*(user_data as *const Arc<(Mutex<ResponseState>, Condvar)>);
-        let chunk_str = std::ffi::CStr::from_ptr(chunk).to_string_lossy();
+    /// Sets the developer instructions for the session
+    ///
+    /// See [`LanguageModelSession::with_instructions`].
+    pub fn instructions(mut self, instructions: impl Into<String>) -> Self {
+        self.instructions = Some(instructions.into());
+        self
+    }
 
-        let (mutex, _) = &**state;
-        if let Ok(mut response_state) = mutex.lock() {
-            response_state.text.push_str(&chunk_str);
-        }
+    /// Resumes the conversation recorded in `transcript`
+    ///
+    /// Instructions set on the builder are added to the front of the transcript;
+    /// a transcript that already has instructions cannot be given others.
+    /// See [`LanguageModelSession::with_transcript`].
+    pub fn transcript(mut self, transcript: Transcript) -> Self {
+        self.transcript = Some(transcript);
+        self
     }
-}
 
-extern "C" fn response_done_callback(user_data: *mut std::os::raw::c_void) {
-    if user_data.is_null() {
-        return;
+    /// Registers a tool the model may call while answering prompts
+    ///
+    /// See [`Tool`].
+    pub fn tool<T>(mut self, tool: T) -> Self
+    where
+        T: Tool + 'static,
+    {
+        self.tools.push(Arc::new(tool));
+        self
     }
 
-    unsafe {
-        let state = Box::from_raw(user_data as *mut Arc<(Mutex<ResponseState>, Condvar)>);
-        let state_arc = (*state).clone();
-        drop(state); // Drop the Box, but Arc is still alive
+    /// Sets how the session keeps its conversation within the context window
+    ///
+    /// Defaults to [`ContextPolicy::Fail`]. See [`ContextPolicy`].
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// # use fm_bindings::{ContextPolicy, LanguageModelSession};
+    /// # fn main() -> Result<(), Box<dyn std::error::Error>> {
+    /// let session = LanguageModelSession::builder()
+    ///     .instructions("You are a friendly tutor.")
+    ///     .context_policy(ContextPolicy::SlidingWindow)
+    ///     .build()?;
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn context_policy(mut self, policy: ContextPolicy) -> Self {
+        self.context_policy = policy;
+        self
+    }
 
-        let (mutex, cvar) = &*state_arc;
-        if let Ok(mut response_state) = mutex.lock() {
-            response_state.finished = true;
-            cvar.notify_all();
+    /// Creates the session
+    ///
+    /// This checks that the backend is available before returning the session.
+    ///
+    /// # Errors
+    ///
+    /// * `Error::ModelNotAvailable` - If the backend reports itself unavailable
+    /// * `Error::InvalidInput` - If the instructions or transcript contain a null
+    ///   byte, instructions are set on top of a transcript that has its own, or
+    ///   two tools have the same name
+    pub fn build(self) -> Result<LanguageModelSession> {
+        if let Some(instructions) = &self.instructions
+            && instructions.contains('\0')
+        {
+            return Err(Error::InvalidInput("Instructions contain null byte".into()));
         }
-    }
-}
 
-extern "C" fn response_error_callback(
-    error: *const std::os::raw::c_char,
-    user_data: *mut std::os::raw::c_void,
-) {
-    if user_data.is_null() {
-        return;
-    }
-
-    unsafe {
-        let state = Box::from_raw(user_data as *mut Arc<(Mutex<ResponseState>, Condvar)>);
-        let state_arc = (*state).clone();
-        drop(state); // Drop the Box, but Arc is still alive
-
-        let (mutex, cvar) = &*state_arc;
-        if let Ok(mut response_state) = mutex.lock() {
-            if !error.is_null() {
-                let error_str = std::ffi::CStr::from_ptr(error)
-                    .to_string_lossy()
-                    .into_owned();
-                response_state.error = Some(error_str);
+        let mut transcript = self.transcript.unwrap_or_default();
+        transcript.validate()?;
+        if let Some(instructions) = self.instructions {
+            if transcript.instructions().is_some() {
+                return Err(Error::InvalidInput(
+                    "Transcript already contains instructions".into(),
+                ));
             }
-
-            response_state.finished = true;
-            cvar.notify_all();
+            transcript.prepend(TranscriptEntry::instructions(instructions));
         }
-    }
-}
 
-// C Callbacks for stream_response()
+        for (index, tool) in self.tools.iter().enumerate() {
+            if self.tools[..index]
+                .iter()
+                .any(|other| other.name() == tool.name())
+            {
+                return Err(Error::InvalidInput(format!(
+                    "Tool {:?} is registered twice",
+                    tool.name()
+                )));
+            }
+        }
 
-type StreamCallback = Box<dyn FnMut(&str)>;
-type StreamUserData = (Arc<(Mutex<StreamState>, Condvar)>, StreamCallback);
+        let backend = self
+            .backend
+            .unwrap_or_else(|| Box::new(SystemBackend::new()));
 
-extern "C" fn stream_chunk_callback(
-    chunk: *const std::os::raw::c_char,
-    user_data: *mut std::os::raw::c_void,
-) {
-    if chunk.is_null() || user_data.is_null() {
-        return;
-    }
+        // Check availability before creating the session (fail-fast)
+        match backend.availability() {
+            Availability::Available => {}
+            reason => return Err(Error::ModelNotAvailable(reason)),
+        }
 
-    unsafe {
-        let data = &mut *(user_data as *mut StreamUserData);
-        let chunk_str = std::ffi::CStr::from_ptr(chunk).to_string_lossy();
-        (data.1)(&chunk_str);
+        Ok(LanguageModelSession {
+            backend: Arc::from(backend),
+            instructions: transcript.instructions().map(Arc::from),
+            transcript: Arc::new(Mutex::new(transcript)),
+            tools: self.tools.into(),
+            call_ids: Arc::default(),
+            context_policy: self.context_policy,
+            cancel_handle: None,
+        })
     }
 }
 
-extern "C" fn stream_done_callback(user_data: *mut std::os::raw::c_void) {
-    if user_data.is_null() {
-        return;
+/// Rejects prompts that no backend can accept
+fn validate_prompt(prompt: &str) -> Result<()> {
+    if prompt.is_empty() {
+        return Err(Error::InvalidInput("Prompt cannot be empty".into()));
     }
-
-    unsafe {
-        let data = Box::from_raw(user_data as *mut StreamUserData);
-        let (mutex, cvar) = &*data.0;
-        if let Ok(mut stream_state) = mutex.lock() {
-            stream_state.finished = true;
-            cvar.notify_all();
-        }
+    if prompt.contains('\0') {
+        return Err(Error::InvalidInput("Prompt contains null byte".into()));
     }
+    Ok(())
 }
 
-extern "C" fn stream_error_callback(
-    error: *const std::os::raw::c_char,
-    user_data: *mut std::os::raw::c_void,
-) {
-    if user_data.is_null() {
-        return;
-    }
-
-    unsafe {
-        let data = Box::from_raw(user_data as *mut StreamUserData);
-        let (mutex, cvar) = &*data.0;
-        if let Ok(mut stream_state) = mutex.lock() {
-            if !error.is_null() {
-                let error_str = std::ffi::CStr::from_ptr(error)
-                    .to_string_lossy()
-                    .into_owned();
-                stream_state.error = Some(error_str);
-            }
-
-            stream_state.finished = true;
-            cvar.notify_all();
-        }
+/// Completes a streamed response collected by the session
+///
+/// A cancelled stream reports the text the caller received, which is all the
+/// backend delivered before the handle was cancelled.
+fn finish_stream(result: Result<()>, response: String) -> Result<String> {
+    match result {
+        Ok(()) => Ok(response),
+        Err(Error::Cancelled { .. }) => Err(Error::Cancelled { partial: response }),
+        Err(error) => Err(error),
     }
 }
+
+/// Decodes a guided response as the type it was generated for
+fn decode<T: Generable>(json: &str) -> Result<T> {
+    serde_json::from_str(json).map_err(|e| {
+        Error::DecodingFailure(format!(
+            "Cannot decode response as {}: {}",
+            std::any::type_name::<T>(),
+            e
+        ))
+    })
+}
@@ -0,0 +1,898 @@
+// src/system.rs
+// System model backend - drives Apple's Foundation Models through the Swift bridge
+
+use super::backend::{GenerationRequest, ModelBackend};
+use super::cancel::CancelHook;
+use super::error::{Error, Result};
+use super::ffi;
+use super::model::{Availability, SystemLanguageModel};
+use super::refusal::Refusal;
+use super::schema::GenerationSchema;
+use super::tokens::TokenInput;
+use super::tool::Tool;
+use super::transcript::TranscriptEntry;
+use serde::Serialize;
+use serde_json::Value;
+use std::ffi::{CStr, CString};
+use std::os::raw::{c_char, c_void};
+use std::panic::{AssertUnwindSafe, catch_unwind};
+use std::ptr::NonNull;
+use std::sync::{Arc, Condvar, Mutex, MutexGuard, PoisonError};
+
+/// The on-device system language model, accessed through the Swift bridge
+///
+/// This is the backend used by [`LanguageModelSession::new`]. On platforms without
+/// the FoundationModels framework it reports itself as unavailable.
+///
+/// Each backend owns one Swift `LanguageModelSession`, created on the first request
+/// and released when the backend is dropped. The Swift session keeps the transcript,
+/// so every prompt sees the earlier turns of the conversation. A backend whose first
+/// request carries a saved transcript (see [`LanguageModelSession::with_transcript`])
+/// starts its Swift session from that transcript.
+///
+/// Guided requests (see [`LanguageModelSession::response_as`]) are generated with
+/// a `DynamicGenerationSchema` built from the request's schema.
+///
+/// The session's tools are registered on the Swift session when it is created.
+/// Foundation Models calls them through the bridge, on one of its own threads, while
+/// it generates a response.
+///
+/// [`LanguageModelSession::prewarm`] creates the Swift session ahead of the first
+/// request and asks Foundation Models to load the model.
+///
+/// [`SystemBackend::with_model`] selects the model configuration the Swift sessions
+/// are created on, for example a specialised [`UseCase`](crate::UseCase).
+///
+/// A request with a [`CancelHandle`](crate::CancelHandle) gets a Swift cancel token
+/// of its own, so cancelling it stops only that request, even while it is still
+/// waiting for an earlier request of the session to finish.
+///
+/// [`LanguageModelSession::new`]: crate::LanguageModelSession::new
+/// [`LanguageModelSession::with_transcript`]: crate::LanguageModelSession::with_transcript
+/// [`LanguageModelSession::response_as`]: crate::LanguageModelSession::response_as
+/// [`LanguageModelSession::prewarm`]: crate::LanguageModelSession::prewarm
+#[derive(Debug, Default)]
+pub struct SystemBackend {
+    model: SystemLanguageModel,
+    session: Mutex<Option<Arc<SessionHandle>>>,
+}
+
+impl SystemBackend {
+    /// Creates a backend for the default system language model
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Creates a backend for the given system language model
+    pub fn with_model(model: SystemLanguageModel) -> Self {
+        Self {
+            model,
+            session: Mutex::default(),
+        }
+    }
+
+    /// Returns the system language model the backend generates with
+    pub fn model(&self) -> &SystemLanguageModel {
+        &self.model
+    }
+
+    /// Streams a request through the bridge, passing on every chunk it delivers
+    ///
+    /// Chunks are text deltas, or whole JSON snapshots for guided requests.
+    fn stream(
+        &self,
+        request: &GenerationRequest<'_>,
+        on_chunk: &mut dyn FnMut(&str),
+    ) -> Result<()> {
+        // Create C strings for FFI
+        let session = self.session(request)?;
+        let (c_prompt, c_options, c_schema) = c_request(request)?;
+        let cancel = RequestCancel::new(request);
+        let _turn = session.tools.begin(request)?;
+
+        // Shared state for streaming
+        let state = Arc::new((Mutex::new(StreamState::default()), Condvar::new()));
+        let state_clone = Arc::clone(&state);
+
+        // Keep what was delivered, in case the request is cancelled
+        let snapshots = request.schema.is_some();
+        let mut partial = String::new();
+        let mut forward = |chunk: &str| {
+            if snapshots {
+                partial.clear();
+            }
+            partial.push_str(chunk);
+            on_chunk(chunk);
+        };
+        let on_chunk: &mut dyn FnMut(&str) = &mut forward;
+
+        // The callback only has to outlive this call: we block below until the
+        // bridge reports completion, after which Swift no longer touches it
+        let on_chunk: &mut (dyn FnMut(&str) + 'static) = unsafe { std::mem::transmute(on_chunk) };
+
+        // Call Swift FFI with streaming mode
+        unsafe {
+            ffi::fm_session_stream(
+                session.raw.as_ptr(),
+                c_prompt.as_ptr(),
+                c_options.as_ref().map_or(std::ptr::null(), |c| c.as_ptr()),
+                c_schema.as_ref().map_or(std::ptr::null(), |c| c.as_ptr()),
+                cancel.as_ptr(),
+                Box::into_raw(Box::new((state_clone, on_chunk))) as *mut _,
+                stream_chunk_callback,
+                stream_done_callback,
+                stream_error_callback,
+            );
+        }
+
+        // Wait for completion
+        let mut stream_state = wait_until_finished(&state, |state| state.finished)?;
+
+        // A cancelled request ends normally or with a cancellation error, either way
+        // it is reported as cancelled
+        if request.is_cancelled() {
+            return Err(Error::Cancelled { partial });
+        }
+
+        // Check for errors
+        if let Some(error) = stream_state.error.take() {
+            return Err(error.into_error(|| self.availability()));
+        }
+
+        Ok(())
+    }
+
+    /// Returns the Swift session, creating it from the request if needed
+    ///
+    /// Callers hold on to the handle until their FFI call returns, so a `reset` in
+    /// the meantime cannot destroy the Swift session or its tools under them.
+    fn session(&self, request: &GenerationRequest<'_>) -> Result<Arc<SessionHandle>> {
+        let mut session = self.session.lock().map_err(|_| Error::PoisonError)?;
+        if let Some(handle) = session.as_ref() {
+            return Ok(Arc::clone(handle));
+        }
+
+        let c_model = self.model.to_c_json()?;
+        let c_model_ptr = c_model.as_ref().map_or(std::ptr::null(), |c| c.as_ptr());
+
+        // The bridge calls the tools through a pointer to this list, which the
+        // handle keeps alive for as long as the Swift session
+        let tools = Box::new(SessionTools::default());
+        let c_tools = c_tools(request.tools)?;
+        let c_tools_ptr = c_tools.as_ref().map_or(std::ptr::null(), |c| c.as_ptr());
+        let tool_user_data = &*tools as *const SessionTools as *mut c_void;
+        let on_tool_call = c_tools
+            .as_ref()
+            .map(|_| tool_call_callback as ffi::ToolCallbackWithData);
+
+        // A fresh session only needs its instructions; a resumed one is rebuilt
+        // from the whole transcript so the Swift session sees the earlier turns
+        let resumed = request
+            .transcript
+            .iter()
+            .any(|entry| !matches!(entry, TranscriptEntry::Instructions { .. }));
+        let raw = if resumed {
+            let c_transcript = CString::new(request.transcript.to_json()?)
+                .map_err(|_| Error::InvalidInput("Transcript contains null byte".into()))?;
+            unsafe {
+                ffi::fm_session_create_from_transcript(
+                    c_model_ptr,
+                    c_transcript.as_ptr(),
+                    c_tools_ptr,
+                    tool_user_data,
+                    on_tool_call,
+                )
+            }
+        } else {
+            let c_instructions = request
+                .instructions
+                .map(CString::new)
+                .transpose()
+                .map_err(|_| Error::InvalidInput("Instructions contain null byte".into()))?;
+            unsafe {
+                ffi::fm_session_create(
+                    c_model_ptr,
+                    c_instructions
+                        .as_ref()
+                        .map_or(std::ptr::null(), |c| c.as_ptr()),
+                    c_tools_ptr,
+                    tool_user_data,
+                    on_tool_call,
+                )
+            }
+        };
+        let raw = NonNull::new(raw).ok_or_else(|| self.creation_error(resumed))?;
+
+        let handle = Arc::new(SessionHandle { raw, tools });
+        *session = Some(Arc::clone(&handle));
+        Ok(handle)
+    }
+
+    /// Explains why the bridge could not create a Swift session
+    fn creation_error(&self, resumed: bool) -> Error {
+        let reason = self.availability();
+        if !reason.is_available() {
+            return Error::ModelNotAvailable(reason);
+        }
+        // The adapter may have been moved or replaced since the model was created
+        if let Err(error) = self.model.validate_adapter() {
+            return error;
+        }
+        if resumed {
+            Error::InternalError("Cannot restore session from transcript".into())
+        } else {
+            Error::InternalError("Cannot register tools on the session".into())
+        }
+    }
+}
+
+/// Owning pointer to a Swift session created by `fm_session_create`
+///
+/// Also owns the tools registered on the Swift session, which are dropped after it.
+/// The backend and every request using the session share the handle, so the Swift
+/// session is destroyed once the last of them lets go.
+#[derive(Debug)]
+struct SessionHandle {
+    raw: NonNull<c_void>,
+    tools: Box<SessionTools>,
+}
+
+/// The tools of a Swift session, passed to the bridge as the tool callback's user data
+///
+/// The Swift session registers its tools once, but each request brings its own,
+/// recording their calls for that request only. Like the Swift session, this serves
+/// one request at a time: the request holds `turn` for its whole FFI call and the
+/// tool callback dispatches to the tools in `current`.
+#[derive(Debug, Default)]
+struct SessionTools {
+    turn: Arc<Turn>,
+    current: Mutex<Vec<Arc<dyn Tool>>>,
+}
+
+impl SessionTools {
+    /// Waits for the previous request to finish, then dispatches to the request's tools
+    ///
+    /// A request cancelled through its handle stops waiting and returns
+    /// `Error::Cancelled` with nothing generated.
+    fn begin(&self, request: &GenerationRequest<'_>) -> Result<TurnGuard<'_>> {
+        // Cancelling wakes the waiting request. The hook takes the lock before it
+        // notifies, so the wakeup cannot fall between the check and the wait.
+        let _hook = request.cancel_handle.map(|handle| {
+            let turn = Arc::clone(&self.turn);
+            handle.on_cancel(move || {
+                let _busy = turn.lock_busy();
+                turn.freed.notify_all();
+            })
+        });
+
+        let mut busy = self.turn.lock_busy();
+        while *busy {
+            if request.is_cancelled() {
+                return Err(Error::Cancelled {
+                    partial: String::new(),
+                });
+            }
+            busy = self
+                .turn
+                .freed
+                .wait(busy)
+                .unwrap_or_else(PoisonError::into_inner);
+        }
+        *busy = true;
+        drop(busy);
+
+        *self.current() = request.tools.to_vec();
+        Ok(TurnGuard { turn: &self.turn })
+    }
+
+    // The list is only replaced whole, so it stays usable after a panic
+    fn current(&self) -> MutexGuard<'_, Vec<Arc<dyn Tool>>> {
+        self.current.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+}
+
+/// Whether a request is using the Swift session, signalled when it lets go
+#[derive(Debug, Default)]
+struct Turn {
+    busy: Mutex<bool>,
+    freed: Condvar,
+}
+
+impl Turn {
+    // The flag is only ever set whole, so it stays usable after a panic
+    fn lock_busy(&self) -> MutexGuard<'_, bool> {
+        self.busy.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+}
+
+/// Hands the Swift session to the next waiting request when dropped
+struct TurnGuard<'a> {
+    turn: &'a Turn,
+}
+
+impl Drop for TurnGuard<'_> {
+    fn drop(&mut self) {
+        *self.turn.lock_busy() = false;
+        self.turn.freed.notify_all();
+    }
+}
+
+// The Swift side serializes requests per session and guards its own state,
+// so the handle can be shared across threads
+unsafe impl Send for SessionHandle {}
+unsafe impl Sync for SessionHandle {}
+
+impl Drop for SessionHandle {
+    fn drop(&mut self) {
+        unsafe {
+            ffi::fm_session_destroy(self.raw.as_ptr());
+        }
+    }
+}
+
+/// Owning pointer to a Swift cancel token created by `fm_cancel_token_create`
+#[derive(Debug)]
+struct CancelToken {
+    raw: NonNull<c_void>,
+}
+
+// The Swift token guards its state with a lock
+unsafe impl Send for CancelToken {}
+unsafe impl Sync for CancelToken {}
+
+impl Drop for CancelToken {
+    fn drop(&mut self) {
+        unsafe {
+            ffi::fm_cancel_token_destroy(self.raw.as_ptr());
+        }
+    }
+}
+
+/// The Swift cancel token of one request, cancelled along with its [`CancelHandle`]
+///
+/// The token is released once the request is over and the handle's hook is gone.
+///
+/// [`CancelHandle`]: crate::CancelHandle
+struct RequestCancel<'a> {
+    token: Option<Arc<CancelToken>>,
+    _hook: Option<CancelHook<'a>>,
+}
+
+impl<'a> RequestCancel<'a> {
+    /// Creates a token for a request with a cancel handle; without one, nothing
+    fn new(request: &GenerationRequest<'a>) -> Self {
+        let Some(handle) = request.cancel_handle else {
+            return Self {
+                token: None,
+                _hook: None,
+            };
+        };
+        let token = NonNull::new(unsafe { ffi::fm_cancel_token_create() })
+            .map(|raw| Arc::new(CancelToken { raw }));
+        let hook = token.clone().map(|token| {
+            handle.on_cancel(move || unsafe { ffi::fm_cancel_token_cancel(token.raw.as_ptr()) })
+        });
+        Self { token, _hook: hook }
+    }
+
+    /// The token to pass to the bridge, or null
+    fn as_ptr(&self) -> *mut c_void {
+        self.token
+            .as_ref()
+            .map_or(std::ptr::null_mut(), |token| token.raw.as_ptr())
+    }
+}
+
+/// Owning pointer to a Swift refusal passed to an error callback
+///
+/// Kept by [`Refusal`] so that its explanation can be requested later.
+#[derive(Debug)]
+pub(crate) struct RefusalHandle {
+    raw: NonNull<c_void>,
+}
+
+// Swift refusals are immutable and Sendable
+unsafe impl Send for RefusalHandle {}
+unsafe impl Sync for RefusalHandle {}
+
+impl RefusalHandle {
+    /// Asks the system model why it declined the request
+    pub(crate) fn explanation(&self) -> Result<String> {
+        let state = Arc::new((Mutex::new(ResponseState::default()), Condvar::new()));
+        let state_clone = Arc::clone(&state);
+
+        unsafe {
+            ffi::fm_refusal_explanation(
+                self.raw.as_ptr(),
+                Box::into_raw(Box::new(state_clone)) as *mut _,
+                response_callback,
+                response_done_callback,
+                response_error_callback,
+            );
+        }
+
+        // Wait for completion
+        let mut response_state = wait_until_finished(&state, |state| state.finished)?;
+
+        if let Some(error) = response_state.error.take() {
+            return Err(error.into_error(|| SystemLanguageModel::default().availability()));
+        }
+        Ok(std::mem::take(&mut response_state.text))
+    }
+}
+
+impl Drop for RefusalHandle {
+    fn drop(&mut self) {
+        unsafe {
+            ffi::fm_refusal_destroy(self.raw.as_ptr());
+        }
+    }
+}
+
+impl ModelBackend for SystemBackend {
+    fn is_available(&self) -> bool {
+        self.availability().is_available()
+    }
+
+    fn availability(&self) -> Availability {
+        self.model.availability()
+    }
+
+    // The Swift session is created again, from the request's transcript, on the next
+    // request. Requests still running on the old one keep it alive until they return.
+    fn reset(&self) {
+        if let Ok(mut session) = self.session.lock() {
+            session.take();
+        }
+    }
+
+    fn context_size(&self) -> usize {
+        self.model.context_size()
+    }
+
+    fn token_count(&self, input: TokenInput<'_>) -> Result<usize> {
+        self.model.token_count(input)
+    }
+
+    fn response(&self, request: &GenerationRequest<'_>) -> Result<String> {
+        // Create C strings for FFI
+        let session = self.session(request)?;
+        let (c_prompt, c_options, c_schema) = c_request(request)?;
+        let cancel = RequestCancel::new(request);
+        let _turn = session.tools.begin(request)?;
+
+        // Shared state for collecting response
+        // Guided requests deliver whole snapshots, so the last one is the response
+        let state = Arc::new((
+            Mutex::new(ResponseState {
+                snapshots: c_schema.is_some(),
+                ..ResponseState::default()
+            }),
+            Condvar::new(),
+        ));
+        let state_clone = Arc::clone(&state);
+
+        // Call Swift FFI with blocking response mode
+        unsafe {
+            ffi::fm_session_response(
+                session.raw.as_ptr(),
+                c_prompt.as_ptr(),
+                c_options.as_ref().map_or(std::ptr::null(), |c| c.as_ptr()),
+                c_schema.as_ref().map_or(std::ptr::null(), |c| c.as_ptr()),
+                cancel.as_ptr(),
+                Box::into_raw(Box::new(state_clone)) as *mut _,
+                response_callback,
+                response_done_callback,
+                response_error_callback,
+            );
+        }
+
+        // Wait for completion
+        let mut response_state = wait_until_finished(&state, |state| state.finished)?;
+
+        if request.is_cancelled() {
+            return Err(Error::Cancelled {
+                partial: std::mem::take(&mut response_state.text),
+            });
+        }
+
+        // Check for errors
+        if let Some(error) = response_state.error.take() {
+            return Err(error.into_error(|| self.availability()));
+        }
+
+        Ok(response_state.text.clone())
+    }
+
+    fn stream_response(
+        &self,
+        request: &GenerationRequest<'_>,
+        on_chunk: &mut dyn FnMut(&str),
+    ) -> Result<()> {
+        // The bridge streams guided responses as snapshots, not deltas; deliver the
+        // finished JSON document as a single chunk
+        if request.schema.is_some() {
+            on_chunk(&self.response(request)?);
+            return Ok(());
+        }
+        self.stream(request, on_chunk)
+    }
+
+    fn stream_snapshots(
+        &self,
+        request: &GenerationRequest<'_>,
+        on_snapshot: &mut dyn FnMut(&str),
+    ) -> Result<()> {
+        // Guided responses already arrive as snapshots
+        if request.schema.is_some() {
+            return self.stream(request, on_snapshot);
+        }
+        let mut text = String::new();
+        self.stream(request, &mut |chunk| {
+            text.push_str(chunk);
+            on_snapshot(&text);
+        })
+    }
+
+    fn prewarm(&self, request: &GenerationRequest<'_>) -> Result<()> {
+        // Creating the Swift session is part of the cost of the first request
+        let session = self.session(request)?;
+        let c_prefix = (!request.prompt.is_empty())
+            .then(|| CString::new(request.prompt))
+            .transpose()
+            .map_err(|_| Error::InvalidInput("Prompt prefix contains null byte".into()))?;
+        unsafe {
+            ffi::fm_session_prewarm(
+                session.raw.as_ptr(),
+                c_prefix.as_ref().map_or(std::ptr::null(), |c| c.as_ptr()),
+            );
+        }
+        Ok(())
+    }
+
+    fn cancel_stream(&self) {
+        if let Ok(session) = self.session.lock()
+            && let Some(handle) = session.as_ref()
+        {
+            unsafe {
+                ffi::fm_session_cancel(handle.raw.as_ptr());
+            }
+        }
+    }
+}
+
+/// C strings of a request: prompt, options and schema
+type CRequest = (CString, Option<CString>, Option<CString>);
+
+/// Converts the prompt, options and schema of a request for the FFI
+///
+/// Default options are passed as null so the bridge keeps the model's defaults,
+/// and a missing schema as null for free-form text.
+fn c_request(request: &GenerationRequest<'_>) -> Result<CRequest> {
+    let c_prompt = CString::new(request.prompt)
+        .map_err(|_| Error::InvalidInput("Prompt contains null byte".into()))?;
+
+    let c_options = if request.options.is_default() {
+        None
+    } else {
+        let json = serde_json::to_string(request.options)
+            .map_err(|e| Error::InternalError(format!("Cannot serialize options: {}", e)))?;
+        Some(CString::new(json).map_err(|e| Error::InternalError(e.to_string()))?)
+    };
+
+    let c_schema = request
+        .schema
+        .map(|schema| {
+            let json = serde_json::to_string(schema)
+                .map_err(|e| Error::InternalError(format!("Cannot serialize schema: {}", e)))?;
+            CString::new(json).map_err(|_| Error::InvalidInput("Schema contains null byte".into()))
+        })
+        .transpose()?;
+
+    Ok((c_prompt, c_options, c_schema))
+}
+
+/// Definition of a tool, as the bridge registers it on the Swift session
+#[derive(Serialize)]
+struct ToolDefinition<'a> {
+    name: &'a str,
+    description: &'a str,
+    parameters: GenerationSchema,
+}
+
+/// Serializes tool definitions for the FFI, or returns `None` when there are no tools
+fn c_tools(tools: &[Arc<dyn Tool>]) -> Result<Option<CString>> {
+    if tools.is_empty() {
+        return Ok(None);
+    }
+    let definitions: Vec<ToolDefinition<'_>> = tools
+        .iter()
+        .map(|tool| ToolDefinition {
+            name: tool.name(),
+            description: tool.description(),
+            parameters: tool.arguments_schema(),
+        })
+        .collect();
+    let json = serde_json::to_string(&definitions)
+        .map_err(|e| Error::InternalError(format!("Cannot serialize tools: {}", e)))?;
+    CString::new(json)
+        .map(Some)
+        .map_err(|_| Error::InvalidInput("Tool definition contains null byte".into()))
+}
+
+// Internal State Types
+
+/// An error reported by the Swift bridge through an error callback
+struct BridgeError {
+    kind: i32,
+    message: String,
+    refusal: Option<RefusalHandle>,
+}
+
+impl BridgeError {
+    /// Takes ownership of the arguments of an error callback
+    ///
+    /// # Safety
+    ///
+    /// `message` must be null or a valid C string, and `detail` null or a refusal
+    /// handle not owned by anything else.
+    unsafe fn new(kind: i32, message: *const c_char, detail: *mut c_void) -> Self {
+        let message = if message.is_null() {
+            String::new()
+        } else {
+            unsafe { CStr::from_ptr(message) }
+                .to_string_lossy()
+                .into_owned()
+        };
+        Self {
+            kind,
+            message,
+            refusal: NonNull::new(detail).map(|raw| RefusalHandle { raw }),
+        }
+    }
+
+    /// Maps the error kind onto an `Error`
+    ///
+    /// The bridge does not say why the model is unavailable, so the reason is
+    /// looked up again with `availability`.
+    fn into_error(self, availability: impl FnOnce() -> Availability) -> Error {
+        let message = self.message;
+        match self.kind {
+            ffi::ERROR_MODEL_NOT_AVAILABLE => match availability() {
+                Availability::Available => Error::ModelNotAvailable(Availability::Other(message)),
+                reason => Error::ModelNotAvailable(reason),
+            },
+            ffi::ERROR_INTERNAL => Error::InternalError(message),
+            ffi::ERROR_INVALID_INPUT => Error::InvalidInput(message),
+            ffi::ERROR_CONTEXT_WINDOW_EXCEEDED => Error::ContextWindowExceeded(message),
+            ffi::ERROR_GUARDRAIL_VIOLATION => Error::GuardrailViolation(message),
+            ffi::ERROR_UNSUPPORTED_LANGUAGE => Error::UnsupportedLanguage(message),
+            ffi::ERROR_ASSETS_UNAVAILABLE => Error::AssetsUnavailable(message),
+            ffi::ERROR_RATE_LIMITED => Error::RateLimited(message),
+            ffi::ERROR_CONCURRENT_REQUESTS => Error::ConcurrentRequests(message),
+            ffi::ERROR_REFUSAL => Error::Refusal(match self.refusal {
+                Some(handle) => Refusal::from_system(message, handle),
+                None => Refusal::new(message),
+            }),
+            ffi::ERROR_DECODING_FAILURE => Error::DecodingFailure(message),
+            ffi::ERROR_GENERATION => Error::GenerationError(message),
+            // Kinds added by a newer bridge are reported as generation errors too
+            _ => Error::GenerationError(message),
+        }
+    }
+}
+
+/// Blocks until the bridge marks `state` as finished
+///
+/// Never returns earlier, since the bridge holds the callbacks' user data until it
+/// reports completion. A poisoned lock is reported only once the wait is over.
+fn wait_until_finished<T>(
+    state: &(Mutex<T>, Condvar),
+    finished: impl Fn(&T) -> bool,
+) -> Result<MutexGuard<'_, T>> {
+    let (mutex, cvar) = state;
+    let mut poisoned = false;
+    let mut guard = mutex.lock().unwrap_or_else(|error| {
+        poisoned = true;
+        error.into_inner()
+    });
+    while !finished(&guard) {
+        guard = cvar.wait(guard).unwrap_or_else(|error| {
+            poisoned = true;
+            error.into_inner()
+        });
+    }
+    if poisoned {
+        return Err(Error::PoisonError);
+    }
+    Ok(guard)
+}
+
+#[derive(Default)]
+struct ResponseState {
+    text: String,
+    snapshots: bool,
+    finished: bool,
+    error: Option<BridgeError>,
+}
+
+#[derive(Default)]
+struct StreamState {
+    finished: bool,
+    error: Option<BridgeError>,
+}
+
+// C Callbacks for response()
+
+extern "C" fn response_callback(
+    chunk: *const std::os::raw::c_char,
+    user_data: *mut std::os::raw::c_void,
+) {
+    if chunk.is_null() || user_data.is_null() {
+        return;
+    }
+
+    unsafe {
+        let state = &*(user_data as *const Arc<(Mutex<ResponseState>, Condvar)>);
+        let chunk_str = std::ffi::CStr::from_ptr(chunk).to_string_lossy();
+
+        let (mutex, _) = &**state;
+        let mut response_state = mutex.lock().unwrap_or_else(PoisonError::into_inner);
+        if response_state.snapshots {
+            response_state.text.clear();
+        }
+        response_state.text.push_str(&chunk_str);
+    }
+}
+
+extern "C" fn response_done_callback(user_data: *mut std::os::raw::c_void) {
+    if user_data.is_null() {
+        return;
+    }
+
+    unsafe {
+        let state = Box::from_raw(user_data as *mut Arc<(Mutex<ResponseState>, Condvar)>);
+        let state_arc = (*state).clone();
+        drop(state); // Drop the Box, but Arc is still alive
+
+        let (mutex, cvar) = &*state_arc;
+        let mut response_state = mutex.lock().unwrap_or_else(PoisonError::into_inner);
+        response_state.finished = true;
+        cvar.notify_all();
+    }
+}
+
+extern "C" fn response_error_callback(
+    kind: i32,
+    error: *const std::os::raw::c_char,
+    detail: *mut std::os::raw::c_void,
+    user_data: *mut std::os::raw::c_void,
+) {
+    // Take ownership of the refusal handle first so it is released in every case
+    let error = unsafe { BridgeError::new(kind, error, detail) };
+    if user_data.is_null() {
+        return;
+    }
+
+    unsafe {
+        let state = Box::from_raw(user_data as *mut Arc<(Mutex<ResponseState>, Condvar)>);
+        let state_arc = (*state).clone();
+        drop(state); // Drop the Box, but Arc is still alive
+
+        let (mutex, cvar) = &*state_arc;
+        let mut response_state = mutex.lock().unwrap_or_else(PoisonError::into_inner);
+        response_state.error = Some(error);
+
+        response_state.finished = true;
+        cvar.notify_all();
+    }
+}
+
+// C Callbacks for stream_response()
+
+type StreamCallback = &'static mut dyn FnMut(&str);
+type StreamUserData = (Arc<(Mutex<StreamState>, Condvar)>, StreamCallback);
+
+extern "C" fn stream_chunk_callback(
+    chunk: *const std::os::raw::c_char,
+    user_data: *mut std::os::raw::c_void,
+) {
+    if chunk.is_null() || user_data.is_null() {
+        return;
+    }
+
+    unsafe {
+        let data = &mut *(user_data as *mut StreamUserData);
+        let chunk_str = std::ffi::CStr::from_ptr(chunk).to_string_lossy();
+        (data.1)(&chunk_str);
+    }
+}
+
+extern "C" fn stream_done_callback(user_data: *mut std::os::raw::c_void) {
+    if user_data.is_null() {
+        return;
+    }
+
+    unsafe {
+        let data = Box::from_raw(user_data as *mut StreamUserData);
+        let (mutex, cvar) = &*data.0;
+        let mut stream_state = mutex.lock().unwrap_or_else(PoisonError::into_inner);
+        stream_state.finished = true;
+        cvar.notify_all();
+    }
+}
+
+extern "C" fn stream_error_callback(
+    kind: i32,
+    error: *const std::os::raw::c_char,
+    detail: *mut std::os::raw::c_void,
+    user_data: *mut std::os::raw::c_void,
+) {
+    // Take ownership of the refusal handle first so it is released in every case
+    let error = unsafe { BridgeError::new(kind, error, detail) };
+    if user_data.is_null() {
+        return;
+    }
+
+    unsafe {
+        let data = Box::from_raw(user_data as *mut StreamUserData);
+        let (mutex, cvar) = &*data.0;
+        let mut stream_state = mutex.lock().unwrap_or_else(PoisonError::into_inner);
+        stream_state.error = Some(error);
+
+        stream_state.finished = true;
+        cvar.notify_all();
+    }
+}
+
+// C Callback for tools
+
+/// Runs the tool the model called and reports its output to the bridge
+///
+/// `user_data` is the session's tool list, holding the running request's tools. A
+/// tool that panics is reported as failed, since unwinding into Swift would abort.
+extern "C" fn tool_call_callback(
+    name: *const c_char,
+    arguments: *const c_char,
+    user_data: *mut c_void,
+    context: *mut c_void,
+    on_result: ffi::ToolResultCallback,
+) {
+    let result = if name.is_null() || arguments.is_null() || user_data.is_null() {
+        Err("Invalid tool call".to_string())
+    } else {
+        unsafe {
+            // Not held while the tool runs, which may take a while
+            let tools = (*(user_data as *const SessionTools)).current().clone();
+            let name = CStr::from_ptr(name).to_string_lossy();
+            let arguments = CStr::from_ptr(arguments).to_string_lossy();
+            call_tool(&tools, &name, &arguments)
+        }
+    };
+
+    let (output, is_error) = match result {
+        Ok(output) => (output, false),
+        Err(message) => (message, true),
+    };
+    // Interior null bytes cannot cross the FFI; drop them rather than the output
+    let c_output = CString::new(output.replace('\0', "")).unwrap_or_default();
+    on_result(c_output.as_ptr(), is_error, context);
+}
+
+fn call_tool(
+    tools: &[Arc<dyn Tool>],
+    name: &str,
+    arguments: &str,
+) -> std::result::Result<String, String> {
+    let tool = tools
+        .iter()
+        .find(|tool| tool.name() == name)
+        .ok_or_else(|| format!("Unknown tool {:?}", name))?;
+    let arguments: Value = serde_json::from_str(arguments)
+        .map_err(|e| format!("Invalid arguments for tool {:?}: {}", name, e))?;
+    match catch_unwind(AssertUnwindSafe(|| tool.call(arguments))) {
+        Ok(Ok(output)) => Ok(output),
+        Ok(Err(error)) => Err(error.to_string()),
+        Err(_) => Err(format!("Tool {:?} panicked", name)),
+    }
+}
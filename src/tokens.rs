@@ -0,0 +1,140 @@
+// src/tokens.rs
+// Token estimation - heuristic token counts for budgeting the context window
+
+//! Heuristic token counting
+//!
+//! Backends that cannot count tokens with the model's own tokenizer fall back on
+//! [`estimate`] and [`estimate_transcript`]: the system model before macOS 26.4 /
+//! iOS 26.4 and on other platforms, and any [`ModelBackend`] that does not override
+//! [`token_count`](crate::ModelBackend::token_count).
+//!
+//! The estimate follows Apple's guidance of three to four characters per token in
+//! English and about one token per character in Chinese, Japanese and Korean:
+//!
+//! * Every run of letters and digits costs one token per four characters, rounded up
+//! * Every CJK ideograph, kana and hangul syllable costs one token
+//! * Every other visible character (punctuation, symbols, emoji) costs one token
+//! * Whitespace is free
+//! * Every transcript entry costs [`ENTRY_OVERHEAD`] more tokens for its role markers
+//!
+//! Rounding up per word makes estimates slightly high for prose, so a budget
+//! checked against them errs on the safe side.
+//!
+//! ```
+//! use fm_bindings::tokens;
+//!
+//! assert_eq!(tokens::estimate("Hello, world!"), 6);
+//! assert_eq!(tokens::estimate("你好"), 2);
+//! ```
+//!
+//! [`ModelBackend`]: crate::ModelBackend
+
+use super::transcript::{Transcript, TranscriptEntry};
+
+/// Context size of the on-device system model, in tokens
+///
+/// Used when the platform cannot report the size itself.
+pub const DEFAULT_CONTEXT_SIZE: usize = 4096;
+
+/// Tokens added for every transcript entry, for the markers around its text
+pub const ENTRY_OVERHEAD: usize = 4;
+
+/// Characters per token in runs of letters and digits
+const CHARS_PER_TOKEN: usize = 4;
+
+/// Text or a transcript to count the tokens of
+///
+/// Created from `&str`, `&String` or `&Transcript`, so token counting methods accept
+/// either directly.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum TokenInput<'a> {
+    /// A prompt or other text
+    Text(&'a str),
+
+    /// Every entry of a transcript
+    Transcript(&'a Transcript),
+}
+
+impl TokenInput<'_> {
+    /// Returns the heuristic token count of the input
+    pub fn estimate(&self) -> usize {
+        match self {
+            TokenInput::Text(text) => estimate(text),
+            TokenInput::Transcript(transcript) => estimate_transcript(transcript),
+        }
+    }
+}
+
+impl<'a> From<&'a str> for TokenInput<'a> {
+    fn from(text: &'a str) -> Self {
+        TokenInput::Text(text)
+    }
+}
+
+impl<'a> From<&'a String> for TokenInput<'a> {
+    fn from(text: &'a String) -> Self {
+        TokenInput::Text(text)
+    }
+}
+
+impl<'a> From<&'a Transcript> for TokenInput<'a> {
+    fn from(transcript: &'a Transcript) -> Self {
+        TokenInput::Transcript(transcript)
+    }
+}
+
+/// Estimates the number of tokens in `text`
+pub fn estimate(text: &str) -> usize {
+    let mut tokens = 0;
+    let mut run: usize = 0;
+    for c in text.chars() {
+        if c.is_alphanumeric() && !is_cjk(c) {
+            run += 1;
+            continue;
+        }
+        tokens += run.div_ceil(CHARS_PER_TOKEN);
+        run = 0;
+        if !c.is_whitespace() {
+            tokens += 1;
+        }
+    }
+    tokens + run.div_ceil(CHARS_PER_TOKEN)
+}
+
+/// Estimates the number of tokens in every entry of `transcript`
+pub fn estimate_transcript(transcript: &Transcript) -> usize {
+    count_transcript(transcript, estimate)
+}
+
+/// Counts the tokens of a transcript with a counter for its texts
+///
+/// Tool call arguments are counted as their JSON.
+pub(crate) fn count_transcript(
+    transcript: &Transcript,
+    mut count: impl FnMut(&str) -> usize,
+) -> usize {
+    transcript
+        .iter()
+        .map(|entry| {
+            let mut tokens = ENTRY_OVERHEAD;
+            tokens += entry.texts().into_iter().map(&mut count).sum::<usize>();
+            if let TranscriptEntry::ToolCall { arguments, .. } = entry {
+                tokens += count(&arguments.to_string());
+            }
+            tokens
+        })
+        .sum()
+}
+
+/// Returns true for characters that are about one token each
+fn is_cjk(c: char) -> bool {
+    matches!(c,
+        '\u{1100}'..='\u{11FF}'     // Hangul Jamo
+        | '\u{3040}'..='\u{30FF}'   // Hiragana and Katakana
+        | '\u{3400}'..='\u{4DBF}'   // CJK Extension A
+        | '\u{4E00}'..='\u{9FFF}'   // CJK Unified Ideographs
+        | '\u{AC00}'..='\u{D7AF}'   // Hangul Syllables
+        | '\u{F900}'..='\u{FAFF}'   // CJK Compatibility Ideographs
+        | '\u{20000}'..='\u{2FFFF}' // Supplementary ideographs
+    )
+}
@@ -0,0 +1,213 @@
+// src/tool.rs
+// Tools - Rust functions the model can call while generating a response
+
+use super::error::{Error, Result};
+use super::schema::GenerationSchema;
+use super::transcript::TranscriptEntry;
+use serde_json::Value;
+use std::fmt;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex, MutexGuard, PoisonError};
+
+/// A function the model can call to look up data or take actions
+///
+/// Register tools on a session with [`SessionBuilder::tool`]. While answering a
+/// prompt the model decides when to call a tool, generates arguments matching
+/// [`arguments_schema`](Self::arguments_schema), and continues its response with
+/// the tool's output. Calls and outputs are recorded in the session transcript.
+///
+/// A tool that fails ends the request on the system model: the error is reported
+/// to the model's framework as a failed tool call, and the session returns
+/// `Error::ToolCallFailed` naming the tool. Backends whose model can read the error
+/// instead, such as the OpenAI-compatible one, send it back as the tool's output
+/// and carry on. A tool that wants the model to recover (for example by asking
+/// the user for a valid city) should return the problem as its output.
+///
+/// Tools may be called from another thread than the one making the request.
+///
+/// [`SessionBuilder::tool`]: crate::SessionBuilder::tool
+///
+/// # Examples
+///
+/// ```no_run
+/// use fm_bindings::{GenerationSchema, LanguageModelSession, Property, Result, Tool};
+/// use serde_json::Value;
+///
+/// struct Weather;
+///
+/// impl Tool for Weather {
+///     fn name(&self) -> &str {
+///         "get_weather"
+///     }
+///
+///     fn description(&self) -> &str {
+///         "Returns the current weather in a city"
+///     }
+///
+///     fn arguments_schema(&self) -> GenerationSchema {
+///         GenerationSchema::object("WeatherArguments")
+///             .with_property(Property::new("city", GenerationSchema::string()))
+///     }
+///
+///     fn call(&self, arguments: Value) -> Result<String> {
+///         Ok(format!("18°C and sunny in {}", arguments["city"]))
+///     }
+/// }
+///
+/// # fn main() -> Result<()> {
+/// let session = LanguageModelSession::builder().tool(Weather).build()?;
+/// let response = session.response("Should I bring an umbrella in Paris today?")?;
+/// # Ok(())
+/// # }
+/// ```
+pub trait Tool: Send + Sync {
+    /// Unique name the model calls the tool by
+    fn name(&self) -> &str;
+
+    /// Explains to the model what the tool does and when to use it
+    fn description(&self) -> &str;
+
+    /// Schema of the arguments, usually an object schema
+    fn arguments_schema(&self) -> GenerationSchema;
+
+    /// Runs the tool with arguments generated by the model
+    ///
+    /// # Errors
+    ///
+    /// An error ends the request with `Error::ToolCallFailed`, unless the backend
+    /// hands it to the model as the tool's output.
+    fn call(&self, arguments: Value) -> Result<String>;
+}
+
+impl fmt::Debug for dyn Tool {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Tool").field("name", &self.name()).finish()
+    }
+}
+
+/// The tool calls made while answering one prompt
+///
+/// Every request gets a journal of its own, so concurrent requests on clones of a
+/// session never see each other's calls. Call ids come from a counter shared by
+/// the session, which keeps them unique across the transcript.
+#[derive(Debug)]
+pub(crate) struct ToolJournal {
+    entries: Vec<TranscriptEntry>,
+    failure: Option<(String, Error)>,
+    call_ids: Arc<AtomicU64>,
+}
+
+impl ToolJournal {
+    /// Creates an empty journal numbering its calls with `call_ids`
+    pub(crate) fn new(call_ids: Arc<AtomicU64>) -> Self {
+        Self {
+            entries: Vec::new(),
+            failure: None,
+            call_ids,
+        }
+    }
+
+    /// Records a call of the tool `name`, returning its id
+    fn call(&mut self, name: &str, arguments: Value) -> String {
+        let id = format!("call-{}", self.call_ids.fetch_add(1, Ordering::Relaxed) + 1);
+        self.entries.push(TranscriptEntry::ToolCall {
+            id: id.clone(),
+            name: name.to_string(),
+            arguments,
+        });
+        id
+    }
+
+    /// Records the result of call `id`
+    ///
+    /// A failed call still gets an output, in case the model is shown the error.
+    fn output(&mut self, id: String, name: &str, result: &Result<String>) {
+        let output = match result {
+            Ok(output) => output.clone(),
+            Err(error) => {
+                self.failure = Some((name.to_string(), error.clone()));
+                error.to_string()
+            }
+        };
+        self.entries.push(TranscriptEntry::ToolOutput {
+            id,
+            name: name.to_string(),
+            output,
+        });
+    }
+
+    /// Records a call made earlier, with its result, without running the tool
+    pub(crate) fn replay(&mut self, name: &str, arguments: Value, result: &Result<String>) {
+        let id = self.call(name, arguments);
+        self.output(id, name, result);
+    }
+
+    /// Takes the recorded calls and outputs, in order
+    pub(crate) fn take(&mut self) -> Vec<TranscriptEntry> {
+        std::mem::take(&mut self.entries)
+    }
+
+    /// The error to report for a failed request: `ToolCallFailed` if a tool failed
+    pub(crate) fn failure(&mut self, error: Error) -> Error {
+        match self.failure.take() {
+            Some((tool, cause)) => Error::ToolCallFailed {
+                tool,
+                message: cause.to_string(),
+            },
+            None => error,
+        }
+    }
+}
+
+/// A session's tool, recording its calls in the journal of one request
+pub(crate) struct RecordedTool {
+    tool: Arc<dyn Tool>,
+    journal: Arc<Mutex<ToolJournal>>,
+}
+
+impl RecordedTool {
+    /// Wraps each of `tools` to record its calls in `journal`
+    pub(crate) fn wrap(
+        tools: &[Arc<dyn Tool>],
+        journal: &Arc<Mutex<ToolJournal>>,
+    ) -> Vec<Arc<dyn Tool>> {
+        tools
+            .iter()
+            .map(|tool| {
+                Arc::new(Self {
+                    tool: tool.clone(),
+                    journal: journal.clone(),
+                }) as Arc<dyn Tool>
+            })
+            .collect()
+    }
+
+    // Entries are only appended, so the journal stays usable after a panic
+    fn journal(&self) -> MutexGuard<'_, ToolJournal> {
+        self.journal.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+}
+
+impl Tool for RecordedTool {
+    fn name(&self) -> &str {
+        self.tool.name()
+    }
+
+    fn description(&self) -> &str {
+        self.tool.description()
+    }
+
+    fn arguments_schema(&self) -> GenerationSchema {
+        self.tool.arguments_schema()
+    }
+
+    fn call(&self, arguments: Value) -> Result<String> {
+        let name = self.tool.name();
+        let id = self.journal().call(name, arguments.clone());
+
+        // The journal is not held while the tool runs, which may take a while
+        let result = self.tool.call(arguments);
+        self.journal().output(id, name, &result);
+        result
+    }
+}
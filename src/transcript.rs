@@ -0,0 +1,207 @@
+// src/transcript.rs
+// Transcript - the record of everything exchanged within a session
+
+use super::error::{Error, Result};
+use serde::{Deserialize, Serialize};
+
+/// The ordered record of a session: instructions, prompts, responses and tool activity
+///
+/// Mirrors the entries of a Foundation Models `Transcript`. A session appends to its
+/// transcript as the conversation goes on; [`LanguageModelSession::transcript`]
+/// returns a snapshot, and [`LanguageModelSession::with_transcript`] resumes a
+/// conversation from one. Transcripts serialize with serde, so they can be persisted
+/// between runs of an application.
+///
+/// [`LanguageModelSession::transcript`]: crate::LanguageModelSession::transcript
+/// [`LanguageModelSession::with_transcript`]: crate::LanguageModelSession::with_transcript
+///
+/// # Examples
+///
+/// ```
+/// use fm_bindings::{Transcript, TranscriptEntry};
+///
+/// # fn main() -> fm_bindings::Result<()> {
+/// let mut transcript = Transcript::new();
+/// transcript.push(TranscriptEntry::prompt("Hi"));
+/// transcript.push(TranscriptEntry::response("Hello!"));
+///
+/// let json = transcript.to_json()?;
+/// assert_eq!(Transcript::from_json(&json)?, transcript);
+/// # Ok(())
+/// # }
+/// ```
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct Transcript {
+    entries: Vec<TranscriptEntry>,
+}
+
+/// A single entry of a [`Transcript`]
+///
+/// Serialized with a `type` tag, e.g. `{"type":"prompt","text":"Hi"}`.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+#[non_exhaustive]
+pub enum TranscriptEntry {
+    /// Developer instructions the session was created with
+    Instructions { text: String },
+
+    /// A prompt sent to the model
+    Prompt { text: String },
+
+    /// A response generated by the model, possibly partial if it was cancelled
+    Response { text: String },
+
+    /// A call the model made to a tool, with JSON arguments
+    ToolCall {
+        id: String,
+        name: String,
+        arguments: serde_json::Value,
+    },
+
+    /// The output a tool returned to the model for the call with the same `id`
+    ToolOutput {
+        id: String,
+        name: String,
+        output: String,
+    },
+}
+
+impl TranscriptEntry {
+    /// Creates an instructions entry
+    pub fn instructions(text: impl Into<String>) -> Self {
+        Self::Instructions { text: text.into() }
+    }
+
+    /// Creates a prompt entry
+    pub fn prompt(text: impl Into<String>) -> Self {
+        Self::Prompt { text: text.into() }
+    }
+
+    /// Creates a response entry
+    pub fn response(text: impl Into<String>) -> Self {
+        Self::Response { text: text.into() }
+    }
+
+    /// Returns every string of the entry that is handed to the model
+    pub(crate) fn texts(&self) -> Vec<&str> {
+        match self {
+            Self::Instructions { text } | Self::Prompt { text } | Self::Response { text } => {
+                vec![text]
+            }
+            Self::ToolCall { id, name, .. } => vec![id, name],
+            Self::ToolOutput { id, name, output } => vec![id, name, output],
+        }
+    }
+}
+
+impl Transcript {
+    /// Creates an empty transcript
+    pub const fn new() -> Self {
+        Self {
+            entries: Vec::new(),
+        }
+    }
+
+    /// Returns the entries, oldest first
+    pub fn entries(&self) -> &[TranscriptEntry] {
+        &self.entries
+    }
+
+    /// Appends an entry
+    pub fn push(&mut self, entry: TranscriptEntry) {
+        self.entries.push(entry);
+    }
+
+    /// Returns the number of entries
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    /// Returns true if the transcript has no entries
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    /// Returns an iterator over the entries, oldest first
+    pub fn iter(&self) -> std::slice::Iter<'_, TranscriptEntry> {
+        self.entries.iter()
+    }
+
+    /// Returns the instructions recorded in the transcript, if any
+    pub fn instructions(&self) -> Option<&str> {
+        self.entries.iter().find_map(|entry| match entry {
+            TranscriptEntry::Instructions { text } => Some(text.as_str()),
+            _ => None,
+        })
+    }
+
+    /// Serializes the transcript to JSON
+    ///
+    /// # Errors
+    ///
+    /// Returns `Error::InternalError` if serialization fails.
+    pub fn to_json(&self) -> Result<String> {
+        serde_json::to_string(self)
+            .map_err(|e| Error::InternalError(format!("Cannot serialize transcript: {}", e)))
+    }
+
+    /// Parses a transcript previously produced by [`to_json`](Self::to_json)
+    ///
+    /// # Errors
+    ///
+    /// Returns `Error::InvalidInput` if `json` is not a valid transcript.
+    pub fn from_json(json: &str) -> Result<Self> {
+        serde_json::from_str(json)
+            .map_err(|e| Error::InvalidInput(format!("Invalid transcript: {}", e)))
+    }
+
+    /// Rejects transcripts that cannot be handed to a model
+    pub(crate) fn validate(&self) -> Result<()> {
+        let has_null_byte = self
+            .entries
+            .iter()
+            .flat_map(TranscriptEntry::texts)
+            .any(|text| text.contains('\0'));
+        if has_null_byte {
+            return Err(Error::InvalidInput("Transcript contains null byte".into()));
+        }
+        Ok(())
+    }
+
+    /// Inserts an entry before all others
+    pub(crate) fn prepend(&mut self, entry: TranscriptEntry) {
+        self.entries.insert(0, entry);
+    }
+}
+
+impl From<Vec<TranscriptEntry>> for Transcript {
+    fn from(entries: Vec<TranscriptEntry>) -> Self {
+        Self { entries }
+    }
+}
+
+impl FromIterator<TranscriptEntry> for Transcript {
+    fn from_iter<I: IntoIterator<Item = TranscriptEntry>>(iter: I) -> Self {
+        Self {
+            entries: iter.into_iter().collect(),
+        }
+    }
+}
+
+impl<'a> IntoIterator for &'a Transcript {
+    type Item = &'a TranscriptEntry;
+    type IntoIter = std::slice::Iter<'a, TranscriptEntry>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.entries.iter()
+    }
+}
+
+impl IntoIterator for Transcript {
+    type Item = TranscriptEntry;
+    type IntoIter = std::vec::IntoIter<TranscriptEntry>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.entries.into_iter()
+    }
+}
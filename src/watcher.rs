@@ -0,0 +1,259 @@
+// src/watcher.rs
+// Availability watcher - notifies when the system model becomes usable
+
+use super::error::{Error, Result};
+use super::model::{Availability, SystemLanguageModel};
+use std::sync::mpsc::{self, Receiver};
+use std::sync::{Arc, Condvar, Mutex};
+use std::thread::{self, JoinHandle};
+use std::time::Duration;
+
+/// Polling interval used unless [`AvailabilityWatcher::with_interval`] says otherwise
+const DEFAULT_INTERVAL: Duration = Duration::from_secs(5);
+
+/// Something whose [`Availability`] can be polled
+///
+/// Implemented by [`SystemLanguageModel`] and by closures returning an
+/// `Availability`, which makes it easy to watch a fake source in tests.
+pub trait AvailabilitySource: Send + Sync {
+    /// Returns the current availability
+    fn availability(&self) -> Availability;
+}
+
+impl AvailabilitySource for SystemLanguageModel {
+    fn availability(&self) -> Availability {
+        SystemLanguageModel::availability(self)
+    }
+}
+
+impl<F> AvailabilitySource for F
+where
+    F: Fn() -> Availability + Send + Sync,
+{
+    fn availability(&self) -> Availability {
+        self()
+    }
+}
+
+/// A change in availability reported by an [`AvailabilityWatcher`]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct AvailabilityChange {
+    /// The availability before the change, or `None` for the initial state
+    pub previous: Option<Availability>,
+
+    /// The availability now
+    pub current: Availability,
+}
+
+impl AvailabilityChange {
+    /// Returns true for the event reporting the state when watching started
+    pub fn is_initial(&self) -> bool {
+        self.previous.is_none()
+    }
+
+    /// Returns true if the model was unavailable and can now serve requests
+    pub fn became_available(&self) -> bool {
+        self.current.is_available()
+            && !self
+                .previous
+                .as_ref()
+                .is_some_and(Availability::is_available)
+    }
+}
+
+/// Polls the availability of a model and reports every change
+///
+/// After Apple Intelligence is switched on, the model assets download in the
+/// background and sessions cannot be created until they are ready. A watcher lets
+/// an app enable its AI features the moment that happens.
+///
+/// The source is polled on a background thread, every five seconds by default.
+/// Unless disabled with [`with_initial_event`](Self::with_initial_event), the first
+/// event reports the state when watching started; later events are sent only when
+/// the availability differs from the previous poll.
+///
+/// # Examples
+///
+/// ```no_run
+/// use fm_bindings::AvailabilityWatcher;
+/// use std::time::Duration;
+///
+/// # fn main() -> fm_bindings::Result<()> {
+/// let (_watch, changes) = AvailabilityWatcher::system()
+///     .with_interval(Duration::from_secs(2))
+///     .channel()?;
+///
+/// for change in changes {
+///     if change.became_available() {
+///         println!("The model is ready");
+///         break;
+///     }
+///     println!("Not ready yet: {}", change.current);
+/// }
+/// # Ok(())
+/// # }
+/// ```
+pub struct AvailabilityWatcher {
+    source: Box<dyn AvailabilitySource>,
+    interval: Duration,
+    initial_event: bool,
+}
+
+impl AvailabilityWatcher {
+    /// Creates a watcher for the given source
+    pub fn new(source: impl AvailabilitySource + 'static) -> Self {
+        Self {
+            source: Box::new(source),
+            interval: DEFAULT_INTERVAL,
+            initial_event: true,
+        }
+    }
+
+    /// Creates a watcher for the default system language model
+    pub fn system() -> Self {
+        Self::new(SystemLanguageModel::default())
+    }
+
+    /// Sets the time between two polls
+    pub fn with_interval(mut self, interval: Duration) -> Self {
+        self.interval = interval;
+        self
+    }
+
+    /// Sets whether the state when watching starts is reported as a first event
+    ///
+    /// Enabled by default.
+    pub fn with_initial_event(mut self, initial_event: bool) -> Self {
+        self.initial_event = initial_event;
+        self
+    }
+
+    /// Starts watching, calling `on_change` on the watcher's thread for every change
+    ///
+    /// Watching continues until the returned handle is stopped or dropped.
+    ///
+    /// # Errors
+    ///
+    /// * `Error::InvalidInput` - If the polling interval is zero
+    /// * `Error::InternalError` - If the watcher's thread cannot be started
+    pub fn watch<F>(self, mut on_change: F) -> Result<WatchHandle>
+    where
+        F: FnMut(AvailabilityChange) + Send + 'static,
+    {
+        self.spawn(move |change| {
+            on_change(change);
+            true
+        })
+    }
+
+    /// Starts watching, sending every change to the returned receiver
+    ///
+    /// Watching continues until the handle is stopped or dropped, or the receiver
+    /// is dropped. The receiver's iterator ends once watching has stopped.
+    ///
+    /// # Errors
+    ///
+    /// * `Error::InvalidInput` - If the polling interval is zero
+    /// * `Error::InternalError` - If the watcher's thread cannot be started
+    pub fn channel(self) -> Result<(WatchHandle, Receiver<AvailabilityChange>)> {
+        let (sender, receiver) = mpsc::channel();
+        let handle = self.spawn(move |change| sender.send(change).is_ok())?;
+        Ok((handle, receiver))
+    }
+
+    /// Runs the polling loop on a new thread until `notify` returns false or the
+    /// handle stops it
+    fn spawn<F>(self, mut notify: F) -> Result<WatchHandle>
+    where
+        F: FnMut(AvailabilityChange) -> bool + Send + 'static,
+    {
+        if self.interval.is_zero() {
+            return Err(Error::InvalidInput(
+                "Polling interval must not be zero".into(),
+            ));
+        }
+
+        let stop = Arc::new((Mutex::new(false), Condvar::new()));
+        let stop_clone = Arc::clone(&stop);
+        let thread = thread::Builder::new()
+            .name("fm-availability-watcher".into())
+            .spawn(move || {
+                let mut last = self.source.availability();
+                if self.initial_event
+                    && !notify(AvailabilityChange {
+                        previous: None,
+                        current: last.clone(),
+                    })
+                {
+                    return;
+                }
+
+                while !wait_for_stop(&stop_clone, self.interval) {
+                    let current = self.source.availability();
+                    if current == last {
+                        continue;
+                    }
+                    let change = AvailabilityChange {
+                        previous: Some(std::mem::replace(&mut last, current.clone())),
+                        current,
+                    };
+                    if !notify(change) {
+                        return;
+                    }
+                }
+            })
+            .map_err(|e| Error::InternalError(format!("Cannot start watcher thread: {}", e)))?;
+
+        Ok(WatchHandle {
+            stop,
+            thread: Some(thread),
+        })
+    }
+}
+
+/// Waits up to `timeout` for a stop request, returning true if one was made
+fn wait_for_stop(stop: &(Mutex<bool>, Condvar), timeout: Duration) -> bool {
+    let (mutex, cvar) = stop;
+    let Ok(stopped) = mutex.lock() else {
+        return true;
+    };
+    cvar.wait_timeout_while(stopped, timeout, |stopped| !*stopped)
+        .map_or(true, |(stopped, _)| *stopped)
+}
+
+/// Keeps an [`AvailabilityWatcher`] running
+///
+/// Dropping the handle stops the watcher, like [`stop`](Self::stop).
+#[derive(Debug)]
+pub struct WatchHandle {
+    stop: Arc<(Mutex<bool>, Condvar)>,
+    thread: Option<JoinHandle<()>>,
+}
+
+impl WatchHandle {
+    /// Stops watching and waits for the watcher's thread to finish
+    ///
+    /// No events are delivered after this returns.
+    pub fn stop(mut self) {
+        self.shutdown();
+    }
+
+    fn shutdown(&mut self) {
+        let (mutex, cvar) = &*self.stop;
+        if let Ok(mut stopped) = mutex.lock() {
+            *stopped = true;
+            cvar.notify_all();
+        }
+        if let Some(thread) = self.thread.take()
+            && thread.thread().id() != thread::current().id()
+        {
+            let _ = thread.join();
+        }
+    }
+}
+
+impl Drop for WatchHandle {
+    fn drop(&mut self) {
+        self.shutdown();
+    }
+}
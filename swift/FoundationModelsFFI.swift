@@ -5,105 +5,860 @@
 import Foundation
 import FoundationModels
 
-// MARK: - Global State
-// We keep task references so we can cancel if needed
-// In production, you'd manage multiple concurrent sessions with IDs
-private var responseTask: Task<Void, Never>?
-private var streamTask: Task<Void, Never>?
+// MARK: - Session Handles
+/// Owns one Swift LanguageModelSession on behalf of a Rust session
+///
+/// Rust holds a retained, opaque pointer to the box (see fm_session_create and
+/// fm_session_destroy), so the Swift session and its transcript live exactly as
+/// long as the Rust value. Each box tracks its own in-flight task so that
+/// cancelling one session never affects another.
+final class SessionBox {
+    let session: LanguageModelSession
+
+    // Serializes requests: a LanguageModelSession handles one request at a time
+    let requestLock = NSLock()
+
+    // Guards `task`, which is read by fm_session_cancel from other threads
+    private let taskLock = NSLock()
+    private var task: Task<Void, Never>?
+
+    init(session: LanguageModelSession) {
+        self.session = session
+    }
+
+    func setTask(_ newTask: Task<Void, Never>?) {
+        taskLock.lock()
+        defer { taskLock.unlock() }
+        task = newTask
+    }
+
+    func cancel() {
+        taskLock.lock()
+        defer { taskLock.unlock() }
+        task?.cancel()
+    }
+}
+
+private func sessionBox(_ handle: UnsafeMutableRawPointer) -> SessionBox {
+    Unmanaged<SessionBox>.fromOpaque(handle).takeUnretainedValue()
+}
+
+/// Cancels the one request it is passed to, unlike SessionBox.cancel()
+///
+/// Rust creates a token per cancellable request (see fm_cancel_token_create) and
+/// cancels it from any thread. A token cancelled before its request has started,
+/// for example while the request waits for the session's lock, cancels the task
+/// as soon as it is attached.
+final class CancelTokenBox {
+    private let lock = NSLock()
+    private var cancelled = false
+    private var task: Task<Void, Never>?
+
+    var isCancelled: Bool {
+        lock.lock()
+        defer { lock.unlock() }
+        return cancelled
+    }
+
+    func attach(_ newTask: Task<Void, Never>) {
+        lock.lock()
+        defer { lock.unlock() }
+        task = newTask
+        if cancelled { newTask.cancel() }
+    }
 
-// Locks to serialize FFI calls and prevent race conditions
-// when multiple threads call the FFI functions concurrently
-private let responseLock = NSLock()
-private let streamLock = NSLock()
+    func cancel() {
+        lock.lock()
+        defer { lock.unlock() }
+        cancelled = true
+        task?.cancel()
+    }
+}
+
+private func cancelTokenBox(_ handle: UnsafeMutableRawPointer?) -> CancelTokenBox? {
+    handle.map { Unmanaged<CancelTokenBox>.fromOpaque($0).takeUnretainedValue() }
+}
 
 // MARK: - C Function Pointer Types
 // These match the callback signatures Rust will pass to us
 public typealias ChunkCallbackWithData = @convention(c) (UnsafePointer<CChar>?, UnsafeMutableRawPointer?) -> Void
 public typealias DoneCallbackWithData = @convention(c) (UnsafeMutableRawPointer?) -> Void
-public typealias ErrorCallbackWithData = @convention(c) (UnsafePointer<CChar>?, UnsafeMutableRawPointer?) -> Void
+public typealias ErrorCallbackWithData = @convention(c) (
+    Int32, UnsafePointer<CChar>?, UnsafeMutableRawPointer?, UnsafeMutableRawPointer?
+) -> Void
+public typealias ToolResultCallback = @convention(c) (UnsafePointer<CChar>?, Bool, UnsafeMutableRawPointer?) -> Void
+public typealias ToolCallbackWithData = @convention(c) (
+    UnsafePointer<CChar>?, UnsafePointer<CChar>?, UnsafeMutableRawPointer?, UnsafeMutableRawPointer?, ToolResultCallback
+) -> Void
+public typealias AvailabilityCallback = @convention(c) (Int32, UnsafePointer<CChar>?, UnsafeMutableRawPointer?) -> Void
+public typealias AdapterErrorCallback = @convention(c) (Int32, UnsafePointer<CChar>?, UnsafeMutableRawPointer?) -> Void
+
+// MARK: - Error Kinds
+/// Kind of a failure reported through ErrorCallbackWithData
+///
+/// Lets Rust tell errors apart without parsing localized messages.
+/// Must match the ERROR_* constants in src/ffi.rs
+enum FFIErrorKind: Int32 {
+    case generation = 0
+    case internalError = 1
+    case invalidInput = 2
+    case modelNotAvailable = 3
+    case contextWindowExceeded = 4
+    case guardrailViolation = 5
+    case unsupportedLanguage = 6
+    case assetsUnavailable = 7
+    case rateLimited = 8
+    case concurrentRequests = 9
+    case refusal = 10
+    case decodingFailure = 11
+
+    /// Classifies an error thrown while generating
+    ///
+    /// See: https://developer.apple.com/documentation/FoundationModels/LanguageModelSession/GenerationError
+    init(_ error: Error) {
+        guard let generationError = error as? LanguageModelSession.GenerationError else {
+            self = .generation
+            return
+        }
+        switch generationError {
+        case .exceededContextWindowSize: self = .contextWindowExceeded
+        case .guardrailViolation: self = .guardrailViolation
+        case .unsupportedLanguageOrLocale: self = .unsupportedLanguage
+        case .assetsUnavailable: self = .assetsUnavailable
+        case .rateLimited: self = .rateLimited
+        case .concurrentRequests: self = .concurrentRequests
+        case .refusal: self = .refusal
+        case .decodingFailure: self = .decodingFailure
+        case .unsupportedGuide: self = .invalidInput
+        @unknown default: self = .generation
+        }
+    }
+}
+
+/// Reports an error of the given kind through the Rust callback
+///
+/// A refusal is handed over as a retained RefusalBox, which Rust releases with fm_refusal_destroy
+private func reportError(
+    _ kind: FFIErrorKind,
+    _ message: String,
+    _ userData: UnsafeMutableRawPointer?,
+    _ onError: ErrorCallbackWithData?,
+    refusal: LanguageModelSession.GenerationError.Refusal? = nil
+) {
+    guard let onError = onError else { return }
+    let detail = refusal.map { Unmanaged.passRetained(RefusalBox($0)).toOpaque() }
+    message.withCString { cString in
+        onError(kind.rawValue, cString, detail, userData)
+    }
+}
+
+// MARK: - Tools
+/// Error thrown by a RustTool whose Rust implementation failed
+///
+/// Foundation Models wraps it in LanguageModelSession.ToolCallError, which ends the request
+struct RustToolError: Error, LocalizedError {
+    let message: String
+
+    var errorDescription: String? { message }
+}
+
+/// Receives the result a Rust tool reports through ToolResultCallback
+private final class ToolResult {
+    var output = ""
+    var isError = false
+}
+
+/// A tool implemented in Rust
+///
+/// The model's arguments are handed to Rust as JSON through the tool callback, which
+/// runs the tool and reports its output before returning. The callback blocks, so the
+/// tool runs on the thread Foundation Models calls it from.
+///
+/// See: https://developer.apple.com/documentation/FoundationModels/Tool
+final class RustTool: Tool, @unchecked Sendable {
+    typealias Arguments = GeneratedContent
+    typealias Output = String
+
+    let name: String
+    let description: String
+    let parameters: GenerationSchema
+
+    // Owned by the Rust session handle, which every request keeps alive until it
+    // returns, so the pointer outlives any call made during a request
+    private let userData: UnsafeMutableRawPointer?
+    private let onCall: ToolCallbackWithData
+
+    init(
+        name: String,
+        description: String,
+        parameters: GenerationSchema,
+        userData: UnsafeMutableRawPointer?,
+        onCall: @escaping ToolCallbackWithData
+    ) {
+        self.name = name
+        self.description = description
+        self.parameters = parameters
+        self.userData = userData
+        self.onCall = onCall
+    }
+
+    func call(arguments: GeneratedContent) async throws -> String {
+        let result = ToolResult()
+        let context = Unmanaged.passUnretained(result).toOpaque()
+        name.withCString { cName in
+            arguments.jsonString.withCString { cArguments in
+                onCall(cName, cArguments, userData, context) { output, isError, context in
+                    guard let context = context else { return }
+                    let result = Unmanaged<ToolResult>.fromOpaque(context).takeUnretainedValue()
+                    result.output = output.flatMap { String(utf8String: $0) } ?? ""
+                    result.isError = isError
+                }
+            }
+        }
+
+        if result.isError {
+            throw RustToolError(message: result.output)
+        }
+        return result.output
+    }
+}
+
+/// Builds the tools of a session from their Rust definitions
+///
+/// The JSON is an array of {"name", "description", "parameters"}, where parameters
+/// is a serialized GenerationSchema (see decodeSchemaNode). No JSON means no tools.
+private func decodeTools(
+    _ toolsJSON: UnsafePointer<CChar>?,
+    userData: UnsafeMutableRawPointer?,
+    onCall: ToolCallbackWithData?
+) -> [any Tool]? {
+    guard let toolsJSON = toolsJSON else { return [] }
+    guard let onCall = onCall,
+          let json = String(utf8String: toolsJSON),
+          let data = json.data(using: .utf8),
+          let rawTools = try? JSONSerialization.jsonObject(with: data) as? [[String: Any]] else {
+        return nil
+    }
+
+    var tools: [any Tool] = []
+    for raw in rawTools {
+        guard let name = raw["name"] as? String,
+              let description = raw["description"] as? String,
+              let rawParameters = raw["parameters"] as? [String: Any],
+              let root = decodeSchemaNode(rawParameters),
+              let parameters = try? GenerationSchema(root: root, dependencies: []) else {
+            return nil
+        }
+        tools.append(RustTool(
+            name: name,
+            description: description,
+            parameters: parameters,
+            userData: userData,
+            onCall: onCall
+        ))
+    }
+    return tools
+}
+
+// MARK: - Model Decoding
+/// Builds the SystemLanguageModel described by its Rust serialization
+///
+/// The JSON has the keys use_case, one of "general" or "content_tagging",
+/// guardrails, one of "default" or "permissive_content_transformations", and
+/// optionally adapter, the path of an .fmadapter package that replaces the use case.
+/// No JSON means SystemLanguageModel.default.
+///
+/// See: https://developer.apple.com/documentation/FoundationModels/SystemLanguageModel/init(useCase:guardrails:)
+private func decodeModel(_ modelJSON: UnsafePointer<CChar>?) -> SystemLanguageModel? {
+    guard let modelJSON = modelJSON else { return SystemLanguageModel.default }
+    guard let json = String(utf8String: modelJSON),
+          let data = json.data(using: .utf8),
+          let object = try? JSONSerialization.jsonObject(with: data) as? [String: Any] else {
+        return nil
+    }
+
+    let useCase: SystemLanguageModel.UseCase
+    switch object["use_case"] as? String {
+    case "general":
+        useCase = .general
+    case "content_tagging":
+        useCase = .contentTagging
+    default:
+        return nil
+    }
+
+    let guardrails: SystemLanguageModel.Guardrails
+    switch object["guardrails"] as? String {
+    case "default", nil:
+        guardrails = .default
+    case "permissive_content_transformations":
+        guardrails = .permissiveContentTransformations
+    default:
+        return nil
+    }
+
+    if let adapterPath = object["adapter"] as? String {
+        guard let adapter = try? SystemLanguageModel.Adapter(fileURL: URL(fileURLWithPath: adapterPath)) else {
+            return nil
+        }
+        return SystemLanguageModel(adapter: adapter, guardrails: guardrails)
+    }
+    return SystemLanguageModel(useCase: useCase, guardrails: guardrails)
+}
+
+// MARK: - Adapters
+/// Checks that an adapter package can be loaded
+///
+/// - Parameters:
+///   - path: C string with the path of an .fmadapter package
+///   - userData: Opaque pointer passed to onError
+///   - onError: Called exactly once, before returning, if the adapter cannot be loaded
+/// - Returns: true if the adapter can be loaded
+///
+/// Error codes (must match the ADAPTER_ERROR_* constants in ffi.rs):
+/// 1 invalid asset, 2 invalid adapter name, 3 no compatible adapter, 4 other
+///
+/// See: https://developer.apple.com/documentation/FoundationModels/SystemLanguageModel/Adapter
+@_cdecl("fm_adapter_validate")
+public func fm_adapter_validate(
+    _ path: UnsafePointer<CChar>?,
+    _ userData: UnsafeMutableRawPointer?,
+    _ onError: AdapterErrorCallback
+) -> Bool {
+    guard let path = path.flatMap({ String(utf8String: $0) }) else {
+        "Invalid adapter path".withCString { cString in
+            onError(4, cString, userData)
+        }
+        return false
+    }
+
+    do {
+        _ = try SystemLanguageModel.Adapter(fileURL: URL(fileURLWithPath: path))
+        return true
+    } catch let error as SystemLanguageModel.Adapter.AssetError {
+        let code: Int32
+        switch error {
+        case .invalidAsset:
+            code = 1
+        case .invalidAdapterName:
+            code = 2
+        case .compatibleAdapterNotFound:
+            code = 3
+        @unknown default:
+            code = 4
+        }
+        error.localizedDescription.withCString { cString in
+            onError(code, cString, userData)
+        }
+        return false
+    } catch {
+        error.localizedDescription.withCString { cString in
+            onError(4, cString, userData)
+        }
+        return false
+    }
+}
 
 // MARK: - Availability Check
-/// Checks if the Foundation Model is available on this system
+/// Reports the availability of a system model
 ///
-/// - Returns: true if the model is available, false otherwise
+/// - Parameters:
+///   - modelJSON: C string with a serialized SystemLanguageModel (null for the default model)
+///   - userData: Opaque pointer passed to onAvailability
+///   - onAvailability: Called exactly once, before returning, with the status code and,
+///     for unknown reasons, a description of the reason
+///
+/// Status codes (must match the AVAILABILITY_* constants in ffi.rs):
+/// 0 available, 1 device not eligible, 2 Apple Intelligence not enabled,
+/// 3 model not ready, 4 other
 ///
-/// This checks SystemLanguageModel.default.isAvailable which requires:
-/// - macOS 26+ or iOS 26+ with Apple Intelligence enabled
-@_cdecl("fm_check_availability")
-public func fm_check_availability() -> Bool {
-    let systemModel = SystemLanguageModel.default
-    return systemModel.isAvailable
+/// See: https://developer.apple.com/documentation/FoundationModels/SystemLanguageModel/Availability-swift.enum
+@_cdecl("fm_model_availability")
+public func fm_model_availability(
+    _ modelJSON: UnsafePointer<CChar>?,
+    _ userData: UnsafeMutableRawPointer?,
+    _ onAvailability: AvailabilityCallback
+) {
+    guard let model = decodeModel(modelJSON) else {
+        "Invalid model configuration".withCString { cString in
+            onAvailability(4, cString, userData)
+        }
+        return
+    }
+
+    switch model.availability {
+    case .available:
+        onAvailability(0, nil, userData)
+    case .unavailable(.deviceNotEligible):
+        onAvailability(1, nil, userData)
+    case .unavailable(.appleIntelligenceNotEnabled):
+        onAvailability(2, nil, userData)
+    case .unavailable(.modelNotReady):
+        onAvailability(3, nil, userData)
+    case .unavailable(let reason):
+        String(describing: reason).withCString { cString in
+            onAvailability(4, cString, userData)
+        }
+    }
 }
 
-// MARK: - Blocking Response
-/// Generates a complete response from the Foundation Model (blocking mode)
+// MARK: - Token Counting
+/// Returns the size of a model's context window in tokens
+///
+/// Returns -1 if the model cannot be decoded or the OS predates the API, in which
+/// case Rust uses its default size.
 ///
 /// - Parameters:
-///   - prompt: C string with the user's prompt
-///   - userData: Opaque pointer passed to all callbacks
-///   - onChunk: Called for each chunk of text generated
-///   - onDone: Called when generation completes successfully
-///   - onError: Called if an error occurs (passes error message)
+///   - modelJSON: C string with a serialized SystemLanguageModel (null for the default model)
 ///
-/// See: https://developer.apple.com/documentation/FoundationModels/LanguageModelSession/respond(to:)
-@_cdecl("fm_response")
-public func fm_response(
+/// See: https://developer.apple.com/documentation/FoundationModels/SystemLanguageModel/contextSize
+@_cdecl("fm_model_context_size")
+public func fm_model_context_size(_ modelJSON: UnsafePointer<CChar>?) -> Int64 {
+    guard #available(macOS 26.4, iOS 26.4, *), let model = decodeModel(modelJSON) else {
+        return -1
+    }
+    return Int64(model.contextSize)
+}
+
+/// Receives the result of a token count from its task
+private final class TokenCount {
+    var value: Int64 = -1
+}
+
+/// Counts the tokens of a text or a transcript with a model's tokenizer (blocking)
+///
+/// Returns -1 if the tokens cannot be counted, in which case Rust falls back on
+/// its heuristic estimate.
+///
+/// - Parameters:
+///   - modelJSON: C string with a serialized SystemLanguageModel (null for the default model)
+///   - text: C string with the text to count, or null
+///   - transcriptJSON: C string with a transcript serialized by the Rust `Transcript` type,
+///     counted when text is null
+///
+/// See: https://developer.apple.com/documentation/FoundationModels/SystemLanguageModel/tokenCount(for:)
+@_cdecl("fm_model_token_count")
+public func fm_model_token_count(
+    _ modelJSON: UnsafePointer<CChar>?,
+    _ text: UnsafePointer<CChar>?,
+    _ transcriptJSON: UnsafePointer<CChar>?
+) -> Int64 {
+    // 1. Token counting needs the model's tokenizer API
+    guard #available(macOS 26.4, iOS 26.4, *), let model = decodeModel(modelJSON) else {
+        return -1
+    }
+
+    // 2. Decode the input
+    let textString = text.flatMap { String(utf8String: $0) }
+    let transcript = transcriptJSON.flatMap { String(utf8String: $0) }.flatMap(decodeTranscript)
+    if textString == nil && transcript == nil {
+        return -1
+    }
+
+    // 3. Count on a task, blocking until it completes
+    let count = TokenCount()
+    let semaphore = DispatchSemaphore(value: 0)
+    Task {
+        defer { semaphore.signal() }
+
+        if let textString = textString {
+            if let tokens = try? await model.tokenCount(for: Prompt(textString)) {
+                count.value = Int64(tokens)
+            }
+        } else if let transcript = transcript {
+            if let tokens = try? await model.tokenCount(for: Array(transcript)) {
+                count.value = Int64(tokens)
+            }
+        }
+    }
+    semaphore.wait()
+    return count.value
+}
+
+// MARK: - Session Lifecycle
+/// Creates a session on a system model and returns an opaque handle to it
+///
+/// - Parameters:
+///   - modelJSON: Optional C string with a serialized SystemLanguageModel (null for the default model)
+///   - instructions: Optional C string with the session instructions (null for none)
+///   - toolsJSON: Optional C string with the tool definitions (null for none)
+///   - toolUserData: Opaque pointer passed back to onToolCall
+///   - onToolCall: Called when the model calls one of the tools
+/// - Returns: A retained handle, to be released with fm_session_destroy, or null if
+///   the model or the tools cannot be decoded
+///
+/// Instructions are passed through LanguageModelSession(model:tools:instructions:) so the
+/// model keeps them separate from (and above) the user's prompts
+///
+/// See: https://developer.apple.com/documentation/FoundationModels/LanguageModelSession/init(model:tools:instructions:)
+@_cdecl("fm_session_create")
+public func fm_session_create(
+    _ modelJSON: UnsafePointer<CChar>?,
+    _ instructions: UnsafePointer<CChar>?,
+    _ toolsJSON: UnsafePointer<CChar>?,
+    _ toolUserData: UnsafeMutableRawPointer?,
+    _ onToolCall: ToolCallbackWithData?
+) -> UnsafeMutableRawPointer? {
+    guard let model = decodeModel(modelJSON),
+          let tools = decodeTools(toolsJSON, userData: toolUserData, onCall: onToolCall) else {
+        return nil
+    }
+
+    let session: LanguageModelSession
+    if let instructions = instructions.flatMap({ String(utf8String: $0) }) {
+        session = LanguageModelSession(model: model, tools: tools, instructions: instructions)
+    } else {
+        session = LanguageModelSession(model: model, tools: tools)
+    }
+
+    return Unmanaged.passRetained(SessionBox(session: session)).toOpaque()
+}
+
+/// Creates a session that resumes a saved conversation
+///
+/// - Parameters:
+///   - modelJSON: As for fm_session_create
+///   - transcriptJSON: C string with a transcript serialized by the Rust `Transcript` type
+///   - toolsJSON, toolUserData, onToolCall: As for fm_session_create
+/// - Returns: A retained handle, to be released with fm_session_destroy, or null if
+///   the model, the transcript or the tools cannot be decoded
+///
+/// See: https://developer.apple.com/documentation/FoundationModels/LanguageModelSession/init(model:tools:transcript:)
+@_cdecl("fm_session_create_from_transcript")
+public func fm_session_create_from_transcript(
+    _ modelJSON: UnsafePointer<CChar>?,
+    _ transcriptJSON: UnsafePointer<CChar>?,
+    _ toolsJSON: UnsafePointer<CChar>?,
+    _ toolUserData: UnsafeMutableRawPointer?,
+    _ onToolCall: ToolCallbackWithData?
+) -> UnsafeMutableRawPointer? {
+    guard let model = decodeModel(modelJSON),
+          let json = transcriptJSON.flatMap({ String(utf8String: $0) }),
+          let transcript = decodeTranscript(json),
+          let tools = decodeTools(toolsJSON, userData: toolUserData, onCall: onToolCall) else {
+        return nil
+    }
+
+    let session = LanguageModelSession(model: model, tools: tools, transcript: transcript)
+    return Unmanaged.passRetained(SessionBox(session: session)).toOpaque()
+}
+
+// MARK: - Options Decoding
+/// Builds GenerationOptions from their Rust serialization
+///
+/// The JSON has the optional keys temperature, maximum_response_tokens and sampling,
+/// where sampling is {"mode": "greedy"}, {"mode": "top_k", "k": 40, "seed": 7} or
+/// {"mode": "nucleus", "probability_threshold": 0.9, "seed": 7}
+///
+/// See: https://developer.apple.com/documentation/FoundationModels/GenerationOptions
+private func decodeOptions(_ json: String) -> GenerationOptions? {
+    guard let data = json.data(using: .utf8),
+          let object = try? JSONSerialization.jsonObject(with: data) as? [String: Any] else {
+        return nil
+    }
+
+    var sampling: GenerationOptions.SamplingMode? = nil
+    if let rawSampling = object["sampling"] as? [String: Any] {
+        let seed = (rawSampling["seed"] as? NSNumber)?.uint64Value
+        switch rawSampling["mode"] as? String {
+        case "greedy":
+            sampling = .greedy
+        case "top_k":
+            guard let k = (rawSampling["k"] as? NSNumber)?.intValue else { return nil }
+            sampling = .random(top: k, seed: seed)
+        case "nucleus":
+            guard let threshold = (rawSampling["probability_threshold"] as? NSNumber)?.doubleValue else {
+                return nil
+            }
+            sampling = .random(probabilityThreshold: threshold, seed: seed)
+        default:
+            return nil
+        }
+    }
+
+    return GenerationOptions(
+        sampling: sampling,
+        temperature: (object["temperature"] as? NSNumber)?.doubleValue,
+        maximumResponseTokens: (object["maximum_response_tokens"] as? NSNumber)?.intValue
+    )
+}
+
+// MARK: - Schema Decoding
+/// Builds a DynamicGenerationSchema from the Rust serialization of `GenerationSchema`
+///
+/// Every node has a "type" key: object (name, description, properties), any_of
+/// (name, description, choices), enum (name, description, choices), array (items,
+/// min_items, max_items), string (pattern), integer and number (minimum, maximum)
+/// or boolean
+///
+/// See: https://developer.apple.com/documentation/FoundationModels/DynamicGenerationSchema
+private func decodeSchemaNode(_ raw: [String: Any]) -> DynamicGenerationSchema? {
+    let name = raw["name"] as? String
+    let description = raw["description"] as? String
+
+    switch raw["type"] as? String {
+    case "object":
+        guard let name = name, let rawProperties = raw["properties"] as? [[String: Any]] else {
+            return nil
+        }
+        var properties: [DynamicGenerationSchema.Property] = []
+        for rawProperty in rawProperties {
+            guard let propertyName = rawProperty["name"] as? String,
+                  let rawSchema = rawProperty["schema"] as? [String: Any],
+                  let schema = decodeSchemaNode(rawSchema) else {
+                return nil
+            }
+            properties.append(DynamicGenerationSchema.Property(
+                name: propertyName,
+                description: rawProperty["description"] as? String,
+                schema: schema,
+                isOptional: rawProperty["optional"] as? Bool ?? false
+            ))
+        }
+        return DynamicGenerationSchema(name: name, description: description, properties: properties)
+    case "any_of":
+        guard let name = name, let rawChoices = raw["choices"] as? [[String: Any]] else { return nil }
+        var choices: [DynamicGenerationSchema] = []
+        for rawChoice in rawChoices {
+            guard let choice = decodeSchemaNode(rawChoice) else { return nil }
+            choices.append(choice)
+        }
+        return DynamicGenerationSchema(name: name, description: description, anyOf: choices)
+    case "enum":
+        guard let name = name, let choices = raw["choices"] as? [String] else { return nil }
+        return DynamicGenerationSchema(name: name, description: description, anyOf: choices)
+    case "array":
+        guard let rawItems = raw["items"] as? [String: Any],
+              let items = decodeSchemaNode(rawItems) else {
+            return nil
+        }
+        return DynamicGenerationSchema(
+            arrayOf: items,
+            minimumElements: (raw["min_items"] as? NSNumber)?.intValue,
+            maximumElements: (raw["max_items"] as? NSNumber)?.intValue
+        )
+    case "string":
+        var guides: [GenerationGuide<String>] = []
+        if let pattern = raw["pattern"] as? String {
+            guard let regex = try? Regex(pattern) else { return nil }
+            guides.append(.pattern(regex))
+        }
+        return DynamicGenerationSchema(type: String.self, guides: guides)
+    case "integer":
+        var guides: [GenerationGuide<Int>] = []
+        if let minimum = (raw["minimum"] as? NSNumber)?.intValue { guides.append(.minimum(minimum)) }
+        if let maximum = (raw["maximum"] as? NSNumber)?.intValue { guides.append(.maximum(maximum)) }
+        return DynamicGenerationSchema(type: Int.self, guides: guides)
+    case "number":
+        var guides: [GenerationGuide<Double>] = []
+        if let minimum = (raw["minimum"] as? NSNumber)?.doubleValue { guides.append(.minimum(minimum)) }
+        if let maximum = (raw["maximum"] as? NSNumber)?.doubleValue { guides.append(.maximum(maximum)) }
+        return DynamicGenerationSchema(type: Double.self, guides: guides)
+    case "boolean":
+        return DynamicGenerationSchema(type: Bool.self)
+    default:
+        return nil
+    }
+}
+
+/// Builds the GenerationSchema of a guided request from its Rust serialization
+private func decodeSchema(_ json: String) -> GenerationSchema? {
+    guard let data = json.data(using: .utf8),
+          let object = try? JSONSerialization.jsonObject(with: data) as? [String: Any],
+          let root = decodeSchemaNode(object) else {
+        return nil
+    }
+    return try? GenerationSchema(root: root, dependencies: [])
+}
+
+// MARK: - Transcript Decoding
+/// Builds a Transcript from its Rust serialization
+///
+/// The JSON has the shape {"entries": [{"type": "prompt", "text": "..."}, ...]}, with
+/// entry types instructions, prompt, response, tool_call and tool_output
+private func decodeTranscript(_ json: String) -> Transcript? {
+    guard let data = json.data(using: .utf8),
+          let object = try? JSONSerialization.jsonObject(with: data) as? [String: Any],
+          let rawEntries = object["entries"] as? [[String: Any]] else {
+        return nil
+    }
+
+    var entries: [Transcript.Entry] = []
+    for raw in rawEntries {
+        guard let type = raw["type"] as? String else { return nil }
+
+        switch type {
+        case "instructions":
+            guard let text = raw["text"] as? String else { return nil }
+            entries.append(.instructions(Transcript.Instructions(
+                segments: [.text(Transcript.TextSegment(content: text))],
+                toolDefinitions: []
+            )))
+        case "prompt":
+            guard let text = raw["text"] as? String else { return nil }
+            entries.append(.prompt(Transcript.Prompt(
+                segments: [.text(Transcript.TextSegment(content: text))]
+            )))
+        case "response":
+            guard let text = raw["text"] as? String else { return nil }
+            entries.append(.response(Transcript.Response(
+                assetIDs: [],
+                segments: [.text(Transcript.TextSegment(content: text))]
+            )))
+        case "tool_call":
+            guard let id = raw["id"] as? String,
+                  let name = raw["name"] as? String,
+                  let argumentsObject = raw["arguments"],
+                  let argumentsData = try? JSONSerialization.data(
+                      withJSONObject: argumentsObject, options: [.fragmentsAllowed]),
+                  let argumentsJSON = String(data: argumentsData, encoding: .utf8),
+                  let arguments = try? GeneratedContent(json: argumentsJSON) else {
+                return nil
+            }
+            entries.append(.toolCalls(Transcript.ToolCalls([
+                Transcript.ToolCall(id: id, toolName: name, arguments: arguments)
+            ])))
+        case "tool_output":
+            guard let id = raw["id"] as? String,
+                  let name = raw["name"] as? String,
+                  let output = raw["output"] as? String else {
+                return nil
+            }
+            entries.append(.toolOutput(Transcript.ToolOutput(
+                id: id,
+                toolName: name,
+                segments: [.text(Transcript.TextSegment(content: output))]
+            )))
+        default:
+            return nil
+        }
+    }
+
+    return Transcript(entries: entries)
+}
+
+/// Releases a session handle created by fm_session_create
+///
+/// Cancels any request still in flight; the handle must not be used afterwards
+@_cdecl("fm_session_destroy")
+public func fm_session_destroy(_ handle: UnsafeMutableRawPointer?) {
+    guard let handle = handle else { return }
+    let box = Unmanaged<SessionBox>.fromOpaque(handle)
+    box.takeUnretainedValue().cancel()
+    box.release()
+}
+
+// MARK: - Generation
+/// Streams a response to `prompt` within the session, delivering text deltas
+///
+/// With a schema the response is guided generation: every chunk is the JSON of the
+/// whole snapshot generated so far rather than a delta.
+///
+/// Blocks the calling thread until the request completes, fails or is cancelled.
+/// Exactly one of onDone/onError is called at the end.
+private func generate(
+    _ handle: UnsafeMutableRawPointer?,
     _ prompt: UnsafePointer<CChar>?,
+    _ optionsJSON: UnsafePointer<CChar>?,
+    _ schemaJSON: UnsafePointer<CChar>?,
+    _ cancelToken: UnsafeMutableRawPointer?,
     _ userData: UnsafeMutableRawPointer?,
     _ onChunk: ChunkCallbackWithData?,
     _ onDone: DoneCallbackWithData?,
-    _ onError: ErrorCallbackWithData?
+    _ onError: ErrorCallbackWithData?,
+    errorPrefix: String
 ) {
-    // 1. Acquire lock to serialize FFI calls
-    responseLock.lock()
-    defer { responseLock.unlock() }
+    // 1. Resolve the session handle
+    guard let handle = handle else {
+        reportError(.internalError, "Invalid session handle", userData, onError)
+        return
+    }
+    let box = sessionBox(handle)
+
+    // 2. Acquire the session's lock to serialize requests
+    box.requestLock.lock()
+    defer { box.requestLock.unlock() }
+
+    // 2b. A request cancelled while it waited ends before it starts; Rust reports it
+    let token = cancelTokenBox(cancelToken)
+    if token?.isCancelled == true {
+        onDone?(userData)
+        return
+    }
 
-    // 2. Convert C string to Swift String
+    // 3. Convert C string to Swift String
     guard let promptCStr = prompt,
           let promptString = String(utf8String: promptCStr) else {
-        "Invalid prompt".withCString { cString in
-            onError?(cString, userData)
-        }
+        reportError(.invalidInput, "Invalid prompt", userData, onError)
         return
     }
 
-    // 3. Cancel any existing response task
-    responseTask?.cancel()
+    // 4. Decode generation options, keeping the model's defaults when none are given
+    var options = GenerationOptions()
+    if let optionsCStr = optionsJSON {
+        guard let decoded = String(utf8String: optionsCStr).flatMap(decodeOptions) else {
+            reportError(.invalidInput, "Invalid generation options", userData, onError)
+            return
+        }
+        options = decoded
+    }
+
+    // 4b. Decode the schema of a guided request
+    var schema: GenerationSchema? = nil
+    if let schemaCStr = schemaJSON {
+        guard let decoded = String(utf8String: schemaCStr).flatMap(decodeSchema) else {
+            reportError(.invalidInput, "Invalid generation schema", userData, onError)
+            return
+        }
+        schema = decoded
+    }
 
-    // 4. Create semaphore to block until async work completes
+    // 5. Create semaphore to block until async work completes
     let semaphore = DispatchSemaphore(value: 0)
 
-    // 5. Start new async task to handle response generation
-    // Note: Availability is checked at session creation time in Rust
-    responseTask = Task {
+    // 6. Start new async task on the persistent session
+    // Earlier prompts and responses stay in session.transcript, so the model sees them
+    let task = Task {
         defer { semaphore.signal() }
 
         do {
-            // 6. Create a session
-            let session = LanguageModelSession()
+            if let schema = schema {
+                // 7. Use streamResponse with the schema to generate structured content
+                let stream = box.session.streamResponse(
+                    to: promptString, schema: schema, includeSchemaInPrompt: true, options: options)
+
+                // 8. Iterate through the stream
+                for try await snapshot in stream {
+                    if Task.isCancelled { break }
 
-            // 7. Use streamResponse to collect tokens (more responsive than respond())
-            // We still deliver all tokens but signal completion only at the end
-            let stream = session.streamResponse(to: promptString)
+                    // 9. Call the Rust callback with the whole snapshot as JSON
+                    snapshot.content.jsonString.withCString { cString in
+                        onChunk?(cString, userData)
+                    }
+                }
+            } else {
+                // 7. Use streamResponse to collect tokens
+                let stream = box.session.streamResponse(to: promptString, options: options)
 
-            var lastText = ""
+                var lastText = ""
 
-            // 8. Iterate through the stream
-            for try await snapshot in stream {
-                // Check if task was cancelled
-                if Task.isCancelled { break }
+                // 8. Iterate through the stream
+                for try await snapshot in stream {
+                    // Check if task was cancelled
+                    if Task.isCancelled { break }
 
-                // Extract the string content from the snapshot
-                let currentText = snapshot.content
+                    // Extract the string content from the snapshot
+                    let currentText = snapshot.content
 
-                // Extract only the new chunks (delta) since last update
-                let newContent = String(currentText.dropFirst(lastText.count))
-                lastText = currentText
+                    // Extract only the new chunks (delta) since last update
+                    let newContent = String(currentText.dropFirst(lastText.count))
+                    lastText = currentText
 
-                // 9. Call the Rust callback with the new chunk
-                if !newContent.isEmpty {
-                    newContent.withCString { cString in
-                        onChunk?(cString, userData)
+                    // 9. Call the Rust callback with the new chunk
+                    if !newContent.isEmpty {
+                        newContent.withCString { cString in
+                            onChunk?(cString, userData)
+                        }
                     }
                 }
             }
@@ -113,113 +868,200 @@ public func fm_response(
 
         } catch {
             // 11. Handle any errors during generation
-            let errorMsg = "Generation error: \(error.localizedDescription)"
-            errorMsg.withCString { cString in
-                onError?(cString, userData)
+            // The kind tells Rust which Error variant to build; only unclassified errors get the prefix
+            let kind = FFIErrorKind(error)
+            let message = kind == .generation
+                ? "\(errorPrefix): \(error.localizedDescription)"
+                : error.localizedDescription
+            var refusal: LanguageModelSession.GenerationError.Refusal? = nil
+            if let generationError = error as? LanguageModelSession.GenerationError,
+               case .refusal(let declined, _) = generationError {
+                refusal = declined
             }
+            reportError(kind, message, userData, onError, refusal: refusal)
         }
     }
+    box.setTask(task)
+    token?.attach(task)
 
     // 12. Block until the async work completes
     semaphore.wait()
+    box.setTask(nil)
 
     // Lock is automatically released via defer when function returns
 }
 
+// MARK: - Blocking Response
+/// Generates a complete response within a session (blocking mode)
+///
+/// - Parameters:
+///   - handle: Session handle from fm_session_create
+///   - prompt: C string with the user's prompt
+///   - optionsJSON: C string with serialized generation options (null for defaults)
+///   - schemaJSON: C string with a serialized generation schema (null for free-form text)
+///   - cancelToken: Token from fm_cancel_token_create that cancels only this request (null for none)
+///   - userData: Opaque pointer passed to all callbacks
+///   - onChunk: Called for each chunk of text generated
+///   - onDone: Called when generation completes successfully
+///   - onError: Called if an error occurs (passes an FFIErrorKind and the error message)
+///
+/// See: https://developer.apple.com/documentation/FoundationModels/LanguageModelSession/respond(to:)
+@_cdecl("fm_session_response")
+public func fm_session_response(
+    _ handle: UnsafeMutableRawPointer?,
+    _ prompt: UnsafePointer<CChar>?,
+    _ optionsJSON: UnsafePointer<CChar>?,
+    _ schemaJSON: UnsafePointer<CChar>?,
+    _ cancelToken: UnsafeMutableRawPointer?,
+    _ userData: UnsafeMutableRawPointer?,
+    _ onChunk: ChunkCallbackWithData?,
+    _ onDone: DoneCallbackWithData?,
+    _ onError: ErrorCallbackWithData?
+) {
+    generate(handle, prompt, optionsJSON, schemaJSON, cancelToken, userData, onChunk, onDone, onError, errorPrefix: "Generation error")
+}
+
 // MARK: - Streaming Response
-/// Starts streaming a response from the Foundation Model
+/// Streams a response within a session
 ///
 /// - Parameters:
+///   - handle: Session handle from fm_session_create
 ///   - prompt: C string with the user's prompt
+///   - optionsJSON: C string with serialized generation options (null for defaults)
+///   - schemaJSON: C string with a serialized generation schema (null for free-form text)
+///   - cancelToken: Token from fm_cancel_token_create that cancels only this request (null for none)
 ///   - userData: Opaque pointer passed to all callbacks
 ///   - onChunk: Called for each chunk of text generated
 ///   - onDone: Called when streaming completes successfully
-///   - onError: Called if an error occurs (passes error message)
+///   - onError: Called if an error occurs (passes an FFIErrorKind and the error message)
 ///
 /// See: https://developer.apple.com/documentation/FoundationModels/LanguageModelSession/streamResponse(to:)
-@_cdecl("fm_start_stream")
-public func fm_start_stream(
+@_cdecl("fm_session_stream")
+public func fm_session_stream(
+    _ handle: UnsafeMutableRawPointer?,
     _ prompt: UnsafePointer<CChar>?,
+    _ optionsJSON: UnsafePointer<CChar>?,
+    _ schemaJSON: UnsafePointer<CChar>?,
+    _ cancelToken: UnsafeMutableRawPointer?,
     _ userData: UnsafeMutableRawPointer?,
     _ onChunk: ChunkCallbackWithData?,
     _ onDone: DoneCallbackWithData?,
     _ onError: ErrorCallbackWithData?
 ) {
-    // 1. Acquire lock to serialize FFI calls
-    streamLock.lock()
-    defer { streamLock.unlock() }
+    generate(handle, prompt, optionsJSON, schemaJSON, cancelToken, userData, onChunk, onDone, onError, errorPrefix: "Streaming error")
+}
 
-    // 2. Convert C string to Swift String
-    guard let promptCStr = prompt,
-          let promptString = String(utf8String: promptCStr) else {
-        "Invalid prompt".withCString { cString in
-            onError?(cString, userData)
-        }
-        return
-    }
+// MARK: - Cancellation
+/// Cancels the request currently in flight on a session, if any
+@_cdecl("fm_session_cancel")
+public func fm_session_cancel(_ handle: UnsafeMutableRawPointer?) {
+    guard let handle = handle else { return }
+    sessionBox(handle).cancel()
+}
 
-    // 3. Cancel any existing stream
-    streamTask?.cancel()
+/// Creates a token that cancels the single request it is passed to
+///
+/// The returned handle must be released with fm_cancel_token_destroy.
+@_cdecl("fm_cancel_token_create")
+public func fm_cancel_token_create() -> UnsafeMutableRawPointer {
+    Unmanaged.passRetained(CancelTokenBox()).toOpaque()
+}
 
-    // 4. Create semaphore to block until async work completes
-    let semaphore = DispatchSemaphore(value: 0)
+/// Cancels the request of a token, now or as soon as it starts
+@_cdecl("fm_cancel_token_cancel")
+public func fm_cancel_token_cancel(_ handle: UnsafeMutableRawPointer?) {
+    cancelTokenBox(handle)?.cancel()
+}
 
-    // 5. Start new async task to handle streaming
-    // Note: Availability is checked at session creation time in Rust
-    streamTask = Task {
-        defer { semaphore.signal() }
+/// Releases a token created by fm_cancel_token_create
+@_cdecl("fm_cancel_token_destroy")
+public func fm_cancel_token_destroy(_ handle: UnsafeMutableRawPointer?) {
+    guard let handle = handle else { return }
+    Unmanaged<CancelTokenBox>.fromOpaque(handle).release()
+}
 
-        do {
-            // 6. Create a session
-            let session = LanguageModelSession()
+// MARK: - Prewarming
+/// Asks the model to load the session's resources ahead of its next request
+///
+/// - Parameters:
+///   - handle: Session handle from fm_session_create
+///   - promptPrefix: Optional C string with the beginning of the next prompt (null if unknown)
+///
+/// Returns immediately; the framework loads the model in the background
+///
+/// See: https://developer.apple.com/documentation/FoundationModels/LanguageModelSession/prewarm(promptPrefix:)
+@_cdecl("fm_session_prewarm")
+public func fm_session_prewarm(_ handle: UnsafeMutableRawPointer?, _ promptPrefix: UnsafePointer<CChar>?) {
+    guard let handle = handle else { return }
+    let box = sessionBox(handle)
 
-            // 7. Start streaming
-            let stream = session.streamResponse(to: promptString)
+    if let prefix = promptPrefix.flatMap({ String(utf8String: $0) }) {
+        box.session.prewarm(promptPrefix: Prompt(prefix))
+    } else {
+        box.session.prewarm()
+    }
+}
 
-            var lastText = ""
+// MARK: - Refusals
+/// Keeps a refusal alive while Rust holds its handle
+private final class RefusalBox {
+    let refusal: LanguageModelSession.GenerationError.Refusal
 
-            // 8. Iterate through the stream
-            for try await snapshot in stream {
-                // Check if task was cancelled
-                if Task.isCancelled { break }
+    init(_ refusal: LanguageModelSession.GenerationError.Refusal) {
+        self.refusal = refusal
+    }
+}
 
-                // Extract the string content from the snapshot
-                let currentText = snapshot.content
+/// Asks the model why it declined a request (blocking mode)
+///
+/// - Parameters:
+///   - refusal: Handle passed to an error callback with FFIErrorKind.refusal
+///   - userData: Opaque pointer passed to all callbacks
+///   - onChunk: Called once with the whole explanation
+///   - onDone: Called when the explanation completes successfully
+///   - onError: Called if an error occurs (passes an FFIErrorKind and the error message)
+///
+/// See: https://developer.apple.com/documentation/FoundationModels/LanguageModelSession/GenerationError/Refusal/explanation
+@_cdecl("fm_refusal_explanation")
+public func fm_refusal_explanation(
+    _ refusal: UnsafeMutableRawPointer?,
+    _ userData: UnsafeMutableRawPointer?,
+    _ onChunk: ChunkCallbackWithData?,
+    _ onDone: DoneCallbackWithData?,
+    _ onError: ErrorCallbackWithData?
+) {
+    // 1. Resolve the refusal handle
+    guard let refusal = refusal else {
+        reportError(.internalError, "Invalid refusal handle", userData, onError)
+        return
+    }
+    let box = Unmanaged<RefusalBox>.fromOpaque(refusal).takeUnretainedValue()
 
-                // Extract only the new chunks (delta) since last update
-                let newContent = String(currentText.dropFirst(lastText.count))
-                lastText = currentText
+    // 2. Generate the explanation, blocking until it completes
+    let semaphore = DispatchSemaphore(value: 0)
+    Task {
+        defer { semaphore.signal() }
 
-                // 9. Call the Rust callback with the new chunk
-                if !newContent.isEmpty {
-                    newContent.withCString { cString in
-                        onChunk?(cString, userData)
-                    }
-                }
+        do {
+            let explanation = try await box.refusal.explanation
+            explanation.content.withCString { cString in
+                onChunk?(cString, userData)
             }
-
-            // 10. Stream completed successfully
             onDone?(userData)
-
         } catch {
-            // 11. Handle any errors during streaming
-            let errorMsg = "Streaming error: \(error.localizedDescription)"
-            errorMsg.withCString { cString in
-                onError?(cString, userData)
-            }
+            let kind = FFIErrorKind(error)
+            let message = kind == .generation
+                ? "Explanation error: \(error.localizedDescription)"
+                : error.localizedDescription
+            reportError(kind, message, userData, onError)
         }
     }
-
-    // 12. Block until the async work completes
     semaphore.wait()
-
-    // Lock is automatically released via defer when function returns
 }
 
-// MARK: - Stop Streaming
-/// Cancels the current streaming task
-@_cdecl("fm_stop_stream")
-public func fm_stop_stream() {
-    streamTask?.cancel()
-    streamTask = nil
+/// Releases a refusal handle passed to an error callback
+@_cdecl("fm_refusal_destroy")
+public func fm_refusal_destroy(_ refusal: UnsafeMutableRawPointer?) {
+    guard let refusal = refusal else { return }
+    Unmanaged<RefusalBox>.fromOpaque(refusal).release()
 }
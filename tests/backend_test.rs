@@ -0,0 +1,681 @@
+//! Session tests against an in-process backend
+//!
+//! These run on every platform: the backend below replaces the Swift bridge,
+//! so only the session logic (validation, delegation, error propagation) is exercised.
+
+use fm_bindings::{
+    Availability, CancelHandle, Error, GenerationRequest, Guardrails, LanguageModelSession,
+    ModelBackend, Refusal, Result, SystemBackend, SystemLanguageModel, TranscriptEntry, UseCase,
+    tokens,
+};
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+
+/// Streams a fixed list of chunks, or fails with a fixed error
+#[derive(Default)]
+struct FixedBackend {
+    unavailable: bool,
+    chunks: Vec<&'static str>,
+    error: Option<Error>,
+    calls: AtomicUsize,
+    cancelled: AtomicBool,
+    resets: AtomicUsize,
+}
+
+impl ModelBackend for FixedBackend {
+    fn is_available(&self) -> bool {
+        !self.unavailable
+    }
+
+    fn stream_response(
+        &self,
+        _request: &GenerationRequest<'_>,
+        on_chunk: &mut dyn FnMut(&str),
+    ) -> Result<()> {
+        self.calls.fetch_add(1, Ordering::SeqCst);
+        if let Some(error) = &self.error {
+            return Err(error.clone());
+        }
+        for chunk in &self.chunks {
+            on_chunk(chunk);
+        }
+        Ok(())
+    }
+
+    fn reset(&self) {
+        self.resets.fetch_add(1, Ordering::SeqCst);
+    }
+
+    fn cancel_stream(&self) {
+        self.cancelled.store(true, Ordering::SeqCst);
+    }
+}
+
+/// Lets a test keep a handle on the backend after moving it into a session
+struct Shared(Arc<FixedBackend>);
+
+impl ModelBackend for Shared {
+    fn is_available(&self) -> bool {
+        self.0.is_available()
+    }
+
+    fn stream_response(
+        &self,
+        request: &GenerationRequest<'_>,
+        on_chunk: &mut dyn FnMut(&str),
+    ) -> Result<()> {
+        self.0.stream_response(request, on_chunk)
+    }
+
+    fn reset(&self) {
+        self.0.reset()
+    }
+
+    fn cancel_stream(&self) {
+        self.0.cancel_stream()
+    }
+}
+
+#[test]
+fn test_unavailable_backend_is_rejected() {
+    let backend = FixedBackend {
+        unavailable: true,
+        ..Default::default()
+    };
+
+    let result = LanguageModelSession::with_backend(backend);
+    assert!(matches!(
+        result,
+        Err(Error::ModelNotAvailable(Availability::Other(_)))
+    ));
+}
+
+#[test]
+fn test_response_collects_chunks() -> Result<()> {
+    let session = LanguageModelSession::with_backend(FixedBackend {
+        chunks: vec!["Hello", ", ", "world"],
+        ..Default::default()
+    })?;
+
+    assert_eq!(session.response("Hi")?, "Hello, world");
+    Ok(())
+}
+
+#[test]
+fn test_stream_response_forwards_chunks() -> Result<()> {
+    let session = LanguageModelSession::with_backend(FixedBackend {
+        chunks: vec!["one", "two", "three"],
+        ..Default::default()
+    })?;
+
+    let mut chunks = Vec::new();
+    session.stream_response("Count", |chunk| chunks.push(chunk.to_string()))?;
+
+    assert_eq!(chunks, ["one", "two", "three"]);
+    Ok(())
+}
+
+#[test]
+fn test_invalid_prompts_never_reach_backend() -> Result<()> {
+    let backend = Arc::new(FixedBackend::default());
+    let session = LanguageModelSession::with_backend(Shared(Arc::clone(&backend)))?;
+
+    assert!(matches!(session.response(""), Err(Error::InvalidInput(_))));
+    assert!(matches!(
+        session.response("nul\0byte"),
+        Err(Error::InvalidInput(_))
+    ));
+    assert!(matches!(
+        session.stream_response("", |_| {}),
+        Err(Error::InvalidInput(_))
+    ));
+
+    assert_eq!(backend.calls.load(Ordering::SeqCst), 0);
+    Ok(())
+}
+
+#[test]
+fn test_backend_errors_are_propagated() -> Result<()> {
+    let session = LanguageModelSession::with_backend(FixedBackend {
+        error: Some(Error::GenerationError("boom".into())),
+        ..Default::default()
+    })?;
+
+    match session.response("Hi") {
+        Err(Error::GenerationError(msg)) => assert_eq!(msg, "boom"),
+        other => panic!("expected generation error, got {:?}", other),
+    }
+    assert!(session.stream_response("Hi", |_| {}).is_err());
+    Ok(())
+}
+
+#[test]
+fn test_typed_backend_errors_are_propagated() -> Result<()> {
+    let session = LanguageModelSession::with_backend(FixedBackend {
+        error: Some(Error::ContextWindowExceeded("too long".into())),
+        ..Default::default()
+    })?;
+
+    match session.response("Hi") {
+        Err(error @ Error::ContextWindowExceeded(_)) => {
+            assert!(!error.is_retryable());
+            assert_eq!(error.to_string(), "Context window exceeded: too long");
+        }
+        other => panic!("expected exceeded context window, got {:?}", other),
+    }
+    assert!(session.transcript().is_empty());
+    Ok(())
+}
+
+#[test]
+fn test_refusal_explanation() -> Result<()> {
+    let session = LanguageModelSession::with_backend(FixedBackend {
+        error: Some(Error::Refusal(
+            Refusal::new("declined").with_explanation("It asks for private data"),
+        )),
+        ..Default::default()
+    })?;
+
+    let Err(Error::Refusal(refusal)) = session.response("Hi") else {
+        panic!("expected a refusal");
+    };
+    assert_eq!(refusal.message(), "declined");
+    assert_eq!(refusal.explanation()?, "It asks for private data");
+
+    // The explanation survives a round trip through a cassette
+    let json = serde_json::to_string(&Error::Refusal(refusal)).expect("serialize");
+    let Ok(Error::Refusal(replayed)) = serde_json::from_str(&json) else {
+        panic!("expected a refusal in {}", json);
+    };
+    assert_eq!(replayed.explanation()?, "It asks for private data");
+    Ok(())
+}
+
+#[test]
+fn test_refusal_without_explanation() {
+    let refusal = Refusal::new("declined");
+    assert!(matches!(
+        refusal.explanation(),
+        Err(Error::GenerationError(_))
+    ));
+    assert_eq!(Error::Refusal(refusal).to_string(), "Refusal: declined");
+}
+
+#[test]
+fn test_transient_errors_are_retryable() {
+    let retryable = [
+        Error::RateLimited(String::new()),
+        Error::ConcurrentRequests(String::new()),
+        Error::AssetsUnavailable(String::new()),
+        Error::DecodingFailure(String::new()),
+        Error::ModelNotAvailable(Availability::ModelNotReady),
+    ];
+    for error in retryable {
+        assert!(error.is_retryable(), "{:?}", error);
+    }
+
+    let permanent = [
+        Error::GuardrailViolation(String::new()),
+        Error::UnsupportedLanguage(String::new()),
+        Error::Refusal(Refusal::new("")),
+        Error::GenerationError(String::new()),
+        Error::InvalidInput(String::new()),
+        Error::ModelNotAvailable(Availability::DeviceNotEligible),
+    ];
+    for error in permanent {
+        assert!(!error.is_retryable(), "{:?}", error);
+    }
+}
+
+#[test]
+fn test_cancel_stream_reaches_backend() -> Result<()> {
+    let backend = Arc::new(FixedBackend::default());
+    let session = LanguageModelSession::with_backend(Shared(Arc::clone(&backend)))?;
+
+    session.clone().cancel_stream();
+    assert!(backend.cancelled.load(Ordering::SeqCst));
+    Ok(())
+}
+
+#[test]
+fn test_cancel_handle_stops_forwarding_chunks() -> Result<()> {
+    // The backend ignores the handle; the session still stops at the cancellation
+    let session = LanguageModelSession::with_backend(FixedBackend {
+        chunks: vec!["one", "two", "three"],
+        ..Default::default()
+    })?;
+    let handle = CancelHandle::new();
+
+    let mut chunks = Vec::new();
+    let result = session
+        .cancellable(&handle)
+        .stream_response("Count", |chunk| {
+            chunks.push(chunk.to_string());
+            handle.cancel();
+        });
+
+    assert!(matches!(result, Err(Error::Cancelled { partial }) if partial == "one"));
+    assert_eq!(chunks, ["one"]);
+    assert!(session.transcript().is_empty());
+    Ok(())
+}
+
+#[test]
+fn test_cancelled_request_resets_backend() -> Result<()> {
+    let backend = Arc::new(FixedBackend {
+        chunks: vec!["one", "two"],
+        ..Default::default()
+    });
+    let session = LanguageModelSession::with_backend(Shared(Arc::clone(&backend)))?;
+    let handle = CancelHandle::new();
+
+    // The backend may keep the partial turn the transcript leaves out
+    let result = session
+        .cancellable(&handle)
+        .stream_response("Count", |_| handle.cancel());
+    assert!(matches!(result, Err(Error::Cancelled { .. })));
+    assert_eq!(backend.resets.load(Ordering::SeqCst), 1);
+
+    session.response("Count")?;
+    assert_eq!(backend.resets.load(Ordering::SeqCst), 1);
+    assert_eq!(session.transcript().len(), 2);
+    Ok(())
+}
+
+#[test]
+fn test_cancelled_handle_never_reaches_backend() -> Result<()> {
+    let backend = Arc::new(FixedBackend::default());
+    let session = LanguageModelSession::with_backend(Shared(Arc::clone(&backend)))?;
+    let handle = CancelHandle::new();
+    handle.cancel();
+
+    let result = session.cancellable(&handle).response("Hi");
+    assert!(matches!(result, Err(Error::Cancelled { partial }) if partial.is_empty()));
+    assert_eq!(backend.calls.load(Ordering::SeqCst), 0);
+    assert_eq!(backend.resets.load(Ordering::SeqCst), 0);
+
+    // The session itself is not affected
+    session.response("Hi")?;
+    assert_eq!(backend.calls.load(Ordering::SeqCst), 1);
+    Ok(())
+}
+
+/// Answers with the instructions it received, followed by the prompt
+struct EchoBackend;
+
+impl ModelBackend for EchoBackend {
+    fn is_available(&self) -> bool {
+        true
+    }
+
+    fn stream_response(
+        &self,
+        request: &GenerationRequest<'_>,
+        on_chunk: &mut dyn FnMut(&str),
+    ) -> Result<()> {
+        on_chunk(request.instructions.unwrap_or("<none>"));
+        on_chunk(" | ");
+        on_chunk(request.prompt);
+        Ok(())
+    }
+
+    fn cancel_stream(&self) {}
+}
+
+#[test]
+fn test_instructions_are_sent_apart_from_prompt() -> Result<()> {
+    let session = LanguageModelSession::builder()
+        .backend(EchoBackend)
+        .instructions("Be brief.")
+        .build()?;
+
+    assert_eq!(session.instructions(), Some("Be brief."));
+    assert_eq!(session.response("Hi")?, "Be brief. | Hi");
+    assert_eq!(session.clone().response("Again")?, "Be brief. | Again");
+
+    let plain = LanguageModelSession::with_backend(EchoBackend)?;
+    assert_eq!(plain.instructions(), None);
+    assert_eq!(plain.response("Hi")?, "<none> | Hi");
+    Ok(())
+}
+
+#[test]
+fn test_instructions_with_null_byte_are_rejected() {
+    let result = LanguageModelSession::builder()
+        .backend(EchoBackend)
+        .instructions("bad\0instructions")
+        .build();
+    assert!(matches!(result, Err(Error::InvalidInput(_))));
+}
+
+/// Answers with the prompts of the transcript it was given
+struct RecallBackend;
+
+impl ModelBackend for RecallBackend {
+    fn is_available(&self) -> bool {
+        true
+    }
+
+    fn stream_response(
+        &self,
+        request: &GenerationRequest<'_>,
+        on_chunk: &mut dyn FnMut(&str),
+    ) -> Result<()> {
+        let prompts: Vec<&str> = request
+            .transcript
+            .iter()
+            .filter_map(|entry| match entry {
+                TranscriptEntry::Prompt { text } => Some(text.as_str()),
+                _ => None,
+            })
+            .collect();
+        on_chunk(&format!("[{}]", prompts.join(",")));
+        Ok(())
+    }
+
+    fn cancel_stream(&self) {}
+}
+
+#[test]
+fn test_follow_up_prompts_see_earlier_turns() -> Result<()> {
+    let session = LanguageModelSession::with_backend(RecallBackend)?;
+
+    assert_eq!(session.response("a")?, "[]");
+    assert_eq!(session.response("b")?, "[a]");
+    session.stream_response("c", |_| {})?;
+
+    // Clones continue the same conversation
+    assert_eq!(session.clone().response("d")?, "[a,b,c]");
+    assert_eq!(
+        session.transcript().entries(),
+        [
+            TranscriptEntry::prompt("a"),
+            TranscriptEntry::response("[]"),
+            TranscriptEntry::prompt("b"),
+            TranscriptEntry::response("[a]"),
+            TranscriptEntry::prompt("c"),
+            TranscriptEntry::response("[a,b]"),
+            TranscriptEntry::prompt("d"),
+            TranscriptEntry::response("[a,b,c]"),
+        ]
+    );
+
+    // A new session starts a fresh conversation
+    let fresh = LanguageModelSession::with_backend(RecallBackend)?;
+    assert_eq!(fresh.response("e")?, "[]");
+    Ok(())
+}
+
+#[test]
+fn test_failed_requests_are_not_recorded() -> Result<()> {
+    let session = LanguageModelSession::with_backend(FixedBackend {
+        error: Some(Error::GenerationError("boom".into())),
+        ..Default::default()
+    })?;
+
+    assert!(session.response("Hi").is_err());
+    assert!(session.stream_response("Hi", |_| {}).is_err());
+    assert!(session.transcript().is_empty());
+    Ok(())
+}
+
+/// Records the prompt prefix and instructions of every prewarm request
+#[derive(Default)]
+struct Prewarming {
+    prewarmed: std::sync::Mutex<Vec<(String, Option<String>)>>,
+}
+
+impl ModelBackend for Prewarming {
+    fn is_available(&self) -> bool {
+        true
+    }
+
+    fn prewarm(&self, request: &GenerationRequest<'_>) -> Result<()> {
+        self.prewarmed.lock().unwrap().push((
+            request.prompt.to_string(),
+            request.instructions.map(str::to_string),
+        ));
+        Ok(())
+    }
+
+    fn stream_response(
+        &self,
+        _request: &GenerationRequest<'_>,
+        _on_chunk: &mut dyn FnMut(&str),
+    ) -> Result<()> {
+        Ok(())
+    }
+
+    fn cancel_stream(&self) {}
+}
+
+/// Lets a test inspect a prewarming backend moved into a session
+struct SharedPrewarming(Arc<Prewarming>);
+
+impl ModelBackend for SharedPrewarming {
+    fn is_available(&self) -> bool {
+        true
+    }
+
+    fn prewarm(&self, request: &GenerationRequest<'_>) -> Result<()> {
+        self.0.prewarm(request)
+    }
+
+    fn stream_response(
+        &self,
+        request: &GenerationRequest<'_>,
+        on_chunk: &mut dyn FnMut(&str),
+    ) -> Result<()> {
+        self.0.stream_response(request, on_chunk)
+    }
+
+    fn cancel_stream(&self) {}
+}
+
+#[test]
+fn test_prewarm_reaches_backend() -> Result<()> {
+    let backend = Arc::new(Prewarming::default());
+    let session = LanguageModelSession::builder()
+        .backend(SharedPrewarming(backend.clone()))
+        .instructions("Translate to French.")
+        .build()?;
+
+    session.prewarm(Some("Translate: "))?;
+    session.prewarm(None)?;
+    assert_eq!(
+        *backend.prewarmed.lock().unwrap(),
+        [
+            (
+                "Translate: ".to_string(),
+                Some("Translate to French.".to_string())
+            ),
+            (String::new(), Some("Translate to French.".to_string())),
+        ]
+    );
+    assert_eq!(session.transcript().len(), 1, "prewarming records nothing");
+    Ok(())
+}
+
+#[test]
+fn test_prewarm_in_background() -> Result<()> {
+    let backend = Arc::new(Prewarming::default());
+    let session = LanguageModelSession::with_backend(SharedPrewarming(backend.clone()))?;
+
+    let handle = session.prewarm_in_background(Some("Summarise: "))?;
+    handle.join().expect("prewarm thread")?;
+    assert_eq!(
+        *backend.prewarmed.lock().unwrap(),
+        [("Summarise: ".to_string(), None)]
+    );
+    Ok(())
+}
+
+#[test]
+fn test_prewarm_rejects_null_bytes() -> Result<()> {
+    let backend = Arc::new(Prewarming::default());
+    let session = LanguageModelSession::with_backend(SharedPrewarming(backend.clone()))?;
+
+    assert!(matches!(
+        session.prewarm(Some("a\0b")),
+        Err(Error::InvalidInput(_))
+    ));
+    assert!(backend.prewarmed.lock().unwrap().is_empty());
+    Ok(())
+}
+
+#[test]
+fn test_prewarm_is_optional_for_backends() -> Result<()> {
+    let session = LanguageModelSession::with_backend(FixedBackend::default())?;
+    session.prewarm(Some("Hello"))?;
+    Ok(())
+}
+
+#[test]
+fn test_backend_reason_is_carried_in_error() {
+    /// Reports that its model is still downloading
+    struct Downloading;
+
+    impl ModelBackend for Downloading {
+        fn is_available(&self) -> bool {
+            false
+        }
+
+        fn availability(&self) -> Availability {
+            Availability::ModelNotReady
+        }
+
+        fn stream_response(
+            &self,
+            _request: &GenerationRequest<'_>,
+            _on_chunk: &mut dyn FnMut(&str),
+        ) -> Result<()> {
+            unreachable!("no session is created")
+        }
+
+        fn cancel_stream(&self) {}
+    }
+
+    let error = LanguageModelSession::with_backend(Downloading)
+        .err()
+        .expect("session on an unavailable backend");
+    assert!(matches!(
+        error,
+        Error::ModelNotAvailable(Availability::ModelNotReady)
+    ));
+    assert!(error.to_string().contains("still downloading"), "{}", error);
+}
+
+#[test]
+fn test_system_model_use_case() {
+    assert_eq!(SystemLanguageModel::new().use_case(), UseCase::General);
+
+    let model = SystemLanguageModel::new().with_use_case(UseCase::ContentTagging);
+    assert_eq!(model.use_case(), UseCase::ContentTagging);
+    assert_eq!(SystemBackend::with_model(model.clone()).model(), &model);
+}
+
+#[test]
+fn test_system_model_guardrails() {
+    assert_eq!(SystemLanguageModel::new().guardrails(), Guardrails::Default);
+
+    let model = SystemLanguageModel::new()
+        .with_use_case(UseCase::ContentTagging)
+        .with_guardrails(Guardrails::PermissiveContentTransformations);
+    assert_eq!(
+        model.guardrails(),
+        Guardrails::PermissiveContentTransformations
+    );
+    assert_eq!(model.use_case(), UseCase::ContentTagging);
+}
+
+#[test]
+fn test_missing_adapter_is_not_found() {
+    let path = std::env::temp_dir().join("fm-bindings-missing.fmadapter");
+    match SystemLanguageModel::with_adapter(&path) {
+        Err(Error::AdapterNotFound(missing)) => assert_eq!(missing, path),
+        other => panic!("expected a missing adapter, got {:?}", other),
+    }
+}
+
+#[cfg(not(target_vendor = "apple"))]
+#[test]
+fn test_adapter_model_unavailable_off_apple() -> Result<()> {
+    let path = std::env::temp_dir().join(format!("fm-bindings-{}.fmadapter", std::process::id()));
+    std::fs::create_dir_all(&path).expect("adapter directory");
+
+    let model = SystemLanguageModel::with_adapter(&path);
+    std::fs::remove_dir_all(&path).expect("remove adapter directory");
+    let model = model?;
+    assert_eq!(model.adapter(), Some(path.as_path()));
+    assert!(matches!(
+        LanguageModelSession::with_model(model),
+        Err(Error::ModelNotAvailable(Availability::DeviceNotEligible))
+    ));
+    Ok(())
+}
+
+#[cfg(not(target_vendor = "apple"))]
+#[test]
+fn test_system_model_availability_off_apple() {
+    let model = SystemLanguageModel::default();
+    assert_eq!(model.availability(), Availability::DeviceNotEligible);
+    assert!(!model.is_available());
+
+    let tagging = SystemLanguageModel::new().with_use_case(UseCase::ContentTagging);
+    assert!(matches!(
+        LanguageModelSession::with_model(tagging),
+        Err(Error::ModelNotAvailable(Availability::DeviceNotEligible))
+    ));
+}
+
+#[cfg(not(target_vendor = "apple"))]
+#[test]
+fn test_system_model_unavailable_off_apple() {
+    assert!(matches!(
+        LanguageModelSession::new(),
+        Err(Error::ModelNotAvailable(Availability::DeviceNotEligible))
+    ));
+    assert!(matches!(
+        LanguageModelSession::with_instructions("Be brief."),
+        Err(Error::ModelNotAvailable(Availability::DeviceNotEligible))
+    ));
+    assert!(matches!(
+        LanguageModelSession::with_transcript(Default::default()),
+        Err(Error::ModelNotAvailable(Availability::DeviceNotEligible))
+    ));
+}
+
+#[cfg(not(target_vendor = "apple"))]
+#[test]
+fn test_system_model_estimates_tokens_off_apple() -> Result<()> {
+    let model = SystemLanguageModel::default();
+    assert_eq!(model.context_size(), tokens::DEFAULT_CONTEXT_SIZE);
+    assert_eq!(model.token_count("Hello, world!")?, 6);
+
+    let transcript = [TranscriptEntry::prompt("Hello, world!")]
+        .into_iter()
+        .collect();
+    assert_eq!(
+        model.token_count(&transcript)?,
+        tokens::estimate_transcript(&transcript)
+    );
+    assert!(matches!(
+        model.token_count("nul\0"),
+        Err(Error::InvalidInput(_))
+    ));
+    Ok(())
+}
+
+#[test]
+fn test_backends_estimate_tokens_by_default() -> Result<()> {
+    let session = LanguageModelSession::with_backend(FixedBackend::default())?;
+    assert_eq!(session.context_size(), tokens::DEFAULT_CONTEXT_SIZE);
+    assert_eq!(session.token_count("the quick brown fox")?, 6);
+
+    session.response("Hi")?;
+    assert_eq!(
+        session.token_count(&session.transcript())?,
+        tokens::estimate_transcript(&session.transcript())
+    );
+    Ok(())
+}
@@ -0,0 +1,300 @@
+//! Tests for the candle CPU backend
+//!
+//! Besides loading failures, these generate with a tiny random model written to a
+//! temporary GGUF file, which exercises streaming, sampling options, cancellation,
+//! rendering of earlier turns and token counting without a real model.
+//! Requires the `candle` feature:
+//! ```sh
+//! cargo test --features candle
+//! ```
+
+use fm_bindings::candle::CandleBackend;
+use fm_bindings::{
+    Error, GenerationOptions, GenerationSchema, SamplingMode, Tool, Transcript, TranscriptEntry,
+    tokens,
+};
+
+fn temp_path(name: &str) -> std::path::PathBuf {
+    std::env::temp_dir().join(format!("fm-bindings-{}-{}", std::process::id(), name))
+}
+
+#[test]
+fn test_missing_model_file() {
+    let result = CandleBackend::load(temp_path("missing.gguf"), temp_path("tokenizer.json"));
+    match result {
+        Err(Error::InvalidInput(msg)) => assert!(msg.contains("missing.gguf"), "{}", msg),
+        Err(other) => panic!("expected invalid input, got {:?}", other),
+        Ok(_) => panic!("loading a missing model should fail"),
+    }
+}
+
+#[test]
+fn test_invalid_model_file() {
+    let path = temp_path("invalid.gguf");
+    std::fs::write(&path, b"not a gguf file").expect("write model file");
+
+    let result = CandleBackend::load(&path, temp_path("tokenizer.json"));
+    assert!(matches!(result, Err(Error::InvalidInput(_))));
+
+    std::fs::remove_file(&path).ok();
+}
+
+/// Words of the stub tokenizer; none of them is an end-of-sequence token
+const VOCAB: &[&str] = &["<unk>", "alpha", "beta", "gamma", "delta", "epsilon"];
+
+/// Writes a tiny random llama model and a matching word-level tokenizer
+///
+/// The model's output is meaningless but deterministic, which is enough to exercise
+/// the generation loop, streaming and cancellation without a real model file.
+fn write_stub_model(name: &str) -> (std::path::PathBuf, std::path::PathBuf) {
+    use candle_core::quantized::{GgmlDType, QTensor, gguf_file};
+    use candle_core::{Device, Tensor};
+
+    let hidden = 8;
+    let feed_forward = 16;
+    let vocab = VOCAB.len();
+
+    let mut seed = 7u32;
+    let mut weights = |shape: &[usize]| {
+        let len = shape.iter().product();
+        let values: Vec<f32> = (0..len)
+            .map(|_| {
+                seed = seed.wrapping_mul(1_103_515_245).wrapping_add(12_345);
+                ((seed >> 16) % 200) as f32 / 100.0 - 1.0
+            })
+            .collect();
+        let tensor = Tensor::from_vec(values, shape, &Device::Cpu).expect("tensor");
+        QTensor::quantize(&tensor, GgmlDType::F32).expect("quantize")
+    };
+
+    let tensors = vec![
+        ("token_embd.weight", weights(&[vocab, hidden])),
+        ("output_norm.weight", weights(&[hidden])),
+        ("output.weight", weights(&[vocab, hidden])),
+        ("blk.0.attn_q.weight", weights(&[hidden, hidden])),
+        ("blk.0.attn_k.weight", weights(&[hidden, hidden])),
+        ("blk.0.attn_v.weight", weights(&[hidden, hidden])),
+        ("blk.0.attn_output.weight", weights(&[hidden, hidden])),
+        ("blk.0.ffn_gate.weight", weights(&[feed_forward, hidden])),
+        ("blk.0.ffn_down.weight", weights(&[hidden, feed_forward])),
+        ("blk.0.ffn_up.weight", weights(&[feed_forward, hidden])),
+        ("blk.0.attn_norm.weight", weights(&[hidden])),
+        ("blk.0.ffn_norm.weight", weights(&[hidden])),
+    ];
+    let metadata = [
+        (
+            "general.architecture",
+            gguf_file::Value::String("llama".into()),
+        ),
+        ("llama.context_length", gguf_file::Value::U32(128)),
+        ("llama.attention.head_count", gguf_file::Value::U32(2)),
+        ("llama.attention.head_count_kv", gguf_file::Value::U32(2)),
+        ("llama.block_count", gguf_file::Value::U32(1)),
+        (
+            "llama.embedding_length",
+            gguf_file::Value::U32(hidden as u32),
+        ),
+        ("llama.rope.dimension_count", gguf_file::Value::U32(4)),
+        (
+            "llama.attention.layer_norm_rms_epsilon",
+            gguf_file::Value::F32(1e-6),
+        ),
+    ];
+
+    let model_path = temp_path(&format!("{}.gguf", name));
+    let mut file = std::fs::File::create(&model_path).expect("create model file");
+    let metadata: Vec<_> = metadata.iter().map(|(k, v)| (*k, v)).collect();
+    let tensors: Vec<_> = tensors.iter().map(|(k, v)| (*k, v)).collect();
+    gguf_file::write(&mut file, &metadata, &tensors).expect("write model file");
+
+    let vocab: serde_json::Map<String, serde_json::Value> = VOCAB
+        .iter()
+        .enumerate()
+        .map(|(id, word)| (word.to_string(), id.into()))
+        .collect();
+    let tokenizer = serde_json::json!({
+        "version": "1.0",
+        "truncation": null,
+        "padding": null,
+        "added_tokens": [],
+        "normalizer": null,
+        "pre_tokenizer": { "type": "Whitespace" },
+        "post_processor": null,
+        "decoder": null,
+        "model": { "type": "WordLevel", "vocab": vocab, "unk_token": "<unk>" }
+    });
+    let tokenizer_path = temp_path(&format!("{}-tokenizer.json", name));
+    std::fs::write(&tokenizer_path, tokenizer.to_string()).expect("write tokenizer");
+
+    (model_path, tokenizer_path)
+}
+
+#[test]
+fn test_streaming_matches_blocking_response() -> fm_bindings::Result<()> {
+    let (model, tokenizer) = write_stub_model("stream");
+    let backend = CandleBackend::load(&model, &tokenizer)?.with_max_tokens(6);
+    let session = fm_bindings::LanguageModelSession::with_backend(backend)?;
+
+    let mut chunks = Vec::new();
+    session.stream_response("alpha beta", |chunk| chunks.push(chunk.to_string()))?;
+    assert_eq!(
+        chunks.len(),
+        6,
+        "one chunk per generated word: {:?}",
+        chunks
+    );
+    assert!(chunks.iter().all(|chunk| !chunk.trim().is_empty()));
+
+    // Greedy decoding is deterministic, given the same conversation
+    let backend = CandleBackend::load(&model, &tokenizer)?.with_max_tokens(6);
+    let fresh = fm_bindings::LanguageModelSession::with_backend(backend)?;
+    assert_eq!(fresh.response("alpha beta")?, chunks.concat());
+
+    std::fs::remove_file(model).ok();
+    std::fs::remove_file(tokenizer).ok();
+    Ok(())
+}
+
+#[test]
+fn test_tokens_are_counted_with_the_tokenizer() -> fm_bindings::Result<()> {
+    let (model, tokenizer) = write_stub_model("tokens");
+    let backend = CandleBackend::load(&model, &tokenizer)?;
+    let session = fm_bindings::LanguageModelSession::with_backend(backend)?;
+
+    assert_eq!(session.context_size(), 128);
+    assert_eq!(session.token_count("alpha beta gamma")?, 3);
+
+    let transcript: Transcript = vec![
+        TranscriptEntry::prompt("alpha beta"),
+        TranscriptEntry::response("delta"),
+    ]
+    .into();
+    assert_eq!(
+        session.token_count(&transcript)?,
+        3 + 2 * tokens::ENTRY_OVERHEAD
+    );
+
+    std::fs::remove_file(model).ok();
+    std::fs::remove_file(tokenizer).ok();
+    Ok(())
+}
+
+#[test]
+fn test_cancel_stream_stops_generation() -> fm_bindings::Result<()> {
+    let (model, tokenizer) = write_stub_model("cancel");
+    let backend = CandleBackend::load(&model, &tokenizer)?.with_max_tokens(50);
+    let session = fm_bindings::LanguageModelSession::with_backend(backend)?;
+
+    let mut chunks = 0;
+    session.stream_response("gamma", |_| {
+        chunks += 1;
+        if chunks == 2 {
+            session.cancel_stream();
+        }
+    })?;
+    assert_eq!(chunks, 2);
+
+    std::fs::remove_file(model).ok();
+    std::fs::remove_file(tokenizer).ok();
+    Ok(())
+}
+
+#[test]
+fn test_generation_options_control_sampling() -> fm_bindings::Result<()> {
+    let (model, tokenizer) = write_stub_model("options");
+    let backend = CandleBackend::load(&model, &tokenizer)?.with_max_tokens(20);
+    let session = fm_bindings::LanguageModelSession::with_backend(backend)?;
+
+    let short = GenerationOptions::new().with_maximum_response_tokens(3);
+    let mut chunks = 0;
+    session.stream_response_with_options("alpha", &short, |_| chunks += 1)?;
+    assert_eq!(chunks, 3);
+
+    // A seeded random mode repeats itself, given the same conversation
+    let seeded = GenerationOptions::new()
+        .with_temperature(1.0)
+        .with_maximum_response_tokens(8)
+        .with_sampling(SamplingMode::nucleus(0.9).with_seed(42));
+    let fresh = || {
+        CandleBackend::load(&model, &tokenizer)
+            .and_then(fm_bindings::LanguageModelSession::with_backend)
+    };
+    assert_eq!(
+        fresh()?.response_with_options("alpha", &seeded)?,
+        fresh()?.response_with_options("alpha", &seeded)?
+    );
+
+    std::fs::remove_file(model).ok();
+    std::fs::remove_file(tokenizer).ok();
+    Ok(())
+}
+
+#[test]
+fn test_earlier_turns_are_rendered() -> fm_bindings::Result<()> {
+    let (model, tokenizer) = write_stub_model("turns");
+    let fresh = || {
+        CandleBackend::load(&model, &tokenizer)
+            .map(|backend| backend.with_max_tokens(4))
+            .and_then(fm_bindings::LanguageModelSession::with_backend)
+    };
+
+    let session = fresh()?;
+    let first = session.response("alpha beta")?;
+    let second = session.response("gamma")?;
+
+    // The same conversation, restored from a transcript, gets the same answer
+    let transcript: Transcript = vec![
+        TranscriptEntry::prompt("alpha beta"),
+        TranscriptEntry::response(first),
+    ]
+    .into();
+    let restored = fm_bindings::LanguageModelSession::builder()
+        .backend(CandleBackend::load(&model, &tokenizer)?.with_max_tokens(4))
+        .transcript(transcript)
+        .build()?;
+    assert_eq!(restored.response("gamma")?, second);
+
+    // Without the earlier turn the model sees a different input
+    assert_ne!(fresh()?.response("gamma")?, second);
+
+    std::fs::remove_file(model).ok();
+    std::fs::remove_file(tokenizer).ok();
+    Ok(())
+}
+
+#[test]
+fn test_tools_are_rejected() -> fm_bindings::Result<()> {
+    /// A tool the backend cannot offer to the model
+    struct Clock;
+
+    impl Tool for Clock {
+        fn name(&self) -> &str {
+            "get_time"
+        }
+
+        fn description(&self) -> &str {
+            "Returns the current time"
+        }
+
+        fn arguments_schema(&self) -> GenerationSchema {
+            GenerationSchema::object("ClockArguments")
+        }
+
+        fn call(&self, _arguments: serde_json::Value) -> fm_bindings::Result<String> {
+            Ok("12:00".into())
+        }
+    }
+
+    let (model, tokenizer) = write_stub_model("tools");
+    let session = fm_bindings::LanguageModelSession::builder()
+        .backend(CandleBackend::load(&model, &tokenizer)?)
+        .tool(Clock)
+        .build()?;
+
+    let result = session.response("alpha");
+    assert!(matches!(result, Err(Error::InvalidInput(message)) if message.contains("get_time")));
+
+    std::fs::remove_file(model).ok();
+    std::fs::remove_file(tokenizer).ok();
+    Ok(())
+}
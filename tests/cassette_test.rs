@@ -0,0 +1,338 @@
+//! Record-and-replay tests for cassette files
+
+use fm_bindings::cassette::{RecordingBackend, ReplayBackend};
+use fm_bindings::{
+    Error, GenerationRequest, GenerationSchema, LanguageModelSession, ModelBackend, Property,
+    Result, Tool, TranscriptEntry,
+};
+use serde_json::{Value, json};
+use std::path::PathBuf;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+/// Answers each prompt with its words as chunks, and fails on "fail"
+struct WordsBackend;
+
+impl ModelBackend for WordsBackend {
+    fn is_available(&self) -> bool {
+        true
+    }
+
+    fn stream_response(
+        &self,
+        request: &GenerationRequest<'_>,
+        on_chunk: &mut dyn FnMut(&str),
+    ) -> Result<()> {
+        let prompt = request.prompt;
+        if prompt == "fail" {
+            return Err(Error::GenerationError("refused".into()));
+        }
+        for word in prompt.split_inclusive(' ') {
+            on_chunk(word);
+        }
+        Ok(())
+    }
+
+    fn cancel_stream(&self) {}
+}
+
+fn cassette_path(name: &str) -> PathBuf {
+    std::env::temp_dir().join(format!("fm-bindings-{}-{}.jsonl", name, std::process::id()))
+}
+
+fn record(path: &PathBuf, prompts: &[&str]) -> Result<()> {
+    let session =
+        LanguageModelSession::with_backend(RecordingBackend::create(WordsBackend, path)?)?;
+    for prompt in prompts {
+        let _ = session.stream_response(prompt, |_| {});
+    }
+    Ok(())
+}
+
+#[test]
+fn test_replay_reproduces_recording() -> Result<()> {
+    let path = cassette_path("roundtrip");
+    record(&path, &["hello big world", "fail"])?;
+
+    let session = LanguageModelSession::with_backend(ReplayBackend::open(&path)?)?;
+
+    let mut chunks = Vec::new();
+    session.stream_response("hello big world", |chunk| chunks.push(chunk.to_string()))?;
+    assert_eq!(chunks, ["hello ", "big ", "world"]);
+
+    match session.response("fail") {
+        Err(Error::GenerationError(msg)) => assert_eq!(msg, "refused"),
+        other => panic!("expected recorded failure, got {:?}", other),
+    }
+
+    std::fs::remove_file(&path).ok();
+    Ok(())
+}
+
+#[test]
+fn test_cassette_is_jsonl_with_timestamps() -> Result<()> {
+    let path = cassette_path("format");
+    record(&path, &["one two", "fail"])?;
+
+    let contents = std::fs::read_to_string(&path).expect("cassette should exist");
+    let lines: Vec<serde_json::Value> = contents
+        .lines()
+        .map(|line| serde_json::from_str(line).expect("each line is JSON"))
+        .collect();
+
+    assert_eq!(lines.len(), 2);
+    assert_eq!(lines[0]["prompt"], "one two");
+    assert_eq!(lines[0]["chunks"][1]["text"], "two");
+    assert!(lines[0]["chunks"][0]["offset_ms"].is_u64());
+    assert_eq!(lines[0]["outcome"]["status"], "completed");
+    assert_eq!(lines[1]["outcome"]["status"], "failed");
+
+    std::fs::remove_file(&path).ok();
+    Ok(())
+}
+
+#[test]
+fn test_mismatched_prompt_is_an_error() -> Result<()> {
+    let path = cassette_path("mismatch");
+    record(&path, &["expected prompt"])?;
+
+    let replay = ReplayBackend::open(&path)?;
+    let session = LanguageModelSession::with_backend(replay)?;
+
+    match session.response("different prompt") {
+        Err(Error::CassetteMismatch { expected, actual }) => {
+            assert_eq!(expected.as_deref(), Some("expected prompt"));
+            assert_eq!(actual, "different prompt");
+        }
+        other => panic!("expected cassette mismatch, got {:?}", other),
+    }
+
+    // The mismatched interaction is still available
+    assert_eq!(session.response("expected prompt")?, "expected prompt");
+
+    // Nothing left to replay
+    assert!(matches!(
+        session.response("expected prompt"),
+        Err(Error::CassetteMismatch { expected: None, .. })
+    ));
+
+    std::fs::remove_file(&path).ok();
+    Ok(())
+}
+
+#[test]
+fn test_invalid_cassette_is_rejected() {
+    let path = cassette_path("invalid");
+    std::fs::write(&path, "{\"prompt\": \"missing fields\"}\n").expect("write cassette");
+
+    assert!(matches!(
+        ReplayBackend::open(&path),
+        Err(Error::InvalidInput(_))
+    ));
+    assert!(matches!(
+        ReplayBackend::open(cassette_path("does-not-exist")),
+        Err(Error::InvalidInput(_))
+    ));
+
+    std::fs::remove_file(&path).ok();
+}
+
+/// Calls `get_weather` for the city named by the prompt, then answers with its output
+struct WeatherBackend;
+
+impl ModelBackend for WeatherBackend {
+    fn is_available(&self) -> bool {
+        true
+    }
+
+    fn stream_response(
+        &self,
+        request: &GenerationRequest<'_>,
+        on_chunk: &mut dyn FnMut(&str),
+    ) -> Result<()> {
+        on_chunk("Looking up. ");
+        let tool = request.tool("get_weather").expect("weather tool");
+        let output = tool.call(json!({ "city": request.prompt }))?;
+        on_chunk(&output);
+        Ok(())
+    }
+
+    fn cancel_stream(&self) {}
+}
+
+/// Knows the weather in Paris only, and counts its calls
+#[derive(Default)]
+struct Weather(Arc<AtomicUsize>);
+
+impl Tool for Weather {
+    fn name(&self) -> &str {
+        "get_weather"
+    }
+
+    fn description(&self) -> &str {
+        "Returns the current temperature in a city"
+    }
+
+    fn arguments_schema(&self) -> GenerationSchema {
+        GenerationSchema::object("WeatherArguments")
+            .with_property(Property::new("city", GenerationSchema::string()))
+    }
+
+    fn call(&self, arguments: Value) -> Result<String> {
+        self.0.fetch_add(1, Ordering::SeqCst);
+        match arguments["city"].as_str() {
+            Some("Paris") => Ok("18°C".into()),
+            _ => Err(Error::InvalidInput("Unknown city".into())),
+        }
+    }
+}
+
+#[test]
+fn test_tool_calls_are_recorded_and_replayed() -> Result<()> {
+    let path = cassette_path("tools");
+    let recorded = {
+        let session = LanguageModelSession::builder()
+            .backend(RecordingBackend::create(WeatherBackend, &path)?)
+            .tool(Weather::default())
+            .build()?;
+        session.response("Paris")?;
+        assert!(session.response("Atlantis").is_err());
+        session.transcript()
+    };
+
+    let contents = std::fs::read_to_string(&path).expect("cassette should exist");
+    let lines: Vec<Value> = contents
+        .lines()
+        .map(|line| serde_json::from_str(line).expect("each line is JSON"))
+        .collect();
+    let call = &lines[0]["tool_calls"][0];
+    assert_eq!(call["name"], "get_weather");
+    assert_eq!(call["arguments"], json!({ "city": "Paris" }));
+    assert_eq!(call["output"], "18°C");
+    assert!(lines[1]["tool_calls"][0]["error"].is_object());
+
+    // The tools are not run again, but the transcript and errors come out the same
+    let calls = Arc::new(AtomicUsize::new(0));
+    let session = LanguageModelSession::builder()
+        .backend(ReplayBackend::open(&path)?)
+        .tool(Weather(calls.clone()))
+        .build()?;
+    assert_eq!(session.response("Paris")?, "Looking up. 18°C");
+    match session.response("Atlantis") {
+        Err(Error::ToolCallFailed { tool, message }) => {
+            assert_eq!(tool, "get_weather");
+            assert!(message.contains("Unknown city"), "{}", message);
+        }
+        other => panic!("expected a tool call error, got {:?}", other),
+    }
+    assert_eq!(calls.load(Ordering::SeqCst), 0);
+    assert_eq!(session.transcript(), recorded);
+    assert!(matches!(
+        session.transcript().entries()[2],
+        TranscriptEntry::ToolOutput { .. }
+    ));
+
+    std::fs::remove_file(&path).ok();
+    Ok(())
+}
+
+/// Streams guided responses as snapshots and answers blocking requests whole,
+/// like the system model, counting the blocking requests
+#[derive(Default)]
+struct SnapshotBackend {
+    blocking: Arc<AtomicUsize>,
+}
+
+const SNAPSHOTS: [&str; 3] = [r#"["red""#, r#"["red", "green""#, r#"["red", "green"]"#];
+
+impl ModelBackend for SnapshotBackend {
+    fn is_available(&self) -> bool {
+        true
+    }
+
+    fn response(&self, _request: &GenerationRequest<'_>) -> Result<String> {
+        self.blocking.fetch_add(1, Ordering::SeqCst);
+        Ok(SNAPSHOTS[2].to_string())
+    }
+
+    fn stream_response(
+        &self,
+        _request: &GenerationRequest<'_>,
+        on_chunk: &mut dyn FnMut(&str),
+    ) -> Result<()> {
+        on_chunk(SNAPSHOTS[2]);
+        Ok(())
+    }
+
+    fn stream_snapshots(
+        &self,
+        _request: &GenerationRequest<'_>,
+        on_snapshot: &mut dyn FnMut(&str),
+    ) -> Result<()> {
+        SNAPSHOTS.iter().for_each(|snapshot| on_snapshot(snapshot));
+        Ok(())
+    }
+
+    fn cancel_stream(&self) {}
+}
+
+#[test]
+fn test_snapshots_and_blocking_responses_are_recorded_and_replayed() -> Result<()> {
+    let path = cassette_path("snapshots");
+    let backend = SnapshotBackend::default();
+    let blocking = backend.blocking.clone();
+    let session = LanguageModelSession::with_backend(RecordingBackend::create(backend, &path)?)?;
+
+    let mut recorded = Vec::new();
+    let colors =
+        session.stream_response_as::<Vec<String>, _>("Colors", |partial| recorded.push(partial))?;
+    assert_eq!(colors, ["red", "green"]);
+    assert!(recorded.len() > 1, "{:?}", recorded);
+    assert_eq!(session.response("Blocking")?, SNAPSHOTS[2]);
+    assert_eq!(
+        blocking.load(Ordering::SeqCst),
+        1,
+        "recording kept the request blocking"
+    );
+
+    let contents = std::fs::read_to_string(&path).expect("cassette should exist");
+    let lines: Vec<Value> = contents
+        .lines()
+        .map(|line| serde_json::from_str(line).expect("each line is JSON"))
+        .collect();
+    assert_eq!(lines[0]["delivery"], "snapshots");
+    assert_eq!(lines[0]["chunks"].as_array().map(Vec::len), Some(3));
+    assert_eq!(lines[1]["delivery"], "whole");
+
+    let session = LanguageModelSession::with_backend(ReplayBackend::open(&path)?)?;
+    let mut replayed = Vec::new();
+    let colors =
+        session.stream_response_as::<Vec<String>, _>("Colors", |partial| replayed.push(partial))?;
+    assert_eq!(colors, ["red", "green"]);
+    assert_eq!(replayed, recorded);
+    assert_eq!(session.response("Blocking")?, SNAPSHOTS[2]);
+
+    std::fs::remove_file(&path).ok();
+    Ok(())
+}
+
+#[cfg(target_os = "linux")]
+#[test]
+fn test_write_failure_keeps_the_result_and_is_reported_later() -> Result<()> {
+    // Every write to /dev/full fails with "no space left on device"
+    let recorder = RecordingBackend::create(WordsBackend, "/dev/full")?;
+
+    let mut chunks = Vec::new();
+    recorder.stream_response(&GenerationRequest::new("hello world"), &mut |chunk| {
+        chunks.push(chunk.to_string())
+    })?;
+    assert_eq!(chunks, ["hello ", "world"]);
+
+    match recorder.response(&GenerationRequest::new("hello")) {
+        Err(Error::InternalError(msg)) => assert!(msg.contains("Cannot write cassette"), "{}", msg),
+        other => panic!("expected the held write failure, got {:?}", other),
+    }
+    recorder.stream_response(&GenerationRequest::new("again"), &mut |_| {})?;
+    assert!(recorder.finish().is_err(), "the last write failed too");
+    Ok(())
+}
@@ -0,0 +1,316 @@
+//! Context policy tests against an in-process backend
+//!
+//! The backend below has a tiny context window and counts one token per word,
+//! so these run on every platform and the trimming of each policy is exact.
+
+use fm_bindings::{
+    ContextPolicy, Error, GenerationOptions, GenerationRequest, LanguageModelSession, ModelBackend,
+    Result, TokenInput, Transcript, TranscriptEntry,
+};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
+
+/// Context window of the backend, in words
+const CONTEXT_SIZE: usize = 40;
+
+/// Answers "ok", summary requests with "SUMMARY", and records what it was sent
+#[derive(Default)]
+struct Window {
+    requests: Mutex<Vec<(String, Option<String>, Transcript)>>,
+    exceed: AtomicUsize,
+    chunks_before_error: bool,
+    resets: AtomicUsize,
+}
+
+impl Window {
+    fn transcripts(&self) -> Vec<Transcript> {
+        let requests = self.requests.lock().unwrap();
+        requests.iter().map(|(_, _, t)| t.clone()).collect()
+    }
+}
+
+impl ModelBackend for Window {
+    fn is_available(&self) -> bool {
+        true
+    }
+
+    fn stream_response(
+        &self,
+        request: &GenerationRequest<'_>,
+        on_chunk: &mut dyn FnMut(&str),
+    ) -> Result<()> {
+        self.requests.lock().unwrap().push((
+            request.prompt.to_string(),
+            request.instructions.map(str::to_string),
+            request.transcript.clone(),
+        ));
+        let summarizing = request
+            .instructions
+            .is_some_and(|instructions| instructions.starts_with("You summarize"));
+        if summarizing {
+            on_chunk("SUMMARY");
+            return Ok(());
+        }
+        if self.exceed.load(Ordering::SeqCst) > 0 {
+            self.exceed.fetch_sub(1, Ordering::SeqCst);
+            if self.chunks_before_error {
+                on_chunk("partial");
+            }
+            return Err(Error::ContextWindowExceeded("Too many tokens".into()));
+        }
+        on_chunk("ok");
+        Ok(())
+    }
+
+    fn cancel_stream(&self) {}
+
+    fn reset(&self) {
+        self.resets.fetch_add(1, Ordering::SeqCst);
+    }
+
+    fn context_size(&self) -> usize {
+        CONTEXT_SIZE
+    }
+
+    fn token_count(&self, input: TokenInput<'_>) -> Result<usize> {
+        let words = |text: &str| text.split_whitespace().count();
+        Ok(match input {
+            TokenInput::Text(text) => words(text),
+            TokenInput::Transcript(transcript) => transcript
+                .iter()
+                .map(|entry| match entry {
+                    TranscriptEntry::Instructions { text }
+                    | TranscriptEntry::Prompt { text }
+                    | TranscriptEntry::Response { text } => words(text),
+                    _ => 0,
+                })
+                .sum(),
+        })
+    }
+}
+
+/// Lets a test keep a handle on the backend after moving it into a session
+struct Shared(Arc<Window>);
+
+impl ModelBackend for Shared {
+    fn is_available(&self) -> bool {
+        true
+    }
+
+    fn stream_response(
+        &self,
+        request: &GenerationRequest<'_>,
+        on_chunk: &mut dyn FnMut(&str),
+    ) -> Result<()> {
+        self.0.stream_response(request, on_chunk)
+    }
+
+    fn cancel_stream(&self) {}
+
+    fn reset(&self) {
+        self.0.reset()
+    }
+
+    fn context_size(&self) -> usize {
+        self.0.context_size()
+    }
+
+    fn token_count(&self, input: TokenInput<'_>) -> Result<usize> {
+        self.0.token_count(input)
+    }
+}
+
+/// Instructions of two words and `turns` turns of ten words each
+fn conversation(turns: usize) -> Transcript {
+    let mut transcript = Transcript::new();
+    transcript.push(TranscriptEntry::instructions("Be brief"));
+    for turn in 1..=turns {
+        transcript.push(TranscriptEntry::prompt(format!(
+            "question {} one two three",
+            turn
+        )));
+        transcript.push(TranscriptEntry::response(format!(
+            "answer {} one two three",
+            turn
+        )));
+    }
+    transcript
+}
+
+fn session(backend: &Arc<Window>, policy: ContextPolicy, turns: usize) -> LanguageModelSession {
+    LanguageModelSession::builder()
+        .backend(Shared(backend.clone()))
+        .transcript(conversation(turns))
+        .context_policy(policy)
+        .build()
+        .unwrap()
+}
+
+/// The prompts of the turns left in a transcript
+fn prompts(transcript: &Transcript) -> Vec<String> {
+    transcript
+        .iter()
+        .filter_map(|entry| match entry {
+            TranscriptEntry::Prompt { text } => Some(text.clone()),
+            _ => None,
+        })
+        .collect()
+}
+
+#[test]
+fn test_default_policy_is_fail() {
+    let backend = Arc::new(Window::default());
+    let session = LanguageModelSession::with_backend(Shared(backend)).unwrap();
+    assert_eq!(session.context_policy(), ContextPolicy::Fail);
+}
+
+#[test]
+fn test_fail_sends_the_whole_transcript() -> Result<()> {
+    // 32 words of transcript and 1 of prompt leave less than a quarter of 40 free
+    let backend = Arc::new(Window::default());
+    let session = session(&backend, ContextPolicy::Fail, 3);
+
+    session.response("next")?;
+    assert_eq!(backend.transcripts(), vec![conversation(3)]);
+    assert_eq!(backend.resets.load(Ordering::SeqCst), 0);
+    Ok(())
+}
+
+#[test]
+fn test_fail_propagates_exceeded_context() {
+    let backend = Arc::new(Window {
+        exceed: AtomicUsize::new(1),
+        ..Default::default()
+    });
+    let session = session(&backend, ContextPolicy::Fail, 3);
+
+    let result = session.response("next");
+    assert!(matches!(result, Err(Error::ContextWindowExceeded(_))));
+    assert_eq!(backend.transcripts().len(), 1);
+    assert_eq!(session.transcript(), conversation(3));
+}
+
+#[test]
+fn test_sliding_window_drops_oldest_turns() -> Result<()> {
+    // 40 - 10 reserved - 1 for the prompt leaves 29: instructions and two turns
+    let backend = Arc::new(Window::default());
+    let session = session(&backend, ContextPolicy::SlidingWindow, 3);
+
+    session.response("next")?;
+    let sent = &backend.transcripts()[0];
+    assert_eq!(sent.instructions(), Some("Be brief"));
+    assert_eq!(
+        prompts(sent),
+        ["question 2 one two three", "question 3 one two three"]
+    );
+    assert_eq!(backend.resets.load(Ordering::SeqCst), 1);
+
+    // The session keeps the shortened conversation, plus the new turn
+    let transcript = session.transcript();
+    assert_eq!(transcript.len(), sent.len() + 2);
+    assert_eq!(prompts(&transcript)[2], "next");
+    Ok(())
+}
+
+#[test]
+fn test_sliding_window_keeps_fitting_transcripts() -> Result<()> {
+    let backend = Arc::new(Window::default());
+    let session = session(&backend, ContextPolicy::SlidingWindow, 2);
+
+    session.response("next")?;
+    assert_eq!(backend.transcripts(), vec![conversation(2)]);
+    assert_eq!(backend.resets.load(Ordering::SeqCst), 0);
+    Ok(())
+}
+
+#[test]
+fn test_maximum_response_tokens_sets_the_reserve() -> Result<()> {
+    // Reserving 2 tokens instead of 10 leaves room for all three turns
+    let backend = Arc::new(Window::default());
+    let session = session(&backend, ContextPolicy::SlidingWindow, 3);
+
+    let options = GenerationOptions::new().with_maximum_response_tokens(2);
+    session.response_with_options("next", &options)?;
+    assert_eq!(backend.transcripts(), vec![conversation(3)]);
+    Ok(())
+}
+
+#[test]
+fn test_summarize_replaces_oldest_turns() -> Result<()> {
+    // A summary may take 5 of the 27 words left for turns, so two turns are kept
+    let backend = Arc::new(Window::default());
+    let session = session(&backend, ContextPolicy::Summarize, 3);
+
+    session.response("next")?;
+    let requests = backend.requests.lock().unwrap().clone();
+    assert_eq!(requests.len(), 2);
+
+    let (summary_prompt, summary_instructions, summary_transcript) = &requests[0];
+    assert!(summary_instructions.is_some());
+    assert!(summary_transcript.is_empty());
+    assert!(summary_prompt.contains("User: question 1 one two three"));
+    assert!(summary_prompt.contains("Assistant: answer 1 one two three"));
+    assert!(!summary_prompt.contains("question 2"));
+
+    let sent = &requests[1].2;
+    let mut expected = Transcript::new();
+    expected.push(TranscriptEntry::instructions("Be brief"));
+    expected.push(TranscriptEntry::prompt(ContextPolicy::SUMMARY_PROMPT));
+    expected.push(TranscriptEntry::response("SUMMARY"));
+    for entry in conversation(3).iter().skip(3) {
+        expected.push(entry.clone());
+    }
+    assert_eq!(sent, &expected);
+    assert_eq!(
+        prompts(&session.transcript())[1..3],
+        prompts(&expected)[1..]
+    );
+    Ok(())
+}
+
+#[test]
+fn test_exceeded_context_is_retried_on_a_shorter_transcript() -> Result<()> {
+    // Two turns fit the estimate, but the model disagrees once
+    let backend = Arc::new(Window {
+        exceed: AtomicUsize::new(1),
+        ..Default::default()
+    });
+    let session = session(&backend, ContextPolicy::SlidingWindow, 2);
+
+    assert_eq!(session.response("next")?, "ok");
+    let transcripts = backend.transcripts();
+    assert_eq!(transcripts.len(), 2);
+    assert_eq!(transcripts[0], conversation(2));
+    assert_eq!(prompts(&transcripts[1]), ["question 2 one two three"]);
+    assert_eq!(transcripts[1].instructions(), Some("Be brief"));
+    Ok(())
+}
+
+#[test]
+fn test_exceeded_context_is_retried_once() {
+    let backend = Arc::new(Window {
+        exceed: AtomicUsize::new(2),
+        ..Default::default()
+    });
+    let session = session(&backend, ContextPolicy::SlidingWindow, 2);
+
+    let result = session.response("next");
+    assert!(matches!(result, Err(Error::ContextWindowExceeded(_))));
+    assert_eq!(backend.transcripts().len(), 2);
+}
+
+#[test]
+fn test_streams_are_not_retried_after_chunks() {
+    let backend = Arc::new(Window {
+        exceed: AtomicUsize::new(1),
+        chunks_before_error: true,
+        ..Default::default()
+    });
+    let session = session(&backend, ContextPolicy::SlidingWindow, 2);
+
+    let mut streamed = String::new();
+    let result = session.stream_response("next", |chunk| streamed.push_str(chunk));
+    assert!(matches!(result, Err(Error::ContextWindowExceeded(_))));
+    assert_eq!(streamed, "partial");
+    assert_eq!(backend.transcripts().len(), 1);
+}
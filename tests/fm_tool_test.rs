@@ -0,0 +1,158 @@
+//! `#[fm_tool]` tests: generated schemas, argument decoding and tool calls
+//!
+//! Tools are called directly or through an in-process backend, so these tests run
+//! on every platform. Requires the `derive` feature (enabled by default).
+
+use fm_bindings::{
+    Error, GenerationRequest, GenerationSchema, LanguageModelSession, ModelBackend, Property,
+    Result, Tool, TranscriptEntry, fm_tool,
+};
+use serde_json::json;
+
+/// Returns the forecast for a city
+///
+/// Covers the next week at most.
+#[fm_tool]
+fn get_forecast(
+    /// City name
+    city: String,
+    #[guide(description = "Days ahead", range = 0..=7)] days: Option<u8>,
+) -> String {
+    format!("Sunny in {} for {} days", city, days.unwrap_or(1))
+}
+
+/// Looks up a user by id
+#[fm_tool(name = "lookup_user")]
+pub fn find_user(id: u32) -> Result<String> {
+    match id {
+        1 => Ok("Ada".into()),
+        _ => Err(Error::InvalidInput(format!("No user {}", id))),
+    }
+}
+
+#[fm_tool(description = "Returns the current time")]
+fn now() -> String {
+    "12:00".into()
+}
+
+#[test]
+fn test_tool_name_and_description() {
+    assert_eq!(GetForecast.name(), "get_forecast");
+    assert_eq!(
+        GetForecast.description(),
+        "Returns the forecast for a city\n\nCovers the next week at most."
+    );
+    assert_eq!(FindUser.name(), "lookup_user");
+    assert_eq!(Now.description(), "Returns the current time");
+}
+
+#[test]
+fn test_arguments_schema_follows_parameters() {
+    let expected = GenerationSchema::object("GetForecastArguments")
+        .with_property(
+            Property::new("city", GenerationSchema::string()).with_description("City name"),
+        )
+        .with_property(
+            Property::new(
+                "days",
+                GenerationSchema::Integer {
+                    minimum: Some(0),
+                    maximum: Some(7),
+                },
+            )
+            .with_description("Days ahead")
+            .with_optional(true),
+        );
+    assert_eq!(GetForecast.arguments_schema(), expected);
+    assert_eq!(
+        Now.arguments_schema(),
+        GenerationSchema::object("NowArguments")
+    );
+}
+
+#[test]
+fn test_arguments_are_decoded() -> Result<()> {
+    assert_eq!(
+        GetForecast.call(json!({ "city": "Oslo", "days": 3 }))?,
+        "Sunny in Oslo for 3 days"
+    );
+    assert_eq!(
+        GetForecast.call(json!({ "city": "Oslo" }))?,
+        "Sunny in Oslo for 1 days",
+        "optional arguments may be left out"
+    );
+    assert_eq!(Now.call(json!({}))?, "12:00");
+    Ok(())
+}
+
+#[test]
+fn test_invalid_arguments_are_rejected() {
+    let missing = GetForecast.call(json!({ "days": 3 }));
+    assert!(matches!(missing, Err(Error::InvalidInput(message)) if message.contains("city")));
+
+    let mistyped = FindUser.call(json!({ "id": "one" }));
+    assert!(
+        matches!(mistyped, Err(Error::InvalidInput(message)) if message.contains("lookup_user"))
+    );
+}
+
+#[test]
+fn test_function_errors_are_returned() {
+    assert_eq!(
+        FindUser.call(json!({ "id": 1 })).ok().as_deref(),
+        Some("Ada")
+    );
+    assert!(matches!(
+        FindUser.call(json!({ "id": 2 })),
+        Err(Error::InvalidInput(_))
+    ));
+}
+
+#[test]
+fn test_function_stays_callable() {
+    assert_eq!(
+        get_forecast("Rome".into(), Some(2)),
+        "Sunny in Rome for 2 days"
+    );
+}
+
+/// Calls `lookup_user` once and answers with its output
+struct CallsLookup;
+
+impl ModelBackend for CallsLookup {
+    fn is_available(&self) -> bool {
+        true
+    }
+
+    fn stream_response(
+        &self,
+        request: &GenerationRequest<'_>,
+        on_chunk: &mut dyn FnMut(&str),
+    ) -> Result<()> {
+        let tool = request
+            .tool("lookup_user")
+            .ok_or_else(|| Error::GenerationError("lookup_user is not offered".into()))?;
+        on_chunk(&tool.call(json!({ "id": 1 }))?);
+        Ok(())
+    }
+
+    fn cancel_stream(&self) {}
+}
+
+#[test]
+fn test_session_calls_generated_tool() -> Result<()> {
+    let session = LanguageModelSession::builder()
+        .backend(CallsLookup)
+        .tool(FindUser)
+        .build()?;
+    assert_eq!(session.response("Who is user 1?")?, "Ada");
+    assert_eq!(
+        session.transcript().entries()[1],
+        TranscriptEntry::ToolCall {
+            id: "call-1".into(),
+            name: "lookup_user".into(),
+            arguments: json!({ "id": 1 }),
+        }
+    );
+    Ok(())
+}
@@ -0,0 +1,11 @@
+//! Compile tests for `#[fm_tool]`: accepted signatures and error messages
+//!
+//! Requires the `derive` feature (enabled by default). After changing a message,
+//! regenerate the expected output with `TRYBUILD=overwrite cargo test --test fm_tool_ui`.
+
+#[test]
+fn test_fm_tool_expansion() {
+    let cases = trybuild::TestCases::new();
+    cases.pass("tests/ui/fm_tool/pass_*.rs");
+    cases.compile_fail("tests/ui/fm_tool/fail_*.rs");
+}
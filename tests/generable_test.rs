@@ -0,0 +1,311 @@
+//! Guided generation tests: derived schemas and `response_as`
+//!
+//! Sessions here run on an in-process backend, so these tests run on every platform.
+//! Requires the `derive` feature (enabled by default).
+
+use fm_bindings::{
+    Error, Generable, GenerationRequest, GenerationSchema, LanguageModelSession, ModelBackend,
+    Property, Result, TranscriptEntry,
+};
+use serde::Deserialize;
+use std::sync::{Arc, Mutex};
+
+/// Answers every request with a fixed reply and records the request schemas
+#[derive(Clone)]
+struct SchemaSpy {
+    reply: String,
+    schemas: Arc<Mutex<Vec<Option<GenerationSchema>>>>,
+}
+
+impl SchemaSpy {
+    fn replying(reply: &str) -> Self {
+        Self {
+            reply: reply.to_string(),
+            schemas: Arc::default(),
+        }
+    }
+}
+
+impl ModelBackend for SchemaSpy {
+    fn is_available(&self) -> bool {
+        true
+    }
+
+    fn stream_response(
+        &self,
+        request: &GenerationRequest<'_>,
+        on_chunk: &mut dyn FnMut(&str),
+    ) -> Result<()> {
+        self.schemas.lock().unwrap().push(request.schema.cloned());
+        on_chunk(&self.reply);
+        Ok(())
+    }
+
+    fn cancel_stream(&self) {}
+}
+
+/// Streams a fixed reply in the given pieces
+struct Chunks(Vec<&'static str>);
+
+impl ModelBackend for Chunks {
+    fn is_available(&self) -> bool {
+        true
+    }
+
+    fn stream_response(
+        &self,
+        _request: &GenerationRequest<'_>,
+        on_chunk: &mut dyn FnMut(&str),
+    ) -> Result<()> {
+        self.0.iter().for_each(|chunk| on_chunk(chunk));
+        Ok(())
+    }
+
+    fn cancel_stream(&self) {}
+}
+
+#[derive(Debug, PartialEq, Generable, Deserialize)]
+#[guide(description = "A recipe suggestion")]
+struct Recipe {
+    title: String,
+    #[guide(description = "Preparation time in minutes", range = 5..=240)]
+    minutes: u32,
+    #[guide(count = 1..=3)]
+    ingredients: Vec<String>,
+    difficulty: Difficulty,
+    #[guide(regex = "^[a-z]+$")]
+    tag: Option<String>,
+}
+
+#[derive(Debug, PartialEq, Generable, Deserialize)]
+enum Difficulty {
+    Easy,
+    Hard,
+}
+
+#[derive(Debug, PartialEq, Generable, Deserialize)]
+enum Shape {
+    Point,
+    Circle(f64),
+    Rect { width: u8, height: u8 },
+}
+
+#[derive(Debug, PartialEq, Generable, Deserialize)]
+struct Scored<T> {
+    value: T,
+    #[guide(range = 0.0..=1.0)]
+    score: f64,
+}
+
+#[test]
+fn test_struct_schema_follows_fields_and_guides() {
+    let expected = GenerationSchema::object("Recipe")
+        .with_property(Property::new("title", GenerationSchema::string()))
+        .with_property(
+            Property::new(
+                "minutes",
+                GenerationSchema::Integer {
+                    minimum: Some(5),
+                    maximum: Some(240),
+                },
+            )
+            .with_description("Preparation time in minutes"),
+        )
+        .with_property(Property::new(
+            "ingredients",
+            GenerationSchema::array(GenerationSchema::string()).with_count(Some(1), Some(3)),
+        ))
+        .with_property(Property::new(
+            "difficulty",
+            GenerationSchema::enumeration("Difficulty", ["Easy", "Hard"]),
+        ))
+        .with_property(
+            Property::new("tag", GenerationSchema::string().with_pattern("^[a-z]+$"))
+                .with_optional(true),
+        )
+        .with_description("A recipe suggestion");
+
+    assert_eq!(Recipe::generation_schema(), expected);
+}
+
+#[test]
+fn test_enum_schema_matches_serde_representation() -> Result<()> {
+    let GenerationSchema::AnyOf { name, choices, .. } = Shape::generation_schema() else {
+        panic!("mixed enums are any-of schemas");
+    };
+    assert_eq!(name, "Shape");
+    assert_eq!(choices.len(), 3);
+    assert_eq!(
+        choices[0],
+        GenerationSchema::enumeration("ShapePoint", ["Point"])
+    );
+    assert_eq!(
+        choices[1],
+        GenerationSchema::object("Circle")
+            .with_property(Property::new("Circle", GenerationSchema::number()))
+    );
+
+    // Values of each choice deserialize into the enum
+    let session = LanguageModelSession::with_backend(SchemaSpy::replying(
+        r#"{"Rect": {"width": 2, "height": 3}}"#,
+    ))?;
+    assert_eq!(
+        session.response_as::<Shape>("A shape")?,
+        Shape::Rect {
+            width: 2,
+            height: 3
+        }
+    );
+    Ok(())
+}
+
+#[test]
+fn test_generic_struct_uses_parameter_schema() {
+    let GenerationSchema::Object { properties, .. } = Scored::<bool>::generation_schema() else {
+        panic!("structs are object schemas");
+    };
+    assert_eq!(properties[0].schema, GenerationSchema::boolean());
+    assert_eq!(
+        properties[1].schema,
+        GenerationSchema::Number {
+            minimum: Some(0.0),
+            maximum: Some(1.0),
+        }
+    );
+}
+
+#[test]
+fn test_response_as_sends_schema_and_decodes() -> Result<()> {
+    let reply = r#"{"title":"Salad","minutes":10,"ingredients":["lettuce"],"difficulty":"Easy"}"#;
+    let spy = SchemaSpy::replying(reply);
+    let session = LanguageModelSession::with_backend(spy.clone())?;
+
+    let recipe: Recipe = session.response_as("A quick lunch")?;
+    assert_eq!(
+        recipe,
+        Recipe {
+            title: "Salad".into(),
+            minutes: 10,
+            ingredients: vec!["lettuce".into()],
+            difficulty: Difficulty::Easy,
+            tag: None,
+        }
+    );
+    session.response("Thanks")?;
+
+    assert_eq!(
+        *spy.schemas.lock().unwrap(),
+        [Some(Recipe::generation_schema()), None]
+    );
+    assert_eq!(
+        session.transcript().entries()[1],
+        TranscriptEntry::response(reply)
+    );
+    Ok(())
+}
+
+#[test]
+fn test_undecodable_response_is_a_decoding_failure() -> Result<()> {
+    let session = LanguageModelSession::with_backend(SchemaSpy::replying("not JSON"))?;
+    let result = session.response_as::<Recipe>("A quick lunch");
+    assert!(matches!(result, Err(Error::DecodingFailure(message)) if message.contains("Recipe")));
+    Ok(())
+}
+
+#[test]
+fn test_json_schema_export() {
+    let json = Recipe::generation_schema().to_json_schema();
+    assert_eq!(json["type"], "object");
+    assert_eq!(json["properties"]["minutes"]["minimum"], 5);
+    assert_eq!(json["properties"]["ingredients"]["maxItems"], 3);
+    assert_eq!(json["properties"]["difficulty"]["enum"][1], "Hard");
+    assert_eq!(
+        json["required"],
+        serde_json::json!(["title", "minutes", "ingredients", "difficulty"])
+    );
+    assert_eq!(json["additionalProperties"], false);
+}
+
+#[test]
+fn test_stream_response_as_reports_growing_partials() -> Result<()> {
+    let session = LanguageModelSession::with_backend(Chunks(vec![
+        r#"{"title": "Sal"#,
+        r#"ad", "minu"#,
+        r#"tes": 10, "ingredients": ["lett"#,
+        r#"uce"], "difficulty": "Ea"#,
+        r#"sy""#,
+        " ",
+        "}",
+    ]))?;
+
+    let mut partials = Vec::new();
+    let recipe: Recipe = session.stream_response_as("A quick lunch", |partial| {
+        partials.push(partial);
+    })?;
+
+    assert_eq!(
+        partials[0],
+        RecipePartial {
+            title: Some("Sal".into()),
+            ..RecipePartial::default()
+        }
+    );
+    assert_eq!(partials[1].title.as_deref(), Some("Salad"));
+    assert_eq!(partials[1].minutes, None, "a dangling key has no value yet");
+    assert_eq!(partials[2].ingredients, Some(vec!["lett".to_string()]));
+    assert_eq!(
+        partials[3].difficulty, None,
+        "an unfinished enum value is not readable"
+    );
+    assert_eq!(partials.len(), 5, "unchanged snapshots are not reported");
+    assert_eq!(partials[4].difficulty, Some(DifficultyPartial::Easy));
+
+    assert_eq!(recipe.title, "Salad");
+    assert_eq!(
+        session.transcript().entries()[1],
+        TranscriptEntry::response(
+            r#"{"title": "Salad", "minutes": 10, "ingredients": ["lettuce"], "difficulty": "Easy" }"#
+        )
+    );
+    Ok(())
+}
+
+#[test]
+fn test_partial_enums_and_generics() -> Result<()> {
+    let session = LanguageModelSession::with_backend(Chunks(vec![
+        r#"{"value": {"Rect": {"wid"#,
+        r#"th": 2, "height": 3}}, "score": 0.5}"#,
+    ]))?;
+
+    let mut partials: Vec<ScoredPartial<Shape>> = Vec::new();
+    let scored: Scored<Shape> =
+        session.stream_response_as("A shape", |partial| partials.push(partial))?;
+
+    assert_eq!(
+        partials[0].value,
+        Some(ShapePartial::Rect {
+            width: None,
+            height: None
+        })
+    );
+    assert_eq!(partials[0].score, None);
+    assert_eq!(
+        partials.last().and_then(|partial| partial.value.clone()),
+        Some(ShapePartial::Rect {
+            width: Some(2),
+            height: Some(3)
+        })
+    );
+    assert_eq!(scored.score, 0.5);
+    Ok(())
+}
+
+#[test]
+fn test_undecodable_stream_is_a_decoding_failure() -> Result<()> {
+    let session = LanguageModelSession::with_backend(Chunks(vec![r#"{"title": "Sal"#]))?;
+    let mut partials = 0;
+    let result = session.stream_response_as::<Recipe, _>("A quick lunch", |_| partials += 1);
+    assert!(matches!(result, Err(Error::DecodingFailure(_))));
+    assert_eq!(partials, 1);
+    Ok(())
+}
@@ -5,7 +5,7 @@
 //! These tests require:
 //! - macOS 26+ or iOS 26+
 //! - Apple Intelligence enabled
-//! - Must be run on an Apple platform (will not compile on Linux/Windows)
+//! - Must be run on an Apple platform (the suite is compiled out elsewhere)
 //!
 //! # Running the tests
 //!
@@ -19,7 +19,14 @@
 //! cargo test --target aarch64-apple-ios-sim
 //! ```
 
-use fm_bindings::{LanguageModelSession, Result};
+#![cfg(target_vendor = "apple")]
+
+use fm_bindings::{
+    CancelHandle, Error, Generable, GenerationOptions, GenerationSchema, Guardrails,
+    LanguageModelSession, Property, Result, SamplingMode, SystemLanguageModel, Tool, Transcript,
+    TranscriptEntry, UseCase,
+};
+use serde::Deserialize;
 use std::sync::{Arc, Mutex};
 use std::thread;
 use std::time::Duration;
@@ -178,6 +185,72 @@ fn test_cancel_streaming_response() -> Result<()> {
     Ok(())
 }
 
+#[test]
+fn test_cancel_handle_stops_blocking_response() -> Result<()> {
+    let session = LanguageModelSession::new()?;
+    let handle = CancelHandle::new();
+
+    // Cancel shortly after the request starts, from another thread
+    let canceller = handle.clone();
+    thread::spawn(move || {
+        thread::sleep(Duration::from_millis(500));
+        canceller.cancel();
+    });
+
+    let result = session
+        .cancellable(&handle)
+        .response("Write a long story about a dragon and a knight");
+    match result {
+        Err(Error::Cancelled { partial }) => {
+            println!("Partial response before cancel: {:?}", partial);
+        }
+        other => panic!("Expected a cancelled request, got {:?}", other),
+    }
+
+    // The session still answers requests without the handle
+    let response = session.response("Say hello")?;
+    assert!(!response.is_empty());
+    assert_eq!(session.transcript().len(), 2);
+
+    println!("✓ Cancel handle test passed");
+    Ok(())
+}
+
+#[test]
+fn test_cancel_handle_stops_a_request_waiting_for_its_turn() -> Result<()> {
+    let session = LanguageModelSession::new()?;
+    let handle = CancelHandle::new();
+
+    thread::scope(|scope| {
+        // The first request keeps the session busy while the second one waits
+        let first =
+            scope.spawn(|| session.response("Write a long story about a dragon and a knight"));
+        thread::sleep(Duration::from_millis(200));
+
+        let canceller = handle.clone();
+        scope.spawn(move || {
+            thread::sleep(Duration::from_millis(300));
+            canceller.cancel();
+        });
+
+        match session.cancellable(&handle).response("Say hello") {
+            Err(Error::Cancelled { partial }) => assert!(partial.is_empty(), "{:?}", partial),
+            other => panic!("Expected a cancelled request, got {:?}", other),
+        }
+        assert!(
+            !first.is_finished(),
+            "The waiting request should stop before the first one finishes"
+        );
+
+        let story = first.join().expect("first request panicked")?;
+        assert!(!story.is_empty());
+        assert_eq!(session.transcript().len(), 2);
+
+        println!("✓ Cancel while waiting test passed");
+        Ok(())
+    })
+}
+
 #[test]
 fn test_empty_prompt_error() -> Result<()> {
     let session = LanguageModelSession::new()?;
@@ -200,3 +273,223 @@ fn test_empty_prompt_error() -> Result<()> {
 
     Ok(())
 }
+
+#[test]
+fn test_session_instructions() -> Result<()> {
+    let session = LanguageModelSession::with_instructions(
+        "You only ever answer with a single word, in uppercase.",
+    )?;
+
+    let response = session.response("What colour is the sky on a clear day?")?;
+    assert!(!response.is_empty(), "Response should not be empty");
+
+    println!("✓ Session instructions test passed");
+    println!("Response: {}", response);
+
+    Ok(())
+}
+
+#[test]
+fn test_multi_turn_conversation() -> Result<()> {
+    let session = LanguageModelSession::new()?;
+
+    session.response("My favourite fruit is the mango. Please remember it.")?;
+    let response = session.response("What is my favourite fruit? Answer with one word.")?;
+    assert!(
+        response.to_lowercase().contains("mango"),
+        "Follow-up should see the earlier turn, got: {}",
+        response
+    );
+    assert_eq!(session.transcript().len(), 4);
+
+    println!("✓ Multi-turn conversation test passed");
+    println!("Response: {}", response);
+
+    Ok(())
+}
+
+#[test]
+fn test_resume_from_transcript() -> Result<()> {
+    let session = LanguageModelSession::new()?;
+    session.response("The secret word is 'pineapple'. Please remember it.")?;
+    let saved = session.transcript().to_json()?;
+    drop(session);
+
+    let resumed = LanguageModelSession::with_transcript(Transcript::from_json(&saved)?)?;
+    let response = resumed.response("What is the secret word? Answer with one word.")?;
+    assert!(
+        response.to_lowercase().contains("pineapple"),
+        "Resumed session should see the saved turns, got: {}",
+        response
+    );
+
+    println!("✓ Resume from transcript test passed");
+    println!("Response: {}", response);
+
+    Ok(())
+}
+
+#[test]
+fn test_greedy_sampling_is_reproducible() -> Result<()> {
+    let options = GenerationOptions::new()
+        .with_sampling(SamplingMode::Greedy)
+        .with_maximum_response_tokens(60);
+    let prompt = "Describe the sea in one sentence.";
+
+    // Separate sessions, so the second answer does not see the first
+    let first = LanguageModelSession::new()?.response_with_options(prompt, &options)?;
+    let second = LanguageModelSession::new()?.response_with_options(prompt, &options)?;
+    assert_eq!(first, second, "Greedy sampling should be deterministic");
+
+    println!("✓ Greedy sampling test passed");
+    println!("Response: {}", first);
+
+    Ok(())
+}
+
+#[derive(Debug, Generable, Deserialize)]
+struct Country {
+    name: String,
+    #[guide(description = "Capital city")]
+    capital: String,
+    #[guide(count = 3)]
+    neighbours: Vec<String>,
+    #[guide(range = 0..=2_000_000_000)]
+    population: u64,
+}
+
+#[test]
+fn test_guided_generation() -> Result<()> {
+    let session = LanguageModelSession::new()?;
+    let country: Country = session.response_as("Describe France.")?;
+
+    assert!(!country.name.is_empty());
+    assert!(!country.capital.is_empty());
+    assert_eq!(
+        country.neighbours.len(),
+        3,
+        "count guide should be enforced"
+    );
+
+    println!("✓ Guided generation test passed");
+    println!("Response: {:?}", country);
+
+    Ok(())
+}
+
+/// Knows the secret code word, which the model cannot guess
+struct SecretCode;
+
+impl Tool for SecretCode {
+    fn name(&self) -> &str {
+        "get_secret_code"
+    }
+
+    fn description(&self) -> &str {
+        "Returns the secret code word of a team"
+    }
+
+    fn arguments_schema(&self) -> GenerationSchema {
+        GenerationSchema::object("SecretCodeArguments")
+            .with_property(Property::new("team", GenerationSchema::string()))
+    }
+
+    fn call(&self, _arguments: serde_json::Value) -> Result<String> {
+        Ok("PAPAYA-7".into())
+    }
+}
+
+#[test]
+fn test_tool_calling() -> Result<()> {
+    let session = LanguageModelSession::builder()
+        .instructions("Use the get_secret_code tool to answer questions about code words.")
+        .tool(SecretCode)
+        .build()?;
+    let response = session.response("What is the secret code word of the blue team?")?;
+
+    assert!(
+        session
+            .transcript()
+            .iter()
+            .any(|entry| matches!(entry, TranscriptEntry::ToolCall { name, .. } if name == "get_secret_code")),
+        "the tool call should be recorded"
+    );
+    assert!(
+        response.contains("PAPAYA"),
+        "response should use the tool output"
+    );
+
+    println!("✓ Tool calling test passed");
+    println!("Response: {}", response);
+
+    Ok(())
+}
+
+#[test]
+fn test_content_tagging_model() -> Result<()> {
+    let model = SystemLanguageModel::new().with_use_case(UseCase::ContentTagging);
+    assert!(model.is_available(), "{}", model.availability());
+
+    let session = LanguageModelSession::with_model(model)?;
+    let response = session.response(
+        "List the topics of this text: The new stadium opens next spring, with a concert.",
+    )?;
+    assert!(!response.is_empty(), "Response should not be empty");
+
+    println!("✓ Content tagging model test passed");
+    println!("Response: {}", response);
+
+    Ok(())
+}
+
+#[test]
+fn test_permissive_guardrails() -> Result<()> {
+    let model =
+        SystemLanguageModel::new().with_guardrails(Guardrails::PermissiveContentTransformations);
+    let session = LanguageModelSession::builder()
+        .model(model)
+        .instructions("Rewrite the user's text politely, keeping its meaning.")
+        .build()?;
+    let response = session.response("This stupid app crashed again and I lost my work!")?;
+    assert!(!response.is_empty(), "Response should not be empty");
+
+    println!("✓ Permissive guardrails test passed");
+    println!("Response: {}", response);
+
+    Ok(())
+}
+
+#[test]
+fn test_prewarm() -> Result<()> {
+    let session = LanguageModelSession::with_instructions("Answer in one word.")?;
+    session.prewarm(Some("Name the capital of "))?;
+    session
+        .prewarm_in_background(None)?
+        .join()
+        .expect("prewarm thread")?;
+
+    let response = session.response("Name the capital of France.")?;
+    assert!(!response.is_empty(), "Response should not be empty");
+
+    println!("✓ Prewarm test passed");
+    println!("Response: {}", response);
+
+    Ok(())
+}
+
+#[test]
+fn test_token_count() -> Result<()> {
+    let session = LanguageModelSession::with_instructions("Answer in one word.")?;
+    let context_size = session.context_size();
+    let prompt_tokens = session.token_count("Name the capital of France.")?;
+    assert!(prompt_tokens > 0 && prompt_tokens < context_size);
+
+    session.response("Name the capital of France.")?;
+    let used = session.token_count(&session.transcript())?;
+    assert!(used > prompt_tokens, "The transcript includes the prompt");
+
+    println!("✓ Token count test passed");
+    println!("{} of {} tokens used", used, context_size);
+
+    Ok(())
+}
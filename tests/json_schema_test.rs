@@ -0,0 +1,233 @@
+//! JSON Schema conversion tests
+//!
+//! Conversion is pure Rust, and sessions here run on an in-process backend, so
+//! these tests run on every platform.
+
+use fm_bindings::{
+    Error, GenerationRequest, GenerationSchema, LanguageModelSession, ModelBackend, Property,
+    Result,
+};
+use schemars::JsonSchema;
+use serde_json::json;
+
+/// Answers every request with a fixed reply
+struct Reply(&'static str);
+
+impl ModelBackend for Reply {
+    fn is_available(&self) -> bool {
+        true
+    }
+
+    fn stream_response(
+        &self,
+        _request: &GenerationRequest<'_>,
+        on_chunk: &mut dyn FnMut(&str),
+    ) -> Result<()> {
+        on_chunk(self.0);
+        Ok(())
+    }
+
+    fn cancel_stream(&self) {}
+}
+
+fn invalid_input(result: Result<GenerationSchema>) -> String {
+    match result {
+        Err(Error::InvalidInput(message)) => message,
+        other => panic!("expected InvalidInput, got {:?}", other),
+    }
+}
+
+#[test]
+fn test_object_array_enum_and_bounds() -> Result<()> {
+    let schema = GenerationSchema::from_json_schema(&json!({
+        "title": "Ticket",
+        "description": "A support ticket",
+        "type": "object",
+        "properties": {
+            "summary": { "type": "string", "description": "One line" },
+            "code": { "type": "string", "pattern": "^[A-Z]{3}$" },
+            "priority": { "enum": ["low", "high"] },
+            "tags": { "type": "array", "items": { "type": "string" }, "minItems": 1, "maxItems": 3 },
+            "votes": { "type": "integer", "exclusiveMinimum": 0, "maximum": 10 },
+            "score": { "type": "number", "minimum": 0.5 },
+            "urgent": { "type": ["boolean", "null"] }
+        },
+        "required": ["summary", "code", "priority", "tags", "votes", "score"],
+        "additionalProperties": false
+    }))?;
+
+    // Properties are converted in key order
+    let expected = GenerationSchema::object("Ticket")
+        .with_property(Property::new(
+            "code",
+            GenerationSchema::string().with_pattern("^[A-Z]{3}$"),
+        ))
+        .with_property(Property::new(
+            "priority",
+            GenerationSchema::enumeration("TicketPriority", ["low", "high"]),
+        ))
+        .with_property(Property::new(
+            "score",
+            GenerationSchema::number().with_range(Some(0.5), None),
+        ))
+        .with_property(
+            Property::new("summary", GenerationSchema::string()).with_description("One line"),
+        )
+        .with_property(Property::new(
+            "tags",
+            GenerationSchema::array(GenerationSchema::string()).with_count(Some(1), Some(3)),
+        ))
+        .with_property(Property::new("urgent", GenerationSchema::boolean()).with_optional(true))
+        .with_property(Property::new(
+            "votes",
+            GenerationSchema::integer().with_range(Some(1.0), Some(10.0)),
+        ))
+        .with_description("A support ticket");
+    assert_eq!(schema, expected);
+    Ok(())
+}
+
+#[test]
+fn test_unsupported_keywords_are_rejected() {
+    let message = invalid_input(GenerationSchema::from_json_schema(&json!({
+        "type": "object",
+        "properties": { "id": { "type": "string", "minLength": 3 } }
+    })));
+    assert_eq!(
+        message,
+        "Unsupported JSON Schema keyword `minLength` at #/properties/id"
+    );
+
+    let rejected = [
+        json!({ "type": "object", "additionalProperties": true }),
+        json!({ "enum": [1, 2] }),
+        json!({ "type": ["string", "integer"] }),
+        json!({ "type": "null" }),
+        json!({ "type": "number", "exclusiveMinimum": 0 }),
+        json!({ "$ref": "https://example.com/schema.json" }),
+        json!({ "not": { "type": "string" } }),
+        json!(true),
+    ];
+    for document in rejected {
+        invalid_input(GenerationSchema::from_json_schema(&document));
+    }
+}
+
+#[test]
+fn test_references_resolve_and_recursion_is_rejected() -> Result<()> {
+    let schema = GenerationSchema::from_json_schema(&json!({
+        "type": "array",
+        "items": { "$ref": "#/$defs/Point" },
+        "$defs": {
+            "Point": {
+                "type": "object",
+                "properties": { "x": { "type": "integer" } },
+                "required": ["x"]
+            }
+        }
+    }))?;
+    let GenerationSchema::Array { items, .. } = schema else {
+        panic!("expected an array schema");
+    };
+    assert_eq!(items.name(), Some("Point"));
+
+    let message = invalid_input(GenerationSchema::from_json_schema(&json!({
+        "$ref": "#/$defs/Node",
+        "$defs": {
+            "Node": {
+                "type": "object",
+                "properties": { "next": { "$ref": "#/$defs/Node" } }
+            }
+        }
+    })));
+    assert!(message.contains("Recursive"), "{}", message);
+    Ok(())
+}
+
+#[derive(JsonSchema)]
+#[allow(dead_code)]
+struct Order {
+    /// Customer name
+    customer: String,
+    quantity: u32,
+    note: Option<String>,
+    status: Status,
+    lines: Vec<Line>,
+}
+
+#[derive(JsonSchema)]
+#[allow(dead_code)]
+enum Status {
+    Open,
+    Shipped,
+}
+
+#[derive(JsonSchema)]
+#[allow(dead_code)]
+struct Line {
+    sku: String,
+}
+
+#[test]
+fn test_schemars_output_is_supported() -> Result<()> {
+    let document = serde_json::to_value(schemars::schema_for!(Order)).expect("schema");
+    let schema = GenerationSchema::from_json_schema(&document)?;
+
+    let GenerationSchema::Object {
+        name, properties, ..
+    } = &schema
+    else {
+        panic!("expected an object schema");
+    };
+    assert_eq!(name, "Order");
+    let property = |key: &str| {
+        properties
+            .iter()
+            .find(|property| property.name == key)
+            .unwrap_or_else(|| panic!("missing property {}", key))
+    };
+    assert_eq!(
+        property("customer").description.as_deref(),
+        Some("Customer name")
+    );
+    assert!(property("note").optional);
+    assert!(!property("quantity").optional);
+    assert_eq!(
+        property("status").schema,
+        GenerationSchema::enumeration("Status", ["Open", "Shipped"])
+    );
+    Ok(())
+}
+
+#[test]
+fn test_response_with_schema_checks_the_value() -> Result<()> {
+    let schema = GenerationSchema::from_json_schema(&json!({
+        "type": "object",
+        "properties": { "answer": { "type": "integer", "minimum": 0 } },
+        "required": ["answer"]
+    }))?;
+
+    let session = LanguageModelSession::with_backend(Reply(r#"{"answer": 4}"#))?;
+    assert_eq!(
+        session.response_with_schema("What is 2+2?", &schema)?,
+        json!({ "answer": 4 })
+    );
+
+    for reply in [
+        r#"{"answer": -1}"#,
+        r#"{"answer": 4, "extra": true}"#,
+        "{}",
+        "4",
+    ] {
+        let session = LanguageModelSession::with_backend(Reply(reply))?;
+        assert!(
+            matches!(
+                session.response_with_schema("What is 2+2?", &schema),
+                Err(Error::DecodingFailure(_))
+            ),
+            "{} should be rejected",
+            reply
+        );
+    }
+    Ok(())
+}
@@ -0,0 +1,272 @@
+//! Tests for the scripted mock model
+//!
+//! Requires the `mock` feature:
+//! ```sh
+//! cargo test --features mock
+//! ```
+
+use fm_bindings::mock::{MockModel, MockResponse};
+use fm_bindings::{
+    Availability, CancelHandle, Error, GenerationOptions, LanguageModelSession, Result,
+    SamplingMode,
+};
+use std::thread;
+use std::time::{Duration, Instant};
+
+#[test]
+fn test_unavailable_model() {
+    let result = LanguageModelSession::with_backend(MockModel::unavailable());
+    assert!(matches!(
+        result,
+        Err(Error::ModelNotAvailable(
+            Availability::AppleIntelligenceNotEnabled
+        ))
+    ));
+}
+
+#[test]
+fn test_unavailability_reason_is_reported() -> Result<()> {
+    let model = MockModel::new();
+    model.set_availability(Availability::ModelNotReady);
+    assert!(matches!(
+        LanguageModelSession::with_backend(model.clone()),
+        Err(Error::ModelNotAvailable(Availability::ModelNotReady))
+    ));
+
+    model.set_availability(Availability::Available);
+    model.push("ready");
+    let session = LanguageModelSession::with_backend(model)?;
+    assert_eq!(session.response("Hi")?, "ready");
+    Ok(())
+}
+
+#[test]
+fn test_availability_change_after_session_creation() -> Result<()> {
+    let model = MockModel::new();
+    model.push("kept for later");
+    let session = LanguageModelSession::with_backend(model.clone())?;
+
+    model.set_available(false);
+    assert!(matches!(
+        session.response("Hi"),
+        Err(Error::ModelNotAvailable(_))
+    ));
+    assert_eq!(
+        model.remaining(),
+        1,
+        "unavailable requests must not consume the script"
+    );
+
+    model.set_available(true);
+    assert_eq!(session.response("Hi")?, "kept for later");
+    Ok(())
+}
+
+#[test]
+fn test_responses_are_served_in_order() -> Result<()> {
+    let model = MockModel::new();
+    model
+        .push("first")
+        .push(MockResponse::chunks(["sec", "ond"]));
+    let session = LanguageModelSession::with_backend(model.clone())?;
+
+    assert_eq!(session.response("a")?, "first");
+
+    let mut chunks = Vec::new();
+    session.stream_response("b", |chunk| chunks.push(chunk.to_string()))?;
+    assert_eq!(chunks, ["sec", "ond"]);
+
+    assert_eq!(model.prompts(), ["a", "b"]);
+    assert_eq!(model.remaining(), 0);
+    Ok(())
+}
+
+#[test]
+fn test_exhausted_script_fails() -> Result<()> {
+    let session = LanguageModelSession::with_backend(MockModel::new())?;
+    assert!(matches!(
+        session.response("Hi"),
+        Err(Error::InternalError(_))
+    ));
+    Ok(())
+}
+
+#[test]
+fn test_injected_errors() -> Result<()> {
+    let model = MockModel::new();
+    model
+        .push(Error::ModelNotAvailable(Availability::ModelNotReady))
+        .push(
+            MockResponse::chunks(["partial"]).then_fail(Error::GenerationError("cut off".into())),
+        );
+    let session = LanguageModelSession::with_backend(model)?;
+
+    assert!(matches!(
+        session.response("a"),
+        Err(Error::ModelNotAvailable(Availability::ModelNotReady))
+    ));
+
+    let mut received = String::new();
+    let result = session.stream_response("b", |chunk| received.push_str(chunk));
+    assert!(matches!(result, Err(Error::GenerationError(msg)) if msg == "cut off"));
+    assert_eq!(received, "partial");
+    Ok(())
+}
+
+#[test]
+fn test_chunk_delay() -> Result<()> {
+    let model = MockModel::new();
+    model.push(MockResponse::chunks(["a", "b", "c"]).with_chunk_delay(Duration::from_millis(20)));
+    let session = LanguageModelSession::with_backend(model)?;
+
+    let start = Instant::now();
+    session.stream_response("slow", |_| {})?;
+    assert!(start.elapsed() >= Duration::from_millis(60));
+    Ok(())
+}
+
+#[test]
+fn test_cancel_stream_stops_delivery() -> Result<()> {
+    let model = MockModel::new();
+    let chunks: Vec<String> = (0..100).map(|i| format!("{i} ")).collect();
+    model.push(MockResponse::chunks(chunks).with_chunk_delay(Duration::from_millis(10)));
+    let session = LanguageModelSession::with_backend(model)?;
+
+    let session_clone = session.clone();
+    let handle = thread::spawn(move || {
+        let mut count = 0;
+        session_clone
+            .stream_response("long", |_| count += 1)
+            .map(|()| count)
+    });
+
+    thread::sleep(Duration::from_millis(100));
+    session.cancel_stream();
+
+    let count = handle.join().expect("stream thread panicked")?;
+    assert!(count > 0 && count < 100, "received {count} chunks");
+    Ok(())
+}
+
+#[test]
+fn test_cancel_handle_stops_only_its_request() -> Result<()> {
+    let model = MockModel::new();
+    let chunks: Vec<String> = (0..20).map(|i| format!("{i} ")).collect();
+    for _ in 0..2 {
+        model
+            .push(MockResponse::chunks(chunks.clone()).with_chunk_delay(Duration::from_millis(10)));
+    }
+    let session = LanguageModelSession::with_backend(model)?;
+    let handle = CancelHandle::new();
+
+    let cancelled = {
+        let session = session.cancellable(&handle);
+        thread::spawn(move || session.stream_response("first", |_| {}))
+    };
+    let other = {
+        let session = session.clone();
+        thread::spawn(move || session.response("second"))
+    };
+
+    thread::sleep(Duration::from_millis(50));
+    handle.cancel();
+
+    let result = cancelled.join().expect("cancelled thread panicked");
+    match result {
+        Err(Error::Cancelled { partial }) => {
+            assert!(!partial.is_empty() && partial.len() < chunks.concat().len());
+            assert!(chunks.concat().starts_with(&partial));
+        }
+        other => panic!("expected Cancelled, got {other:?}"),
+    }
+    assert_eq!(
+        other.join().expect("other thread panicked")?,
+        chunks.concat()
+    );
+
+    // Only the completed request is recorded
+    assert_eq!(session.transcript().len(), 2);
+    Ok(())
+}
+
+#[test]
+fn test_cancel_handle_stops_blocking_response() -> Result<()> {
+    let model = MockModel::new();
+    let chunks: Vec<String> = (0..100).map(|i| format!("{i} ")).collect();
+    model.push(MockResponse::chunks(chunks).with_chunk_delay(Duration::from_millis(10)));
+    let session = LanguageModelSession::with_backend(model)?;
+    let handle = CancelHandle::new();
+
+    let canceller = handle.clone();
+    thread::spawn(move || {
+        thread::sleep(Duration::from_millis(100));
+        canceller.cancel();
+    });
+
+    let start = Instant::now();
+    let result = session.cancellable(&handle).response("long");
+    assert!(start.elapsed() < Duration::from_millis(900));
+    assert!(matches!(result, Err(Error::Cancelled { partial }) if partial.starts_with("0 1 ")));
+    Ok(())
+}
+
+#[test]
+fn test_instructions_are_recorded() -> Result<()> {
+    let model = MockModel::new();
+    model.push("ok").push("ok");
+    let session = LanguageModelSession::builder()
+        .backend(model.clone())
+        .instructions("Be terse.")
+        .build()?;
+
+    session.response("a")?;
+    LanguageModelSession::with_backend(model.clone())?.response("b")?;
+
+    assert_eq!(model.prompts(), ["a", "b"]);
+    assert_eq!(model.instructions(), [Some("Be terse.".to_string()), None]);
+    Ok(())
+}
+
+#[test]
+fn test_generation_options_are_forwarded() -> Result<()> {
+    let model = MockModel::new();
+    model.push("ok").push("ok");
+    let session = LanguageModelSession::with_backend(model.clone())?;
+
+    let options = GenerationOptions::new()
+        .with_temperature(0.5)
+        .with_maximum_response_tokens(64)
+        .with_sampling(SamplingMode::top_k(40).with_seed(7));
+    session.response_with_options("a", &options)?;
+    session.stream_response("b", |_| {})?;
+
+    assert_eq!(model.options(), [options, GenerationOptions::default()]);
+    Ok(())
+}
+
+#[test]
+fn test_invalid_generation_options_never_reach_model() -> Result<()> {
+    let model = MockModel::new();
+    let session = LanguageModelSession::with_backend(model.clone())?;
+
+    let invalid = [
+        GenerationOptions::new().with_temperature(-1.0),
+        GenerationOptions::new().with_temperature(f64::NAN),
+        GenerationOptions::new().with_maximum_response_tokens(0),
+        GenerationOptions::new().with_sampling(SamplingMode::top_k(0)),
+        GenerationOptions::new().with_sampling(SamplingMode::nucleus(1.5)),
+        GenerationOptions::new().with_sampling(SamplingMode::nucleus(0.0)),
+    ];
+    for options in &invalid {
+        assert!(
+            matches!(
+                session.response_with_options("a", options),
+                Err(Error::InvalidInput(_))
+            ),
+            "{:?} should be rejected",
+            options
+        );
+    }
+    assert!(model.prompts().is_empty());
+    Ok(())
+}
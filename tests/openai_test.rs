@@ -0,0 +1,732 @@
+//! Tests for the OpenAI-compatible backend against a local stub server
+//!
+//! Requires the `openai` feature:
+//! ```sh
+//! cargo test --features openai
+//! ```
+
+use fm_bindings::openai::OpenAiBackend;
+use fm_bindings::{
+    Availability, Error, GenerationOptions, GenerationSchema, LanguageModelSession, Property,
+    Result, SamplingMode, Tool, TranscriptEntry,
+};
+use std::io::{BufRead, BufReader, Read, Write};
+use std::net::{TcpListener, TcpStream};
+use std::sync::mpsc::{self, Receiver};
+use std::thread;
+use std::time::{Duration, Instant};
+
+/// A request received by the stub server
+struct Received {
+    request_line: String,
+    headers: Vec<String>,
+    body: String,
+}
+
+/// Serves one canned HTTP response per connection, in order
+///
+/// Returns the base URL and a channel yielding each received request.
+fn stub_server(responses: Vec<String>) -> (String, Receiver<Received>) {
+    let listener = TcpListener::bind("127.0.0.1:0").expect("bind stub server");
+    let address = listener.local_addr().expect("stub address");
+    let (tx, rx) = mpsc::channel();
+
+    thread::spawn(move || {
+        for response in responses {
+            let Ok((stream, _)) = listener.accept() else {
+                return;
+            };
+            let received = read_request(&stream);
+            let _ = tx.send(received);
+            let mut stream = stream;
+            let _ = stream.write_all(response.as_bytes());
+        }
+    });
+
+    (format!("http://{}/v1", address), rx)
+}
+
+fn read_request(stream: &TcpStream) -> Received {
+    let mut reader = BufReader::new(stream);
+    let mut request_line = String::new();
+    reader.read_line(&mut request_line).expect("request line");
+
+    let mut headers = Vec::new();
+    let mut content_length = 0;
+    loop {
+        let mut line = String::new();
+        reader.read_line(&mut line).expect("header line");
+        let line = line.trim_end().to_string();
+        if line.is_empty() {
+            break;
+        }
+        if let Some((name, value)) = line.split_once(':')
+            && name.eq_ignore_ascii_case("content-length")
+        {
+            content_length = value.trim().parse().expect("content length");
+        }
+        headers.push(line);
+    }
+
+    let mut body = vec![0; content_length];
+    reader.read_exact(&mut body).expect("request body");
+
+    Received {
+        request_line: request_line.trim_end().to_string(),
+        headers,
+        body: String::from_utf8(body).expect("utf-8 body"),
+    }
+}
+
+fn http_response(status: &str, content_type: &str, body: &str) -> String {
+    format!(
+        "HTTP/1.1 {}\r\nContent-Type: {}\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+        status,
+        content_type,
+        body.len(),
+        body
+    )
+}
+
+fn models_ok() -> String {
+    http_response("200 OK", "application/json", r#"{"data":[{"id":"stub"}]}"#)
+}
+
+fn sse(events: &[&str]) -> String {
+    let body: String = events
+        .iter()
+        .map(|event| format!("data: {}\n\n", event))
+        .collect();
+    http_response("200 OK", "text/event-stream", &body)
+}
+
+fn delta(content: &str) -> String {
+    format!(
+        r#"{{"choices":[{{"index":0,"delta":{{"content":{:?}}},"finish_reason":null}}]}}"#,
+        content
+    )
+}
+
+fn session(base_url: &str) -> Result<LanguageModelSession> {
+    LanguageModelSession::with_backend(
+        OpenAiBackend::new(base_url, "stub-model").with_api_key("secret"),
+    )
+}
+
+/// Reports a fixed temperature for any city
+struct Weather;
+
+impl Tool for Weather {
+    fn name(&self) -> &str {
+        "get_weather"
+    }
+
+    fn description(&self) -> &str {
+        "Returns the current weather in a city"
+    }
+
+    fn arguments_schema(&self) -> GenerationSchema {
+        GenerationSchema::object("WeatherArguments")
+            .with_property(Property::new("city", GenerationSchema::string()))
+    }
+
+    fn call(&self, arguments: serde_json::Value) -> Result<String> {
+        Ok(format!(
+            "18°C in {}",
+            arguments["city"].as_str().unwrap_or("?")
+        ))
+    }
+}
+
+#[test]
+fn test_blocking_response() -> Result<()> {
+    let completion = r#"{"choices":[{"index":0,"message":{"role":"assistant","content":"4"},"finish_reason":"stop"}]}"#;
+    let (url, requests) = stub_server(vec![
+        models_ok(),
+        http_response("200 OK", "application/json", completion),
+    ]);
+
+    let session = session(&url)?;
+    assert_eq!(session.response("What is 2+2?")?, "4");
+
+    let models = requests.recv().expect("models request");
+    assert_eq!(models.request_line, "GET /v1/models HTTP/1.1");
+
+    let completion = requests.recv().expect("completion request");
+    assert_eq!(
+        completion.request_line,
+        "POST /v1/chat/completions HTTP/1.1"
+    );
+    assert!(
+        completion
+            .headers
+            .iter()
+            .any(|h| h.eq_ignore_ascii_case("authorization: Bearer secret"))
+    );
+
+    let body: serde_json::Value = serde_json::from_str(&completion.body).expect("JSON body");
+    assert_eq!(body["model"], "stub-model");
+    assert_eq!(body["stream"], false);
+    assert_eq!(body["messages"][0]["role"], "user");
+    assert_eq!(body["messages"][0]["content"], "What is 2+2?");
+    Ok(())
+}
+
+#[test]
+fn test_instructions_are_sent_as_system_message() -> Result<()> {
+    let completion = r#"{"choices":[{"index":0,"message":{"role":"assistant","content":"Oui"},"finish_reason":"stop"}]}"#;
+    let (url, requests) = stub_server(vec![
+        models_ok(),
+        http_response("200 OK", "application/json", completion),
+    ]);
+
+    let session = LanguageModelSession::builder()
+        .backend(OpenAiBackend::new(&url, "stub-model"))
+        .instructions("Reply in French.")
+        .build()?;
+    assert_eq!(session.response("Yes?")?, "Oui");
+
+    requests.recv().expect("models request");
+    let body: serde_json::Value =
+        serde_json::from_str(&requests.recv().expect("completion request").body)
+            .expect("JSON body");
+    assert_eq!(
+        body["messages"],
+        serde_json::json!([
+            { "role": "system", "content": "Reply in French." },
+            { "role": "user", "content": "Yes?" },
+        ])
+    );
+    Ok(())
+}
+
+#[test]
+fn test_transcript_is_replayed_as_messages() -> Result<()> {
+    let completion = |content: &str| {
+        let body = format!(
+            r#"{{"choices":[{{"index":0,"message":{{"role":"assistant","content":{:?}}},"finish_reason":"stop"}}]}}"#,
+            content
+        );
+        http_response("200 OK", "application/json", &body)
+    };
+    let (url, requests) = stub_server(vec![models_ok(), completion("Hi Ada"), completion("Ada")]);
+
+    let session = session(&url)?;
+    session.response("I am Ada")?;
+    assert_eq!(session.response("Who am I?")?, "Ada");
+
+    requests.recv().expect("models request");
+    requests.recv().expect("first completion request");
+    let body: serde_json::Value =
+        serde_json::from_str(&requests.recv().expect("second completion request").body)
+            .expect("JSON body");
+    assert_eq!(
+        body["messages"],
+        serde_json::json!([
+            { "role": "user", "content": "I am Ada" },
+            { "role": "assistant", "content": "Hi Ada" },
+            { "role": "user", "content": "Who am I?" },
+        ])
+    );
+    Ok(())
+}
+
+#[test]
+fn test_generation_options_are_sent() -> Result<()> {
+    let completion = r#"{"choices":[{"index":0,"message":{"role":"assistant","content":"ok"},"finish_reason":"stop"}]}"#;
+    let (url, requests) = stub_server(vec![
+        models_ok(),
+        http_response("200 OK", "application/json", completion),
+        http_response("200 OK", "application/json", completion),
+    ]);
+
+    let session = session(&url)?;
+    let nucleus = GenerationOptions::new()
+        .with_temperature(0.7)
+        .with_maximum_response_tokens(50)
+        .with_sampling(SamplingMode::nucleus(0.9).with_seed(3));
+    session.response_with_options("a", &nucleus)?;
+    let greedy = GenerationOptions::new().with_sampling(SamplingMode::Greedy);
+    session.response_with_options("b", &greedy)?;
+
+    requests.recv().expect("models request");
+    let body = |received: Received| -> serde_json::Value {
+        serde_json::from_str(&received.body).expect("JSON body")
+    };
+    let first = body(requests.recv().expect("first completion request"));
+    assert_eq!(first["temperature"], 0.7);
+    assert_eq!(first["max_tokens"], 50);
+    assert_eq!(first["top_p"], 0.9);
+    assert_eq!(first["seed"], 3);
+
+    let second = body(requests.recv().expect("second completion request"));
+    assert_eq!(second["temperature"], 0.0);
+    assert!(second.get("max_tokens").is_none());
+    assert!(second.get("seed").is_none());
+    Ok(())
+}
+
+#[test]
+fn test_schema_is_sent_as_response_format() -> Result<()> {
+    let completion = r#"{"choices":[{"index":0,"message":{"role":"assistant","content":"[\"Seine\",\"Loire\"]"},"finish_reason":"stop"}]}"#;
+    let (url, requests) = stub_server(vec![
+        models_ok(),
+        http_response("200 OK", "application/json", completion),
+    ]);
+
+    let session = session(&url)?;
+    let rivers: Vec<String> = session.response_as("Name two French rivers")?;
+    assert_eq!(rivers, ["Seine", "Loire"]);
+
+    requests.recv().expect("models request");
+    let received = requests.recv().expect("completion request");
+    let body: serde_json::Value = serde_json::from_str(&received.body).expect("JSON body");
+    let format = &body["response_format"];
+    assert_eq!(format["type"], "json_schema");
+    assert_eq!(format["json_schema"]["strict"], true);
+    assert_eq!(format["json_schema"]["schema"]["type"], "array");
+    assert_eq!(format["json_schema"]["schema"]["items"]["type"], "string");
+    Ok(())
+}
+
+#[test]
+fn test_tool_calls_are_run_and_sent_back() -> Result<()> {
+    let tool_call = r#"{"choices":[{"index":0,"message":{"role":"assistant","content":null,"tool_calls":[{"id":"call_1","type":"function","function":{"name":"get_weather","arguments":"{\"city\":\"Paris\"}"}}]},"finish_reason":"tool_calls"}]}"#;
+    let answer = r#"{"choices":[{"index":0,"message":{"role":"assistant","content":"It is 18°C."},"finish_reason":"stop"}]}"#;
+    let (url, requests) = stub_server(vec![
+        models_ok(),
+        http_response("200 OK", "application/json", tool_call),
+        http_response("200 OK", "application/json", answer),
+    ]);
+
+    let session = LanguageModelSession::builder()
+        .backend(OpenAiBackend::new(&url, "stub-model"))
+        .tool(Weather)
+        .build()?;
+    assert_eq!(session.response("Weather in Paris?")?, "It is 18°C.");
+
+    requests.recv().expect("models request");
+    let first: serde_json::Value =
+        serde_json::from_str(&requests.recv().expect("first completion").body).expect("JSON body");
+    let function = &first["tools"][0]["function"];
+    assert_eq!(function["name"], "get_weather");
+    assert_eq!(
+        function["parameters"]["properties"]["city"]["type"],
+        "string"
+    );
+
+    let second: serde_json::Value =
+        serde_json::from_str(&requests.recv().expect("second completion").body).expect("JSON body");
+    let messages = second["messages"].as_array().expect("messages");
+    assert_eq!(messages.len(), 3);
+    assert_eq!(messages[1]["tool_calls"][0]["id"], "call_1");
+    assert_eq!(messages[2]["role"], "tool");
+    assert_eq!(messages[2]["tool_call_id"], "call_1");
+    assert_eq!(messages[2]["content"], "18°C in Paris");
+
+    let entries = session.transcript().entries().to_vec();
+    assert_eq!(
+        entries[2],
+        TranscriptEntry::ToolOutput {
+            id: "call-1".into(),
+            name: "get_weather".into(),
+            output: "18°C in Paris".into(),
+        }
+    );
+    Ok(())
+}
+
+#[test]
+fn test_parallel_tool_calls_are_run_separately() -> Result<()> {
+    /// Returns a fixed time
+    struct Clock;
+
+    impl Tool for Clock {
+        fn name(&self) -> &str {
+            "get_time"
+        }
+
+        fn description(&self) -> &str {
+            "Returns the current time"
+        }
+
+        fn arguments_schema(&self) -> GenerationSchema {
+            GenerationSchema::object("ClockArguments")
+        }
+
+        fn call(&self, _arguments: serde_json::Value) -> Result<String> {
+            Ok("12:00".into())
+        }
+    }
+
+    let tool_calls = r#"{"choices":[{"index":0,"message":{"role":"assistant","content":null,"tool_calls":[{"id":"call_1","type":"function","function":{"name":"get_weather","arguments":"{\"city\":\"Paris\"}"}},{"id":"call_2","type":"function","function":{"name":"get_time","arguments":"{}"}}]},"finish_reason":"tool_calls"}]}"#;
+    let answer = r#"{"choices":[{"index":0,"message":{"role":"assistant","content":"18°C at noon."},"finish_reason":"stop"}]}"#;
+    let (url, requests) = stub_server(vec![
+        models_ok(),
+        http_response("200 OK", "application/json", tool_calls),
+        http_response("200 OK", "application/json", answer),
+    ]);
+
+    let session = LanguageModelSession::builder()
+        .backend(OpenAiBackend::new(&url, "stub-model"))
+        .tool(Weather)
+        .tool(Clock)
+        .build()?;
+    assert_eq!(session.response("Weather and time?")?, "18°C at noon.");
+
+    requests.recv().expect("models request");
+    requests.recv().expect("first completion");
+    let second: serde_json::Value =
+        serde_json::from_str(&requests.recv().expect("second completion").body).expect("JSON body");
+    let messages = second["messages"].as_array().expect("messages");
+    assert_eq!(messages.len(), 4);
+    let calls = &messages[1]["tool_calls"];
+    assert_eq!(calls[0]["id"], "call_1");
+    assert_eq!(calls[0]["function"]["name"], "get_weather");
+    assert_eq!(calls[1]["id"], "call_2");
+    assert_eq!(calls[1]["function"]["name"], "get_time");
+    assert_eq!(calls[1]["function"]["arguments"], "{}");
+    assert_eq!(messages[2]["tool_call_id"], "call_1");
+    assert_eq!(messages[2]["content"], "18°C in Paris");
+    assert_eq!(messages[3]["tool_call_id"], "call_2");
+    assert_eq!(messages[3]["content"], "12:00");
+    Ok(())
+}
+
+#[test]
+fn test_streamed_tool_calls_are_assembled() -> Result<()> {
+    let call_start = r#"{"choices":[{"index":0,"delta":{"tool_calls":[{"index":0,"id":"call_1","type":"function","function":{"name":"get_weather","arguments":"{\"ci"}}]},"finish_reason":null}]}"#;
+    let call_end = r#"{"choices":[{"index":0,"delta":{"tool_calls":[{"index":0,"function":{"arguments":"ty\":\"Oslo\"}"}}]},"finish_reason":null}]}"#;
+    let tool_stop = r#"{"choices":[{"index":0,"delta":{},"finish_reason":"tool_calls"}]}"#;
+    let stop = r#"{"choices":[{"index":0,"delta":{},"finish_reason":"stop"}]}"#;
+    let (url, requests) = stub_server(vec![
+        models_ok(),
+        sse(&[call_start, call_end, tool_stop, "[DONE]"]),
+        sse(&[&delta("Mild"), &delta("."), stop, "[DONE]"]),
+    ]);
+
+    let session = LanguageModelSession::builder()
+        .backend(OpenAiBackend::new(&url, "stub-model"))
+        .tool(Weather)
+        .build()?;
+    let mut chunks = Vec::new();
+    session.stream_response("Weather in Oslo?", |chunk| chunks.push(chunk.to_string()))?;
+    assert_eq!(chunks, ["Mild", "."]);
+
+    requests.recv().expect("models request");
+    requests.recv().expect("first completion");
+    let second: serde_json::Value =
+        serde_json::from_str(&requests.recv().expect("second completion").body).expect("JSON body");
+    assert_eq!(
+        second["messages"][1]["tool_calls"][0]["function"]["arguments"],
+        r#"{"city":"Oslo"}"#
+    );
+    assert_eq!(second["messages"][2]["content"], "18°C in Oslo");
+    Ok(())
+}
+
+#[test]
+fn test_tool_errors_are_sent_to_the_model() -> Result<()> {
+    /// Fails every call
+    struct Offline;
+
+    impl Tool for Offline {
+        fn name(&self) -> &str {
+            "get_weather"
+        }
+
+        fn description(&self) -> &str {
+            "Returns the current weather in a city"
+        }
+
+        fn arguments_schema(&self) -> GenerationSchema {
+            GenerationSchema::object("WeatherArguments")
+        }
+
+        fn call(&self, _arguments: serde_json::Value) -> Result<String> {
+            Err(Error::InvalidInput("Weather service offline".into()))
+        }
+    }
+
+    let tool_call = r#"{"choices":[{"index":0,"message":{"role":"assistant","content":null,"tool_calls":[{"id":"call_1","type":"function","function":{"name":"get_weather","arguments":"{}"}}]},"finish_reason":"tool_calls"}]}"#;
+    let answer = r#"{"choices":[{"index":0,"message":{"role":"assistant","content":"I cannot tell."},"finish_reason":"stop"}]}"#;
+    let (url, requests) = stub_server(vec![
+        models_ok(),
+        http_response("200 OK", "application/json", tool_call),
+        http_response("200 OK", "application/json", answer),
+    ]);
+
+    let session = LanguageModelSession::builder()
+        .backend(OpenAiBackend::new(&url, "stub-model"))
+        .tool(Offline)
+        .build()?;
+    assert_eq!(session.response("Weather?")?, "I cannot tell.");
+
+    requests.recv().expect("models request");
+    requests.recv().expect("first completion");
+    let second: serde_json::Value =
+        serde_json::from_str(&requests.recv().expect("second completion").body).expect("JSON body");
+    let message = &second["messages"][2];
+    assert_eq!(message["role"], "tool");
+    assert_eq!(message["tool_call_id"], "call_1");
+    let content = message["content"].as_str().expect("tool output");
+    assert!(content.contains("Weather service offline"), "{}", content);
+
+    let entries = session.transcript().entries().to_vec();
+    assert!(matches!(&entries[2], TranscriptEntry::ToolOutput { output, .. } if output == content));
+    Ok(())
+}
+
+#[test]
+fn test_unknown_tool_is_a_generation_error() -> Result<()> {
+    let tool_call = r#"{"choices":[{"index":0,"message":{"role":"assistant","tool_calls":[{"id":"call_1","type":"function","function":{"name":"get_time","arguments":"{}"}}]},"finish_reason":"tool_calls"}]}"#;
+    let (url, _requests) = stub_server(vec![
+        models_ok(),
+        http_response("200 OK", "application/json", tool_call),
+    ]);
+
+    let session = LanguageModelSession::builder()
+        .backend(OpenAiBackend::new(&url, "stub-model"))
+        .tool(Weather)
+        .build()?;
+    let result = session.response("What time is it?");
+    assert!(matches!(result, Err(Error::GenerationError(message)) if message.contains("get_time")));
+    assert_eq!(session.transcript().entries().len(), 0);
+    Ok(())
+}
+
+#[test]
+fn test_streaming_response() -> Result<()> {
+    let stop = r#"{"choices":[{"index":0,"delta":{},"finish_reason":"stop"}]}"#;
+    let (url, requests) = stub_server(vec![
+        models_ok(),
+        sse(&[&delta("Hel"), &delta("lo"), &delta("!"), stop, "[DONE]"]),
+    ]);
+
+    let session = session(&url)?;
+    let mut chunks = Vec::new();
+    session.stream_response("Greet me", |chunk| chunks.push(chunk.to_string()))?;
+    assert_eq!(chunks, ["Hel", "lo", "!"]);
+
+    requests.recv().expect("models request");
+    let body: serde_json::Value =
+        serde_json::from_str(&requests.recv().expect("completion request").body)
+            .expect("JSON body");
+    assert_eq!(body["stream"], true);
+    Ok(())
+}
+
+#[test]
+fn test_unreachable_server_is_unavailable() {
+    // Bind and drop a listener to find a port nobody is listening on
+    let address = TcpListener::bind("127.0.0.1:0")
+        .and_then(|listener| listener.local_addr())
+        .expect("free port");
+    let backend = OpenAiBackend::new(format!("http://{}/v1", address), "stub-model");
+
+    assert!(matches!(
+        LanguageModelSession::with_backend(backend),
+        Err(Error::ModelNotAvailable(Availability::Other(_)))
+    ));
+}
+
+#[test]
+fn test_silent_server_is_unavailable() {
+    // The listener accepts connections but never answers
+    let listener = TcpListener::bind("127.0.0.1:0").expect("bind silent server");
+    let address = listener.local_addr().expect("silent address");
+    let backend = OpenAiBackend::new(format!("http://{}/v1", address), "stub-model")
+        .with_timeout(Duration::from_millis(200));
+
+    let started = Instant::now();
+    assert!(matches!(
+        LanguageModelSession::with_backend(backend),
+        Err(Error::ModelNotAvailable(Availability::Other(_)))
+    ));
+    assert!(started.elapsed() < Duration::from_secs(5));
+    drop(listener);
+}
+
+#[test]
+fn test_http_errors_are_mapped() -> Result<()> {
+    let (url, _requests) = stub_server(vec![
+        models_ok(),
+        http_response(
+            "400 Bad Request",
+            "application/json",
+            r#"{"error":{"message":"context length exceeded"}}"#,
+        ),
+        http_response("503 Service Unavailable", "text/plain", "loading model"),
+        http_response("500 Internal Server Error", "text/plain", "boom"),
+    ]);
+
+    let session = session(&url)?;
+
+    match session.response("first") {
+        Err(Error::InvalidInput(msg)) => assert_eq!(msg, "HTTP 400: context length exceeded"),
+        other => panic!("expected invalid input, got {:?}", other),
+    }
+    match session.response("second") {
+        Err(Error::ModelNotAvailable(Availability::Other(msg))) => {
+            assert_eq!(msg, "HTTP 503: loading model")
+        }
+        other => panic!("expected unavailable model, got {:?}", other),
+    }
+    match session.stream_response("third", |_| {}) {
+        Err(Error::GenerationError(msg)) => assert_eq!(msg, "HTTP 500: boom"),
+        other => panic!("expected generation error, got {:?}", other),
+    }
+    Ok(())
+}
+
+#[test]
+fn test_error_codes_are_mapped() -> Result<()> {
+    let (url, _requests) = stub_server(vec![
+        models_ok(),
+        http_response(
+            "400 Bad Request",
+            "application/json",
+            r#"{"error":{"message":"too long","code":"context_length_exceeded"}}"#,
+        ),
+        http_response(
+            "429 Too Many Requests",
+            "application/json",
+            r#"{"error":{"message":"slow down","code":"rate_limit_exceeded"}}"#,
+        ),
+    ]);
+
+    let session = session(&url)?;
+
+    match session.response("long") {
+        Err(Error::ContextWindowExceeded(msg)) => assert_eq!(msg, "HTTP 400: too long"),
+        other => panic!("expected exceeded context window, got {:?}", other),
+    }
+    match session.response("again") {
+        Err(error @ Error::RateLimited(_)) => assert!(error.is_retryable()),
+        other => panic!("expected rate limiting, got {:?}", other),
+    }
+    Ok(())
+}
+
+#[test]
+fn test_llama_cpp_and_vllm_errors_are_mapped() -> Result<()> {
+    let llama_cpp_overflow = r#"{"error":{"code":400,"message":"the request exceeds the available context size","type":"exceed_context_size_error","n_prompt_tokens":5000,"n_ctx":4096}}"#;
+    let llama_cpp_invalid =
+        r#"{"error":{"code":400,"message":"bad sampling","type":"invalid_request_error"}}"#;
+    let vllm_overflow = r#"{"object":"error","message":"This model's maximum context length is 4096 tokens.","type":"BadRequestError","param":null,"code":400}"#;
+    let (url, _requests) = stub_server(vec![
+        models_ok(),
+        http_response("400 Bad Request", "application/json", llama_cpp_overflow),
+        http_response("400 Bad Request", "application/json", llama_cpp_invalid),
+        http_response("400 Bad Request", "application/json", vllm_overflow),
+        sse(&[llama_cpp_overflow]),
+    ]);
+
+    let session = session(&url)?;
+
+    match session.response("long") {
+        Err(Error::ContextWindowExceeded(msg)) => assert_eq!(
+            msg,
+            "HTTP 400: the request exceeds the available context size"
+        ),
+        other => panic!("expected exceeded context window, got {:?}", other),
+    }
+    match session.response("invalid") {
+        Err(Error::InvalidInput(msg)) => assert_eq!(msg, "HTTP 400: bad sampling"),
+        other => panic!("expected invalid input, got {:?}", other),
+    }
+    assert!(matches!(
+        session.response("long again"),
+        Err(Error::ContextWindowExceeded(_))
+    ));
+    match session.stream_response("streamed", |_| {}) {
+        Err(Error::ContextWindowExceeded(msg)) => {
+            assert_eq!(msg, "the request exceeds the available context size")
+        }
+        other => panic!("expected exceeded context window, got {:?}", other),
+    }
+    Ok(())
+}
+
+#[test]
+fn test_sse_failures_are_mapped() -> Result<()> {
+    let (url, _requests) = stub_server(vec![
+        models_ok(),
+        sse(&[
+            &delta("partial"),
+            r#"{"error":{"message":"out of memory"}}"#,
+        ]),
+        sse(&[&delta("partial"), "not json"]),
+        sse(&[&delta("truncated")]),
+    ]);
+
+    let session = session(&url)?;
+
+    let mut received = String::new();
+    match session.stream_response("error event", |chunk| received.push_str(chunk)) {
+        Err(Error::GenerationError(msg)) => assert_eq!(msg, "out of memory"),
+        other => panic!("expected generation error, got {:?}", other),
+    }
+    assert_eq!(received, "partial");
+
+    assert!(matches!(
+        session.stream_response("malformed", |_| {}),
+        Err(Error::GenerationError(_))
+    ));
+    assert!(matches!(
+        session.stream_response("truncated", |_| {}),
+        Err(Error::GenerationError(_))
+    ));
+    Ok(())
+}
+
+#[test]
+fn test_content_filter_is_a_guardrail_violation() -> Result<()> {
+    let filtered = r#"{"choices":[{"index":0,"message":{"role":"assistant","content":""},"finish_reason":"content_filter"}]}"#;
+    let stop = r#"{"choices":[{"index":0,"delta":{},"finish_reason":"content_filter"}]}"#;
+    let (url, _requests) = stub_server(vec![
+        models_ok(),
+        http_response("200 OK", "application/json", filtered),
+        sse(&[&delta("Some"), stop, "[DONE]"]),
+    ]);
+
+    let session = session(&url)?;
+    assert!(matches!(
+        session.response("blocked"),
+        Err(Error::GuardrailViolation(_))
+    ));
+    assert!(matches!(
+        session.stream_response("blocked while streaming", |_| {}),
+        Err(Error::GuardrailViolation(_))
+    ));
+    assert!(session.transcript().is_empty());
+    Ok(())
+}
+
+#[test]
+fn test_refusal_carries_explanation() -> Result<()> {
+    let refused = r#"{"choices":[{"index":0,"message":{"role":"assistant","content":null,"refusal":"I can't help with that."},"finish_reason":"stop"}]}"#;
+    let (url, _requests) = stub_server(vec![
+        models_ok(),
+        http_response("200 OK", "application/json", refused),
+        sse(&[
+            r#"{"choices":[{"index":0,"delta":{"refusal":"I can't "}}]}"#,
+            r#"{"choices":[{"index":0,"delta":{"refusal":"do that."},"finish_reason":"stop"}]}"#,
+            "[DONE]",
+        ]),
+    ]);
+
+    let session = session(&url)?;
+    match session.response("declined") {
+        Err(Error::Refusal(refusal)) => {
+            assert_eq!(refusal.explanation()?, "I can't help with that.")
+        }
+        other => panic!("expected a refusal, got {:?}", other),
+    }
+    match session.stream_response("declined while streaming", |_| {}) {
+        Err(Error::Refusal(refusal)) => assert_eq!(refusal.explanation()?, "I can't do that."),
+        other => panic!("expected a refusal, got {:?}", other),
+    }
+    assert!(session.transcript().is_empty());
+    Ok(())
+}
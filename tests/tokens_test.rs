@@ -0,0 +1,90 @@
+//! Heuristic token estimator tests
+//!
+//! The estimator is pure Rust, so these run on every platform.
+
+use fm_bindings::tokens::{self, ENTRY_OVERHEAD, TokenInput};
+use fm_bindings::{Transcript, TranscriptEntry};
+use serde_json::json;
+
+#[test]
+fn test_empty_text_has_no_tokens() {
+    assert_eq!(tokens::estimate(""), 0);
+    assert_eq!(tokens::estimate(" \n\t "), 0);
+}
+
+#[test]
+fn test_words_cost_one_token_per_four_characters() {
+    assert_eq!(tokens::estimate("a"), 1);
+    assert_eq!(tokens::estimate("word"), 1);
+    assert_eq!(tokens::estimate("words"), 2);
+    assert_eq!(tokens::estimate("the quick brown fox"), 6);
+    assert_eq!(tokens::estimate("internationalization"), 5);
+    assert_eq!(tokens::estimate("2026"), 1);
+}
+
+#[test]
+fn test_punctuation_and_symbols_cost_one_token_each() {
+    assert_eq!(tokens::estimate("Hello, world!"), 6);
+    assert_eq!(tokens::estimate("a+b=c"), 5);
+    assert_eq!(tokens::estimate("🦀"), 1);
+}
+
+#[test]
+fn test_cjk_characters_cost_one_token_each() {
+    assert_eq!(tokens::estimate("你好世界"), 4);
+    assert_eq!(tokens::estimate("こんにちは"), 5);
+    assert_eq!(tokens::estimate("안녕하세요"), 5);
+    // Latin runs next to ideographs are still grouped
+    assert_eq!(tokens::estimate("Rust语言"), 3);
+}
+
+#[test]
+fn test_accented_words_are_grouped_like_ascii() {
+    assert_eq!(tokens::estimate("café"), 1);
+    assert_eq!(tokens::estimate("Übersetzung"), 3);
+}
+
+#[test]
+fn test_transcript_entries_add_overhead() {
+    let transcript: Transcript = vec![
+        TranscriptEntry::instructions("Be brief"),
+        TranscriptEntry::prompt("Hi"),
+        TranscriptEntry::response("Hello!"),
+    ]
+    .into();
+
+    // "Be brief" 3, "Hi" 1, "Hello!" 3
+    assert_eq!(
+        tokens::estimate_transcript(&transcript),
+        7 + 3 * ENTRY_OVERHEAD
+    );
+    assert_eq!(tokens::estimate_transcript(&Transcript::new()), 0);
+}
+
+#[test]
+fn test_tool_calls_count_their_arguments() {
+    let call = TranscriptEntry::ToolCall {
+        id: "1".into(),
+        name: "weather".into(),
+        arguments: json!({ "city": "Paris" }),
+    };
+    let without_arguments = TranscriptEntry::ToolCall {
+        id: "1".into(),
+        name: "weather".into(),
+        arguments: json!({}),
+    };
+
+    let with = tokens::estimate_transcript(&vec![call].into());
+    let without = tokens::estimate_transcript(&vec![without_arguments].into());
+    assert_eq!(with - without, tokens::estimate(r#""city":"Paris""#));
+}
+
+#[test]
+fn test_token_input_conversions() {
+    let text = String::from("Hello, world!");
+    let transcript: Transcript = vec![TranscriptEntry::prompt("Hello, world!")].into();
+
+    assert_eq!(TokenInput::from("Hello, world!").estimate(), 6);
+    assert_eq!(TokenInput::from(&text), TokenInput::Text("Hello, world!"));
+    assert_eq!(TokenInput::from(&transcript).estimate(), 6 + ENTRY_OVERHEAD);
+}
@@ -0,0 +1,297 @@
+//! Tool calling tests against an in-process backend
+//!
+//! The backend below plays the model: it calls the request's tools the way the
+//! Swift bridge does, so tool registration, transcript recording and error
+//! mapping are exercised on every platform.
+
+use fm_bindings::{
+    Error, GenerationRequest, GenerationSchema, LanguageModelSession, ModelBackend, Property,
+    Result, Tool, TranscriptEntry,
+};
+use serde_json::{Value, json};
+use std::sync::{Arc, Barrier, Mutex};
+use std::thread;
+
+/// Calls each scripted tool in turn, then answers with the tools' outputs joined
+struct ToolCaller {
+    calls: Vec<(&'static str, Value)>,
+    offered: Arc<Mutex<Vec<String>>>,
+}
+
+impl ToolCaller {
+    fn calling(calls: Vec<(&'static str, Value)>) -> Self {
+        Self {
+            calls,
+            offered: Arc::default(),
+        }
+    }
+}
+
+impl ModelBackend for ToolCaller {
+    fn is_available(&self) -> bool {
+        true
+    }
+
+    fn stream_response(
+        &self,
+        request: &GenerationRequest<'_>,
+        on_chunk: &mut dyn FnMut(&str),
+    ) -> Result<()> {
+        *self.offered.lock().unwrap() = request
+            .tools
+            .iter()
+            .map(|tool| tool.name().to_string())
+            .collect();
+
+        let mut outputs = Vec::new();
+        for (name, arguments) in &self.calls {
+            let tool = request
+                .tool(name)
+                .ok_or_else(|| Error::GenerationError(format!("No tool {}", name)))?;
+            outputs.push(tool.call(arguments.clone())?);
+        }
+        on_chunk(&outputs.join(", "));
+        Ok(())
+    }
+
+    fn cancel_stream(&self) {}
+}
+
+/// Looks up the temperature of a few cities
+struct Weather;
+
+impl Tool for Weather {
+    fn name(&self) -> &str {
+        "get_weather"
+    }
+
+    fn description(&self) -> &str {
+        "Returns the current temperature in a city"
+    }
+
+    fn arguments_schema(&self) -> GenerationSchema {
+        GenerationSchema::object("WeatherArguments")
+            .with_property(Property::new("city", GenerationSchema::string()))
+    }
+
+    fn call(&self, arguments: Value) -> Result<String> {
+        match arguments["city"].as_str() {
+            Some("Paris") => Ok("18°C".into()),
+            Some("Oslo") => Ok("4°C".into()),
+            _ => Err(Error::InvalidInput(format!(
+                "Unknown city {}",
+                arguments["city"]
+            ))),
+        }
+    }
+}
+
+/// Returns a fixed time
+struct Clock;
+
+impl Tool for Clock {
+    fn name(&self) -> &str {
+        "get_time"
+    }
+
+    fn description(&self) -> &str {
+        "Returns the current time"
+    }
+
+    fn arguments_schema(&self) -> GenerationSchema {
+        GenerationSchema::object("ClockArguments")
+    }
+
+    fn call(&self, _arguments: Value) -> Result<String> {
+        Ok("12:00".into())
+    }
+}
+
+#[test]
+fn test_tool_calls_are_recorded_in_transcript() -> Result<()> {
+    let backend = ToolCaller::calling(vec![
+        ("get_weather", json!({ "city": "Paris" })),
+        ("get_time", json!({})),
+    ]);
+    let offered = backend.offered.clone();
+    let session = LanguageModelSession::builder()
+        .backend(backend)
+        .tool(Weather)
+        .tool(Clock)
+        .build()?;
+
+    assert_eq!(session.response("Weather and time?")?, "18°C, 12:00");
+    assert_eq!(*offered.lock().unwrap(), ["get_weather", "get_time"]);
+    assert_eq!(
+        session.transcript().entries(),
+        [
+            TranscriptEntry::prompt("Weather and time?"),
+            TranscriptEntry::ToolCall {
+                id: "call-1".into(),
+                name: "get_weather".into(),
+                arguments: json!({ "city": "Paris" }),
+            },
+            TranscriptEntry::ToolOutput {
+                id: "call-1".into(),
+                name: "get_weather".into(),
+                output: "18°C".into(),
+            },
+            TranscriptEntry::ToolCall {
+                id: "call-2".into(),
+                name: "get_time".into(),
+                arguments: json!({}),
+            },
+            TranscriptEntry::ToolOutput {
+                id: "call-2".into(),
+                name: "get_time".into(),
+                output: "12:00".into(),
+            },
+            TranscriptEntry::response("18°C, 12:00"),
+        ]
+    );
+    Ok(())
+}
+
+#[test]
+fn test_call_ids_stay_unique_across_prompts() -> Result<()> {
+    let session = LanguageModelSession::builder()
+        .backend(ToolCaller::calling(vec![(
+            "get_weather",
+            json!({ "city": "Oslo" }),
+        )]))
+        .tool(Weather)
+        .build()?;
+
+    session.stream_response("Weather?", |_| {})?;
+    session.stream_response("And now?", |_| {})?;
+
+    let ids: Vec<String> = session
+        .transcript()
+        .entries()
+        .iter()
+        .filter_map(|entry| match entry {
+            TranscriptEntry::ToolCall { id, .. } => Some(id.clone()),
+            _ => None,
+        })
+        .collect();
+    assert_eq!(ids, ["call-1", "call-2"]);
+    Ok(())
+}
+
+#[test]
+fn test_failing_tool_is_a_tool_call_error() -> Result<()> {
+    let session = LanguageModelSession::builder()
+        .backend(ToolCaller::calling(vec![(
+            "get_weather",
+            json!({ "city": "Atlantis" }),
+        )]))
+        .tool(Weather)
+        .build()?;
+
+    let result = session.response("Weather in Atlantis?");
+    match result {
+        Err(Error::ToolCallFailed { tool, message }) => {
+            assert_eq!(tool, "get_weather");
+            assert!(message.contains("Atlantis"), "{}", message);
+        }
+        other => panic!("expected a tool call error, got {:?}", other),
+    }
+    assert!(
+        session.transcript().is_empty(),
+        "failed requests are not recorded"
+    );
+    Ok(())
+}
+
+#[test]
+fn test_tool_error_is_reported_even_when_backend_rewords_it() -> Result<()> {
+    /// Reports every failure as a generic generation error, like the Swift bridge
+    struct Rewording(ToolCaller);
+
+    impl ModelBackend for Rewording {
+        fn is_available(&self) -> bool {
+            true
+        }
+
+        fn stream_response(
+            &self,
+            request: &GenerationRequest<'_>,
+            on_chunk: &mut dyn FnMut(&str),
+        ) -> Result<()> {
+            self.0
+                .stream_response(request, on_chunk)
+                .map_err(|e| Error::GenerationError(format!("Tool call failed: {}", e)))
+        }
+
+        fn cancel_stream(&self) {}
+    }
+
+    let session = LanguageModelSession::builder()
+        .backend(Rewording(ToolCaller::calling(vec![(
+            "get_weather",
+            json!({}),
+        )])))
+        .tool(Weather)
+        .build()?;
+
+    let result = session.response("Weather?");
+    assert!(matches!(result, Err(Error::ToolCallFailed { tool, .. }) if tool == "get_weather"));
+    Ok(())
+}
+
+#[test]
+fn test_duplicate_tool_names_are_rejected() {
+    let result = LanguageModelSession::builder()
+        .backend(ToolCaller::calling(Vec::new()))
+        .tool(Weather)
+        .tool(Weather)
+        .build();
+    assert!(matches!(result, Err(Error::InvalidInput(message)) if message.contains("get_weather")));
+}
+
+#[test]
+fn test_concurrent_requests_keep_their_own_tool_calls() -> Result<()> {
+    /// Looks up the weather in the city named by the prompt, in step with a second request
+    struct InStep(Barrier);
+
+    impl ModelBackend for InStep {
+        fn is_available(&self) -> bool {
+            true
+        }
+
+        fn stream_response(
+            &self,
+            request: &GenerationRequest<'_>,
+            on_chunk: &mut dyn FnMut(&str),
+        ) -> Result<()> {
+            let tool = request.tool("get_weather").unwrap();
+            let output = tool.call(json!({ "city": request.prompt }));
+            // Both requests have called their tool before either finishes
+            self.0.wait();
+            on_chunk(&output?);
+            Ok(())
+        }
+
+        fn cancel_stream(&self) {}
+    }
+
+    let session = LanguageModelSession::builder()
+        .backend(InStep(Barrier::new(2)))
+        .tool(Weather)
+        .build()?;
+
+    let other = session.clone();
+    let failing = thread::spawn(move || other.response("Atlantis"));
+    assert_eq!(session.response("Paris")?, "18°C");
+    let result = failing.join().unwrap();
+    assert!(matches!(result, Err(Error::ToolCallFailed { tool, .. }) if tool == "get_weather"));
+
+    let entries = session.transcript().entries().to_vec();
+    assert_eq!(entries.len(), 4, "{:?}", entries);
+    assert_eq!(entries[0], TranscriptEntry::prompt("Paris"));
+    assert!(
+        matches!(&entries[1], TranscriptEntry::ToolCall { arguments, .. } if arguments["city"] == "Paris")
+    );
+    assert!(matches!(&entries[2], TranscriptEntry::ToolOutput { output, .. } if output == "18°C"));
+    Ok(())
+}
@@ -0,0 +1,187 @@
+//! Transcript serialization and session resume tests
+//!
+//! Sessions here run on an in-process backend, so these tests run on every platform.
+
+use fm_bindings::{
+    Error, GenerationRequest, LanguageModelSession, ModelBackend, Result, Transcript,
+    TranscriptEntry,
+};
+use serde_json::json;
+use std::sync::{Arc, Mutex};
+
+/// The instructions and transcript of one request
+type Seen = (Option<String>, Transcript);
+
+/// Records the transcript of every request and answers "ok"
+#[derive(Clone, Default)]
+struct TranscriptSpy {
+    seen: Arc<Mutex<Vec<Seen>>>,
+}
+
+impl ModelBackend for TranscriptSpy {
+    fn is_available(&self) -> bool {
+        true
+    }
+
+    fn stream_response(
+        &self,
+        request: &GenerationRequest<'_>,
+        on_chunk: &mut dyn FnMut(&str),
+    ) -> Result<()> {
+        self.seen.lock().unwrap().push((
+            request.instructions.map(str::to_string),
+            request.transcript.clone(),
+        ));
+        on_chunk("ok");
+        Ok(())
+    }
+
+    fn cancel_stream(&self) {}
+}
+
+fn sample() -> Transcript {
+    Transcript::from(vec![
+        TranscriptEntry::instructions("Be brief."),
+        TranscriptEntry::prompt("Weather in Paris?"),
+        TranscriptEntry::ToolCall {
+            id: "call-1".into(),
+            name: "weather".into(),
+            arguments: json!({ "city": "Paris" }),
+        },
+        TranscriptEntry::ToolOutput {
+            id: "call-1".into(),
+            name: "weather".into(),
+            output: "18°C, sunny".into(),
+        },
+        TranscriptEntry::response("Sunny, 18°C."),
+    ])
+}
+
+#[test]
+fn test_transcript_json_round_trip() -> Result<()> {
+    let transcript = sample();
+    let json = transcript.to_json()?;
+
+    let value: serde_json::Value = serde_json::from_str(&json).expect("valid JSON");
+    assert_eq!(
+        value,
+        json!({
+            "entries": [
+                { "type": "instructions", "text": "Be brief." },
+                { "type": "prompt", "text": "Weather in Paris?" },
+                {
+                    "type": "tool_call",
+                    "id": "call-1",
+                    "name": "weather",
+                    "arguments": { "city": "Paris" },
+                },
+                {
+                    "type": "tool_output",
+                    "id": "call-1",
+                    "name": "weather",
+                    "output": "18°C, sunny",
+                },
+                { "type": "response", "text": "Sunny, 18°C." },
+            ]
+        })
+    );
+
+    assert_eq!(Transcript::from_json(&json)?, transcript);
+    assert_eq!(transcript.instructions(), Some("Be brief."));
+    Ok(())
+}
+
+#[test]
+fn test_invalid_transcript_json_is_rejected() {
+    assert!(matches!(
+        Transcript::from_json(r#"{"entries":[{"type":"shout","text":"hi"}]}"#),
+        Err(Error::InvalidInput(_))
+    ));
+    assert!(matches!(
+        Transcript::from_json("not json"),
+        Err(Error::InvalidInput(_))
+    ));
+}
+
+#[test]
+fn test_session_records_transcript() -> Result<()> {
+    let session = LanguageModelSession::builder()
+        .backend(TranscriptSpy::default())
+        .instructions("Be brief.")
+        .build()?;
+    session.response("Hi")?;
+
+    assert_eq!(
+        session.transcript().entries(),
+        [
+            TranscriptEntry::instructions("Be brief."),
+            TranscriptEntry::prompt("Hi"),
+            TranscriptEntry::response("ok"),
+        ]
+    );
+    Ok(())
+}
+
+#[test]
+fn test_session_resumes_from_saved_transcript() -> Result<()> {
+    let saved = sample().to_json()?;
+
+    let spy = TranscriptSpy::default();
+    let session = LanguageModelSession::builder()
+        .backend(spy.clone())
+        .transcript(Transcript::from_json(&saved)?)
+        .build()?;
+
+    assert_eq!(session.instructions(), Some("Be brief."));
+    session.response("And tomorrow?")?;
+
+    let seen = spy.seen.lock().unwrap();
+    assert_eq!(seen[0].0.as_deref(), Some("Be brief."));
+    assert_eq!(seen[0].1, sample());
+
+    let transcript = session.transcript();
+    assert_eq!(transcript.len(), sample().len() + 2);
+    assert_eq!(
+        transcript.entries()[sample().len()..],
+        [
+            TranscriptEntry::prompt("And tomorrow?"),
+            TranscriptEntry::response("ok"),
+        ]
+    );
+    Ok(())
+}
+
+#[test]
+fn test_builder_instructions_are_prepended_to_transcript() -> Result<()> {
+    let session = LanguageModelSession::builder()
+        .backend(TranscriptSpy::default())
+        .transcript(Transcript::from(vec![
+            TranscriptEntry::prompt("Hi"),
+            TranscriptEntry::response("Hello"),
+        ]))
+        .instructions("Be brief.")
+        .build()?;
+
+    assert_eq!(session.instructions(), Some("Be brief."));
+    assert_eq!(
+        session.transcript().entries()[0],
+        TranscriptEntry::instructions("Be brief.")
+    );
+    Ok(())
+}
+
+#[test]
+fn test_invalid_transcripts_are_rejected() {
+    let conflicting = LanguageModelSession::builder()
+        .backend(TranscriptSpy::default())
+        .transcript(sample())
+        .instructions("Be verbose.")
+        .build();
+    assert!(matches!(conflicting, Err(Error::InvalidInput(_))));
+
+    let null_byte = LanguageModelSession::builder()
+        .backend(TranscriptSpy::default())
+        .transcript(Transcript::from(vec![TranscriptEntry::prompt("nul\0")]))
+        .build();
+    assert!(matches!(null_byte, Err(Error::InvalidInput(_))));
+}
@@ -0,0 +1,9 @@
+use fm_bindings::fm_tool;
+
+/// Waits
+#[fm_tool]
+async fn wait() -> String {
+    String::new()
+}
+
+fn main() {}
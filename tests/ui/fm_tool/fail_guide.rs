@@ -0,0 +1,9 @@
+use fm_bindings::fm_tool;
+
+/// Returns the weather
+#[fm_tool]
+fn weather(#[guide(range = 0..=10)] city: String) -> String {
+    city
+}
+
+fn main() {}
@@ -0,0 +1,8 @@
+use fm_bindings::fm_tool;
+
+#[fm_tool]
+fn undocumented(city: String) -> String {
+    city
+}
+
+fn main() {}
@@ -0,0 +1,9 @@
+use fm_bindings::fm_tool;
+
+/// Adds two numbers
+#[fm_tool]
+fn add((a, b): (i32, i32)) -> String {
+    (a + b).to_string()
+}
+
+fn main() {}
@@ -0,0 +1,13 @@
+use fm_bindings::fm_tool;
+
+struct Weather;
+
+impl Weather {
+    /// Returns the weather
+    #[fm_tool]
+    fn get(&self, city: String) -> String {
+        city
+    }
+}
+
+fn main() {}
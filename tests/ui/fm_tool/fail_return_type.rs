@@ -0,0 +1,9 @@
+use fm_bindings::fm_tool;
+
+/// Counts letters
+#[fm_tool]
+fn count(text: String) -> usize {
+    text.len()
+}
+
+fn main() {}
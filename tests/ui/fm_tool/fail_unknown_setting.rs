@@ -0,0 +1,9 @@
+use fm_bindings::fm_tool;
+
+/// Returns the weather
+#[fm_tool(title = "Weather")]
+fn weather(city: String) -> String {
+    city
+}
+
+fn main() {}
@@ -0,0 +1,33 @@
+use fm_bindings::{Error, Generable, Result, Tool, fm_tool};
+use serde::Deserialize;
+
+#[derive(Generable, Deserialize)]
+enum Unit {
+    Celsius,
+    Fahrenheit,
+}
+
+/// Converts a temperature
+#[fm_tool]
+pub(crate) fn convert(
+    /// Temperature to convert
+    mut value: f64,
+    #[guide(description = "Target unit")] unit: Unit,
+    #[guide(count = 1..=3)] tags: Vec<String>,
+    r#type: Option<String>,
+) -> Result<String> {
+    value += 1.0;
+    let _ = (unit, tags, r#type);
+    Ok(value.to_string())
+}
+
+/// Always fails
+#[fm_tool(name = "broken")]
+fn fail() -> std::result::Result<String, Error> {
+    Err(Error::InvalidInput("broken".into()))
+}
+
+fn main() {
+    let tools: Vec<Box<dyn Tool>> = vec![Box::new(Convert), Box::new(Fail)];
+    assert_eq!(tools[1].name(), "broken");
+}
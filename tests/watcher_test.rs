@@ -0,0 +1,133 @@
+//! Availability watcher tests against a fake status source
+//!
+//! The source below is a shared `Availability` the tests change by hand, so the
+//! watcher's transition logic runs on every platform.
+
+use fm_bindings::{Availability, AvailabilityChange, AvailabilityWatcher, Error, Result};
+use std::sync::mpsc::{self, RecvTimeoutError};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+const INTERVAL: Duration = Duration::from_millis(5);
+const TIMEOUT: Duration = Duration::from_secs(5);
+
+/// A status the test controls, and a watcher polling it
+fn fake_source(initial: Availability) -> (Arc<Mutex<Availability>>, AvailabilityWatcher) {
+    let status = Arc::new(Mutex::new(initial));
+    let source = status.clone();
+    let watcher =
+        AvailabilityWatcher::new(move || source.lock().unwrap().clone()).with_interval(INTERVAL);
+    (status, watcher)
+}
+
+fn set(status: &Mutex<Availability>, availability: Availability) {
+    *status.lock().unwrap() = availability;
+}
+
+#[test]
+fn test_initial_state_is_reported_first() -> Result<()> {
+    let (_status, watcher) = fake_source(Availability::ModelNotReady);
+    let (_watch, changes) = watcher.channel()?;
+
+    let change = changes.recv_timeout(TIMEOUT).expect("initial event");
+    assert!(change.is_initial());
+    assert_eq!(
+        change,
+        AvailabilityChange {
+            previous: None,
+            current: Availability::ModelNotReady,
+        }
+    );
+    assert!(!change.became_available());
+    Ok(())
+}
+
+#[test]
+fn test_each_transition_is_reported_once() -> Result<()> {
+    let (status, watcher) = fake_source(Availability::AppleIntelligenceNotEnabled);
+    let (_watch, changes) = watcher.channel()?;
+    changes.recv_timeout(TIMEOUT).expect("initial event");
+
+    set(&status, Availability::ModelNotReady);
+    let change = changes.recv_timeout(TIMEOUT).expect("first transition");
+    assert_eq!(
+        change.previous,
+        Some(Availability::AppleIntelligenceNotEnabled)
+    );
+    assert_eq!(change.current, Availability::ModelNotReady);
+
+    set(&status, Availability::Available);
+    let change = changes.recv_timeout(TIMEOUT).expect("second transition");
+    assert!(change.became_available());
+
+    // An unchanged status is polled many times but never reported again
+    assert_eq!(
+        changes.recv_timeout(INTERVAL * 20),
+        Err(RecvTimeoutError::Timeout)
+    );
+    Ok(())
+}
+
+#[test]
+fn test_initial_event_can_be_disabled() -> Result<()> {
+    let (status, watcher) = fake_source(Availability::ModelNotReady);
+    let (_watch, changes) = watcher.with_initial_event(false).channel()?;
+    assert_eq!(
+        changes.recv_timeout(INTERVAL * 20),
+        Err(RecvTimeoutError::Timeout)
+    );
+
+    set(&status, Availability::Available);
+    let change = changes.recv_timeout(TIMEOUT).expect("transition");
+    assert_eq!(change.previous, Some(Availability::ModelNotReady));
+    assert!(change.became_available());
+    Ok(())
+}
+
+#[test]
+fn test_callback_receives_changes_until_stopped() -> Result<()> {
+    let (status, watcher) = fake_source(Availability::DeviceNotEligible);
+    let (sender, received) = mpsc::channel();
+    let watch = watcher.watch(move |change| sender.send(change.current).unwrap())?;
+
+    assert_eq!(
+        received.recv_timeout(TIMEOUT),
+        Ok(Availability::DeviceNotEligible)
+    );
+    set(&status, Availability::Other("Restricted".into()));
+    assert_eq!(
+        received.recv_timeout(TIMEOUT),
+        Ok(Availability::Other("Restricted".into()))
+    );
+
+    // Stopping joins the watcher thread, which drops the callback and its sender
+    watch.stop();
+    set(&status, Availability::Available);
+    assert_eq!(
+        received.recv_timeout(TIMEOUT),
+        Err(RecvTimeoutError::Disconnected)
+    );
+    Ok(())
+}
+
+#[test]
+fn test_dropping_handle_ends_channel() -> Result<()> {
+    let (_status, watcher) = fake_source(Availability::Available);
+    let (watch, changes) = watcher.with_interval(Duration::from_secs(3600)).channel()?;
+    changes.recv_timeout(TIMEOUT).expect("initial event");
+
+    // The long interval must not delay stopping
+    drop(watch);
+    assert_eq!(
+        changes.recv_timeout(TIMEOUT),
+        Err(RecvTimeoutError::Disconnected)
+    );
+    Ok(())
+}
+
+#[test]
+fn test_zero_interval_is_rejected() {
+    let (_status, watcher) = fake_source(Availability::Available);
+    let result = watcher.with_interval(Duration::ZERO).channel();
+    assert!(matches!(result, Err(Error::InvalidInput(_))));
+}